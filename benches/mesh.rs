@@ -0,0 +1,20 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use gfx::bench::{self, SIZES};
+use gfx::terrain::{self, MeshOptions, SyntheticKind};
+
+fn field_to_vertices(c: &mut Criterion) {
+    let options = MeshOptions::default();
+    let mut group = c.benchmark_group("field_to_vertices");
+    group.sample_size(10);
+    for size in SIZES {
+        let buffer = terrain::generate_synthetic(size, size, SyntheticKind::Noise);
+        group.throughput(Throughput::Elements((size * size) as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &buffer, |b, buffer| {
+            b.iter(|| bench::mesh(black_box(buffer), size, &options))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, field_to_vertices);
+criterion_main!(benches);
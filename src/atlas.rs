@@ -0,0 +1,216 @@
+use anyhow::*;
+use gdal::{
+    programs::raster::{build_vrt, BuildVRTOptions},
+    spatial_ref::SpatialRef,
+    Dataset,
+};
+use image::{imageops, RgbaImage};
+use std::path::{Path, PathBuf};
+use tracing::{debug, debug_span, info, trace, warn};
+
+/// Extensions of the imagery tile next to a DEM tile of the same name
+const IMAGERY_EXTENSIONS: [&str; 4] = ["png", "jpg", "jpeg", "webp"];
+/// Largest width or height of the assembled imagery
+const MAX_IMAGERY_SIZE: u32 = 8192;
+/// NoData of the mosaic when the tiles declare none, so gaps between them
+/// don't read as an elevation of 0
+const GAP_NO_DATA: f64 = -32768.0;
+
+/// DEM tiles of a directory mosaicked into one DEM, with their imagery tiles
+/// assembled to match
+#[derive(Debug, Clone)]
+pub struct Atlas {
+    /// VRT over the DEM tiles, loads like a single GeoTIFF
+    pub dem: PathBuf,
+    /// Imagery covering the whole mosaic, `None` when no tile has any
+    pub imagery: Option<PathBuf>,
+}
+
+/// DEM tile placed in the mosaic
+struct Tile {
+    path: PathBuf,
+    dataset: Dataset,
+    /// Projected [min x, min y, max x, max y]
+    bounds: [f64; 4],
+}
+
+impl Atlas {
+    /// Reads every GeoTIFF in `dir` as a tile placed by its geotransform.
+    /// Tiles without a geotransform, rotated ones and ones in another CRS
+    /// than the first are skipped. Where tiles overlap the later one in file
+    /// name order wins, gaps between them are NoData. The imagery tile of
+    /// `name.tif` is `name.png`, `.jpg` or `.webp`, tiles without one are
+    /// left black. Both results are written to the temp directory.
+    pub fn load(dir: &str) -> Result<Self> {
+        let span = debug_span!("Atlas::load", dir = dir);
+        let _enter = span.enter();
+
+        let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)
+            .with_context(|| format!("Failed to read {}", dir))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| has_extension(path, &["tif", "tiff"]))
+            .collect();
+        paths.sort();
+
+        let mut tiles = Vec::new();
+        let mut crs: Option<SpatialRef> = None;
+        for path in paths {
+            match open_tile(&path, &mut crs) {
+                Result::Ok(tile) => tiles.push(tile),
+                Err(e) => warn!("Skipping tile {}: {:#}", path.display(), e),
+            }
+        }
+        if tiles.is_empty() {
+            bail!("No usable GeoTIFF tiles in {}", dir);
+        }
+        let overlaps = tiles
+            .iter()
+            .enumerate()
+            .flat_map(|(i, a)| tiles[i + 1..].iter().map(move |b| (a, b)))
+            .filter(|(a, b)| intersects(a.bounds, b.bounds))
+            .count();
+        if overlaps > 0 {
+            warn!("{} pairs of tiles overlap, later tiles win", overlaps);
+        }
+        info!("Mosaicking {} tiles from {}", tiles.len(), dir);
+
+        let name = Path::new(dir)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "atlas".to_string());
+        let dem = std::env::temp_dir().join(format!("{}.vrt", name));
+        let has_no_data = tiles[0]
+            .dataset
+            .rasterband(1)
+            .is_ok_and(|band| band.no_data_value().is_some());
+        let mut arguments = vec!["-resolution".to_string(), "highest".to_string()];
+        if !has_no_data {
+            arguments.extend(["-vrtnodata".to_string(), GAP_NO_DATA.to_string()]);
+        }
+        let options = BuildVRTOptions::new(arguments)?;
+        let datasets: Vec<&Dataset> = tiles.iter().map(|tile| &tile.dataset).collect();
+        let mosaic = build_vrt(Some(&dem), &datasets, Some(options))
+            .context("Failed to build the tile mosaic")?;
+        let geotransform = mosaic.geo_transform()?;
+        let (width, height) = mosaic.raster_size();
+        // Dropping the dataset writes the VRT file
+        drop(mosaic);
+        debug!("Mosaic {}: {}x{}", dem.display(), width, height);
+
+        let imagery = match assemble_imagery(&tiles, geotransform, (width, height)) {
+            Some(image) => {
+                let path = std::env::temp_dir().join(format!("{}_imagery.png", name));
+                image
+                    .save(&path)
+                    .with_context(|| format!("Failed to write {}", path.display()))?;
+                Some(path)
+            }
+            None => None,
+        };
+        Ok(Self { dem, imagery })
+    }
+}
+
+fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    path.extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .is_some_and(|extension| extensions.contains(&extension.as_str()))
+}
+
+/// Opens a tile and checks it can be placed next to the others, `crs` is
+/// that of the first tile
+fn open_tile(path: &Path, crs: &mut Option<SpatialRef>) -> Result<Tile> {
+    let dataset = Dataset::open(path)?;
+    let geotransform = dataset
+        .geo_transform()
+        .context("No geotransform to place it with")?;
+    let [x, a, b, y, c, d] = geotransform;
+    if b != 0.0 || c != 0.0 {
+        bail!("Rotated geotransform {:?}", geotransform);
+    }
+    let projection = dataset.projection();
+    let tile_crs = if projection.is_empty() {
+        None
+    } else {
+        Some(SpatialRef::from_wkt(&projection)?)
+    };
+    match (crs.as_ref(), tile_crs) {
+        (None, tile_crs) => *crs = tile_crs,
+        (Some(crs), Some(tile_crs)) if *crs == tile_crs => {}
+        (Some(_), _) => bail!("CRS differs from the first tile"),
+    }
+    let (width, height) = dataset.raster_size();
+    let (x1, y1) = (x + a * width as f64, y + d * height as f64);
+    trace!("Tile {}: {}x{}", path.display(), width, height);
+    Ok(Tile {
+        path: path.to_path_buf(),
+        dataset,
+        bounds: [x.min(x1), y.min(y1), x.max(x1), y.max(y1)],
+    })
+}
+
+fn intersects(a: [f64; 4], b: [f64; 4]) -> bool {
+    a[0] < b[2] && b[0] < a[2] && a[1] < b[3] && b[1] < a[3]
+}
+
+/// Scales the imagery tiles into one image over the mosaic, at the resolution
+/// of the first one relative to its DEM tile
+fn assemble_imagery(
+    tiles: &[Tile],
+    [x0, a, _, y0, _, d]: [f64; 6],
+    (width, height): (usize, usize),
+) -> Option<RgbaImage> {
+    let images: Vec<(&Tile, RgbaImage)> = tiles
+        .iter()
+        .filter_map(|tile| {
+            let path = IMAGERY_EXTENSIONS
+                .iter()
+                .map(|extension| tile.path.with_extension(extension))
+                .find(|path| path.exists());
+            let Some(path) = path else {
+                warn!("No imagery for tile {}", tile.path.display());
+                return None;
+            };
+            match image::open(&path) {
+                Result::Ok(image) => Some((tile, image.to_rgba8())),
+                Err(e) => {
+                    warn!("Failed to read imagery {}: {}", path.display(), e);
+                    None
+                }
+            }
+        })
+        .collect();
+    let (first, first_image) = images.first()?;
+
+    let (tile_width, _) = first.dataset.raster_size();
+    let scale = (first_image.width() as f64 / tile_width as f64)
+        .min(MAX_IMAGERY_SIZE as f64 / width.max(height) as f64);
+    let canvas_width = ((width as f64 * scale).round() as u32).max(1);
+    let canvas_height = ((height as f64 * scale).round() as u32).max(1);
+    debug!(
+        "Imagery {}x{} from {} tiles",
+        canvas_width,
+        canvas_height,
+        images.len()
+    );
+    let mut canvas =
+        RgbaImage::from_pixel(canvas_width, canvas_height, image::Rgba([0, 0, 0, 255]));
+    for (tile, image) in &images {
+        // Tile bounds in mosaic pixels, the mosaic is north up like the tiles
+        let [min_x, min_y, max_x, max_y] = tile.bounds;
+        let (left, right) = ((min_x - x0) / a, (max_x - x0) / a);
+        let (top, bottom) = if d < 0.0 {
+            ((max_y - y0) / d, (min_y - y0) / d)
+        } else {
+            ((min_y - y0) / d, (max_y - y0) / d)
+        };
+        let [left, right, top, bottom] = [left, right, top, bottom].map(|v| (v * scale).round());
+        let size = (
+            (right - left).max(1.0) as u32,
+            (bottom - top).max(1.0) as u32,
+        );
+        let resized = imageops::resize(image, size.0, size.1, imageops::FilterType::Triangle);
+        imageops::replace(&mut canvas, &resized, left as i64, top as i64);
+    }
+    Some(canvas)
+}
@@ -0,0 +1,53 @@
+use crate::terrain::{self, HeightField, MeshOptions, SyntheticKind};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+use tracing::debug_span;
+
+/// Synthetic grid sizes meshed by `bench_mesh` and `benches/mesh.rs`
+pub const SIZES: [usize; 4] = [256, 512, 1024, 2048];
+
+/// Meshing of each size is repeated for at least this long
+const MIN_TIME: Duration = Duration::from_millis(500);
+
+/// Meshes a `size`x`size` elevation buffer the way a loaded DEM is meshed
+pub fn mesh(buffer: &[f64], size: usize, options: &MeshOptions) -> terrain::Mesh {
+    let field = HeightField::new(buffer, size, size, options);
+    terrain::field_to_vertices(&field, options)
+}
+
+/// Times meshing synthetic terrain of every size in `SIZES` and prints the
+/// results as a table, for `--bench-mesh`
+pub fn bench_mesh() {
+    let span = debug_span!("bench_mesh");
+    let _enter = span.enter();
+
+    let options = MeshOptions::default();
+    println!(
+        "| {:>5} | {:>10} | {:>10} | {:>10} | {:>12} |",
+        "Size", "Vertices", "Indices", "Time (ms)", "Vertices/s"
+    );
+    println!("|-------|------------|------------|------------|--------------|");
+    for size in SIZES {
+        let buffer = terrain::generate_synthetic(size, size, SyntheticKind::Noise);
+
+        let start = Instant::now();
+        let mut runs = 0;
+        let (vertices, indices) = loop {
+            let mesh = black_box(mesh(black_box(&buffer), size, &options));
+            runs += 1;
+            if start.elapsed() >= MIN_TIME {
+                break (mesh.vertices.len(), mesh.indices.len());
+            }
+        };
+        let per_run = start.elapsed().as_secs_f64() / runs as f64;
+
+        println!(
+            "| {:>5} | {:>10} | {:>10} | {:>10.2} | {:>12.0} |",
+            size,
+            vertices,
+            indices,
+            per_run * 1000.0,
+            vertices as f64 / per_run
+        );
+    }
+}
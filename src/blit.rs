@@ -0,0 +1,122 @@
+use tracing::trace;
+
+/// Draws a texture over the whole render target, used to present the terrain
+/// rendered at `RenderSettings::render_scale`
+pub struct Blit {
+    pipeline: egui_wgpu::wgpu::RenderPipeline,
+    bind_group_layout: egui_wgpu::wgpu::BindGroupLayout,
+}
+
+impl Blit {
+    pub fn new(device: &egui_wgpu::wgpu::Device, format: egui_wgpu::wgpu::TextureFormat) -> Self {
+        let shader = device.create_shader_module(egui_wgpu::wgpu::include_wgsl!("blit.wgsl"));
+        let bind_group_layout =
+            device.create_bind_group_layout(&egui_wgpu::wgpu::BindGroupLayoutDescriptor {
+                label: Some("Blit Bind Group Layout"),
+                entries: &[
+                    egui_wgpu::wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: egui_wgpu::wgpu::ShaderStages::FRAGMENT,
+                        ty: egui_wgpu::wgpu::BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: egui_wgpu::wgpu::TextureViewDimension::D2,
+                            sample_type: egui_wgpu::wgpu::TextureSampleType::Float {
+                                filterable: true,
+                            },
+                        },
+                        count: None,
+                    },
+                    egui_wgpu::wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: egui_wgpu::wgpu::ShaderStages::FRAGMENT,
+                        ty: egui_wgpu::wgpu::BindingType::Sampler(
+                            egui_wgpu::wgpu::SamplerBindingType::Filtering,
+                        ),
+                        count: None,
+                    },
+                ],
+            });
+        let layout = device.create_pipeline_layout(&egui_wgpu::wgpu::PipelineLayoutDescriptor {
+            label: Some("Blit Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&egui_wgpu::wgpu::RenderPipelineDescriptor {
+            label: Some("Blit Pipeline"),
+            layout: Some(&layout),
+            vertex: egui_wgpu::wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(egui_wgpu::wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(egui_wgpu::wgpu::ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: egui_wgpu::wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: egui_wgpu::wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: egui_wgpu::wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+        trace!("Blit pipeline created");
+
+        Self {
+            pipeline,
+            bind_group_layout,
+        }
+    }
+
+    /// Bind group that samples `view` with `sampler` for `draw`
+    pub fn bind_group(
+        &self,
+        device: &egui_wgpu::wgpu::Device,
+        view: &egui_wgpu::wgpu::TextureView,
+        sampler: &egui_wgpu::wgpu::Sampler,
+    ) -> egui_wgpu::wgpu::BindGroup {
+        device.create_bind_group(&egui_wgpu::wgpu::BindGroupDescriptor {
+            label: Some("Blit Bind Group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                egui_wgpu::wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: egui_wgpu::wgpu::BindingResource::TextureView(view),
+                },
+                egui_wgpu::wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: egui_wgpu::wgpu::BindingResource::Sampler(sampler),
+                },
+            ],
+        })
+    }
+
+    /// Covers `target` with the texture of `bind_group`
+    pub fn draw(
+        &self,
+        encoder: &mut egui_wgpu::wgpu::CommandEncoder,
+        target: &egui_wgpu::wgpu::TextureView,
+        bind_group: &egui_wgpu::wgpu::BindGroup,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&egui_wgpu::wgpu::RenderPassDescriptor {
+            label: Some("Blit Pass"),
+            color_attachments: &[Some(egui_wgpu::wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: egui_wgpu::wgpu::Operations {
+                    load: egui_wgpu::wgpu::LoadOp::Clear(egui_wgpu::wgpu::Color::BLACK),
+                    store: egui_wgpu::wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
@@ -1,8 +1,8 @@
+use crate::keys::Action;
 use cgmath::*;
 use egui_winit::winit::{
     dpi::PhysicalPosition,
     event::{ElementState, MouseScrollDelta},
-    keyboard::KeyCode,
 };
 use std::f32::consts::FRAC_PI_2;
 use std::time::Duration;
@@ -17,6 +17,10 @@ pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
 
 const SAFE_FRAC_PI_2: f32 = FRAC_PI_2 - 0.0001;
 
+/// Smallest near plane `fit_to_bounds` picks, relative to the far plane.
+/// Keeps the far/near ratio, and with it the depth precision, bounded.
+const MIN_NEAR_FRACTION: f32 = 1e-4;
+
 #[derive(Debug)]
 pub struct Camera {
     pub position: Point3<f32>,
@@ -37,6 +41,18 @@ impl Camera {
         }
     }
 
+    /// Rotates the camera around the vertical axis through `center` and turns
+    /// it to face `center`
+    pub fn orbit<A: Into<Rad<f32>>>(&mut self, center: Point3<f32>, angle: A) {
+        let offset = Basis3::from_angle_y(-angle.into()).rotate_vector(self.position - center);
+        self.position = center + offset;
+
+        let to_center = -offset;
+        self.yaw = Rad(to_center.z.atan2(to_center.x));
+        let pitch = (to_center.y / to_center.magnitude()).asin();
+        self.pitch = Rad(pitch.clamp(-SAFE_FRAC_PI_2, SAFE_FRAC_PI_2));
+    }
+
     pub fn calc_matrix(&self) -> Matrix4<f32> {
         let (sin_pitch, cos_pitch) = self.pitch.0.sin_cos();
         let (sin_yaw, cos_yaw) = self.yaw.0.sin_cos();
@@ -49,6 +65,56 @@ impl Camera {
     }
 }
 
+/// Camera pose and field of view a `Flight` interpolates
+#[derive(Debug, Clone, Copy)]
+pub struct View {
+    pub position: Point3<f32>,
+    pub yaw: Rad<f32>,
+    pub pitch: Rad<f32>,
+    pub fovy: Rad<f32>,
+}
+
+/// Eased move between two views over a fixed time
+#[derive(Debug)]
+pub struct Flight {
+    from: View,
+    to: View,
+    elapsed: f32,
+    /// Seconds
+    duration: f32,
+}
+
+impl Flight {
+    pub fn new(from: View, to: View, duration: f32) -> Self {
+        Self {
+            from,
+            to,
+            elapsed: 0.0,
+            duration,
+        }
+    }
+
+    /// View after `dt` more of the flight and whether it has arrived
+    pub fn advance(&mut self, dt: Duration) -> (View, bool) {
+        self.elapsed += dt.as_secs_f32();
+        let t = if self.duration > 0.0 {
+            (self.elapsed / self.duration).min(1.0)
+        } else {
+            1.0
+        };
+        let t = t * t * (3.0 - 2.0 * t);
+        // Turn the short way around
+        let yaw = (self.to.yaw - self.from.yaw).normalize_signed();
+        let view = View {
+            position: self.from.position + (self.to.position - self.from.position) * t,
+            yaw: self.from.yaw + yaw * t,
+            pitch: self.from.pitch + (self.to.pitch - self.from.pitch) * t,
+            fovy: self.from.fovy + (self.to.fovy - self.from.fovy) * t,
+        };
+        (view, t >= 1.0)
+    }
+}
+
 pub struct Projection {
     pub aspect: f32,
     pub fovy: Rad<f32>,
@@ -70,6 +136,32 @@ impl Projection {
         self.aspect = width as f32 / height as f32;
     }
 
+    /// Moves the near and far planes as close together as possible while
+    /// keeping the box from `min` to `max` visible from `eye`. `clearance` is
+    /// the distance to the nearest surface when `eye` is inside the box,
+    /// usually the height above the ground.
+    pub fn fit_to_bounds(
+        &mut self,
+        eye: Point3<f32>,
+        min: Point3<f32>,
+        max: Point3<f32>,
+        clearance: f32,
+    ) {
+        let mut nearest = Vector3::zero();
+        let mut farthest = Vector3::zero();
+        for i in 0..3 {
+            nearest[i] = eye[i].clamp(min[i], max[i]) - eye[i];
+            farthest[i] = (eye[i] - min[i]).abs().max((max[i] - eye[i]).abs());
+        }
+        let far = farthest.magnitude() * 1.01;
+        let near = match nearest.magnitude() {
+            distance if distance > 0.0 => distance,
+            _ => clearance,
+        };
+        self.zfar = far.max(1.0);
+        self.znear = (near * 0.5).max(self.zfar * MIN_NEAR_FRACTION);
+    }
+
     pub fn calc_matrix(&self) -> Matrix4<f32> {
         OPENGL_TO_WGPU_MATRIX * perspective(self.fovy, self.aspect, self.znear, self.zfar)
     }
@@ -85,9 +177,45 @@ pub struct CameraController {
     amount_down: f32,
     rotate_horizontal: f32,
     rotate_vertical: f32,
+    look_left: f32,
+    look_right: f32,
+    look_up: f32,
+    look_down: f32,
     scroll: f32,
     speed: f32,
-    sensitivity: f32,
+    /// Mouse look rate in radians per pixel per second
+    pub sensitivity: f32,
+    /// Whether moving the mouse up looks down
+    pub invert_y: bool,
+    /// Keyboard look rate in radians per second
+    pub look_speed: f32,
+    /// Scale the movement speed with the height above the terrain
+    pub auto_speed: bool,
+    /// Extra speed per world unit of height above the terrain, relative to `speed`
+    pub auto_speed_factor: f32,
+    /// Ease movement to a stop instead of stopping when keys are released
+    pub smoothing: bool,
+    /// Ease mouse and keyboard look the same way
+    pub look_smoothing: bool,
+    /// Rate the smoothed velocities decay towards the input, per second
+    pub damping: f32,
+    /// World units moved per arrow key press in precision mode
+    pub nudge_step: f32,
+    /// Arrow keys step the camera by `nudge_step` instead of moving it continuously
+    precision: bool,
+    /// Pending steps along (right, up, forward), applied in `update_camera`
+    nudge: Vector3<f32>,
+    height_above_terrain: f32,
+    /// World units per second, smoothed when `smoothing` is set
+    velocity: Vector3<f32>,
+    /// Yaw/pitch rate in radians per second, smoothed when `look_smoothing` is set
+    look_velocity: Vector2<f32>,
+    /// Keep the camera over `bounds` grown by `bounds_margin`
+    pub clamp_to_bounds: bool,
+    /// Distance the camera may leave `bounds`, relative to their larger side
+    pub bounds_margin: f32,
+    /// XZ extent of the terrain, see `set_bounds`
+    bounds: Option<[Point2<f32>; 2]>,
 }
 
 impl CameraController {
@@ -101,50 +229,104 @@ impl CameraController {
             amount_down: 0.0,
             rotate_horizontal: 0.0,
             rotate_vertical: 0.0,
+            look_left: 0.0,
+            look_right: 0.0,
+            look_up: 0.0,
+            look_down: 0.0,
             scroll: 0.0,
             speed,
             sensitivity,
+            invert_y: false,
+            look_speed: 1.0,
+            auto_speed: false,
+            auto_speed_factor: 0.05,
+            smoothing: false,
+            look_smoothing: false,
+            damping: 6.0,
+            nudge_step: 0.1,
+            precision: false,
+            nudge: Vector3::zero(),
+            height_above_terrain: 0.0,
+            velocity: Vector3::zero(),
+            look_velocity: Vector2::zero(),
+            clamp_to_bounds: false,
+            bounds_margin: 0.1,
+            bounds: None,
         }
     }
 
-    pub fn process_keyboard(&mut self, key: KeyCode, state: ElementState) -> bool {
+    pub fn precision(&self) -> bool {
+        self.precision
+    }
+
+    /// Switches precision mode, stopping any movement of held keys
+    pub fn set_precision(&mut self, precision: bool) {
+        self.precision = precision;
+        self.amount_left = 0.0;
+        self.amount_right = 0.0;
+        self.amount_forward = 0.0;
+        self.amount_backward = 0.0;
+        self.amount_up = 0.0;
+        self.amount_down = 0.0;
+        self.velocity = Vector3::zero();
+    }
+
+    /// Applies the camera actions of a key, see `KeyBindings::actions`.
+    /// Returns whether any of them moves the camera.
+    pub fn process_keyboard(&mut self, actions: &[Action], state: ElementState) -> bool {
+        if self.precision {
+            // Discrete steps, key repeat keeps stepping while held
+            let step = actions.iter().find_map(|action| match action {
+                Action::StepForward => Some(Vector3::unit_z()),
+                Action::StepBackward => Some(-Vector3::unit_z()),
+                Action::StepLeft => Some(-Vector3::unit_x()),
+                Action::StepRight => Some(Vector3::unit_x()),
+                Action::StepUp => Some(Vector3::unit_y()),
+                Action::StepDown => Some(-Vector3::unit_y()),
+                _ => None,
+            });
+            if let Some(step) = step {
+                if state == ElementState::Pressed {
+                    self.nudge += step * self.nudge_step;
+                }
+                return true;
+            }
+        }
+
         let amount = if state == ElementState::Pressed {
             1.0
         } else {
             0.0
         };
-        match key {
-            KeyCode::KeyW | KeyCode::ArrowUp => {
-                self.amount_forward = amount;
-                true
-            }
-            KeyCode::KeyA | KeyCode::ArrowLeft => {
-                self.amount_left = amount;
-                true
-            }
-            KeyCode::KeyS | KeyCode::ArrowDown => {
-                self.amount_backward = amount;
-                true
-            }
-            KeyCode::KeyD | KeyCode::ArrowRight => {
-                self.amount_right = amount;
-                true
-            }
-            KeyCode::Space => {
-                self.amount_up = amount;
-                true
-            }
-            KeyCode::ShiftLeft => {
-                self.amount_down = amount;
-                true
-            }
-            _ => false,
+        let mut handled = false;
+        for action in actions {
+            let target = match action {
+                Action::MoveForward => &mut self.amount_forward,
+                Action::MoveLeft => &mut self.amount_left,
+                Action::MoveBackward => &mut self.amount_backward,
+                Action::MoveRight => &mut self.amount_right,
+                Action::MoveUp => &mut self.amount_up,
+                Action::MoveDown => &mut self.amount_down,
+                // Keyboard look for when mouse look isn't an option
+                Action::LookUp => &mut self.look_up,
+                Action::LookDown => &mut self.look_down,
+                Action::LookLeft => &mut self.look_left,
+                Action::LookRight => &mut self.look_right,
+                _ => continue,
+            };
+            *target = amount;
+            handled = true;
         }
+        handled
     }
 
     pub fn process_mouse(&mut self, mouse_dx: f64, mouse_dy: f64) {
         self.rotate_horizontal = mouse_dx as f32;
-        self.rotate_vertical = mouse_dy as f32;
+        self.rotate_vertical = if self.invert_y {
+            -mouse_dy as f32
+        } else {
+            mouse_dy as f32
+        };
     }
 
     pub fn process_scroll(&mut self, delta: &MouseScrollDelta) {
@@ -155,15 +337,49 @@ impl CameraController {
         };
     }
 
+    /// Height of the camera above the terrain surface, used by `auto_speed`
+    pub fn set_height_above_terrain(&mut self, height: f32) {
+        self.height_above_terrain = height.max(0.0);
+    }
+
+    /// XZ extent of the terrain `clamp_to_bounds` keeps the camera over
+    pub fn set_bounds(&mut self, min: Point2<f32>, max: Point2<f32>) {
+        self.bounds = Some([min, max]);
+    }
+
+    /// Movement speed in world units per second for the current height
+    pub fn current_speed(&self) -> f32 {
+        if self.auto_speed {
+            self.speed * (1.0 + self.height_above_terrain * self.auto_speed_factor)
+        } else {
+            self.speed
+        }
+    }
+
     pub fn update_camera(&mut self, camera: &mut Camera, dt: Duration) {
         let dt = dt.as_secs_f32();
+        let speed = self.current_speed();
+        // Exponential decay, independent of the frame rate
+        let blend = 1.0 - (-self.damping * dt).exp();
 
-        // Move forward/backward and left/right
+        // Move forward/backward, left/right and up/down. Since we don't use
+        // roll, up/down can just modify the y coordinate directly.
         let (yaw_sin, yaw_cos) = camera.yaw.0.sin_cos();
         let forward = Vector3::new(yaw_cos, 0.0, yaw_sin).normalize();
         let right = Vector3::new(-yaw_sin, 0.0, yaw_cos).normalize();
-        camera.position += forward * (self.amount_forward - self.amount_backward) * self.speed * dt;
-        camera.position += right * (self.amount_right - self.amount_left) * self.speed * dt;
+        let target = (forward * (self.amount_forward - self.amount_backward)
+            + right * (self.amount_right - self.amount_left)
+            + Vector3::unit_y() * (self.amount_up - self.amount_down))
+            * speed;
+        if self.smoothing {
+            self.velocity += (target - self.velocity) * blend;
+        } else {
+            self.velocity = target;
+        }
+        camera.position += self.velocity * dt;
+        camera.position +=
+            right * self.nudge.x + Vector3::unit_y() * self.nudge.y + forward * self.nudge.z;
+        self.nudge = Vector3::zero();
 
         // Move in/out (aka. "zoom")
         // Note: this isn't an actual zoom. The camera's position
@@ -172,16 +388,38 @@ impl CameraController {
         let (pitch_sin, pitch_cos) = camera.pitch.0.sin_cos();
         let scrollward =
             Vector3::new(pitch_cos * yaw_cos, pitch_sin, pitch_cos * yaw_sin).normalize();
-        camera.position += scrollward * self.scroll * self.speed * self.sensitivity * dt;
+        camera.position += scrollward * self.scroll * speed * self.sensitivity * dt;
         self.scroll = 0.0;
 
-        // Move up/down. Since we don't use roll, we can just
-        // modify the y coordinate directly.
-        camera.position.y += (self.amount_up - self.amount_down) * self.speed * dt;
+        if let (true, Some([min, max])) = (self.clamp_to_bounds, self.bounds) {
+            let margin = (max.x - min.x).max(max.y - min.y) * self.bounds_margin.max(0.0);
+            let clamped = Point2::new(
+                camera.position.x.clamp(min.x - margin, max.x + margin),
+                camera.position.z.clamp(min.y - margin, max.y + margin),
+            );
+            if clamped.x != camera.position.x || clamped.y != camera.position.z {
+                camera.position.x = clamped.x;
+                camera.position.z = clamped.y;
+                // Don't keep pushing against the boundary
+                self.velocity.x = 0.0;
+                self.velocity.z = 0.0;
+            }
+        }
 
         // Rotate
-        camera.yaw += Rad(self.rotate_horizontal) * self.sensitivity * dt;
-        camera.pitch += Rad(-self.rotate_vertical) * self.sensitivity * dt;
+        let target = Vector2::new(
+            self.rotate_horizontal * self.sensitivity
+                + (self.look_right - self.look_left) * self.look_speed,
+            -self.rotate_vertical * self.sensitivity
+                + (self.look_up - self.look_down) * self.look_speed,
+        );
+        if self.look_smoothing {
+            self.look_velocity += (target - self.look_velocity) * blend;
+        } else {
+            self.look_velocity = target;
+        }
+        camera.yaw += Rad(self.look_velocity.x) * dt;
+        camera.pitch += Rad(self.look_velocity.y) * dt;
 
         // If process_mouse isn't called every frame, these values
         // will not get set to zero, and the camera will rotate
@@ -0,0 +1,47 @@
+use egui_wgpu::wgpu::{Adapter, DownlevelFlags, Features};
+use tracing::info;
+
+/// Optional features requested from the device when the adapter has them
+const OPTIONAL_FEATURES: Features = Features::POLYGON_MODE_LINE.union(Features::TIMESTAMP_QUERY);
+
+/// Optional GPU capabilities, detected once per device. Controls that need
+/// one are disabled in the GUI when it's missing instead of failing at
+/// pipeline creation.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Capabilities {
+    /// `PolygonMode::Line`, for `RenderSettings::wireframe`
+    pub polygon_mode_line: bool,
+    /// GPU timestamps for timing passes
+    pub timestamp_query: bool,
+    /// Storage buffers readable from vertex shaders
+    pub vertex_storage: bool,
+}
+
+impl Capabilities {
+    pub fn detect(adapter: &Adapter) -> Self {
+        let features = adapter.features();
+        let downlevel = adapter.get_downlevel_capabilities();
+        let capabilities = Self {
+            polygon_mode_line: features.contains(Features::POLYGON_MODE_LINE),
+            timestamp_query: features.contains(Features::TIMESTAMP_QUERY),
+            vertex_storage: downlevel.flags.contains(DownlevelFlags::VERTEX_STORAGE)
+                && adapter.limits().max_storage_buffers_per_shader_stage > 0,
+        };
+        info!("GPU capabilities: {:?}", capabilities);
+        capabilities
+    }
+
+    /// Features to request from the device, the optional ones the adapter has
+    pub fn required_features(adapter: &Adapter) -> Features {
+        adapter.features() & OPTIONAL_FEATURES
+    }
+
+    /// Name and availability of each capability, for the GUI
+    pub fn list(&self) -> [(&'static str, bool); 3] {
+        [
+            ("Wireframe (polygon mode line)", self.polygon_mode_line),
+            ("Timestamp Queries", self.timestamp_query),
+            ("Vertex Storage Buffers", self.vertex_storage),
+        ]
+    }
+}
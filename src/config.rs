@@ -0,0 +1,151 @@
+use crate::terrain::SyntheticKind;
+use anyhow::*;
+
+/// Where the elevation data comes from
+#[derive(Debug, Clone)]
+pub enum DemSource {
+    /// GeoTIFF file
+    File(String),
+    /// Generated terrain, for running without any data files
+    Synthetic(SyntheticKind),
+}
+
+impl DemSource {
+    /// Grid size of generated terrain
+    pub const SYNTHETIC_SIZE: usize = 512;
+}
+
+/// Startup configuration parsed from the command line
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub width: u32,
+    pub height: u32,
+    pub fullscreen: bool,
+    /// Preset file user preferences are loaded from and saved to
+    pub preset: String,
+    pub dem: DemSource,
+    /// Start orbiting the terrain until the user takes over, for demos
+    pub auto_rotate: bool,
+    /// Time meshing synthetic terrain and exit without opening a window
+    pub bench_mesh: bool,
+    /// Prefer a non-sRGB surface format, for compositing into linear pipelines
+    pub linear_surface: bool,
+    /// Start with `RenderSettings::packed_vertices`, for large DEMs
+    pub packed_vertices: bool,
+    /// Satellite image draped over the terrain, PNG, JPEG or WebP, or a
+    /// GeoTIFF orthophoto with RGB(A) bands. The bundled `satelite.png` when
+    /// unset.
+    pub imagery: Option<String>,
+    /// Largest width or height of the satellite image texture, it is
+    /// downsampled above this or the device limit
+    pub max_texture_size: Option<u32>,
+    /// Render every view of this `preset::Batch` file to a PNG and exit
+    pub batch: Option<String>,
+    /// Directory of DEM and imagery tiles mosaicked with `atlas::Atlas`,
+    /// replaces `dem` and, unless set, `imagery`
+    pub atlas: Option<String>,
+    /// Load the DEM coarse to fine with `progressive::Progressive`
+    pub progressive: bool,
+    /// Start from this overview of the DEM instead of the full resolution,
+    /// see `gtiff::load_geotiff_as_texture`
+    pub preview_level: Option<usize>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            width: 1280,
+            height: 720,
+            fullscreen: false,
+            preset: "preset.toml".to_string(),
+            dem: DemSource::File("output.tif".to_string()),
+            auto_rotate: false,
+            bench_mesh: false,
+            linear_surface: false,
+            packed_vertices: false,
+            imagery: None,
+            max_texture_size: None,
+            batch: None,
+            atlas: None,
+            progressive: false,
+            preview_level: None,
+        }
+    }
+}
+
+impl Config {
+    pub fn from_args() -> Result<Self> {
+        Self::parse(std::env::args().skip(1))
+    }
+
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Self> {
+        let mut config = Self::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--width" => config.width = parse_value(&arg, args.next())?,
+                "--height" => config.height = parse_value(&arg, args.next())?,
+                "--fullscreen" => config.fullscreen = true,
+                "--auto-rotate" => config.auto_rotate = true,
+                "--bench-mesh" => config.bench_mesh = true,
+                "--linear-surface" => config.linear_surface = true,
+                "--packed-vertices" => config.packed_vertices = true,
+                "--progressive" => config.progressive = true,
+                "--preview-level" => config.preview_level = Some(parse_value(&arg, args.next())?),
+                "--imagery" => config.imagery = Some(parse_value(&arg, args.next())?),
+                "--max-texture-size" => {
+                    config.max_texture_size = Some(parse_value(&arg, args.next())?)
+                }
+                "--batch" => config.batch = Some(parse_value(&arg, args.next())?),
+                "--atlas" => config.atlas = Some(parse_value(&arg, args.next())?),
+                "--preset" => config.preset = parse_value(&arg, args.next())?,
+                "--dem" => config.dem = DemSource::File(parse_value(&arg, args.next())?),
+                "--synthetic" => config.dem = DemSource::Synthetic(parse_value(&arg, args.next())?),
+                _ => bail!("Unknown argument: {}", arg),
+            }
+        }
+
+        if config.width == 0 || config.height == 0 {
+            bail!("Window size must be non-zero");
+        }
+        if let Some(level) = config.preview_level {
+            // Level n decimates by 2^(n + 1), which has to fit a usize
+            if level >= usize::BITS as usize - 1 {
+                bail!(
+                    "Preview level must be below {}, got {}",
+                    usize::BITS - 1,
+                    level
+                );
+            }
+        }
+        Ok(config)
+    }
+}
+
+fn parse_value<T: std::str::FromStr>(arg: &str, value: Option<String>) -> Result<T> {
+    let value = value.with_context(|| format!("Missing value for {}", arg))?;
+    value
+        .parse()
+        .map_err(|_| anyhow!("Invalid value for {}: {}", arg, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Config> {
+        Config::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn preview_level_in_range() {
+        let config = parse(&["--preview-level", "3"]).unwrap();
+        assert_eq!(config.preview_level, Some(3));
+    }
+
+    #[test]
+    fn preview_level_too_large() {
+        assert!(parse(&["--preview-level", "63"]).is_err());
+        assert!(parse(&["--preview-level", "1000"]).is_err());
+    }
+}
@@ -0,0 +1,197 @@
+use anyhow::*;
+use std::path::PathBuf;
+use std::sync::mpsc::{channel, Receiver};
+
+/// Whether a DEM path is an http(s) URL that needs downloading first
+pub fn is_url(path: &str) -> bool {
+    path.starts_with("http://") || path.starts_with("https://")
+}
+
+/// Downloads `url` to a file in the temp directory and returns its path, so
+/// it can be opened through the regular file loaders. `progress` is called
+/// with the downloaded and total bytes as the download goes.
+#[cfg(feature = "reqwest")]
+pub fn download(url: &str, mut progress: impl FnMut(u64, Option<u64>)) -> Result<PathBuf> {
+    use std::io::{Read, Write};
+    use tracing::{debug, debug_span, info};
+
+    /// Downloaded bytes between progress log lines
+    const PROGRESS_STEP: u64 = 8 * 1024 * 1024;
+
+    let span = debug_span!("download", url = url);
+    let _enter = span.enter();
+
+    let mut response = reqwest::blocking::get(url)
+        .and_then(|response| response.error_for_status())
+        .with_context(|| format!("Failed to download {}", url))?;
+    let total = response.content_length();
+    debug!("Content length: {:?}", total);
+
+    let (path, mut file) = create_temp_file(url)?;
+    debug!("Downloading to {}", path.display());
+
+    let mut buffer = vec![0; 64 * 1024];
+    let mut downloaded = 0;
+    let mut next_report = PROGRESS_STEP;
+    loop {
+        let read = response
+            .read(&mut buffer)
+            .with_context(|| format!("Failed to download {}", url))?;
+        if read == 0 {
+            break;
+        }
+        file.write_all(&buffer[..read])
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        downloaded += read as u64;
+        progress(downloaded, total);
+        if downloaded >= next_report {
+            match total {
+                Some(total) => info!(
+                    "Downloaded {:.1} of {:.1} MiB",
+                    downloaded as f64 / 1048576.0,
+                    total as f64 / 1048576.0
+                ),
+                None => info!("Downloaded {:.1} MiB", downloaded as f64 / 1048576.0),
+            }
+            next_report += PROGRESS_STEP;
+        }
+    }
+    info!("Downloaded {} bytes to {}", downloaded, path.display());
+    Ok(path)
+}
+
+/// Name of the temp file a download of `url` goes to. The query and fragment
+/// are left out, they can hold tokens and characters file names can't. The
+/// name is a hash of the URL and `unique`, only the extension is kept so
+/// GDAL picks the right driver.
+fn temp_name(url: &str, unique: u64) -> String {
+    use std::hash::{Hash, Hasher};
+
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    let extension = path
+        .rsplit('/')
+        .next()
+        .and_then(|name| name.rsplit_once('.'))
+        .map(|(_, extension)| extension)
+        .filter(|extension| {
+            !extension.is_empty() && extension.chars().all(|c| c.is_ascii_alphanumeric())
+        })
+        .unwrap_or("tif");
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    (url, unique).hash(&mut hasher);
+    format!("terrain-render-{:016x}.{}", hasher.finish(), extension)
+}
+
+/// Creates a new file for a download of `url` in the temp directory, never
+/// one another download or instance is using
+#[cfg_attr(not(feature = "reqwest"), allow(dead_code))]
+fn create_temp_file(url: &str) -> Result<(PathBuf, std::fs::File)> {
+    let seed = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |time| time.as_nanos() as u64)
+        ^ ((std::process::id() as u64) << 32);
+    for attempt in 0..16 {
+        let path = std::env::temp_dir().join(temp_name(url, seed.wrapping_add(attempt)));
+        match std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+        {
+            std::result::Result::Ok(file) => return Ok((path, file)),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to create {}", path.display()))
+            }
+        }
+    }
+    bail!("Failed to find a free temp file name for {}", url)
+}
+
+/// Deletes a file `download` wrote once the DEM in it is no longer shown
+pub fn remove(path: &std::path::Path) {
+    match std::fs::remove_file(path) {
+        std::result::Result::Ok(()) => tracing::debug!("Removed {}", path.display()),
+        Err(e) => tracing::warn!("Failed to remove {}: {}", path.display(), e),
+    }
+}
+
+#[cfg(not(feature = "reqwest"))]
+pub fn download(url: &str, _progress: impl FnMut(u64, Option<u64>)) -> Result<PathBuf> {
+    bail!(
+        "Can't download {}, rebuild with `--features reqwest` to load URLs",
+        url
+    )
+}
+
+/// Message from a `Download` thread
+enum Update {
+    Progress(u64, Option<u64>),
+    Done(std::result::Result<PathBuf, String>),
+}
+
+/// `download` running on a thread so the window can show its progress.
+/// `State::poll_download` opens the DEM once it is done.
+pub struct Download {
+    pub url: String,
+    /// Downloaded and total bytes at the last `poll`
+    pub progress: (u64, Option<u64>),
+    updates: Receiver<Update>,
+}
+
+impl Download {
+    pub fn start(url: &str) -> Self {
+        let (sender, updates) = channel();
+        let source = url.to_string();
+        std::thread::spawn(move || {
+            let result = download(&source, |downloaded, total| {
+                let _ = sender.send(Update::Progress(downloaded, total));
+            });
+            let _ = sender.send(Update::Done(result.map_err(|e| format!("{:#}", e))));
+        });
+        Self {
+            url: url.to_string(),
+            progress: (0, None),
+            updates,
+        }
+    }
+
+    /// The downloaded file or the error once the download finished
+    pub fn poll(&mut self) -> Option<std::result::Result<PathBuf, String>> {
+        for update in self.updates.try_iter() {
+            match update {
+                Update::Progress(downloaded, total) => self.progress = (downloaded, total),
+                Update::Done(result) => return Some(result),
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn temp_name_drops_query_and_fragment() {
+        let name = temp_name("https://example.com/dem.tif?token=secret#part", 1);
+        assert!(name.ends_with(".tif"));
+        assert!(!name.contains("secret") && !name.contains('?') && !name.contains('#'));
+    }
+
+    #[test]
+    fn temp_name_keeps_extension() {
+        assert!(temp_name("https://example.com/data/dem.vrt", 1).ends_with(".vrt"));
+        assert!(temp_name("https://example.com/data/", 1).ends_with(".tif"));
+        assert!(temp_name("https://example.com/dem.t%20f", 1).ends_with(".tif"));
+    }
+
+    #[test]
+    fn temp_name_is_unique() {
+        let url = "https://example.com/";
+        assert_ne!(temp_name(url, 1), temp_name(url, 2));
+        assert_ne!(
+            temp_name("https://a.example.com/dem.tif", 1),
+            temp_name("https://b.example.com/dem.tif", 1)
+        );
+    }
+}
@@ -1,107 +1,1744 @@
 use egui_wgpu::wgpu::{
     Device, Queue, Texture, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
 };
-use gdal::Dataset;
-use tracing::{debug, debug_span, error, trace};
+use gdal::{
+    raster::{Buffer, GdalDataType, ResampleAlg},
+    spatial_ref::{AxisMappingStrategy, CoordTransform, SpatialRef},
+    Dataset, DriverManager,
+};
+use tracing::{debug, debug_span, info, trace, warn};
+
+/// Vertical unit of the elevation values stored in a DEM
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ElevationUnit {
+    #[default]
+    Meters,
+    Feet,
+}
+
+impl ElevationUnit {
+    pub const ALL: [ElevationUnit; 2] = [ElevationUnit::Meters, ElevationUnit::Feet];
+
+    const FEET_TO_METERS: f64 = 0.3048;
+
+    /// Parses the unit string GDAL reports for a band (e.g. "m", "metre", "ft")
+    pub fn from_gdal(unit: &str) -> Option<Self> {
+        match unit.trim().to_lowercase().as_str() {
+            "m" | "meter" | "meters" | "metre" | "metres" => Some(Self::Meters),
+            "ft" | "foot" | "feet" | "us survey foot" | "ftus" => Some(Self::Feet),
+            _ => None,
+        }
+    }
+
+    pub fn to_meters(self, value: f64) -> f64 {
+        match self {
+            Self::Meters => value,
+            Self::Feet => value * Self::FEET_TO_METERS,
+        }
+    }
+
+    pub fn from_meters(self, value: f64) -> f64 {
+        match self {
+            Self::Meters => value,
+            Self::Feet => value / Self::FEET_TO_METERS,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Meters => "Meters",
+            Self::Feet => "Feet",
+        }
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Self::Meters => "m",
+            Self::Feet => "ft",
+        }
+    }
+}
+
+/// Georeferencing of a raster
+#[derive(Debug, Clone)]
+pub struct GeoReference {
+    /// GDAL affine geotransform, identity when the file has none
+    pub geotransform: [f64; 6],
+    /// Spatial reference as WKT, empty when the file has none
+    pub crs_wkt: String,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl GeoReference {
+    pub const IDENTITY: [f64; 6] = [0.0, 1.0, 0.0, 0.0, 0.0, 1.0];
+
+    fn from_dataset(dataset: &Dataset) -> Self {
+        let geotransform = match dataset.geo_transform() {
+            Ok(geotransform) => geotransform,
+            Err(e) => {
+                warn!("No geotransform, falling back to identity: {}", e);
+                Self::IDENTITY
+            }
+        };
+        let (width, height) = dataset.raster_size();
+        let geo = Self {
+            geotransform,
+            crs_wkt: dataset.projection(),
+            width,
+            height,
+        };
+        if geo.is_rotated() {
+            // The mesh is laid out on the pixel grid, the rotation only turns north
+            warn!(
+                "Geotransform is rotated or sheared, terrain is shown in pixel space: {:?}",
+                geotransform
+            );
+        }
+        geo
+    }
+
+    /// The same extent on a `width`x`height` grid, for a decimated copy of
+    /// the raster
+    pub fn decimated(&self, (width, height): (usize, usize)) -> Self {
+        let [x, a, b, y, c, d] = self.geotransform;
+        let column_scale = self.width as f64 / width as f64;
+        let row_scale = self.height as f64 / height as f64;
+        Self {
+            geotransform: [
+                x,
+                a * column_scale,
+                b * row_scale,
+                y,
+                c * column_scale,
+                d * row_scale,
+            ],
+            crs_wkt: self.crs_wkt.clone(),
+            width,
+            height,
+        }
+    }
+
+    /// Whether the rotation/shear terms of the geotransform are nonzero, so
+    /// pixel columns and rows aren't aligned with the CRS axes. Everything
+    /// here maps through the full affine transform, but the rendered terrain
+    /// stays on the pixel grid.
+    pub fn is_rotated(&self) -> bool {
+        self.geotransform[2] != 0.0 || self.geotransform[4] != 0.0
+    }
+
+    /// Georeferenced coordinate of a fractional (column, row)
+    pub fn pixel_to_projected(&self, column: f64, row: f64) -> [f64; 2] {
+        let [x, a, b, y, c, d] = self.geotransform;
+        [x + a * column + b * row, y + c * column + d * row]
+    }
+
+    /// Geotransform of a window of the raster whose first pixel is at
+    /// (column, row), see `ElevationTile::geotransform`
+    pub fn window_geotransform(&self, column: usize, row: usize) -> [f64; 6] {
+        let [x, y] = self.pixel_to_projected(column as f64, row as f64);
+        let [_, a, b, _, c, d] = self.geotransform;
+        [x, a, b, y, c, d]
+    }
+
+    /// Fractional (column, row) of a georeferenced coordinate, the inverse of
+    /// `pixel_to_projected`. `None` when the geotransform is singular and has
+    /// no inverse.
+    pub fn projected_to_pixel(&self, x: f64, y: f64) -> Option<[f64; 2]> {
+        let [x0, a, b, y0, c, d] = self.geotransform;
+        let det = a * d - b * c;
+        if det == 0.0 {
+            return None;
+        }
+        let (dx, dy) = (x - x0, y - y0);
+        Some([(d * dx - b * dy) / det, (a * dy - c * dx) / det])
+    }
+
+    fn corners(&self) -> [[f64; 2]; 4] {
+        let (width, height) = (self.width as f64, self.height as f64);
+        [(0.0, 0.0), (width, 0.0), (0.0, height), (width, height)]
+            .map(|(column, row)| self.pixel_to_projected(column, row))
+    }
 
-/// Returns Texture and not normalized buffer with pixel data from a GeoTIFF file
-pub fn load_geotiff_as_texture(device: &Device, queue: &Queue, path: &str) -> (Texture, Vec<f64>) {
+    /// Raster extent in its own CRS as [min x, min y, max x, max y]
+    pub fn projected_bounds(&self) -> [f64; 4] {
+        bounds_of(&self.corners())
+    }
+
+    /// Raster extent as [min lon, min lat, max lon, max lat] in WGS84, `None`
+    /// without a spatial reference
+    pub fn geographic_bounds(&self) -> gdal::errors::Result<Option<[f64; 4]>> {
+        let Some(transform) = self.geographic_transform()? else {
+            return Ok(None);
+        };
+        let corners = self.corners();
+        let mut x = corners.map(|c| c[0]);
+        let mut y = corners.map(|c| c[1]);
+        transform.transform_coords(&mut x, &mut y, &mut [])?;
+        let corners = [0, 1, 2, 3].map(|i| [x[i], y[i]]);
+        Ok(Some(bounds_of(&corners)))
+    }
+
+    /// Transform from the raster's CRS to WGS84 with x as longitude, `None`
+    /// without a spatial reference
+    pub fn geographic_transform(&self) -> gdal::errors::Result<Option<CoordTransform>> {
+        if self.crs_wkt.is_empty() {
+            return Ok(None);
+        }
+        let mut source = SpatialRef::from_wkt(&self.crs_wkt)?;
+        let mut target = SpatialRef::from_epsg(4326)?;
+        // Keep x as longitude regardless of the axis order of the CRS definitions
+        source.set_axis_mapping_strategy(AxisMappingStrategy::TraditionalGisOrder);
+        target.set_axis_mapping_strategy(AxisMappingStrategy::TraditionalGisOrder);
+        Ok(Some(CoordTransform::new(&source, &target)?))
+    }
+
+    /// ESRI world file of a `width`x`height` image covering the raster: the
+    /// pixel size and rotation terms, then the center of the top left pixel
+    pub fn world_file(&self, width: u32, height: u32) -> String {
+        let [x, a, b, y, c, d] = self.geotransform;
+        let column_scale = self.width as f64 / width as f64;
+        let row_scale = self.height as f64 / height as f64;
+        let [a, b, c, d] = [
+            a * column_scale,
+            b * row_scale,
+            c * column_scale,
+            d * row_scale,
+        ];
+        let [center_x, center_y] = [x + (a + b) / 2.0, y + (c + d) / 2.0];
+        [a, c, b, d, center_x, center_y]
+            .map(|v| format!("{}\n", v))
+            .concat()
+    }
+
+    /// Spatial reference as ESRI flavored WKT, the form `.prj` files hold.
+    /// `None` without a spatial reference.
+    pub fn esri_wkt(&self) -> gdal::errors::Result<Option<String>> {
+        if self.crs_wkt.is_empty() {
+            return Ok(None);
+        }
+        let srs = SpatialRef::from_wkt(&self.crs_wkt)?;
+        srs.morph_to_esri()?;
+        Ok(Some(srs.to_wkt()?))
+    }
+
+    /// Direction of north in (column, row) space, image up without a
+    /// geotransform or with a singular one
+    pub fn north(&self) -> [f64; 2] {
+        if self.geotransform == Self::IDENTITY {
+            return [0.0, -1.0];
+        }
+        // Invert the linear part of the geotransform for a step towards +y
+        let [_, a, b, _, c, d] = self.geotransform;
+        let det = a * d - b * c;
+        // A singular geotransform has no direction towards +y
+        if det.abs() <= f64::EPSILON {
+            return [0.0, -1.0];
+        }
+        let [column, row] = [-b / det, a / det];
+        let length = column.hypot(row);
+        [column / length, row / length]
+    }
+
+    /// Grid bearing in degrees clockwise from north between two fractional
+    /// (column, row), `None` when they coincide. North is the CRS +y axis, or
+    /// image up without a geotransform.
+    pub fn bearing(&self, from: [f64; 2], to: [f64; 2]) -> Option<f64> {
+        let [x0, y0] = self.pixel_to_projected(from[0], from[1]);
+        let [x1, y1] = self.pixel_to_projected(to[0], to[1]);
+        let (east, mut north) = (x1 - x0, y1 - y0);
+        if self.geotransform == Self::IDENTITY {
+            // Rows grow downwards
+            north = -north;
+        }
+        if east.hypot(north) <= f64::EPSILON {
+            return None;
+        }
+        Some(east.atan2(north).to_degrees().rem_euclid(360.0))
+    }
+
+    /// Ground size of a pixel in meters, `None` without a spatial reference
+    pub fn meters_per_pixel(&self) -> Option<f64> {
+        const METERS_PER_DEGREE: f64 = 111_320.0;
+
+        if self.crs_wkt.is_empty() {
+            return None;
+        }
+        let srs = match SpatialRef::from_wkt(&self.crs_wkt) {
+            Ok(srs) => srs,
+            Err(e) => {
+                warn!("Failed to parse spatial reference: {}", e);
+                return None;
+            }
+        };
+        let [_, a, b, y, c, d] = self.geotransform;
+        let pixel_width = a.hypot(c);
+        let pixel_height = b.hypot(d);
+        if srs.is_geographic() {
+            // Degrees, longitude shrinks with the latitude of the raster center
+            let latitude = y + (c * self.width as f64 + d * self.height as f64) / 2.0;
+            let width = pixel_width * METERS_PER_DEGREE * latitude.to_radians().cos();
+            let height = pixel_height * METERS_PER_DEGREE;
+            Some((width * height).sqrt())
+        } else {
+            Some((pixel_width * pixel_height).sqrt() * srs.linear_units())
+        }
+    }
+}
+
+/// Axis aligned [min x, min y, max x, max y] around `points`
+fn bounds_of(points: &[[f64; 2]]) -> [f64; 4] {
+    points.iter().fold(
+        [
+            f64::INFINITY,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+            f64::NEG_INFINITY,
+        ],
+        |[min_x, min_y, max_x, max_y], &[x, y]| {
+            [min_x.min(x), min_y.min(y), max_x.max(x), max_y.max(y)]
+        },
+    )
+}
+
+/// DEM contents that can't produce a meaningful terrain
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Degenerate {
+    /// Every pixel is NoData
+    AllNoData,
+    /// Every valid pixel has this elevation, the terrain is a flat plane
+    Constant(f64),
+}
+
+impl std::fmt::Display for Degenerate {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::AllNoData => write!(f, "Every pixel of the DEM is NoData"),
+            Self::Constant(value) => write!(
+                f,
+                "The DEM has a constant elevation of {}, the terrain is flat",
+                value
+            ),
+        }
+    }
+}
+
+/// Whether a raw band value is the band's `no_data` value
+fn is_no_data_value(v: f64, no_data: Option<f64>) -> bool {
+    no_data.is_some_and(|no_data| v == no_data || (no_data.is_nan() && v.is_nan()))
+}
+
+/// Checks the valid pixels of `data`, `no_data_mask` is non-zero for NoData
+pub fn find_degenerate(data: &[f64], no_data_mask: &[u8]) -> Option<Degenerate> {
+    let mut valid = data
+        .iter()
+        .zip(no_data_mask)
+        .filter(|(v, &mask)| mask == 0 && v.is_finite())
+        .map(|(&v, _)| v);
+    let first = match valid.next() {
+        Some(first) => first,
+        None => return Some(Degenerate::AllNoData),
+    };
+    if valid.all(|v| v == first) {
+        Some(Degenerate::Constant(first))
+    } else {
+        None
+    }
+}
+
+/// Why `load_geotiff_as_texture` couldn't load a GeoTIFF
+#[derive(Debug)]
+pub enum GtiffError {
+    /// GDAL couldn't open the file as a raster
+    OpenFailed(gdal::errors::GdalError),
+    /// The raster has no first band
+    BandFailed(gdal::errors::GdalError),
+    /// The pixels of the first band couldn't be read
+    ReadFailed(gdal::errors::GdalError),
+    /// Every pixel is NoData, there is no elevation to render
+    AllNoData,
+    /// The raster is wider or taller than a texture can be, see
+    /// `load_geotiff_tiled`
+    TooLarge {
+        width: usize,
+        height: usize,
+        limit: usize,
+    },
+}
+
+impl std::fmt::Display for GtiffError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::OpenFailed(e) => write!(f, "Failed to open GeoTIFF file: {}", e),
+            Self::BandFailed(e) => write!(f, "Failed to get raster band: {}", e),
+            Self::ReadFailed(e) => write!(f, "Failed to read pixel data: {}", e),
+            Self::AllNoData => write!(f, "{}", Degenerate::AllNoData),
+            Self::TooLarge {
+                width,
+                height,
+                limit,
+            } => write!(
+                f,
+                "GeoTIFF is {}x{}, over the GPU texture limit of {}",
+                width, height, limit
+            ),
+        }
+    }
+}
+
+impl std::error::Error for GtiffError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::OpenFailed(e) | Self::BandFailed(e) | Self::ReadFailed(e) => Some(e),
+            Self::AllNoData | Self::TooLarge { .. } => None,
+        }
+    }
+}
+
+/// Elevation data loaded from a GeoTIFF file
+pub struct Elevation {
+    /// Normalized elevation uploaded to the GPU, averaged down to the device
+    /// limit for DEMs over it so it can be smaller than `geo`
+    pub texture: Texture,
+    /// Not normalized elevation with the band scale/offset applied, in `unit`.
+    /// NaN where the band holds its NoData value.
+    pub buffer: Vec<f64>,
+    /// Unit reported by the band, meters if unknown
+    pub unit: ElevationUnit,
+    /// R8 mask that is 1.0 where the band holds its NoData value
+    pub no_data_mask: Texture,
+    /// Number of NoData pixels in the band
+    pub no_data_count: usize,
+    /// Whether each pixel of `buffer` is NoData
+    pub is_no_data: Vec<bool>,
+    /// NoData value of the band
+    pub no_data: Option<f64>,
+    /// Type the band was read in before converting to `buffer`
+    pub sample_type: SampleType,
+    pub geo: GeoReference,
+    /// Whether the band is palette indexed, `texture` then holds RGBA colors
+    /// from its color table and `buffer` is flat
+    pub indexed: bool,
+    /// Elevation `texture` is normalized over, in `unit`
+    pub range: [f64; 2],
+    /// Full resolution `tile_windows` of a DEM over the texture limit, in
+    /// row-major order. Empty when `texture` holds the whole DEM.
+    pub tiles: Vec<ElevationTile>,
+}
+
+/// Texture tile of a DEM over the texture limit, see `Elevation::tiles`
+pub struct ElevationTile {
+    /// Column and row of the tile's first pixel in the DEM
+    pub offset: [usize; 2],
+    /// Width and height of the tile in pixels
+    pub size: [usize; 2],
+    /// Geotransform of the tile's pixels, the DEM's moved to `offset`
+    pub geotransform: [f64; 6],
+    /// Normalized elevation of the tile's pixels, like `Elevation::texture`
+    pub texture: Texture,
+    /// R8 NoData mask of the tile's pixels, like `Elevation::no_data_mask`
+    pub no_data_mask: Texture,
+}
+
+impl ElevationTile {
+    /// The tile's `tile_windows` window, `[column, row, width, height]`
+    pub fn window(&self) -> [usize; 4] {
+        let ([column, row], [width, height]) = (self.offset, self.size);
+        [column, row, width, height]
+    }
+}
+
+/// Type the pixels of a band are read in, see `RawPixels`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleType {
+    Byte,
+    Int16,
+    UInt16,
+    Float32,
+    /// Every other band type, read widened to f64
+    Float64,
+}
+
+impl SampleType {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Byte => "Byte",
+            Self::Int16 => "Int16",
+            Self::UInt16 => "UInt16",
+            Self::Float32 => "Float32",
+            Self::Float64 => "Float64",
+        }
+    }
+}
+
+/// Band pixels in their native type. Reading an Int16 DEM as f64 would take
+/// four times the memory before the values are converted.
+enum RawPixels {
+    Byte(Vec<u8>),
+    Int16(Vec<i16>),
+    UInt16(Vec<u16>),
+    Float32(Vec<f32>),
+    Float64(Vec<f64>),
+}
+
+impl RawPixels {
+    /// Reads the `size` window of `band` with its top left pixel at `offset`,
+    /// averaged down to `shape` pixels when it is smaller
+    fn read(
+        band: &gdal::raster::RasterBand,
+        offset: (usize, usize),
+        size: (usize, usize),
+        shape: (usize, usize),
+    ) -> gdal::errors::Result<Self> {
+        fn read_as<T: gdal::raster::GdalType + Copy>(
+            band: &gdal::raster::RasterBand,
+            offset: (usize, usize),
+            size: (usize, usize),
+            shape: (usize, usize),
+        ) -> gdal::errors::Result<Vec<T>> {
+            let window = (offset.0 as isize, offset.1 as isize);
+            let resample = (shape != size).then_some(ResampleAlg::Average);
+            Ok(band
+                .read_as::<T>(window, size, shape, resample)?
+                .into_shape_and_vec()
+                .1)
+        }
+        Ok(match band.band_type() {
+            GdalDataType::UInt8 => Self::Byte(read_as(band, offset, size, shape)?),
+            GdalDataType::Int16 => Self::Int16(read_as(band, offset, size, shape)?),
+            GdalDataType::UInt16 => Self::UInt16(read_as(band, offset, size, shape)?),
+            GdalDataType::Float32 => Self::Float32(read_as(band, offset, size, shape)?),
+            _ => Self::Float64(read_as(band, offset, size, shape)?),
+        })
+    }
+
+    fn sample_type(&self) -> SampleType {
+        match self {
+            Self::Byte(_) => SampleType::Byte,
+            Self::Int16(_) => SampleType::Int16,
+            Self::UInt16(_) => SampleType::UInt16,
+            Self::Float32(_) => SampleType::Float32,
+            Self::Float64(_) => SampleType::Float64,
+        }
+    }
+
+    /// Pixels widened to f64 one at a time
+    fn values(&self) -> Box<dyn Iterator<Item = f64> + '_> {
+        match self {
+            Self::Byte(data) => Box::new(data.iter().map(|&v| v as f64)),
+            Self::Int16(data) => Box::new(data.iter().map(|&v| v as f64)),
+            Self::UInt16(data) => Box::new(data.iter().map(|&v| v as f64)),
+            Self::Float32(data) => Box::new(data.iter().map(|&v| v as f64)),
+            Self::Float64(data) => Box::new(data.iter().copied()),
+        }
+    }
+}
+
+/// Returns Texture, not normalized buffer with pixel data and the
+/// georeferencing (`Elevation::geo`) of a GeoTIFF file. With `preview_level`
+/// the pixels come from that overview of the file instead of the full
+/// resolution, or from a full resolution read decimated by 2^(level + 1) if
+/// the file has no such overview. The size and georeferencing are then the
+/// preview's.
+pub fn load_geotiff_as_texture(
+    device: &Device,
+    queue: &Queue,
+    path: &str,
+    preview_level: Option<usize>,
+) -> Result<Elevation, GtiffError> {
     let span = debug_span!("gtiff_to_texture", path = path);
     let _enter = span.enter();
 
     // Open the GeoTIFF file
-    let dataset = match Dataset::open(path) {
-        Ok(dataset) => {
-            trace!("Opened GeoTIFF file");
-            dataset
-        }
+    let dataset = Dataset::open(path).map_err(GtiffError::OpenFailed)?;
+    trace!("Opened GeoTIFF file");
+    let band = dataset.rasterband(1).map_err(GtiffError::BandFailed)?;
+    trace!("Got raster band");
+
+    // Get image dimensions
+    let full_size = band.size();
+    debug!("GeoTIFF dimensions: {}x{}", full_size.0, full_size.1);
+    let overview = preview_level.and_then(|level| match band.overview(level) {
+        Ok(overview) => Some(overview),
         Err(e) => {
-            error!("Failed to open GeoTIFF file: {}", e);
-            panic!("Failed to open GeoTIFF file");
+            warn!("No overview {}, decimating instead: {}", level, e);
+            None
+        }
+    });
+    let (width, height) = match (&overview, preview_level) {
+        (Some(overview), _) => overview.size(),
+        (None, Some(level)) => {
+            // Saturated, a level past the pointer width previews a single pixel
+            let factor = u32::try_from(level.saturating_add(1))
+                .ok()
+                .and_then(|shift| 1usize.checked_shl(shift))
+                .unwrap_or(usize::MAX);
+            (full_size.0.div_ceil(factor), full_size.1.div_ceil(factor))
+        }
+        (None, None) => full_size,
+    };
+    if (width, height) != full_size {
+        debug!("Previewing at {}x{}", width, height);
+    }
+    let geo = GeoReference::from_dataset(&dataset).decimated((width, height));
+    debug!("Geotransform: {:?}", geo.geotransform);
+
+    let limit = device.limits().max_texture_dimension_2d as usize;
+    if width > limit || height > limit {
+        return Err(GtiffError::TooLarge {
+            width,
+            height,
+            limit,
+        });
+    }
+
+    // Read the pixel data
+    let buffer = match &overview {
+        Some(overview) => RawPixels::read(overview, (0, 0), (width, height), (width, height)),
+        None => RawPixels::read(&band, (0, 0), full_size, (width, height)),
+    }
+    .map_err(GtiffError::ReadFailed)?;
+    let sample_type = buffer.sample_type();
+    trace!("Read pixel data as {}", sample_type.name());
+
+    // Apply the band's scale/offset so values are in real elevation units
+    let scale = band.scale().unwrap_or(1.0);
+    let offset = band.offset().unwrap_or(0.0);
+    trace!("Scale: {}, offset: {}", scale, offset);
+
+    let unit = match ElevationUnit::from_gdal(&band.unit()) {
+        Some(unit) => unit,
+        None => {
+            if !band.unit().is_empty() {
+                warn!("Unknown elevation unit {:?}, assuming meters", band.unit());
+            }
+            ElevationUnit::default()
         }
     };
-    let band = match dataset.rasterband(1) {
-        Ok(band) => {
-            trace!("Got raster band");
-            band
+    debug!("Elevation unit: {:?}", unit);
+
+    // One pass over the native pixels builds the NoData mask from the raw
+    // values, before scale/offset are applied, and either the elevation or
+    // the color table colors. NoData sentinels such as -9999 would otherwise
+    // end up in every min/max.
+    let no_data = band.no_data_value();
+    // Indexed rasters hold classes rather than heights, they are expanded to
+    // RGBA through the color table and rendered flat
+    let color_table = band.color_table();
+    let pixel_count = width * height;
+    let mut no_data_mask: Vec<u8> = Vec::with_capacity(pixel_count);
+    let mut data: Vec<f64> = Vec::new();
+    let mut colors: Vec<u8> = Vec::new();
+    match &color_table {
+        Some(_) => colors.reserve_exact(pixel_count * 4),
+        None => data.reserve_exact(pixel_count),
+    }
+    for v in buffer.values() {
+        let is_no_data = is_no_data_value(v, no_data);
+        no_data_mask.push(if is_no_data { 255 } else { 0 });
+        match &color_table {
+            Some(table) => colors.extend(match table.entry_as_rgb(v as usize) {
+                Some(entry) => [entry.r, entry.g, entry.b, entry.a].map(|c| c.clamp(0, 255) as u8),
+                None => [0; 4],
+            }),
+            None => data.push(if is_no_data {
+                f64::NAN
+            } else {
+                v * scale + offset
+            }),
         }
-        Err(e) => {
-            error!("Failed to get raster band: {}", e);
-            panic!("Failed to get raster band");
+    }
+    // The native pixels aren't needed past here, free them before the
+    // normalized copy is allocated
+    drop(buffer);
+    let no_data_count = no_data_mask.iter().filter(|&&v| v != 0).count();
+    debug!("NoData value: {:?}, {} pixels", no_data, no_data_count);
+
+    // Statistics stored in the file or its .aux.xml spare scanning every
+    // pixel, they are of the raw values and exclude NoData
+    let range = match band.get_statistics(false, true) {
+        Ok(Some(statistics)) => {
+            let [a, b] = [statistics.min, statistics.max].map(|v| v * scale + offset);
+            debug!("Range from band statistics: {} to {}", a.min(b), a.max(b));
+            [a.min(b), a.max(b)]
+        }
+        result => {
+            if let Err(e) = result {
+                warn!("Failed to read band statistics: {}", e);
+            }
+            let range = [
+                data.iter().cloned().fold(f64::INFINITY, f64::min),
+                data.iter().cloned().fold(f64::NEG_INFINITY, f64::max),
+            ];
+            debug!(
+                "No band statistics, scanned range: {} to {}",
+                range[0], range[1]
+            );
+            range
         }
     };
 
-    // Get image dimensions
+    let (texture, data) = match &color_table {
+        Some(table) => {
+            debug!("Color table with {} entries", table.entry_count());
+            let texture = upload_texture(
+                device,
+                queue,
+                "GeoTIFF Texture",
+                TextureFormat::Rgba8Unorm,
+                &colors,
+                width,
+                height,
+            );
+            drop(colors);
+            (texture, vec![0.0; pixel_count])
+        }
+        None => {
+            match find_degenerate(&data, &no_data_mask) {
+                Some(Degenerate::AllNoData) => return Err(GtiffError::AllNoData),
+                Some(degenerate) => warn!("{}", degenerate),
+                None => {}
+            }
+
+            let normalized_data = normalize(&data, Some(range));
+            let texture = upload_elevation(
+                device,
+                queue,
+                "GeoTIFF Texture",
+                &normalized_data,
+                width,
+                height,
+            );
+            (texture, data)
+        }
+    };
+
+    let no_data_texture = upload_texture(
+        device,
+        queue,
+        "GeoTIFF NoData Mask",
+        TextureFormat::R8Unorm,
+        &no_data_mask,
+        width,
+        height,
+    );
+    debug!("Uploaded GeoTIFF data to GPU");
+
+    Ok(Elevation {
+        texture,
+        buffer: data,
+        unit,
+        no_data_mask: no_data_texture,
+        no_data_count,
+        // Collected in place, the mask's allocation is reused
+        is_no_data: no_data_mask.into_iter().map(|v| v != 0).collect(),
+        no_data,
+        sample_type,
+        geo,
+        indexed: color_table.is_some(),
+        range,
+        // Larger rasters fail with `GtiffError::TooLarge` above
+        tiles: Vec::new(),
+    })
+}
+
+/// Loads a GeoTIFF, reading it with `load_geotiff_tiled` when it is over
+/// the texture limit instead of failing with `GtiffError::TooLarge`
+pub fn load_elevation(
+    device: &Device,
+    queue: &Queue,
+    path: &str,
+    preview_level: Option<usize>,
+) -> Result<Elevation, GtiffError> {
+    match load_geotiff_as_texture(device, queue, path, preview_level) {
+        Err(GtiffError::TooLarge {
+            width,
+            height,
+            limit,
+        }) => {
+            info!(
+                "{}x{} is over the texture limit of {}, reading it in tiles",
+                width, height, limit
+            );
+            load_geotiff_tiled(device, queue, path)
+        }
+        result => result,
+    }
+}
+
+/// Windows of at most `tile_size` pixels square covering a `width`x`height`
+/// raster in row-major order, as `[column, row, width, height]`. Neighboring
+/// windows share their edge pixels, so every grid cell is in exactly one.
+pub fn tile_windows(width: usize, height: usize, tile_size: usize) -> Vec<[usize; 4]> {
+    let step = tile_size.max(2) - 1;
+    let mut windows = Vec::new();
+    for row in (0..height.saturating_sub(1).max(1)).step_by(step) {
+        for column in (0..width.saturating_sub(1).max(1)).step_by(step) {
+            windows.push([
+                column,
+                row,
+                (width - column).min(tile_size),
+                (height - row).min(tile_size),
+            ]);
+        }
+    }
+    windows
+}
+
+/// Reads a GeoTIFF over the texture limit one `tile_windows` window at a
+/// time, so only a tile of native pixels is in memory next to the
+/// elevation. Each window becomes an `ElevationTile` at full resolution,
+/// `texture` is averaged down to fit the limit for the overviews and level
+/// of detail, see `fitted_size`. Color tables are ignored, indexed rasters
+/// that large are read as elevation.
+pub fn load_geotiff_tiled(
+    device: &Device,
+    queue: &Queue,
+    path: &str,
+) -> Result<Elevation, GtiffError> {
+    let span = debug_span!("load_geotiff_tiled", path = path);
+    let _enter = span.enter();
+
+    let dataset = Dataset::open(path).map_err(GtiffError::OpenFailed)?;
+    let band = dataset.rasterband(1).map_err(GtiffError::BandFailed)?;
     let (width, height) = band.size();
-    debug!("GeoTIFF dimensions: {}x{}", width, height);
+    let geo = GeoReference::from_dataset(&dataset);
+    let scale = band.scale().unwrap_or(1.0);
+    let offset = band.offset().unwrap_or(0.0);
+    let unit = ElevationUnit::from_gdal(&band.unit()).unwrap_or_default();
+    let no_data = band.no_data_value();
+    if band.color_table().is_some() {
+        warn!("Ignoring the color table of {}, it is too large", path);
+    }
+    let limit = device.limits().max_texture_dimension_2d as usize;
 
-    // Read the pixel data
-    let buffer = match band.read_as::<f64>((0, 0), (width, height), (width, height), None) {
-        Ok(buffer) => {
-            trace!("Read pixel data");
-            buffer
+    let windows = tile_windows(width, height, limit);
+    let mut buffer = vec![f64::NAN; width * height];
+    let mut sample_type = SampleType::Float64;
+    for &[column, row, tile_width, tile_height] in &windows {
+        let size = (tile_width, tile_height);
+        let pixels =
+            RawPixels::read(&band, (column, row), size, size).map_err(GtiffError::ReadFailed)?;
+        sample_type = pixels.sample_type();
+        for (i, v) in pixels.values().enumerate() {
+            let (x, y) = (column + i % tile_width, row + i / tile_width);
+            buffer[y * width + x] = if is_no_data_value(v, no_data) {
+                f64::NAN
+            } else {
+                v * scale + offset
+            };
         }
-        Err(e) => {
-            error!("Failed to read pixel data: {}", e);
-            panic!("Failed to read pixel data");
+        trace!("Read tile at {}, {}: {}x{}", column, row, size.0, size.1);
+    }
+    debug!(
+        "Read {}x{} as {} tiles of up to {}px",
+        width,
+        height,
+        windows.len(),
+        limit
+    );
+
+    let range = match band.get_statistics(false, true) {
+        Ok(Some(statistics)) => {
+            let [a, b] = [statistics.min, statistics.max].map(|v| v * scale + offset);
+            [a.min(b), a.max(b)]
         }
+        _ => buffer
+            .iter()
+            .fold([f64::INFINITY, f64::NEG_INFINITY], |[min, max], &v| {
+                [min.min(v), max.max(v)]
+            }),
     };
+    if range[0] > range[1] {
+        return Err(GtiffError::AllNoData);
+    }
 
-    // Normalize data to fit into [0, 1] r
-    let min_val = buffer.data().iter().cloned().fold(f64::INFINITY, f64::min);
-    let max_val = buffer
+    Ok(elevation_from_parts(
+        device,
+        queue,
+        buffer,
+        unit,
+        no_data,
+        sample_type,
+        geo,
+        range,
+    ))
+}
+
+/// Elevation at a fraction of the DEM resolution, see `read_overviews` and
+/// `State::build_overviews`
+pub struct Overview {
+    /// DEM pixels per overview pixel along each axis
+    pub factor: usize,
+    pub width: usize,
+    pub height: usize,
+    /// Not normalized elevation, NaN where all the pixels it covers are
+    /// NoData, like `Elevation::buffer`
+    pub buffer: Vec<f64>,
+}
+
+/// Reads the first band decimated by 2, 4, 8... for at most `levels` levels,
+/// stopping before an overview would be smaller than 2x2. Levels the file
+/// has an internal or .ovr overview of that size for are read from it,
+/// the others are averaged down from the full band by GDAL.
+pub fn read_overviews(path: &str, levels: usize) -> gdal::errors::Result<Vec<Overview>> {
+    let span = debug_span!("read_overviews", path = path);
+    let _enter = span.enter();
+
+    let dataset = Dataset::open(path)?;
+    let band = dataset.rasterband(1)?;
+    let (width, height) = band.size();
+    let file_overviews = (0..band.overview_count()?.max(0) as usize)
+        .map(|index| band.overview(index))
+        .collect::<gdal::errors::Result<Vec<_>>>()?;
+    debug!("Band has {} overviews", file_overviews.len());
+    let scale = band.scale().unwrap_or(1.0);
+    let offset = band.offset().unwrap_or(0.0);
+    let no_data = band.no_data_value();
+
+    let mut overviews = Vec::new();
+    for level in 1..=levels {
+        let factor = 1 << level;
+        let size = (width.div_ceil(factor), height.div_ceil(factor));
+        if size.0 < 2 || size.1 < 2 {
+            break;
+        }
+        let buffer = match file_overviews
+            .iter()
+            .find(|overview| overview.size() == size)
+        {
+            Some(overview) => {
+                trace!("Reading overview {} from the file", level);
+                overview.read_as::<f64>((0, 0), size, size, None)?
+            }
+            None => {
+                trace!("No {}x{} overview in the file, decimating", size.0, size.1);
+                band.read_as::<f64>((0, 0), (width, height), size, Some(ResampleAlg::Average))?
+            }
+        };
+        overviews.push(Overview {
+            factor,
+            width: size.0,
+            height: size.1,
+            buffer: buffer
+                .data()
+                .iter()
+                .map(|&v| {
+                    if is_no_data_value(v, no_data) {
+                        f64::NAN
+                    } else {
+                        v * scale + offset
+                    }
+                })
+                .collect(),
+        });
+    }
+    Ok(overviews)
+}
+
+/// Loads the first three bands of an orthophoto GeoTIFF as red, green and
+/// blue, and the fourth as alpha when present, into an `Rgba8UnormSrgb`
+/// texture for the imagery. Bands are read as bytes. Rasters over the device
+/// limit or `max_dimension` are averaged down by GDAL while reading.
+pub fn load_geotiff_rgb_as_texture(
+    device: &Device,
+    queue: &Queue,
+    path: &str,
+    max_dimension: Option<u32>,
+) -> anyhow::Result<crate::texture::Texture> {
+    let span = debug_span!("gtiff_rgb_to_texture", path = path);
+    let _enter = span.enter();
+
+    let dataset = Dataset::open(path).map_err(GtiffError::OpenFailed)?;
+    let bands = dataset.raster_count();
+    if bands < 3 {
+        anyhow::bail!(
+            "{} has {} band(s), RGB imagery needs at least 3",
+            path,
+            bands
+        );
+    }
+    let (width, height) = dataset.raster_size();
+    if width == 0 || height == 0 {
+        anyhow::bail!("{} is empty", path);
+    }
+    let limit = device
+        .limits()
+        .max_texture_dimension_2d
+        .min(max_dimension.unwrap_or(u32::MAX))
+        .max(1) as usize;
+    let scale = (limit as f64 / width.max(height) as f64).min(1.0);
+    let size = (
+        ((width as f64 * scale) as usize).max(1),
+        ((height as f64 * scale) as usize).max(1),
+    );
+    if size != (width, height) {
+        warn!(
+            "{} is {}x{}, downsampled to {}x{} to fit {}",
+            path, width, height, size.0, size.1, limit
+        );
+    }
+
+    let channels = (1..=bands.min(4))
+        .map(|index| {
+            let band = dataset.rasterband(index).map_err(GtiffError::BandFailed)?;
+            let buffer = band
+                .read_as::<u8>((0, 0), (width, height), size, Some(ResampleAlg::Average))
+                .map_err(GtiffError::ReadFailed)?;
+            Ok(buffer.into_shape_and_vec().1)
+        })
+        .collect::<Result<Vec<Vec<u8>>, GtiffError>>()?;
+    trace!("Read {} bands at {}x{}", channels.len(), size.0, size.1);
+
+    let rgba: Vec<u8> = (0..size.0 * size.1)
+        .flat_map(|i| {
+            [
+                channels[0][i],
+                channels[1][i],
+                channels[2][i],
+                channels.get(3).map_or(255, |alpha| alpha[i]),
+            ]
+        })
+        .collect();
+    let image = image::RgbaImage::from_raw(size.0 as u32, size.1 as u32, rgba)
+        .expect("RGBA buffer matches the read size");
+    crate::texture::Texture::from_image(
+        device,
+        queue,
+        &image::DynamicImage::ImageRgba8(image),
+        Some(path),
+        max_dimension,
+    )
+}
+
+/// Writes the first band of `path` decimated by `factor` to a GeoTIFF at
+/// `output`, in meters with the band scale/offset applied and a geotransform
+/// covering the same extent. GDAL averages the pixels, from overviews when
+/// the file has them. Returns the decimated size.
+pub fn save_decimated(
+    path: &str,
+    factor: usize,
+    output: &str,
+) -> gdal::errors::Result<(usize, usize)> {
+    let span = debug_span!("save_decimated", path = path, factor = factor);
+    let _enter = span.enter();
+
+    let dataset = Dataset::open(path)?;
+    let band = dataset.rasterband(1)?;
+    let (width, height) = band.size();
+    let size = (width.div_ceil(factor), height.div_ceil(factor));
+    let scale = band.scale().unwrap_or(1.0);
+    let offset = band.offset().unwrap_or(0.0);
+    let unit = ElevationUnit::from_gdal(&band.unit()).unwrap_or_default();
+    let no_data = band.no_data_value();
+    let buffer = band.read_as::<f64>((0, 0), (width, height), size, Some(ResampleAlg::Average))?;
+    let values: Vec<f64> = buffer
+        .data()
+        .iter()
+        .map(|&v| {
+            if is_no_data_value(v, no_data) {
+                f64::NAN
+            } else {
+                unit.to_meters(v * scale + offset)
+            }
+        })
+        .collect();
+
+    let geo = GeoReference::from_dataset(&dataset).decimated(size);
+    trace!("Decimated {}x{} to {}x{}", width, height, size.0, size.1);
+    save_geotiff(output, &values, &geo, no_data)?;
+    Ok(size)
+}
+
+/// Reads the first band at full resolution with the band scale/offset
+/// applied, `None` where it is NoData. Returns (width, height, values),
+/// without creating any textures.
+pub fn read_elevation(path: &str) -> gdal::errors::Result<(usize, usize, Vec<Option<f64>>)> {
+    let span = debug_span!("read_elevation", path = path);
+    let _enter = span.enter();
+
+    let dataset = Dataset::open(path)?;
+    let band = dataset.rasterband(1)?;
+    let (width, height) = band.size();
+    let scale = band.scale().unwrap_or(1.0);
+    let offset = band.offset().unwrap_or(0.0);
+    let no_data = band.no_data_value();
+    let buffer = band.read_as::<f64>((0, 0), (width, height), (width, height), None)?;
+    trace!("Read {}x{} pixels", width, height);
+    let values = buffer
         .data()
         .iter()
-        .cloned()
-        .fold(f64::NEG_INFINITY, f64::max);
+        .map(|&v| (!is_no_data_value(v, no_data)).then_some(v * scale + offset))
+        .collect();
+    Ok((width, height, values))
+}
+
+/// Builds elevation from heights in meters that didn't come from a file, such
+/// as synthetic terrain. It has no NoData and no georeferencing.
+pub fn elevation_from_buffer(
+    device: &Device,
+    queue: &Queue,
+    buffer: Vec<f64>,
+    width: usize,
+    height: usize,
+) -> Elevation {
+    let range = [
+        buffer.iter().cloned().fold(f64::INFINITY, f64::min),
+        buffer.iter().cloned().fold(f64::NEG_INFINITY, f64::max),
+    ];
+    let geo = GeoReference {
+        geotransform: GeoReference::IDENTITY,
+        crs_wkt: String::new(),
+        width,
+        height,
+    };
+    elevation_from_parts(
+        device,
+        queue,
+        buffer,
+        ElevationUnit::Meters,
+        None,
+        SampleType::Float64,
+        geo,
+        range,
+    )
+}
+
+/// Uploads elevation already in memory, such as an edited DEM again after a
+/// device loss. `buffer` is in `unit` and NaN where it is NoData, `geo` holds
+/// its size.
+#[allow(clippy::too_many_arguments)]
+pub fn elevation_from_parts(
+    device: &Device,
+    queue: &Queue,
+    buffer: Vec<f64>,
+    unit: ElevationUnit,
+    no_data: Option<f64>,
+    sample_type: SampleType,
+    geo: GeoReference,
+    range: [f64; 2],
+) -> Elevation {
+    let (width, height) = (geo.width, geo.height);
+    let span = debug_span!("elevation_from_parts", width = width, height = height);
+    let _enter = span.enter();
+
+    let normalized_data = normalize(&buffer, Some(range));
+    let limit = device.limits().max_texture_dimension_2d as usize;
+    let is_no_data: Vec<bool> = buffer.iter().map(|v| v.is_nan()).collect();
+    let mut no_data_mask: Vec<u8> = is_no_data
+        .iter()
+        .map(|&n| if n { 255 } else { 0 })
+        .collect();
+    let tiles = if width > limit || height > limit {
+        upload_tiles(device, queue, &normalized_data, &no_data_mask, &geo, limit)
+    } else {
+        Vec::new()
+    };
+    let texture = upload_elevation(
+        device,
+        queue,
+        "Elevation Texture",
+        &normalized_data,
+        width,
+        height,
+    );
+    // The mask is fitted to the limit like the elevation texture
+    let fitted = fitted_size(width, height, limit);
+    let (mut mask_width, mut mask_height) = (width, height);
+    while (mask_width, mask_height) != fitted {
+        (no_data_mask, mask_width, mask_height) =
+            downsample_mask(&no_data_mask, mask_width, mask_height);
+    }
+    let no_data_texture = upload_texture(
+        device,
+        queue,
+        "Elevation NoData Mask",
+        TextureFormat::R8Unorm,
+        &no_data_mask,
+        mask_width,
+        mask_height,
+    );
+    debug!("Uploaded elevation to GPU");
+
+    Elevation {
+        texture,
+        buffer,
+        unit,
+        no_data_mask: no_data_texture,
+        no_data_count: is_no_data.iter().filter(|&&n| n).count(),
+        is_no_data,
+        no_data,
+        sample_type,
+        geo,
+        indexed: false,
+        range,
+        tiles,
+    }
+}
+
+/// Pixels of the `[column, row, width, height]` window of a raster
+/// `raster_width` pixels wide, in row-major order
+pub fn crop<T: Copy>(data: &[T], raster_width: usize, window: [usize; 4]) -> Vec<T> {
+    let [column, row, width, height] = window;
+    (row..row + height)
+        .flat_map(|y| data[y * raster_width + column..y * raster_width + column + width].iter())
+        .copied()
+        .collect()
+}
+
+/// Uploads every `tile_windows` window of `normalized` and its NoData mask
+/// at full resolution, see `ElevationTile`
+fn upload_tiles(
+    device: &Device,
+    queue: &Queue,
+    normalized: &[f32],
+    no_data_mask: &[u8],
+    geo: &GeoReference,
+    limit: usize,
+) -> Vec<ElevationTile> {
+    let windows = tile_windows(geo.width, geo.height, limit);
+    let tiles: Vec<ElevationTile> = windows
+        .iter()
+        .map(|&window| {
+            let [column, row, width, height] = window;
+            let label = format!("Elevation Tile {}, {}", column, row);
+            ElevationTile {
+                offset: [column, row],
+                size: [width, height],
+                geotransform: geo.window_geotransform(column, row),
+                texture: upload_elevation(
+                    device,
+                    queue,
+                    &label,
+                    &crop(normalized, geo.width, window),
+                    width,
+                    height,
+                ),
+                no_data_mask: upload_texture(
+                    device,
+                    queue,
+                    &format!("{} NoData Mask", label),
+                    TextureFormat::R8Unorm,
+                    &crop(no_data_mask, geo.width, window),
+                    width,
+                    height,
+                ),
+            }
+        })
+        .collect();
+    debug!(
+        "Uploaded {} elevation tiles of up to {}px",
+        tiles.len(),
+        limit
+    );
+    tiles
+}
+
+/// `write_normalized` for every tile of a `width` pixels wide DEM
+pub fn write_tiles<'a>(
+    queue: &Queue,
+    tiles: impl IntoIterator<Item = &'a ElevationTile>,
+    normalized: &[f32],
+    width: usize,
+) {
+    for tile in tiles {
+        write_normalized(
+            queue,
+            &tile.texture,
+            &crop(normalized, width, tile.window()),
+            tile.size,
+        );
+    }
+}
+
+/// Normalizes data to fit into [0, 1] between `range`, or the data's own
+/// min/max when `None`. Values outside an explicit range are clamped.
+pub fn normalize(data: &[f64], range: Option<[f64; 2]>) -> Vec<f32> {
+    let [min_val, max_val] = range.unwrap_or_else(|| {
+        [
+            data.iter().cloned().fold(f64::INFINITY, f64::min),
+            data.iter().cloned().fold(f64::NEG_INFINITY, f64::max),
+        ]
+    });
     trace!("Min value: {}", min_val);
     trace!("Max value: {}", max_val);
 
-    let normalized_data: Vec<f32> = buffer
-        .data()
+    // A constant DEM has no range to divide by
+    let range = if max_val > min_val {
+        max_val - min_val
+    } else {
+        1.0
+    };
+    // NoData is NaN, it is masked out by the shader and sampled as the minimum
+    let normalized_data: Vec<f32> = data
         .iter()
-        .map(|&v| ((v - min_val) / (max_val - min_val)) as f32)
+        .map(|&v| {
+            if v.is_nan() {
+                0.0
+            } else {
+                ((v - min_val) / range).clamp(0.0, 1.0) as f32
+            }
+        })
         .collect();
 
     // Debug some values from normalized_data
     debug!("Normalized data [:10]:");
-    for i in 0..10 {
+    for i in 0..10.min(normalized_data.len()) {
         debug!("{}: {}", i, normalized_data[i]);
     }
+    normalized_data
+}
+
+/// Bins of the histogram `percentile_range` reads the percentiles from
+const PERCENTILE_BINS: usize = 4096;
+
+/// Values at the `[low, high]` percentiles of the valid data, from a
+/// histogram over its min/max. `None` without valid data.
+pub fn percentile_range(
+    data: &[f64],
+    is_no_data: &[bool],
+    [low, high]: [f32; 2],
+) -> Option<[f64; 2]> {
+    let valid = || {
+        data.iter()
+            .zip(is_no_data)
+            .filter(|&(v, &no_data)| !no_data && v.is_finite())
+            .map(|(&v, _)| v)
+    };
+    let (min, max) = valid().fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), v| {
+        (min.min(v), max.max(v))
+    });
+    if min > max {
+        return None;
+    }
+    if max == min {
+        return Some([min, max]);
+    }
 
-    // Create a wgpu texture
+    let bin_width = (max - min) / PERCENTILE_BINS as f64;
+    let mut histogram = vec![0usize; PERCENTILE_BINS];
+    let mut count = 0;
+    for v in valid() {
+        histogram[(((v - min) / bin_width) as usize).min(PERCENTILE_BINS - 1)] += 1;
+        count += 1;
+    }
+    let at = |percentile: f32| {
+        let target = (percentile.clamp(0.0, 100.0) as f64 / 100.0 * count as f64).ceil() as usize;
+        let mut seen = 0;
+        for (bin, &n) in histogram.iter().enumerate() {
+            seen += n;
+            if seen >= target.max(1) {
+                return min + bin as f64 * bin_width;
+            }
+        }
+        max
+    };
+    // The upper bound is the top of its bin, so 100 gives the maximum
+    let range = [at(low), (at(high) + bin_width).min(max)];
+    trace!("Percentile range {:?}: {:?}", [low, high], range);
+    Some(range)
+}
+
+/// Number of levels of a full mip chain down to 1x1
+pub fn mip_level_count(width: usize, height: usize) -> u32 {
+    usize::BITS - width.max(height).max(1).leading_zeros()
+}
+
+/// Next level of a mip chain, each texel averages the 2x2 texels it covers.
+/// Odd sizes round down like the texture's own mip sizes.
+pub fn downsample(data: &[f32], width: usize, height: usize) -> (Vec<f32>, usize, usize) {
+    let (half_width, half_height) = ((width / 2).max(1), (height / 2).max(1));
+    let at = |x: usize, y: usize| data[y.min(height - 1) * width + x.min(width - 1)];
+    let level = (0..half_height)
+        .flat_map(|y| {
+            (0..half_width).map(move |x| {
+                let (x, y) = (x * 2, y * 2);
+                (at(x, y) + at(x + 1, y) + at(x, y + 1) + at(x + 1, y + 1)) / 4.0
+            })
+        })
+        .collect();
+    (level, half_width, half_height)
+}
+
+/// `downsample` for a NoData mask, a texel is NoData when any pixel it
+/// covers is
+fn downsample_mask(mask: &[u8], width: usize, height: usize) -> (Vec<u8>, usize, usize) {
+    let (half_width, half_height) = ((width / 2).max(1), (height / 2).max(1));
+    let at = |x: usize, y: usize| mask[y.min(height - 1) * width + x.min(width - 1)];
+    let level = (0..half_height)
+        .flat_map(|y| {
+            (0..half_width).map(move |x| {
+                let (x, y) = (x * 2, y * 2);
+                at(x, y)
+                    .max(at(x + 1, y))
+                    .max(at(x, y + 1))
+                    .max(at(x + 1, y + 1))
+            })
+        })
+        .collect();
+    (level, half_width, half_height)
+}
+
+/// Size of a `width`x`height` raster halved with `downsample` until neither
+/// side is over `limit`, the size its texture is uploaded at
+pub fn fitted_size(width: usize, height: usize, limit: usize) -> (usize, usize) {
+    let (mut width, mut height) = (width, height);
+    while width > limit.max(1) || height > limit.max(1) {
+        (width, height) = ((width / 2).max(1), (height / 2).max(1));
+    }
+    (width, height)
+}
+
+/// Replaces the contents of an R32Float elevation texture with the
+/// `width`x`height` elevation `normalized`, halved down to the texture's
+/// size when it is larger. Every level of its mip chain is downsampled from it.
+pub fn write_normalized(
+    queue: &Queue,
+    texture: &Texture,
+    normalized: &[f32],
+    [width, height]: [usize; 2],
+) {
+    let size = texture.size();
+    let (mut width, mut height) = (width, height);
+    let mut level = std::borrow::Cow::Borrowed(normalized);
+    while (width, height) != (size.width as usize, size.height as usize) {
+        let (data, next_width, next_height) = downsample(&level, width, height);
+        (level, width, height) = (data.into(), next_width, next_height);
+    }
+    for mip_level in 0..texture.mip_level_count() {
+        if mip_level > 0 {
+            let (data, next_width, next_height) = downsample(&level, width, height);
+            (level, width, height) = (data.into(), next_width, next_height);
+        }
+        queue.write_texture(
+            egui_wgpu::wgpu::ImageCopyTexture {
+                mip_level,
+                ..texture.as_image_copy()
+            },
+            bytemuck::cast_slice(&level),
+            egui_wgpu::wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(width as u32 * std::mem::size_of::<f32>() as u32),
+                rows_per_image: Some(height as u32),
+            },
+            egui_wgpu::wgpu::Extent3d {
+                width: width as u32,
+                height: height as u32,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+    trace!("Wrote {} elevation mip levels", texture.mip_level_count());
+}
+
+/// Creates an R32Float elevation texture with a full mip chain, so distant
+/// terrain samples a level matching its footprint instead of aliasing.
+/// Elevation over the device limit is fitted to it, see `fitted_size`.
+fn upload_elevation(
+    device: &Device,
+    queue: &Queue,
+    label: &str,
+    normalized: &[f32],
+    width: usize,
+    height: usize,
+) -> Texture {
+    let limit = device.limits().max_texture_dimension_2d as usize;
+    let (fitted_width, fitted_height) = fitted_size(width, height, limit);
+    if (fitted_width, fitted_height) != (width, height) {
+        debug!(
+            "{} fitted from {}x{} to {}x{}",
+            label, width, height, fitted_width, fitted_height
+        );
+    }
     let texture = device.create_texture(&TextureDescriptor {
-        label: Some("GeoTIFF Texture"),
+        label: Some(label),
         size: egui_wgpu::wgpu::Extent3d {
-            width: width as u32,
-            height: height as u32,
+            width: fitted_width as u32,
+            height: fitted_height as u32,
             depth_or_array_layers: 1,
         },
+        mip_level_count: mip_level_count(fitted_width, fitted_height),
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format: TextureFormat::R32Float,
+        usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+    trace!("Created {}", label);
+    write_normalized(queue, &texture, normalized, [width, height]);
+    texture
+}
+
+/// Creates a 2D texture and uploads tightly packed `pixels` of `format` to it
+fn upload_texture(
+    device: &Device,
+    queue: &Queue,
+    label: &str,
+    format: TextureFormat,
+    pixels: &[u8],
+    width: usize,
+    height: usize,
+) -> Texture {
+    let size = egui_wgpu::wgpu::Extent3d {
+        width: width as u32,
+        height: height as u32,
+        depth_or_array_layers: 1,
+    };
+    let texture = device.create_texture(&TextureDescriptor {
+        label: Some(label),
+        size,
         mip_level_count: 1,
         sample_count: 1,
         dimension: TextureDimension::D2,
-        format: TextureFormat::R32Float, // Use a floating-point format
+        format,
         usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
         view_formats: &[],
     });
-    trace!("Created GeoTIFF texture");
+    trace!("Created {}", label);
 
-    // Upload data to GPU
+    let bytes_per_pixel = format.block_copy_size(None).unwrap_or(4);
     queue.write_texture(
         texture.as_image_copy(),
-        bytemuck::cast_slice(&normalized_data), // Convert to bytes
+        pixels,
         egui_wgpu::wgpu::ImageDataLayout {
             offset: 0,
-            bytes_per_row: Some(width as u32 * std::mem::size_of::<f32>() as u32),
+            bytes_per_row: Some(width as u32 * bytes_per_pixel),
             rows_per_image: Some(height as u32),
         },
-        egui_wgpu::wgpu::Extent3d {
-            width: width as u32,
-            height: height as u32,
-            depth_or_array_layers: 1,
-        },
+        size,
     );
-    debug!("Uploaded GeoTIFF data to GPU");
+    texture
+}
+
+/// Writes a single band f64 GeoTIFF with the given georeferencing. Pixels that
+/// are NaN are written as `no_data` when it is set.
+pub fn save_geotiff(
+    path: &str,
+    buffer: &[f64],
+    geo: &GeoReference,
+    no_data: Option<f64>,
+) -> gdal::errors::Result<()> {
+    let span = debug_span!("save_geotiff", path = path);
+    let _enter = span.enter();
+
+    let driver = DriverManager::get_driver_by_name("GTiff")?;
+    let mut dataset = driver.create_with_band_type::<f64, _>(path, geo.width, geo.height, 1)?;
+    dataset.set_geo_transform(&geo.geotransform)?;
+    if !geo.crs_wkt.is_empty() {
+        dataset.set_projection(&geo.crs_wkt)?;
+    }
+    trace!("Created GeoTIFF dataset");
+
+    let data = buffer
+        .iter()
+        .map(|&v| match no_data {
+            Some(no_data) if v.is_nan() => no_data,
+            _ => v,
+        })
+        .collect();
+    let mut band = dataset.rasterband(1)?;
+    band.set_no_data_value(no_data)?;
+    band.write(
+        (0, 0),
+        (geo.width, geo.height),
+        &mut Buffer::new((geo.width, geo.height), data),
+    )?;
+    debug!("Saved GeoTIFF");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tile_windows_cover_large_raster() {
+        let (width, height, limit) = (10000, 10000, 8192);
+        let windows = tile_windows(width, height, limit);
+        assert_eq!(windows.len(), 4);
+        let mut cells = vec![0u8; (width - 1) * (height - 1)];
+        for &[column, row, tile_width, tile_height] in &windows {
+            assert!(tile_width <= limit && tile_height <= limit);
+            assert!(column + tile_width <= width && row + tile_height <= height);
+            for y in row..row + tile_height - 1 {
+                for x in column..column + tile_width - 1 {
+                    cells[y * (width - 1) + x] += 1;
+                }
+            }
+        }
+        assert!(cells.iter().all(|&n| n == 1));
+    }
+
+    #[test]
+    fn tile_windows_fit_small_raster() {
+        assert_eq!(tile_windows(100, 50, 8192), vec![[0, 0, 100, 50]]);
+    }
+
+    #[test]
+    fn fitted_size_halves_to_limit() {
+        assert_eq!(fitted_size(10000, 10000, 8192), (5000, 5000));
+        assert_eq!(fitted_size(8192, 100, 8192), (8192, 100));
+        assert_eq!(fitted_size(16385, 3, 8192), (8192, 1));
+    }
+
+    #[test]
+    fn fitted_size_matches_downsample() {
+        let (mut data, mut width, mut height) = (vec![0.0f32; 37 * 20], 37, 20);
+        while width > 8 || height > 8 {
+            (data, width, height) = downsample(&data, width, height);
+        }
+        assert_eq!(fitted_size(37, 20, 8), (width, height));
+        assert_eq!(data.len(), width * height);
+    }
+
+    #[test]
+    fn downsample_mask_keeps_no_data() {
+        let mask = [0, 255, 0, 0, 0, 0, 0, 0];
+        let (level, width, height) = downsample_mask(&mask, 4, 2);
+        assert_eq!((width, height), (2, 1));
+        assert_eq!(level, vec![255, 0]);
+    }
+
+    #[test]
+    fn crop_tiles_rebuild_raster() {
+        let (width, height) = (100, 70);
+        let data: Vec<usize> = (0..width * height).collect();
+        for window in tile_windows(width, height, 32) {
+            let [column, row, tile_width, tile_height] = window;
+            let tile = crop(&data, width, window);
+            assert_eq!(tile.len(), tile_width * tile_height);
+            for (i, &v) in tile.iter().enumerate() {
+                let (x, y) = (column + i % tile_width, row + i / tile_width);
+                assert_eq!(v, y * width + x);
+            }
+        }
+    }
+
+    #[test]
+    fn window_geotransform_moves_origin() {
+        let geo = rotated();
+        let tile = GeoReference {
+            geotransform: geo.window_geotransform(150, 4),
+            ..rotated()
+        };
+        assert_eq!(tile.geotransform[0], geo.pixel_to_projected(150.0, 4.0)[0]);
+        let expected = geo.pixel_to_projected(153.0, 6.0);
+        for (actual, expected) in tile.pixel_to_projected(3.0, 2.0).into_iter().zip(expected) {
+            assert!((actual - expected).abs() < 1e-6, "{} {}", actual, expected);
+        }
+    }
 
-    (texture, buffer.data().to_vec())
+    fn rotated() -> GeoReference {
+        // 30 degrees of rotation with 10 by 20 unit pixels and a little shear
+        let (sin, cos) = 30f64.to_radians().sin_cos();
+        GeoReference {
+            geotransform: [
+                500_000.0,
+                10.0 * cos,
+                20.0 * sin + 1.5,
+                4_200_000.0,
+                10.0 * sin,
+                -20.0 * cos,
+            ],
+            crs_wkt: String::new(),
+            width: 300,
+            height: 200,
+        }
+    }
+
+    #[test]
+    fn projected_to_pixel_inverts_rotated_geotransform() {
+        let geo = rotated();
+        assert!(geo.is_rotated());
+        for [column, row] in [[0.0, 0.0], [299.5, 0.25], [12.0, 199.0], [150.5, 100.5]] {
+            let [x, y] = geo.pixel_to_projected(column, row);
+            let [back_column, back_row] = geo.projected_to_pixel(x, y).unwrap();
+            assert!(
+                (back_column - column).abs() < 1e-6,
+                "{} {}",
+                back_column,
+                column
+            );
+            assert!((back_row - row).abs() < 1e-6, "{} {}", back_row, row);
+        }
+    }
+
+    #[test]
+    fn projected_to_pixel_of_singular_geotransform() {
+        let geo = GeoReference {
+            geotransform: [10.0, 2.0, 4.0, 20.0, 1.0, 2.0],
+            ..rotated()
+        };
+        assert_eq!(geo.projected_to_pixel(12.0, 21.0), None);
+    }
+
+    #[test]
+    fn north_of_singular_geotransform() {
+        let geo = GeoReference {
+            geotransform: [10.0, 2.0, 4.0, 20.0, 1.0, 2.0],
+            ..rotated()
+        };
+        assert_eq!(geo.north(), [0.0, -1.0]);
+    }
+
+    #[test]
+    fn decimated_covers_the_same_extent() {
+        let geo = rotated();
+        let preview = geo.decimated((75, 50));
+        assert_eq!((preview.width, preview.height), (75, 50));
+        assert_eq!(preview.crs_wkt, geo.crs_wkt);
+        assert_eq!(preview.projected_bounds(), geo.projected_bounds());
+        // A preview pixel covers 4x4 full resolution pixels
+        for [column, row] in [[0.0, 0.0], [10.0, 7.5], [75.0, 50.0]] {
+            let [x, y] = preview.pixel_to_projected(column, row);
+            let [full_x, full_y] = geo.pixel_to_projected(column * 4.0, row * 4.0);
+            assert!((x - full_x).abs() < 1e-6 && (y - full_y).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn decimated_rounds_up_odd_sizes() {
+        let geo = GeoReference {
+            geotransform: [100.0, 2.0, 0.0, 50.0, 0.0, -2.0],
+            crs_wkt: String::new(),
+            width: 5,
+            height: 3,
+        };
+        // The size of a level 0 preview, halved and rounded up
+        let preview = geo.decimated((5usize.div_ceil(2), 3usize.div_ceil(2)));
+        assert_eq!(preview.geotransform[0], 100.0);
+        assert_eq!(preview.geotransform[3], 50.0);
+        assert!((preview.geotransform[1] - 2.0 * 5.0 / 3.0).abs() < 1e-12);
+        assert!((preview.geotransform[5] + 2.0 * 3.0 / 2.0).abs() < 1e-12);
+        assert_eq!(preview.projected_bounds(), geo.projected_bounds());
+    }
+
+    #[test]
+    fn find_degenerate_constant() {
+        let data = [12.5, 12.5, -9999.0, 12.5];
+        let mask = [0, 0, 255, 0];
+        assert!(matches!(
+            find_degenerate(&data, &mask),
+            Some(Degenerate::Constant(v)) if v == 12.5
+        ));
+    }
+
+    #[test]
+    fn find_degenerate_all_no_data() {
+        let data = [-9999.0, f64::NAN, -9999.0];
+        assert!(matches!(
+            find_degenerate(&data, &[255, 0, 255]),
+            Some(Degenerate::AllNoData)
+        ));
+        assert!(matches!(
+            find_degenerate(&[], &[]),
+            Some(Degenerate::AllNoData)
+        ));
+    }
+
+    #[test]
+    fn find_degenerate_varied() {
+        let data = [1.0, 2.0, f64::NAN];
+        assert!(find_degenerate(&data, &[0, 0, 0]).is_none());
+    }
 }
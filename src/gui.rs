@@ -34,6 +34,29 @@ impl EguiRenderer {
         }
     }
 
+    /// Scales the GUI to `pixels_per_point` on a window with the
+    /// `native_pixels_per_point` scale factor
+    pub fn set_pixels_per_point(&self, pixels_per_point: f32, native_pixels_per_point: f32) {
+        let zoom = pixels_per_point / native_pixels_per_point;
+        if self.context.zoom_factor() != zoom {
+            self.context.set_zoom_factor(zoom);
+        }
+    }
+
+    /// Makes a wgpu texture available to `egui::Image`, until `free_texture`
+    pub fn register_texture(
+        &mut self,
+        device: &egui_wgpu::wgpu::Device,
+        view: &egui_wgpu::wgpu::TextureView,
+        filter: egui_wgpu::wgpu::FilterMode,
+    ) -> egui::TextureId {
+        self.renderer.register_native_texture(device, view, filter)
+    }
+
+    pub fn free_texture(&mut self, id: &egui::TextureId) {
+        self.renderer.free_texture(id);
+    }
+
     pub fn handle_input(
         &mut self,
         window: &egui_winit::winit::window::Window,
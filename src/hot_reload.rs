@@ -0,0 +1,55 @@
+use notify::{RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+use tracing::{debug, error};
+
+/// Watches `src/shader.wgsl` of the source tree the binary was built from
+pub struct ShaderWatcher {
+    path: PathBuf,
+    _watcher: notify::RecommendedWatcher,
+    changes: Receiver<()>,
+}
+
+impl ShaderWatcher {
+    pub fn new() -> notify::Result<Self> {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/shader.wgsl");
+        let (sender, changes) = channel();
+        let file_name = path.file_name().map(|name| name.to_owned());
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| match event {
+                Ok(event) => {
+                    let is_shader = event
+                        .paths
+                        .iter()
+                        .any(|path| path.file_name() == file_name.as_deref());
+                    if is_shader && (event.kind.is_modify() || event.kind.is_create()) {
+                        let _ = sender.send(());
+                    }
+                }
+                Err(e) => error!("Shader watch error: {}", e),
+            })?;
+        // Editors often replace the file instead of writing it, which would
+        // end a watch on the file itself
+        let directory = path.parent().unwrap_or(Path::new("."));
+        watcher.watch(directory, RecursiveMode::NonRecursive)?;
+        debug!("Watching {}", path.display());
+        Ok(Self {
+            path,
+            _watcher: watcher,
+            changes,
+        })
+    }
+
+    /// Source of the shader when it changed since the last call
+    pub fn poll(&self) -> Option<String> {
+        // One save can send several events
+        self.changes.try_iter().last()?;
+        match std::fs::read_to_string(&self.path) {
+            Ok(source) => Some(source),
+            Err(e) => {
+                error!("Failed to read {}: {}", self.path.display(), e);
+                None
+            }
+        }
+    }
+}
@@ -0,0 +1,298 @@
+use egui_winit::winit::keyboard::KeyCode;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
+
+/// Something a key can be bound to, see `KeyBindings`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Action {
+    MoveForward,
+    MoveBackward,
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    LookUp,
+    LookDown,
+    LookLeft,
+    LookRight,
+    /// Fixed steps in precision mode, see `CameraController::set_precision`
+    StepForward,
+    StepBackward,
+    StepLeft,
+    StepRight,
+    StepUp,
+    StepDown,
+    Pause,
+    CopyCamera,
+    AddBookmark,
+    NextBookmark,
+    Precision,
+    GrabMouse,
+    Fullscreen,
+    /// Also releases the grabbed cursor instead of quitting while it is grabbed
+    Quit,
+}
+
+impl Action {
+    /// Precision steps only apply in precision mode, so they may share keys
+    /// with the other actions
+    fn is_step(self) -> bool {
+        matches!(
+            self,
+            Self::StepForward
+                | Self::StepBackward
+                | Self::StepLeft
+                | Self::StepRight
+                | Self::StepUp
+                | Self::StepDown
+        )
+    }
+}
+
+/// Physical key, written by its winit `KeyCode` name, e.g. `"KeyW"`,
+/// `"ArrowUp"`, `"ShiftLeft"` or `"F11"`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Key(pub KeyCode);
+
+/// Keys that can be bound, the names are looked up by their `Debug` output
+const KEYS: [KeyCode; 97] = {
+    use KeyCode::*;
+    [
+        KeyA,
+        KeyB,
+        KeyC,
+        KeyD,
+        KeyE,
+        KeyF,
+        KeyG,
+        KeyH,
+        KeyI,
+        KeyJ,
+        KeyK,
+        KeyL,
+        KeyM,
+        KeyN,
+        KeyO,
+        KeyP,
+        KeyQ,
+        KeyR,
+        KeyS,
+        KeyT,
+        KeyU,
+        KeyV,
+        KeyW,
+        KeyX,
+        KeyY,
+        KeyZ,
+        Digit0,
+        Digit1,
+        Digit2,
+        Digit3,
+        Digit4,
+        Digit5,
+        Digit6,
+        Digit7,
+        Digit8,
+        Digit9,
+        F1,
+        F2,
+        F3,
+        F4,
+        F5,
+        F6,
+        F7,
+        F8,
+        F9,
+        F10,
+        F11,
+        F12,
+        ArrowUp,
+        ArrowDown,
+        ArrowLeft,
+        ArrowRight,
+        PageUp,
+        PageDown,
+        Home,
+        End,
+        Insert,
+        Delete,
+        Space,
+        Enter,
+        Tab,
+        Backspace,
+        Escape,
+        ShiftLeft,
+        ShiftRight,
+        ControlLeft,
+        ControlRight,
+        AltLeft,
+        AltRight,
+        Backquote,
+        Minus,
+        Equal,
+        BracketLeft,
+        BracketRight,
+        Backslash,
+        Semicolon,
+        Quote,
+        Comma,
+        Period,
+        Slash,
+        Numpad0,
+        Numpad1,
+        Numpad2,
+        Numpad3,
+        Numpad4,
+        Numpad5,
+        Numpad6,
+        Numpad7,
+        Numpad8,
+        Numpad9,
+        NumpadAdd,
+        NumpadSubtract,
+        NumpadMultiply,
+        NumpadDivide,
+        NumpadDecimal,
+        NumpadEnter,
+        CapsLock,
+    ]
+};
+
+impl Serialize for Key {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("{:?}", self.0))
+    }
+}
+
+impl<'de> Deserialize<'de> for Key {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        KEYS.into_iter()
+            .find(|key| format!("{:?}", key) == name)
+            .map(Key)
+            .ok_or_else(|| de::Error::custom(format!("Unknown key {:?}", name)))
+    }
+}
+
+/// Keys bound to each action, the `[keys]` table of the preset. Every action
+/// takes a list so it can have several keys, an empty list unbinds it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeyBindings {
+    pub move_forward: Vec<Key>,
+    pub move_backward: Vec<Key>,
+    pub move_left: Vec<Key>,
+    pub move_right: Vec<Key>,
+    pub move_up: Vec<Key>,
+    pub move_down: Vec<Key>,
+    pub look_up: Vec<Key>,
+    pub look_down: Vec<Key>,
+    pub look_left: Vec<Key>,
+    pub look_right: Vec<Key>,
+    pub step_forward: Vec<Key>,
+    pub step_backward: Vec<Key>,
+    pub step_left: Vec<Key>,
+    pub step_right: Vec<Key>,
+    pub step_up: Vec<Key>,
+    pub step_down: Vec<Key>,
+    pub pause: Vec<Key>,
+    pub copy_camera: Vec<Key>,
+    pub add_bookmark: Vec<Key>,
+    pub next_bookmark: Vec<Key>,
+    pub precision: Vec<Key>,
+    pub grab_mouse: Vec<Key>,
+    pub fullscreen: Vec<Key>,
+    pub quit: Vec<Key>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        let keys = |keys: &[KeyCode]| keys.iter().copied().map(Key).collect();
+        Self {
+            move_forward: keys(&[KeyCode::KeyW, KeyCode::ArrowUp]),
+            move_backward: keys(&[KeyCode::KeyS, KeyCode::ArrowDown]),
+            move_left: keys(&[KeyCode::KeyA, KeyCode::ArrowLeft]),
+            move_right: keys(&[KeyCode::KeyD, KeyCode::ArrowRight]),
+            move_up: keys(&[KeyCode::Space]),
+            move_down: keys(&[KeyCode::ShiftLeft]),
+            look_up: keys(&[KeyCode::KeyI]),
+            look_down: keys(&[KeyCode::KeyK]),
+            look_left: keys(&[KeyCode::KeyJ]),
+            look_right: keys(&[KeyCode::KeyL]),
+            step_forward: keys(&[KeyCode::ArrowUp]),
+            step_backward: keys(&[KeyCode::ArrowDown]),
+            step_left: keys(&[KeyCode::ArrowLeft]),
+            step_right: keys(&[KeyCode::ArrowRight]),
+            step_up: keys(&[KeyCode::PageUp]),
+            step_down: keys(&[KeyCode::PageDown]),
+            pause: keys(&[KeyCode::KeyP]),
+            copy_camera: keys(&[KeyCode::KeyC]),
+            add_bookmark: keys(&[KeyCode::KeyB]),
+            next_bookmark: keys(&[KeyCode::KeyG]),
+            precision: keys(&[KeyCode::KeyN]),
+            grab_mouse: keys(&[KeyCode::KeyM]),
+            fullscreen: keys(&[KeyCode::F11]),
+            quit: keys(&[KeyCode::Escape]),
+        }
+    }
+}
+
+impl KeyBindings {
+    fn bindings(&self) -> [(Action, &[Key]); 24] {
+        [
+            (Action::MoveForward, &self.move_forward),
+            (Action::MoveBackward, &self.move_backward),
+            (Action::MoveLeft, &self.move_left),
+            (Action::MoveRight, &self.move_right),
+            (Action::MoveUp, &self.move_up),
+            (Action::MoveDown, &self.move_down),
+            (Action::LookUp, &self.look_up),
+            (Action::LookDown, &self.look_down),
+            (Action::LookLeft, &self.look_left),
+            (Action::LookRight, &self.look_right),
+            (Action::StepForward, &self.step_forward),
+            (Action::StepBackward, &self.step_backward),
+            (Action::StepLeft, &self.step_left),
+            (Action::StepRight, &self.step_right),
+            (Action::StepUp, &self.step_up),
+            (Action::StepDown, &self.step_down),
+            (Action::Pause, &self.pause),
+            (Action::CopyCamera, &self.copy_camera),
+            (Action::AddBookmark, &self.add_bookmark),
+            (Action::NextBookmark, &self.next_bookmark),
+            (Action::Precision, &self.precision),
+            (Action::GrabMouse, &self.grab_mouse),
+            (Action::Fullscreen, &self.fullscreen),
+            (Action::Quit, &self.quit),
+        ]
+        .map(|(action, keys)| (action, keys.as_slice()))
+    }
+
+    /// Actions bound to `key`, in declaration order
+    pub fn actions(&self, key: KeyCode) -> Vec<Action> {
+        self.bindings()
+            .into_iter()
+            .filter(|(_, keys)| keys.contains(&Key(key)))
+            .map(|(action, _)| action)
+            .collect()
+    }
+
+    /// Keys bound to more than one action that can apply at the same time.
+    /// Precision steps only conflict with each other.
+    pub fn conflicts(&self) -> Vec<(KeyCode, Vec<Action>)> {
+        let mut actions: BTreeMap<(bool, KeyCode), Vec<Action>> = BTreeMap::new();
+        for (action, keys) in self.bindings() {
+            for &Key(key) in keys {
+                actions
+                    .entry((action.is_step(), key))
+                    .or_default()
+                    .push(action);
+            }
+        }
+        actions
+            .into_iter()
+            .filter(|(_, actions)| actions.len() > 1)
+            .map(|((_, key), actions)| (key, actions))
+            .collect()
+    }
+}
@@ -1,18 +1,44 @@
 use egui_winit::winit::{
+    dpi::PhysicalSize,
     event::*,
     event_loop::EventLoop,
-    keyboard::{KeyCode, PhysicalKey},
-    window::WindowBuilder,
+    keyboard::PhysicalKey,
+    window::{Fullscreen, WindowBuilder},
 };
 use tracing::{debug, error, info, info_span, trace, warn};
 
+pub mod atlas;
+pub mod bench;
+pub mod blit;
 pub mod camera;
+pub mod capabilities;
+pub mod config;
+pub mod download;
 pub mod gtiff;
 pub mod gui;
+#[cfg(feature = "hot-reload")]
+pub mod hot_reload;
+pub mod keys;
+pub mod light;
+pub mod lod;
+pub mod morph;
+pub mod outline;
+pub mod overlay;
+pub mod preset;
+pub mod preview;
+pub mod profile;
+pub mod progressive;
+pub mod ramp;
+pub mod shadow;
 pub mod state;
 pub mod terrain;
 pub mod texture;
+pub mod track;
+pub mod water;
 
+use config::{Config, DemSource};
+use keys::Action;
+use preset::Preset;
 use state::State;
 
 pub async fn run() {
@@ -26,6 +52,21 @@ pub async fn run() {
         let span = info_span!("initialization");
         let _enter = span.enter();
 
+        let mut config = match Config::from_args() {
+            Ok(config) => {
+                debug!("Config: {:?}", config);
+                config
+            }
+            Err(e) => {
+                error!("Failed to parse arguments: {:?}", e);
+                panic!();
+            }
+        };
+        if config.bench_mesh {
+            bench::bench_mesh();
+            return;
+        }
+
         trace!("Creating event loop and window");
         event_loop = match EventLoop::new() {
             Ok(event_loop) => {
@@ -39,6 +80,10 @@ pub async fn run() {
         };
         window = match WindowBuilder::new()
             .with_title("Terrain Renderer")
+            .with_inner_size(PhysicalSize::new(config.width, config.height))
+            .with_fullscreen(config.fullscreen.then_some(Fullscreen::Borderless(None)))
+            // Batch rendering only needs the window for the device
+            .with_visible(config.batch.is_none())
             .build(&event_loop)
         {
             Ok(window) => {
@@ -52,10 +97,94 @@ pub async fn run() {
         };
         debug!("Event loop and window created");
 
+        if let Some(dir) = &config.atlas {
+            let atlas = match atlas::Atlas::load(dir) {
+                Ok(atlas) => atlas,
+                Err(e) => {
+                    error!("Failed to load the tile atlas: {:?}", e);
+                    panic!();
+                }
+            };
+            config.dem = DemSource::File(atlas.dem.to_string_lossy().into_owned());
+            if config.imagery.is_none() {
+                config.imagery = atlas
+                    .imagery
+                    .map(|path| path.to_string_lossy().into_owned());
+            }
+        }
+
+        // Batch renders have no GUI to show the progress in, so they wait for
+        // the download. Otherwise `State::start_download` runs it in the background.
+        let mut batch_download = None;
+        if let (Some(_), DemSource::File(path)) = (&config.batch, &mut config.dem) {
+            if download::is_url(path) {
+                info!("Downloading DEM from {}", path);
+                *path = match download::download(path, |_, _| {}) {
+                    Ok(file) => {
+                        let dem = file.to_string_lossy().into_owned();
+                        batch_download = Some(file);
+                        dem
+                    }
+                    Err(e) => {
+                        error!("Failed to download DEM: {:?}", e);
+                        return;
+                    }
+                };
+            }
+        }
+
+        // Batch renders need the full resolution
+        let mut progressive = None;
+        if let (true, None, DemSource::File(path)) =
+            (config.progressive, &config.batch, &config.dem)
+        {
+            if download::is_url(path) {
+                debug!("Downloaded DEMs are loaded directly");
+            } else {
+                match progressive::Progressive::start(path) {
+                    Ok(Some((stage, loader))) => {
+                        config.dem = DemSource::File(stage.path);
+                        progressive = Some(loader);
+                    }
+                    Ok(None) => {}
+                    Err(e) => warn!("Failed to start progressive loading: {:?}", e),
+                }
+            }
+        }
+
         trace!("Creating state");
-        state = State::new(&window).await;
+        state = State::new(&window, &config).await;
+        if let Some(loader) = progressive {
+            state.start_progressive(loader);
+        }
+        if let DemSource::File(path) = &config.dem {
+            if download::is_url(path) {
+                state.start_download(path);
+            }
+        }
         debug!("State created");
+
+        match Preset::load(&config.preset) {
+            Ok(preset) => state.apply_preset(&preset),
+            Err(e) => warn!("Failed to load preset, using defaults: {:?}", e),
+        }
+        state.preset_path = config.preset;
+        state.auto_rotate = config.auto_rotate;
+        if config.packed_vertices {
+            state.set_packed_vertices(true);
+        }
         info!("Initialization complete");
+
+        if let Some(path) = &config.batch {
+            if let Err(e) = state.render_batch(path) {
+                error!("Batch rendering failed: {:?}", e);
+            }
+            state.shutdown();
+            if let Some(file) = batch_download {
+                download::remove(&file);
+            }
+            return;
+        }
     }
     let mut surface_configured = false;
     let mut last_render_time = std::time::Instant::now();
@@ -67,7 +196,7 @@ pub async fn run() {
             event: DeviceEvent::MouseMotion{ delta, },
             .. // We're not using device_id currently
         } => {
-            if !egui_consumed && state.mouse_pressed {
+            if state.mouse_grabbed() || (!egui_consumed && state.mouse_pressed) {
                 state.camera_controller.process_mouse(delta.0, delta.1)
             }
         }
@@ -77,16 +206,36 @@ pub async fn run() {
         } if window_id == state.window().id() && !state.input(event) => {
             if !state.input(event) {
                 match event {
-                    WindowEvent::CloseRequested
-                    | WindowEvent::KeyboardInput {
+                    WindowEvent::CloseRequested => control_flow.exit(),
+
+                    WindowEvent::KeyboardInput {
                         event:
                             KeyEvent {
                                 state: ElementState::Pressed,
-                                physical_key: PhysicalKey::Code(KeyCode::Escape),
+                                physical_key: PhysicalKey::Code(key),
+                                repeat,
                                 ..
                             },
                         ..
-                    } => control_flow.exit(),
+                    } => {
+                        let actions = state.key_bindings.actions(*key);
+                        if actions.contains(&Action::Quit) {
+                            control_flow.exit();
+                        } else if actions.contains(&Action::Fullscreen) && !repeat {
+                            let window = state.window();
+                        if window.fullscreen().is_some() {
+                            debug!("Leaving fullscreen");
+                            window.set_fullscreen(None);
+                        } else {
+                            debug!("Entering fullscreen");
+                            window.set_fullscreen(Some(Fullscreen::Borderless(None)));
+                        }
+                        }
+                    }
+
+                    WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                        state.set_scale_factor(*scale_factor);
+                    }
 
                     WindowEvent::Resized(physical_size) => {
                         surface_configured = true;
@@ -99,6 +248,17 @@ pub async fn run() {
                             return;
                         }
 
+                        if state.is_device_lost() {
+                            pollster::block_on(state.recover());
+                        }
+                        state.poll_download();
+                        if let Some(path) = state.take_open_dem_request() {
+                            state.open_dem(path);
+                        }
+                        if let Some(stage) = state.take_progressive_stage() {
+                            state.load_stage(stage);
+                        }
+
                         let now = std::time::Instant::now();
                         let dt = now - last_render_time;
                         last_render_time = now;
@@ -131,6 +291,10 @@ pub async fn run() {
                 egui_consumed = state.egui.handle_input(&mut state.window, event);
             }
         }
+        Event::LoopExiting => {
+            info!("Shutting down");
+            state.shutdown();
+        }
         _ => {}
     });
 }
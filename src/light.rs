@@ -0,0 +1,101 @@
+use cgmath::{Deg, InnerSpace, Vector3};
+
+/// Lights the shader sums, the sun included
+pub const MAX_LIGHTS: usize = 4;
+
+/// Sun moving along a simple east to west arc over a simulated day
+#[derive(Debug)]
+pub struct Sun {
+    /// Simulated time of day in hours, [0, 24)
+    pub time: f32,
+    /// Real seconds it takes to simulate a full day
+    pub day_length: f32,
+    /// Altitude of the sun at noon
+    pub max_altitude: Deg<f32>,
+    /// Whether the time of day advances in `update`
+    pub playing: bool,
+    /// Linear RGB
+    pub color: [f32; 3],
+    pub intensity: f32,
+}
+
+impl Default for Sun {
+    fn default() -> Self {
+        Self {
+            time: 10.0,
+            day_length: 120.0,
+            max_altitude: Deg(60.0),
+            playing: false,
+            color: [1.0; 3],
+            intensity: 1.0,
+        }
+    }
+}
+
+impl Sun {
+    const SUNRISE: f32 = 6.0;
+    const SUNSET: f32 = 18.0;
+
+    pub fn advance(&mut self, dt: std::time::Duration) {
+        if !self.playing || self.day_length <= 0.0 {
+            return;
+        }
+        self.time = (self.time + dt.as_secs_f32() / self.day_length * 24.0).rem_euclid(24.0);
+    }
+
+    /// Azimuth clockwise from north and altitude above the horizon
+    pub fn azimuth_altitude(&self) -> (Deg<f32>, Deg<f32>) {
+        let day = (self.time - Self::SUNRISE) / (Self::SUNSET - Self::SUNRISE);
+        let azimuth = Deg(90.0 + 180.0 * day);
+        let altitude = self.max_altitude * (std::f32::consts::PI * day).sin();
+        (azimuth, altitude)
+    }
+
+    /// Unit vector pointing towards the sun, north is -z and up is +y
+    pub fn direction(&self) -> Vector3<f32> {
+        let (azimuth, altitude) = self.azimuth_altitude();
+        direction(azimuth, altitude)
+    }
+
+    /// Formats the time of day as HH:MM
+    pub fn clock(&self) -> String {
+        let minutes = (self.time * 60.0) as u32;
+        format!("{:02}:{:02}", minutes / 60, minutes % 60)
+    }
+}
+
+/// Fixed directional light next to the sun, for fill and rim lighting
+#[derive(Debug, Clone)]
+pub struct Light {
+    /// Clockwise from north
+    pub azimuth: Deg<f32>,
+    /// Above the horizon
+    pub altitude: Deg<f32>,
+    /// Linear RGB
+    pub color: [f32; 3],
+    pub intensity: f32,
+}
+
+impl Default for Light {
+    fn default() -> Self {
+        Self {
+            azimuth: Deg(315.0),
+            altitude: Deg(45.0),
+            color: [1.0; 3],
+            intensity: 0.5,
+        }
+    }
+}
+
+impl Light {
+    /// Unit vector pointing towards the light, see `Sun::direction`
+    pub fn direction(&self) -> Vector3<f32> {
+        direction(self.azimuth, self.altitude)
+    }
+}
+
+fn direction(azimuth: Deg<f32>, altitude: Deg<f32>) -> Vector3<f32> {
+    let (sin_az, cos_az) = cgmath::Rad::from(azimuth).0.sin_cos();
+    let (sin_alt, cos_alt) = cgmath::Rad::from(altitude).0.sin_cos();
+    Vector3::new(sin_az * cos_alt, sin_alt, -cos_az * cos_alt).normalize()
+}
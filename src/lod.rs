@@ -0,0 +1,183 @@
+use super::state::Vertex;
+use super::terrain::HeightField;
+use tracing::{debug, trace_span};
+
+/// Vertices along each edge of a chunk, one more than a power of two so the
+/// samples of a chunk line up with every second sample of its children
+pub const CHUNK_RESOLUTION: u32 = 33;
+
+/// Chunk of the quadtree, `x` and `y` count chunks of its level from the
+/// top left corner of the DEM
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Node {
+    pub level: u32,
+    pub x: u32,
+    pub y: u32,
+}
+
+/// Quadtree over the DEM, the root covers the DEM padded to a power of two
+/// and the deepest level has one vertex per DEM pixel
+pub struct QuadTree {
+    width: u32,
+    height: u32,
+    /// Size of the root in DEM pixels
+    root_size: u32,
+    max_level: u32,
+}
+
+impl QuadTree {
+    pub fn new(width: u32, height: u32) -> Self {
+        let cells = CHUNK_RESOLUTION - 1;
+        let root_size = (width.max(height) - 1).next_power_of_two().max(cells);
+        let max_level = (root_size / cells).trailing_zeros();
+        debug!(
+            "Quadtree: root size {}, {} levels",
+            root_size,
+            max_level + 1
+        );
+        Self {
+            width,
+            height,
+            root_size,
+            max_level,
+        }
+    }
+
+    /// Size of a node in DEM pixels
+    pub fn node_size(&self, node: Node) -> u32 {
+        self.root_size >> node.level
+    }
+
+    /// Selects the chunks to draw for a camera at `camera` (column, world
+    /// height, row). A node is split while the camera is closer to its bounds
+    /// than `lod_factor` times its size, so chunks near the camera render at
+    /// full resolution and distant ones decimated. `top` is the world height
+    /// of the highest point.
+    pub fn select(&self, camera: [f64; 3], top: f64, lod_factor: f64) -> Vec<Node> {
+        let mut nodes = Vec::new();
+        let mut stack = vec![Node {
+            level: 0,
+            x: 0,
+            y: 0,
+        }];
+        while let Some(node) = stack.pop() {
+            let size = self.node_size(node) as f64;
+            let left = node.x as f64 * size;
+            let top_row = node.y as f64 * size;
+            // Chunks entirely past the DEM padding have nothing to draw
+            if left >= (self.width - 1) as f64 || top_row >= (self.height - 1) as f64 {
+                continue;
+            }
+
+            let distance =
+                |value: f64, low: f64, high: f64| (low - value).max(0.0).max(value - high);
+            let dx = distance(camera[0], left, left + size);
+            let dy = distance(camera[1], 0.0, top);
+            let dz = distance(camera[2], top_row, top_row + size);
+            let distance = (dx * dx + dy * dy + dz * dz).sqrt();
+            if node.level < self.max_level && distance < size * lod_factor {
+                for (x, y) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    stack.push(Node {
+                        level: node.level + 1,
+                        x: node.x * 2 + x,
+                        y: node.y * 2 + y,
+                    });
+                }
+            } else {
+                nodes.push(node);
+            }
+        }
+        nodes
+    }
+
+    /// Meshes the selected chunks as a triangle list. Each chunk gets a skirt
+    /// hanging down from its edges that covers the cracks T-junctions between
+    /// different levels leave.
+    pub fn build(&self, field: &HeightField, nodes: &[Node]) -> (Vec<Vertex>, Vec<u32>) {
+        let span = trace_span!("QuadTree::build");
+        let _enter = span.enter();
+
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        let max_column = (field.width() - 1) as f64;
+        let max_row = (field.height() - 1) as f64;
+        let n = CHUNK_RESOLUTION;
+        for &node in nodes {
+            let size = self.node_size(node) as f64;
+            let step = size / (n - 1) as f64;
+            let base = vertices.len() as u32;
+            let index = |x: u32, y: u32| base + y * n + x;
+
+            // Samples past the DEM are clamped onto its edge and collapse
+            for y in 0..n {
+                for x in 0..n {
+                    let column = (node.x as f64 * size + x as f64 * step).min(max_column);
+                    let row = (node.y as f64 * size + y as f64 * step).min(max_row);
+                    vertices.push(field.vertex(column, row, [step, step]));
+                }
+            }
+            for y in 0..n - 1 {
+                for x in 0..n - 1 {
+                    indices.extend_from_slice(&[
+                        index(x, y),
+                        index(x, y + 1),
+                        index(x + 1, y),
+                        index(x + 1, y),
+                        index(x, y + 1),
+                        index(x + 1, y + 1),
+                    ]);
+                }
+            }
+
+            // The coarser edge of a neighbour interpolates between samples of
+            // this chunk, so a crack can't reach below the chunk's lowest point
+            let chunk = &vertices[base as usize..];
+            let lowest = chunk
+                .iter()
+                .map(|v| v.position[1])
+                .fold(f32::INFINITY, f32::min);
+            let skirt_height = lowest - step as f32;
+            // Edges on the DEM border have no neighbour to crack against
+            let left = node.x as f64 * size;
+            let top = node.y as f64 * size;
+            let edges: [(bool, Vec<u32>); 4] = [
+                (top > 0.0, (0..n).map(|x| index(x, 0)).collect()),
+                (
+                    left + size < max_column,
+                    (0..n).map(|y| index(n - 1, y)).collect(),
+                ),
+                (
+                    top + size < max_row,
+                    (0..n).rev().map(|x| index(x, n - 1)).collect(),
+                ),
+                (left > 0.0, (0..n).rev().map(|y| index(0, y)).collect()),
+            ];
+            for (_, edge) in edges.into_iter().filter(|(inner, _)| *inner) {
+                let skirt = vertices.len() as u32;
+                for &i in &edge {
+                    let mut vertex = vertices[i as usize];
+                    vertex.position[1] = skirt_height;
+                    vertices.push(vertex);
+                }
+                for i in 0..edge.len() as u32 - 1 {
+                    // Counter-clockwise seen from outside the chunk
+                    indices.extend_from_slice(&[
+                        edge[i as usize],
+                        edge[i as usize + 1],
+                        skirt + i,
+                        edge[i as usize + 1],
+                        skirt + i + 1,
+                        skirt + i,
+                    ]);
+                }
+            }
+        }
+        debug!(
+            "Built {} chunks: {} verticies, {} indices",
+            nodes.len(),
+            vertices.len(),
+            indices.len()
+        );
+        (vertices, indices)
+    }
+}
@@ -0,0 +1,88 @@
+use anyhow::{bail, Result};
+use std::f32::consts::TAU;
+use std::time::Duration;
+
+/// Blend between the loaded DEM and a second one of the same size, for
+/// showing change between two surveys
+#[derive(Debug, Clone)]
+pub struct Morph {
+    /// Elevation of the DEM loaded at startup
+    from: Vec<f64>,
+    /// Elevation of the second DEM, the first DEM's value where it is NoData
+    to: Vec<f64>,
+    /// 0 shows `from`, 1 shows `to`
+    pub factor: f32,
+    /// Sweep `factor` back and forth over time instead of using the slider
+    pub animate: bool,
+    /// Seconds for one sweep from `from` to `to` and back
+    pub period: f32,
+    /// Position within the period, in radians
+    phase: f32,
+}
+
+impl Morph {
+    /// Fails when `to` is not `width`x`height` like `from`
+    pub fn new(
+        from: Vec<f64>,
+        (width, height): (usize, usize),
+        (to_width, to_height, to): (usize, usize, Vec<Option<f64>>),
+    ) -> Result<Self> {
+        if (to_width, to_height) != (width, height) {
+            bail!(
+                "Morph target is {}x{}, the loaded DEM is {}x{}",
+                to_width,
+                to_height,
+                width,
+                height
+            );
+        }
+        let to = from
+            .iter()
+            .zip(to)
+            .map(|(&from, to)| to.unwrap_or(from))
+            .collect();
+        Ok(Self {
+            from,
+            to,
+            factor: 0.0,
+            animate: false,
+            period: 4.0,
+            phase: 0.0,
+        })
+    }
+
+    /// Elevation of the loaded DEM, which the blend is restored to when the
+    /// morph is removed
+    pub fn from(&self) -> &[f64] {
+        &self.from
+    }
+
+    /// Flips the sign of both DEMs, see `RenderSettings::invert_elevation`
+    pub fn negate(&mut self) {
+        for value in self.from.iter_mut().chain(&mut self.to) {
+            *value = -*value;
+        }
+    }
+
+    /// Advances the animation, returns whether `factor` changed
+    pub fn advance(&mut self, dt: Duration) -> bool {
+        if !self.animate || self.period <= 0.0 {
+            return false;
+        }
+        self.phase = (self.phase + TAU * dt.as_secs_f32() / self.period) % TAU;
+        self.factor = 0.5 - 0.5 * self.phase.cos();
+        true
+    }
+
+    /// Elevation at `factor` between the two DEMs, `is_no_data` pixels keep
+    /// the loaded DEM's value
+    pub fn blend(&self, is_no_data: &[bool]) -> Vec<f64> {
+        let t = self.factor.clamp(0.0, 1.0) as f64;
+        self.from
+            .iter()
+            .zip(&self.to)
+            .zip(is_no_data)
+            .map(|((&a, &b), &no_data)| if no_data { a } else { a + (b - a) * t })
+            .collect()
+    }
+}
@@ -0,0 +1,175 @@
+use tracing::trace;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct OutlineUniform {
+    pub color: [f32; 4],
+    pub znear: f32,
+    pub zfar: f32,
+    /// Relative depth change, see `RenderSettings::outline_threshold`
+    pub threshold: f32,
+    /// Non-zero when the target isn't sRGB and the shader has to encode
+    pub encode_srgb: u32,
+    /// 1 / log2(zfar + 1) when the depth is logarithmic, see `CameraUniform`
+    pub log_depth: f32,
+    /// Non-zero when the depth is reversed, see `RenderSettings::reversed_z`
+    pub reversed_z: u32,
+    pub _padding: [f32; 2],
+}
+
+/// Edge detection over the depth buffer, drawn on top of the terrain for a
+/// technical illustration look
+pub struct Outline {
+    pipeline: egui_wgpu::wgpu::RenderPipeline,
+    bind_group_layout: egui_wgpu::wgpu::BindGroupLayout,
+    uniform_buffer: egui_wgpu::wgpu::Buffer,
+    bind_group: egui_wgpu::wgpu::BindGroup,
+}
+
+impl Outline {
+    pub fn new(
+        device: &egui_wgpu::wgpu::Device,
+        format: egui_wgpu::wgpu::TextureFormat,
+        depth_view: &egui_wgpu::wgpu::TextureView,
+    ) -> Self {
+        let shader = device.create_shader_module(egui_wgpu::wgpu::include_wgsl!("outline.wgsl"));
+        let bind_group_layout =
+            device.create_bind_group_layout(&egui_wgpu::wgpu::BindGroupLayoutDescriptor {
+                label: Some("Outline Bind Group Layout"),
+                entries: &[
+                    egui_wgpu::wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: egui_wgpu::wgpu::ShaderStages::FRAGMENT,
+                        ty: egui_wgpu::wgpu::BindingType::Buffer {
+                            ty: egui_wgpu::wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    // Read with textureLoad, the depth texture can't be
+                    // attached while it is sampled
+                    egui_wgpu::wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: egui_wgpu::wgpu::ShaderStages::FRAGMENT,
+                        ty: egui_wgpu::wgpu::BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: egui_wgpu::wgpu::TextureViewDimension::D2,
+                            sample_type: egui_wgpu::wgpu::TextureSampleType::Depth,
+                        },
+                        count: None,
+                    },
+                ],
+            });
+        let uniform_buffer = device.create_buffer(&egui_wgpu::wgpu::BufferDescriptor {
+            label: Some("Outline Buffer"),
+            size: std::mem::size_of::<OutlineUniform>() as egui_wgpu::wgpu::BufferAddress,
+            usage: egui_wgpu::wgpu::BufferUsages::UNIFORM | egui_wgpu::wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let layout = device.create_pipeline_layout(&egui_wgpu::wgpu::PipelineLayoutDescriptor {
+            label: Some("Outline Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&egui_wgpu::wgpu::RenderPipelineDescriptor {
+            label: Some("Outline Pipeline"),
+            layout: Some(&layout),
+            vertex: egui_wgpu::wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(egui_wgpu::wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(egui_wgpu::wgpu::ColorTargetState {
+                    format,
+                    blend: Some(egui_wgpu::wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: egui_wgpu::wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: egui_wgpu::wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: egui_wgpu::wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+        let bind_group = create_bind_group(device, &bind_group_layout, &uniform_buffer, depth_view);
+        trace!("Outline pipeline created");
+
+        Self {
+            pipeline,
+            bind_group_layout,
+            uniform_buffer,
+            bind_group,
+        }
+    }
+
+    /// Rebinds the depth texture after it was recreated
+    pub fn set_depth(
+        &mut self,
+        device: &egui_wgpu::wgpu::Device,
+        depth_view: &egui_wgpu::wgpu::TextureView,
+    ) {
+        self.bind_group = create_bind_group(
+            device,
+            &self.bind_group_layout,
+            &self.uniform_buffer,
+            depth_view,
+        );
+    }
+
+    /// Blends the outlines over `target`, which has to match the depth texture in size
+    pub fn draw(
+        &self,
+        encoder: &mut egui_wgpu::wgpu::CommandEncoder,
+        queue: &egui_wgpu::wgpu::Queue,
+        target: &egui_wgpu::wgpu::TextureView,
+        uniform: OutlineUniform,
+    ) {
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+        let mut render_pass = encoder.begin_render_pass(&egui_wgpu::wgpu::RenderPassDescriptor {
+            label: Some("Outline Pass"),
+            color_attachments: &[Some(egui_wgpu::wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: egui_wgpu::wgpu::Operations {
+                    load: egui_wgpu::wgpu::LoadOp::Load,
+                    store: egui_wgpu::wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    pub fn destroy(&self) {
+        self.uniform_buffer.destroy();
+    }
+}
+
+fn create_bind_group(
+    device: &egui_wgpu::wgpu::Device,
+    layout: &egui_wgpu::wgpu::BindGroupLayout,
+    uniform_buffer: &egui_wgpu::wgpu::Buffer,
+    depth_view: &egui_wgpu::wgpu::TextureView,
+) -> egui_wgpu::wgpu::BindGroup {
+    device.create_bind_group(&egui_wgpu::wgpu::BindGroupDescriptor {
+        label: Some("Outline Bind Group"),
+        layout,
+        entries: &[
+            egui_wgpu::wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            },
+            egui_wgpu::wgpu::BindGroupEntry {
+                binding: 1,
+                resource: egui_wgpu::wgpu::BindingResource::TextureView(depth_view),
+            },
+        ],
+    })
+}
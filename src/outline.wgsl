@@ -0,0 +1,65 @@
+// Outlines depth discontinuities over the rendered terrain
+
+struct Outline {
+    color: vec4<f32>,
+    znear: f32,
+    zfar: f32,
+    threshold: f32,
+    encode_srgb: u32,
+    log_depth: f32,
+    reversed_z: u32,
+    _padding0: f32,
+    _padding1: f32,
+}
+@group(0) @binding(0)
+var<uniform> outline: Outline;
+@group(0) @binding(1)
+var depth: texture_depth_2d;
+
+// One triangle that covers the whole screen, no vertex buffer needed
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+}
+
+// Distance from the camera for a depth buffer value in [0, 1]
+fn linearDepth(texel: vec2<i32>) -> f32 {
+    let dims = vec2<i32>(textureDimensions(depth));
+    var d = textureLoad(depth, clamp(texel, vec2<i32>(0), dims - 1), 0);
+    if outline.log_depth > 0.0 {
+        return exp2(d / outline.log_depth) - 1.0;
+    }
+    if outline.reversed_z != 0u {
+        d = 1.0 - d;
+    }
+    return outline.znear * outline.zfar / (outline.zfar - d * (outline.zfar - outline.znear));
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let p = vec2<i32>(position.xy);
+    let tl = linearDepth(p + vec2<i32>(-1, -1));
+    let t = linearDepth(p + vec2<i32>(0, -1));
+    let tr = linearDepth(p + vec2<i32>(1, -1));
+    let l = linearDepth(p + vec2<i32>(-1, 0));
+    let c = linearDepth(p);
+    let r = linearDepth(p + vec2<i32>(1, 0));
+    let bl = linearDepth(p + vec2<i32>(-1, 1));
+    let b = linearDepth(p + vec2<i32>(0, 1));
+    let br = linearDepth(p + vec2<i32>(1, 1));
+
+    // Sobel, relative to the depth so distant edges aren't drowned out
+    let gx = (tr + 2.0 * r + br) - (tl + 2.0 * l + bl);
+    let gy = (bl + 2.0 * b + br) - (tl + 2.0 * t + tr);
+    let edge = length(vec2<f32>(gx, gy)) / c;
+    let coverage = smoothstep(outline.threshold, outline.threshold * 2.0, edge);
+    if coverage <= 0.0 {
+        discard;
+    }
+    var color = outline.color.rgb;
+    if outline.encode_srgb != 0u {
+        color = select(1.055 * pow(color, vec3<f32>(1.0 / 2.4)) - 0.055, color * 12.92, color <= vec3<f32>(0.0031308));
+    }
+    return vec4<f32>(color, coverage);
+}
@@ -0,0 +1,92 @@
+use egui::{Align2, Color32, FontId, Painter, Pos2, Shape, Stroke, Vec2};
+
+const COLOR: Color32 = Color32::WHITE;
+const SHADOW: Color32 = Color32::from_black_alpha(160);
+
+/// Draws a compass arrow around `center`, `north` is the screen direction of north
+pub fn draw_north_arrow(painter: &Painter, center: Pos2, north: Vec2) {
+    const RADIUS: f32 = 24.0;
+
+    let side = north.rot90() * (RADIUS * 0.4);
+    let tip = center + north * RADIUS;
+    let tail = center - north * RADIUS;
+    painter.circle(center, RADIUS + 6.0, SHADOW, Stroke::NONE);
+    painter.add(Shape::convex_polygon(
+        vec![tip, center + side, center - side],
+        Color32::from_rgb(220, 60, 60),
+        Stroke::NONE,
+    ));
+    painter.add(Shape::convex_polygon(
+        vec![tail, center - side, center + side],
+        COLOR,
+        Stroke::NONE,
+    ));
+    painter.text(
+        center + north * (RADIUS + 16.0),
+        Align2::CENTER_CENTER,
+        "N",
+        FontId::proportional(14.0),
+        COLOR,
+    );
+}
+
+/// Draws a reticle at `center` for aiming picks
+pub fn draw_crosshair(painter: &Painter, center: Pos2) {
+    const SIZE: f32 = 10.0;
+    const GAP: f32 = 3.0;
+
+    for stroke in [Stroke::new(3.0, SHADOW), Stroke::new(1.0, COLOR)] {
+        for direction in [Vec2::X, -Vec2::X, Vec2::Y, -Vec2::Y] {
+            painter.line_segment(
+                [center + direction * GAP, center + direction * SIZE],
+                stroke,
+            );
+        }
+    }
+}
+
+/// Draws a scale bar of a round length with its left end at `origin`
+pub fn draw_scale_bar(painter: &Painter, origin: Pos2, pixels_per_meter: f32) {
+    const TARGET_WIDTH: f32 = 150.0;
+
+    let meters = nice_length((TARGET_WIDTH / pixels_per_meter) as f64);
+    let width = meters as f32 * pixels_per_meter;
+    let end = origin + Vec2::new(width, 0.0);
+    let tick = Vec2::new(0.0, -6.0);
+
+    painter.rect_filled(
+        egui::Rect::from_min_max(origin + Vec2::new(-8.0, -28.0), end + Vec2::new(8.0, 6.0)),
+        4.0,
+        SHADOW,
+    );
+    let stroke = Stroke::new(2.0, COLOR);
+    painter.line_segment([origin, end], stroke);
+    painter.line_segment([origin, origin + tick], stroke);
+    painter.line_segment([end, end + tick], stroke);
+    let label = if meters >= 1000.0 {
+        format!("{} km", meters / 1000.0)
+    } else {
+        format!("{} m", meters)
+    };
+    painter.text(
+        origin + Vec2::new(width / 2.0, -8.0),
+        Align2::CENTER_BOTTOM,
+        label,
+        FontId::proportional(12.0),
+        COLOR,
+    );
+}
+
+/// Largest 1, 2 or 5 times a power of ten not above `length`
+fn nice_length(length: f64) -> f64 {
+    let magnitude = 10f64.powf(length.log10().floor());
+    let leading = length / magnitude;
+    let step = if leading >= 5.0 {
+        5.0
+    } else if leading >= 2.0 {
+        2.0
+    } else {
+        1.0
+    };
+    step * magnitude
+}
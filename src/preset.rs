@@ -0,0 +1,156 @@
+use crate::{keys::KeyBindings, ramp::ColorRamp};
+use anyhow::*;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tracing::{debug, debug_span};
+
+/// User preferences persisted between runs as TOML
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Preset {
+    pub input: InputPreset,
+    pub imagery: ImageryPreset,
+    /// Hypsometric tint mixed into the imagery
+    pub tint: ColorRamp,
+    /// View to start from, pasted from `CameraPreset::to_toml`
+    pub camera: Option<CameraPreset>,
+    /// Views cycled through with `G`, dropped with `B`
+    pub bookmarks: Vec<CameraPreset>,
+    pub render: RenderPreset,
+    pub keys: KeyBindings,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InputPreset {
+    pub mouse_sensitivity: f32,
+    pub invert_y: bool,
+}
+
+impl Default for InputPreset {
+    fn default() -> Self {
+        Self {
+            mouse_sensitivity: 1.0,
+            invert_y: false,
+        }
+    }
+}
+
+/// Registration of the satellite imagery over the terrain
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ImageryPreset {
+    /// Offset in texture coordinates
+    pub offset: [f32; 2],
+    pub scale: f32,
+}
+
+impl Default for ImageryPreset {
+    fn default() -> Self {
+        Self {
+            offset: [0.0; 2],
+            scale: 1.0,
+        }
+    }
+}
+
+/// Render pass clears and depth convention, see `RenderSettings::clear_depth`
+/// and `RenderSettings::reversed_z`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RenderPreset {
+    /// Linear RGB, default `[0.1, 0.2, 0.3]`
+    pub clear_color: [f32; 3],
+    /// Default 1, the far plane
+    pub clear_depth: f32,
+    /// Default off
+    pub reversed_z: bool,
+}
+
+impl Default for RenderPreset {
+    fn default() -> Self {
+        Self {
+            clear_color: [0.1, 0.2, 0.3],
+            clear_depth: 1.0,
+            reversed_z: false,
+        }
+    }
+}
+
+/// Camera view, copied to the clipboard for sharing
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CameraPreset {
+    pub position: [f32; 3],
+    pub yaw_degrees: f32,
+    pub pitch_degrees: f32,
+    /// Vertical field of view
+    pub fovy_degrees: f32,
+}
+
+impl CameraPreset {
+    /// `[camera]` table that can be pasted into a preset file
+    pub fn to_toml(&self) -> Result<String> {
+        #[derive(Serialize)]
+        struct Snippet<'a> {
+            camera: &'a CameraPreset,
+        }
+
+        Ok(toml::to_string(&Snippet { camera: self })?)
+    }
+}
+
+/// Views rendered to PNG files by `--batch`, one `[[views]]` table each
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Batch {
+    pub views: Vec<BatchView>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchView {
+    /// Output file name without the extension
+    pub name: String,
+    pub camera: CameraPreset,
+}
+
+impl Batch {
+    pub fn load(path: &str) -> Result<Self> {
+        let span = debug_span!("Batch::load", path = path);
+        let _enter = span.enter();
+
+        let text =
+            std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path))?;
+        let batch: Self =
+            toml::from_str(&text).with_context(|| format!("Failed to parse {}", path))?;
+        debug!("Loaded {} views", batch.views.len());
+        Ok(batch)
+    }
+}
+
+impl Preset {
+    /// Reads a preset file, a missing file gives the default preset
+    pub fn load(path: &str) -> Result<Self> {
+        let span = debug_span!("Preset::load", path = path);
+        let _enter = span.enter();
+
+        if !Path::new(path).exists() {
+            debug!("No preset file, using defaults");
+            return Ok(Self::default());
+        }
+        let text =
+            std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path))?;
+        let preset = toml::from_str(&text).with_context(|| format!("Failed to parse {}", path))?;
+        debug!("Loaded preset: {:?}", preset);
+        Ok(preset)
+    }
+
+    pub fn save(&self, path: &str) -> Result<()> {
+        let span = debug_span!("Preset::save", path = path);
+        let _enter = span.enter();
+
+        let text = toml::to_string_pretty(self)?;
+        std::fs::write(path, text).with_context(|| format!("Failed to write {}", path))?;
+        debug!("Saved preset");
+        Ok(())
+    }
+}
@@ -0,0 +1,96 @@
+use tracing::trace;
+
+/// Largest width or height of the elevation preview
+const MAX_SIZE: usize = 512;
+
+/// The loaded imagery and the normalized elevation registered with egui as
+/// images, for spotting misloaded or misaligned data. The R32Float
+/// elevation can't be sampled by egui, so it is previewed through a
+/// downscaled grayscale copy.
+pub struct TexturePreviews {
+    pub diffuse: egui::TextureId,
+    pub elevation: egui::TextureId,
+    /// Width over height of the elevation, for sizing both previews
+    pub aspect: f32,
+    elevation_texture: egui_wgpu::wgpu::Texture,
+}
+
+impl TexturePreviews {
+    pub fn new(
+        device: &egui_wgpu::wgpu::Device,
+        queue: &egui_wgpu::wgpu::Queue,
+        egui: &mut crate::gui::EguiRenderer,
+        diffuse: &egui_wgpu::wgpu::TextureView,
+        normalized: &[f32],
+        [width, height]: [usize; 2],
+    ) -> Self {
+        let scale = (MAX_SIZE as f64 / width.max(height) as f64).min(1.0);
+        let size = egui_wgpu::wgpu::Extent3d {
+            width: ((width as f64 * scale) as u32).max(1),
+            height: ((height as f64 * scale) as u32).max(1),
+            depth_or_array_layers: 1,
+        };
+        let elevation_texture = device.create_texture(&egui_wgpu::wgpu::TextureDescriptor {
+            label: Some("Elevation Preview"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: egui_wgpu::wgpu::TextureDimension::D2,
+            format: egui_wgpu::wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: egui_wgpu::wgpu::TextureUsages::TEXTURE_BINDING
+                | egui_wgpu::wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let elevation_view =
+            elevation_texture.create_view(&egui_wgpu::wgpu::TextureViewDescriptor::default());
+        let previews = Self {
+            diffuse: egui.register_texture(device, diffuse, egui_wgpu::wgpu::FilterMode::Linear),
+            elevation: egui.register_texture(
+                device,
+                &elevation_view,
+                egui_wgpu::wgpu::FilterMode::Nearest,
+            ),
+            aspect: width as f32 / height as f32,
+            elevation_texture,
+        };
+        previews.write_elevation(queue, normalized, [width, height]);
+        trace!("Texture previews created at {}x{}", size.width, size.height);
+        previews
+    }
+
+    /// Updates the elevation preview from the normalized `width`x`height` elevation
+    pub fn write_elevation(
+        &self,
+        queue: &egui_wgpu::wgpu::Queue,
+        normalized: &[f32],
+        [width, height]: [usize; 2],
+    ) {
+        let size = self.elevation_texture.size();
+        let pixels: Vec<u8> = (0..size.height as usize)
+            .flat_map(|y| {
+                let row = y * height / size.height as usize;
+                (0..size.width as usize).flat_map(move |x| {
+                    let column = x * width / size.width as usize;
+                    let v = (normalized[row * width + column].clamp(0.0, 1.0) * 255.0) as u8;
+                    [v, v, v, 255]
+                })
+            })
+            .collect();
+        queue.write_texture(
+            self.elevation_texture.as_image_copy(),
+            &pixels,
+            egui_wgpu::wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(size.width * 4),
+                rows_per_image: Some(size.height),
+            },
+            size,
+        );
+    }
+
+    pub fn destroy(&self, egui: &mut crate::gui::EguiRenderer) {
+        egui.free_texture(&self.diffuse);
+        egui.free_texture(&self.elevation);
+        self.elevation_texture.destroy();
+    }
+}
@@ -0,0 +1,60 @@
+/// Polyline across the terrain in dataset coordinates (column, row)
+#[derive(Debug, Default, Clone)]
+pub struct Profile {
+    pub points: Vec<[f64; 2]>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ProfileSample {
+    /// Distance along the polyline in cells
+    pub distance: f64,
+    pub column: f64,
+    pub row: f64,
+    /// Elevation in meters
+    pub elevation: f64,
+}
+
+impl Profile {
+    /// Samples the elevation every `spacing` cells along the polyline, skipping
+    /// positions where `elevation_at` has no data
+    pub fn sample(
+        &self,
+        spacing: f64,
+        elevation_at: impl Fn(f64, f64) -> Option<f64>,
+    ) -> Vec<ProfileSample> {
+        let mut samples = Vec::new();
+        let mut start_distance = 0.0;
+        for (index, segment) in self.points.windows(2).enumerate() {
+            let [x0, y0] = segment[0];
+            let [x1, y1] = segment[1];
+            let length = ((x1 - x0).powi(2) + (y1 - y0).powi(2)).sqrt();
+            let steps = (length / spacing).ceil().max(1.0) as usize;
+            for step in 0..=steps {
+                // The first sample of a segment is the last one of the previous segment
+                if step == 0 && index > 0 {
+                    continue;
+                }
+                let t = step as f64 / steps as f64;
+                let column = x0 + (x1 - x0) * t;
+                let row = y0 + (y1 - y0) * t;
+                if let Some(elevation) = elevation_at(column, row) {
+                    samples.push(ProfileSample {
+                        distance: start_distance + length * t,
+                        column,
+                        row,
+                        elevation,
+                    });
+                }
+            }
+            start_distance += length;
+        }
+        samples
+    }
+
+    pub fn length(&self) -> f64 {
+        self.points
+            .windows(2)
+            .map(|s| ((s[1][0] - s[0][0]).powi(2) + (s[1][1] - s[0][1]).powi(2)).sqrt())
+            .sum()
+    }
+}
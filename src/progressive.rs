@@ -0,0 +1,138 @@
+use crate::gtiff;
+use gdal::Dataset;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+use tracing::{debug, debug_span, error, info, warn};
+
+/// Largest width or height of the first stage shown
+const COARSE_SIZE: usize = 512;
+/// Decimation of each stage over the next one
+const STAGE_STEP: usize = 4;
+
+/// DEM at one step of a coarse to fine load
+#[derive(Debug, Clone)]
+pub struct Stage {
+    pub path: String,
+    /// DEM pixels per stage pixel along each axis, 1 for the DEM itself
+    pub factor: usize,
+}
+
+/// Coarse to fine loading of a large DEM. A heavily decimated copy is shown
+/// right away while finer ones are written in the background, each is
+/// swapped in with `State::load_stage` when it is ready and the last one is
+/// the DEM itself.
+pub struct Progressive {
+    stages: Receiver<Stage>,
+    /// Decimation of the DEM currently shown
+    pub factor: usize,
+    /// Directory of this load's stages, removed with them on drop
+    dir: PathBuf,
+}
+
+impl Progressive {
+    /// Writes the coarsest stage of the DEM at `path` and starts on the finer
+    /// ones. `None` when the DEM is small enough to load directly.
+    pub fn start(path: &str) -> anyhow::Result<Option<(Stage, Self)>> {
+        let span = debug_span!("Progressive::start", path = path);
+        let _enter = span.enter();
+
+        let (width, height) = Dataset::open(path)?.raster_size();
+        let mut factor = 1;
+        while width.max(height).div_ceil(factor) > COARSE_SIZE {
+            factor *= 2;
+        }
+        if factor == 1 {
+            debug!("{}x{} is small enough to load directly", width, height);
+            return Ok(None);
+        }
+        let dir = create_stage_dir()?;
+        let first = Stage {
+            path: stage_path(&dir, path, factor),
+            factor,
+        };
+        gtiff::save_decimated(path, factor, &first.path)?;
+        info!("Showing {}x{} at 1/{} first", width, height, factor);
+
+        let (sender, stages) = channel();
+        let source = path.to_string();
+        let stage_dir = dir.clone();
+        std::thread::spawn(move || {
+            let mut factor = factor / STAGE_STEP;
+            while factor > 1 {
+                // Gone once the load was dropped
+                if !stage_dir.exists() {
+                    return;
+                }
+                let path = stage_path(&stage_dir, &source, factor);
+                if let Err(e) = gtiff::save_decimated(&source, factor, &path) {
+                    error!("Failed to write the 1/{} stage: {}", factor, e);
+                    break;
+                }
+                if sender.send(Stage { path, factor }).is_err() {
+                    return;
+                }
+                factor /= STAGE_STEP;
+            }
+            let _ = sender.send(Stage {
+                path: source,
+                factor: 1,
+            });
+        });
+        Ok(Some((
+            first.clone(),
+            Self {
+                stages,
+                factor,
+                dir,
+            },
+        )))
+    }
+
+    /// Finest stage that finished since the last call, stages that were
+    /// overtaken by a finer one are skipped
+    pub fn poll(&self) -> Option<Stage> {
+        self.stages.try_iter().last()
+    }
+}
+
+impl Drop for Progressive {
+    /// The full resolution stage is the DEM itself, the decimated ones
+    /// aren't needed once it is swapped in or the load is abandoned
+    fn drop(&mut self) {
+        match std::fs::remove_dir_all(&self.dir) {
+            Ok(()) => debug!("Removed stages in {}", self.dir.display()),
+            Err(e) => warn!("Failed to remove {}: {}", self.dir.display(), e),
+        }
+    }
+}
+
+/// New directory in the temp directory for the stages of one load, so loads
+/// of DEMs with the same name or in other instances don't share files
+fn create_stage_dir() -> std::io::Result<PathBuf> {
+    let seed = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |time| time.as_nanos());
+    let mut attempt = 0;
+    loop {
+        let dir = std::env::temp_dir().join(format!(
+            "terrain-render-stages-{}-{:x}",
+            std::process::id(),
+            seed + attempt
+        ));
+        match std::fs::create_dir(&dir) {
+            Ok(()) => return Ok(dir),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists && attempt < 16 => attempt += 1,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+fn stage_path(dir: &Path, path: &str, factor: usize) -> String {
+    let name = Path::new(path)
+        .file_stem()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "dem".to_string());
+    dir.join(format!("{}_{}.tif", name, factor))
+        .to_string_lossy()
+        .into_owned()
+}
@@ -0,0 +1,117 @@
+use egui_wgpu::wgpu::{Device, Queue, Texture};
+use serde::{Deserialize, Serialize};
+
+/// Texels of the lookup texture the ramp is sampled from in the shader
+pub const LUT_SIZE: u32 = 256;
+
+/// Color at a normalized elevation
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ColorStop {
+    /// Normalized elevation in [0, 1]
+    pub position: f32,
+    /// Linear RGB
+    pub color: [f32; 3],
+}
+
+/// Piecewise linear hypsometric tint, uploaded as a 1D lookup texture
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ColorRamp {
+    /// Sorted by position, see `sort`
+    pub stops: Vec<ColorStop>,
+}
+
+impl Default for ColorRamp {
+    fn default() -> Self {
+        Self {
+            stops: vec![
+                ColorStop {
+                    position: 0.0,
+                    color: [1.0, 0.0, 0.0],
+                },
+                ColorStop {
+                    position: 1.0,
+                    color: [0.0, 1.0, 0.0],
+                },
+            ],
+        }
+    }
+}
+
+impl ColorRamp {
+    /// Keeps the stops ordered after positions were edited
+    pub fn sort(&mut self) {
+        self.stops.sort_by(|a, b| a.position.total_cmp(&b.position));
+    }
+
+    /// Color at normalized elevation `t`, clamped to the first and last stop.
+    /// Expects sorted stops.
+    pub fn sample(&self, t: f32) -> [f32; 3] {
+        let (Some(first), Some(last)) = (self.stops.first(), self.stops.last()) else {
+            return [0.0; 3];
+        };
+        if t <= first.position {
+            return first.color;
+        }
+        for pair in self.stops.windows(2) {
+            let [a, b] = [pair[0], pair[1]];
+            if t <= b.position {
+                let f = if b.position > a.position {
+                    (t - a.position) / (b.position - a.position)
+                } else {
+                    1.0
+                };
+                return [0, 1, 2].map(|i| a.color[i] + (b.color[i] - a.color[i]) * f);
+            }
+        }
+        last.color
+    }
+
+    fn lut(&self) -> Vec<u8> {
+        (0..LUT_SIZE)
+            .flat_map(|i| {
+                let [r, g, b] = self.sample(i as f32 / (LUT_SIZE - 1) as f32);
+                [r, g, b, 1.0].map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8)
+            })
+            .collect()
+    }
+
+    pub fn create_texture(&self, device: &Device, queue: &Queue) -> Texture {
+        let texture = device.create_texture(&egui_wgpu::wgpu::TextureDescriptor {
+            label: Some("Tint Ramp"),
+            size: egui_wgpu::wgpu::Extent3d {
+                width: LUT_SIZE,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: egui_wgpu::wgpu::TextureDimension::D1,
+            format: egui_wgpu::wgpu::TextureFormat::Rgba8Unorm,
+            usage: egui_wgpu::wgpu::TextureUsages::TEXTURE_BINDING
+                | egui_wgpu::wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        self.write_texture(queue, &texture);
+        texture
+    }
+
+    /// Uploads the ramp into a texture made by `create_texture`
+    pub fn write_texture(&self, queue: &Queue, texture: &Texture) {
+        queue.write_texture(
+            egui_wgpu::wgpu::ImageCopyTexture {
+                aspect: egui_wgpu::wgpu::TextureAspect::All,
+                texture,
+                mip_level: 0,
+                origin: egui_wgpu::wgpu::Origin3d::ZERO,
+            },
+            &self.lut(),
+            egui_wgpu::wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * LUT_SIZE),
+                rows_per_image: None,
+            },
+            texture.size(),
+        );
+    }
+}
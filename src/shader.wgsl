@@ -2,27 +2,118 @@
 
 struct Camera {
     view_proj: mat4x4<f32>,
+    log_depth: f32,
+    _padding0: f32,
+    _padding1: f32,
+    _padding2: f32,
 }
 @group(1) @binding(0)
 var<uniform> camera: Camera;
 
+struct Light {
+    // Towards the light, w unused
+    direction: vec4<f32>,
+    // Color times intensity, w unused
+    color: vec4<f32>,
+}
+
+struct Settings {
+    opacity: f32,
+    show_no_data: u32,
+    lighting: u32,
+    contour_interval: f32,
+    light_count: u32,
+    normal_map: u32,
+    _padding0: f32,
+    _padding1: f32,
+    imagery_offset: vec2<f32>,
+    imagery_scale: f32,
+    underside: u32,
+    underside_color: vec4<f32>,
+    exposure: f32,
+    gamma: f32,
+    solid: u32,
+    isobands: u32,
+    solid_color: vec4<f32>,
+    // The sun first, then the fill lights
+    lights: array<Light, 4>,
+    // Box `PackedVertexInput` positions are fractions of, w unused
+    packing_min: vec4<f32>,
+    packing_extent: vec4<f32>,
+    // Transparent below `fade_start` and opaque above `fade_end`, world heights
+    fade: u32,
+    fade_start: f32,
+    fade_end: f32,
+    // Fragments outside the world [min x, min z, max x, max z] `clip_box` are discarded
+    clip: u32,
+    clip_box: vec4<f32>,
+}
+@group(2) @binding(0)
+var<uniform> settings: Settings;
+
 struct VertexInput {
     @location(0) position: vec3<f32>,
     @location(1) tex_coords: vec2<f32>,
+    @location(2) normal: vec3<f32>,
+    @location(3) color: vec4<f32>,
 }
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) tex_coords: vec2<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) world_height: f32,
+    @location(3) color: vec4<f32>,
+    @location(4) clip_w: f32,
+    @location(5) world_position: vec3<f32>,
+}
+
+// Logarithmic depth of a clip space w, see `CameraUniform::log_depth`
+fn logDepth(w: f32) -> f32 {
+    return log2(max(1e-6, 1.0 + w)) * camera.log_depth;
+}
+
+// `PackedVertex`, the normalized formats decode to these ranges
+struct PackedVertexInput {
+    @location(0) position: vec4<f32>,
+    @location(1) tex_coords: vec2<f32>,
+    @location(2) normal: vec4<f32>,
+    @location(3) color: vec4<f32>,
+}
+
+@vertex
+fn vs_packed(
+    packed: PackedVertexInput,
+) -> VertexOutput {
+    var model: VertexInput;
+    model.position = settings.packing_min.xyz + packed.position.xyz * settings.packing_extent.xyz;
+    model.tex_coords = packed.tex_coords;
+    model.normal = normalize(packed.normal.xyz);
+    model.color = packed.color;
+    return transformVertex(model);
 }
 
 @vertex
 fn vs_main(
     model: VertexInput,
 ) -> VertexOutput {
+    return transformVertex(model);
+}
+
+fn transformVertex(model: VertexInput) -> VertexOutput {
     var out: VertexOutput;
     out.tex_coords = model.tex_coords;
+    out.normal = model.normal;
+    out.world_height = model.position.y;
+    out.world_position = model.position;
+    out.color = model.color;
     out.clip_position = camera.view_proj * vec4<f32>(model.position, 1.0);
+    out.clip_w = out.clip_position.w;
+    // Approximate per vertex for clipping, the `_log_depth` fragment entry
+    // points write the exact value
+    if camera.log_depth > 0.0 {
+        out.clip_position.z = logDepth(out.clip_position.w) * out.clip_position.w;
+    }
     return out;
 }
 
@@ -35,6 +126,13 @@ fn vs_main(
 struct Dimensions {
     width: f32,
     height: f32,
+    indexed: u32,
+    encode_srgb: u32,
+    // Fraction of the DEM before the first pixel of the elevation, mask and
+    // normal textures, and DEM widths per texture width. Zero and one unless
+    // they hold one tile of a DEM over the texture limit.
+    tile_offset: vec2<f32>,
+    tile_scale: vec2<f32>,
 }
 
 @group(0) @binding(0)
@@ -47,39 +145,251 @@ var geo_texture: texture_2d<f32>;
 var geo_sampler: sampler;
 @group(0) @binding(4)
 var<uniform> u_dimensions: Dimensions;
+@group(0) @binding(5)
+var no_data_mask: texture_2d<f32>;
+@group(0) @binding(6)
+var tint_ramp: texture_1d<f32>;
+@group(0) @binding(7)
+var tint_sampler: sampler;
+// Per pixel normals, see `RenderSettings::normal_map`
+@group(0) @binding(8)
+var normal_texture: texture_2d<f32>;
+@group(0) @binding(9)
+var normal_sampler: sampler;
+
+// Cast shadows, see `shadow::ShadowMap`. Only the fragment stage reads the
+// group, the shadow pass renders with `vs_main` and no group 3.
+struct Shadow {
+    light_view_proj: mat4x4<f32>,
+    enabled: u32,
+    softness: u32,
+    bias: f32,
+    _padding0: f32,
+}
+@group(3) @binding(0)
+var<uniform> shadow: Shadow;
+@group(3) @binding(1)
+var shadow_map: texture_depth_2d;
+@group(3) @binding(2)
+var shadow_sampler: sampler_comparison;
+
+// Fraction of the sun reaching a world position, averaged over the PCF
+// kernel. Positions outside the map are lit.
+fn sunVisibility(world_position: vec3<f32>) -> f32 {
+    if shadow.enabled == 0u {
+        return 1.0;
+    }
+    let clip = shadow.light_view_proj * vec4<f32>(world_position, 1.0);
+    let ndc = clip.xyz / clip.w;
+    let uv = vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+    if any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) || ndc.z > 1.0 {
+        return 1.0;
+    }
+    let texel = 1.0 / vec2<f32>(textureDimensions(shadow_map));
+    let radius = i32(shadow.softness);
+    var lit = 0.0;
+    for (var y = -radius; y <= radius; y++) {
+        for (var x = -radius; x <= radius; x++) {
+            let offset = vec2<f32>(f32(x), f32(y)) * texel;
+            lit += textureSampleCompareLevel(shadow_map, shadow_sampler, uv + offset, ndc.z - shadow.bias);
+        }
+    }
+    let taps = 2 * radius + 1;
+    return lit / f32(taps * taps);
+}
+
+// DEM texture coordinates in the elevation, mask and normal textures
+fn tileUv(uv: vec2<f32>) -> vec2<f32> {
+    return (uv - u_dimensions.tile_offset) * u_dimensions.tile_scale;
+}
+
+fn isNoData(uv: vec2<f32>) -> bool {
+    let dims = vec2<i32>(textureDimensions(no_data_mask));
+    let texel = clamp(vec2<i32>(uv * vec2<f32>(dims)), vec2<i32>(0), dims - 1);
+    return textureLoad(no_data_mask, texel, 0).r > 0.5;
+}
+
+// `sun` scales the first light, the fill lights cast no shadows
+fn directionalLight(normal: vec3<f32>, sun: f32) -> vec3<f32> {
+    let ambient = 0.25;
+    let n = normalize(normal);
+    var diffuse = vec3<f32>(0.0);
+    for (var i = 0u; i < min(settings.light_count, 4u); i++) {
+        let light = settings.lights[i];
+        // Fade out as a light sets instead of dropping to ambient at once
+        var daylight = clamp(light.direction.y * 4.0, 0.0, 1.0);
+        if i == 0u {
+            daylight *= sun;
+        }
+        diffuse += light.color.rgb * max(dot(n, light.direction.xyz), 0.0) * daylight;
+    }
+    return ambient + (1.0 - ambient) * diffuse;
+}
+
+// Colors here are linear and encoded to sRGB afterwards, see `encodeOutput`.
+// Exposure scales linear light and gamma is applied in the encoded
+// space, converting there and back keeps the defaults an exact no-op.
+fn toneMap(color: vec3<f32>) -> vec3<f32> {
+    let exposed = max(color * exp2(settings.exposure), vec3<f32>(0.0));
+    if settings.gamma == 1.0 {
+        return exposed;
+    }
+    let encoded = linearToSrgb(min(exposed, vec3<f32>(1.0)));
+    return srgbToLinear(pow(encoded, vec3<f32>(1.0 / settings.gamma)));
+}
+
+// On a linear surface nothing encodes after the shader, so it does it here
+fn encodeOutput(color: vec3<f32>) -> vec3<f32> {
+    if u_dimensions.encode_srgb != 0u {
+        return linearToSrgb(clamp(color, vec3<f32>(0.0), vec3<f32>(1.0)));
+    }
+    return color;
+}
+
+fn linearToSrgb(c: vec3<f32>) -> vec3<f32> {
+    return select(1.055 * pow(c, vec3<f32>(1.0 / 2.4)) - 0.055, c * 12.92, c <= vec3<f32>(0.0031308));
+}
+
+fn srgbToLinear(c: vec3<f32>) -> vec3<f32> {
+    return select(pow((c + 0.055) / 1.055, vec3<f32>(2.4)), c / 12.92, c <= vec3<f32>(0.04045));
+}
 
 fn smoothTintBlend(height: f32) -> vec3<f32> {
-    return mix(vec3<f32>(1.0, 0.0, 0.0),
-               vec3<f32>(0.0, 1.0, 0.0),
-               height);
+    return textureSample(tint_ramp, tint_sampler, height).rgb;
+}
+
+// Ramp color at the middle of the band `height` falls in, for flat classes
+// with sharp boundaries
+fn isobandColor(height: f32) -> vec3<f32> {
+    let count = f32(settings.isobands);
+    let band = min(floor(clamp(height, 0.0, 1.0) * count), count - 1.0);
+    return textureSample(tint_ramp, tint_sampler, (band + 0.5) / count).rgb;
 }
 
+// Filters the non-filterable R32Float elevation by hand, from the mip level
+// matching the screen space footprint of `uv`
 fn bilinearSample(texture: texture_2d<f32>, smp: sampler, uv: vec2<f32>) -> f32 {
-    let tex_size = textureDimensions(texture);
+    let base_size = vec2<f32>(textureDimensions(texture));
+    let footprint = max(length(dpdx(uv * base_size)), length(dpdy(uv * base_size)));
+    let level = clamp(i32(floor(log2(max(footprint, 1.0)))), 0, i32(textureNumLevels(texture)) - 1);
+    let tex_size = textureDimensions(texture, level);
     let texel_size = 1.0 / vec2<f32>(tex_size);
 
     let uv_texel = uv * vec2<f32>(tex_size);
     let i_uv = floor(uv_texel);
     let f_uv = fract(uv_texel);
 
-    let tl = textureSample(texture, smp, (i_uv + vec2<f32>(0.0, 0.0)) * texel_size).r;
-    let tr = textureSample(texture, smp, (i_uv + vec2<f32>(1.0, 0.0)) * texel_size).r;
-    let bl = textureSample(texture, smp, (i_uv + vec2<f32>(0.0, 1.0)) * texel_size).r;
-    let br = textureSample(texture, smp, (i_uv + vec2<f32>(1.0, 1.0)) * texel_size).r;
+    let tl = textureSampleLevel(texture, smp, (i_uv + vec2<f32>(0.0, 0.0)) * texel_size, f32(level)).r;
+    let tr = textureSampleLevel(texture, smp, (i_uv + vec2<f32>(1.0, 0.0)) * texel_size, f32(level)).r;
+    let bl = textureSampleLevel(texture, smp, (i_uv + vec2<f32>(0.0, 1.0)) * texel_size, f32(level)).r;
+    let br = textureSampleLevel(texture, smp, (i_uv + vec2<f32>(1.0, 1.0)) * texel_size, f32(level)).r;
 
     let t = mix(tl, tr, f_uv.x);
     let b = mix(bl, br, f_uv.x);
     return mix(t, b, f_uv.y);
 }
 
+struct LogDepthOutput {
+    @location(0) color: vec4<f32>,
+    @builtin(frag_depth) depth: f32,
+}
+
+@fragment
+fn fs_main(in: VertexOutput, @builtin(front_facing) front_facing: bool) -> @location(0) vec4<f32> {
+    return shade(in, front_facing);
+}
+
+@fragment
+fn fs_main_log_depth(in: VertexOutput, @builtin(front_facing) front_facing: bool) -> LogDepthOutput {
+    var out: LogDepthOutput;
+    out.color = shade(in, front_facing);
+    out.depth = logDepth(in.clip_w);
+    return out;
+}
+
 @fragment
-fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    let base_color = textureSample(t_diffuse, s_diffuse, in.tex_coords).rgb;
-    let height = textureSample(geo_texture, geo_sampler, in.tex_coords).r;
-    let int_height = bilinearSample(geo_texture, geo_sampler, in.tex_coords);
-    let tint_color = smoothTintBlend(int_height);
-    let final_color = mix(base_color, tint_color, 0.1);
-    return vec4<f32>(final_color, 1.0);
+fn fs_contour(in: VertexOutput) -> @location(0) vec4<f32> {
+    return contour(in);
+}
+
+@fragment
+fn fs_contour_log_depth(in: VertexOutput) -> LogDepthOutput {
+    var out: LogDepthOutput;
+    out.color = contour(in);
+    out.depth = logDepth(in.clip_w);
+    return out;
+}
+
+// Terrain alpha at a world height, the opacity faded out below `fade_start`
+fn alpha(world_height: f32) -> f32 {
+    if settings.fade == 0u {
+        return settings.opacity;
+    }
+    var fade = f32(world_height >= settings.fade_start);
+    // smoothstep is undefined for reversed edges
+    if settings.fade_end > settings.fade_start {
+        fade = smoothstep(settings.fade_start, settings.fade_end, world_height);
+    }
+    return fade * settings.opacity;
+}
+
+fn isClipped(world_position: vec3<f32>) -> bool {
+    let xz = world_position.xz;
+    return settings.clip != 0u && (any(xz < settings.clip_box.xy) || any(xz > settings.clip_box.zw));
+}
+
+fn shade(in: VertexOutput, front_facing: bool) -> vec4<f32> {
+    if isClipped(in.world_position) {
+        discard;
+    }
+    if settings.underside != 0u && !front_facing {
+        // Flat so it can't be mistaken for the lit surface
+        return vec4<f32>(encodeOutput(toneMap(settings.underside_color.rgb)), alpha(in.world_height));
+    }
+    // Manual registration of the imagery, scaled around the terrain center
+    let imagery_uv = (in.tex_coords - 0.5) * settings.imagery_scale + 0.5 + settings.imagery_offset;
+    var final_color = settings.solid_color.rgb;
+    // Uniform branch, the textures aren't sampled at all in solid mode
+    if settings.solid == 0u {
+        let base_color = textureSample(t_diffuse, s_diffuse, imagery_uv).rgb;
+        let geo_color = textureSample(geo_texture, geo_sampler, tileUv(in.tex_coords));
+        let int_height = bilinearSample(geo_texture, geo_sampler, tileUv(in.tex_coords));
+        let tint_color = smoothTintBlend(int_height);
+        final_color = mix(base_color, tint_color, 0.1);
+        if settings.isobands != 0u {
+            final_color = isobandColor(int_height);
+        }
+        if u_dimensions.indexed != 0u {
+            // Thematic raster, show its color table instead of the imagery
+            final_color = geo_color.rgb;
+        }
+    }
+    final_color *= in.color.rgb;
+    if settings.lighting != 0u {
+        var normal = in.normal;
+        if settings.normal_map != 0u {
+            normal = textureSample(normal_texture, normal_sampler, tileUv(in.tex_coords)).xyz;
+        }
+        final_color *= directionalLight(normal, sunVisibility(in.world_position));
+    }
+    if settings.show_no_data != 0u && isNoData(tileUv(in.tex_coords)) {
+        final_color = vec3<f32>(1.0, 0.0, 1.0);
+    }
+    return vec4<f32>(encodeOutput(toneMap(final_color)), alpha(in.world_height));
+}
+
+fn contour(in: VertexOutput) -> vec4<f32> {
+    if isClipped(in.world_position) {
+        discard;
+    }
+    // Distance to the nearest contour in screen pixels, for constant width lines
+    let level = in.world_height / settings.contour_interval;
+    let distance = abs(fract(level - 0.5) - 0.5) / fwidth(level);
+    let coverage = 1.0 - clamp(distance - 0.5, 0.0, 1.0);
+    if coverage <= 0.0 {
+        discard;
+    }
+    return vec4<f32>(encodeOutput(vec3<f32>(0.1)), coverage * alpha(in.world_height));
 }
 
 // @fragment
@@ -0,0 +1,213 @@
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Point3, Vector3};
+use tracing::trace;
+
+/// Resolutions the shadow map can be rendered at
+pub const RESOLUTIONS: [u32; 4] = [512, 1024, 2048, 4096];
+/// Offset of the compared depth against shadow acne, in shadow map depth
+pub const BIAS: f32 = 0.001;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ShadowUniform {
+    /// World to shadow map clip space, see `light_view_proj`
+    pub light_view_proj: [[f32; 4]; 4],
+    /// Non-zero when the sun casts shadows, the map isn't sampled otherwise
+    pub enabled: u32,
+    /// Radius of the PCF kernel in texels, 0 samples once
+    pub softness: u32,
+    pub bias: f32,
+    pub _padding: f32,
+}
+
+/// Orthographic projection along `direction`, towards the sun, that fits the
+/// box between `min` and `max` from any direction
+pub fn light_view_proj(
+    direction: Vector3<f32>,
+    min: Point3<f32>,
+    max: Point3<f32>,
+) -> Matrix4<f32> {
+    let center = min.midpoint(max);
+    let radius = ((max - min).magnitude() / 2.0).max(f32::EPSILON);
+    let direction = direction.normalize();
+    let up = if direction.y.abs() > 0.99 {
+        Vector3::unit_z()
+    } else {
+        Vector3::unit_y()
+    };
+    let view = Matrix4::look_at_rh(center + direction * radius * 2.0, center, up);
+    let projection = cgmath::ortho(-radius, radius, -radius, radius, radius, radius * 3.0);
+    crate::camera::OPENGL_TO_WGPU_MATRIX * projection * view
+}
+
+/// Depth of the terrain seen from the sun, sampled by the terrain shader for
+/// cast shadows
+pub struct ShadowMap {
+    texture: egui_wgpu::wgpu::Texture,
+    view: egui_wgpu::wgpu::TextureView,
+    sampler: egui_wgpu::wgpu::Sampler,
+    bind_group_layout: egui_wgpu::wgpu::BindGroupLayout,
+    uniform_buffer: egui_wgpu::wgpu::Buffer,
+    bind_group: egui_wgpu::wgpu::BindGroup,
+}
+
+impl ShadowMap {
+    pub fn new(device: &egui_wgpu::wgpu::Device, resolution: u32) -> Self {
+        let bind_group_layout =
+            device.create_bind_group_layout(&egui_wgpu::wgpu::BindGroupLayoutDescriptor {
+                label: Some("Shadow Bind Group Layout"),
+                entries: &[
+                    egui_wgpu::wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: egui_wgpu::wgpu::ShaderStages::FRAGMENT,
+                        ty: egui_wgpu::wgpu::BindingType::Buffer {
+                            ty: egui_wgpu::wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    egui_wgpu::wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: egui_wgpu::wgpu::ShaderStages::FRAGMENT,
+                        ty: egui_wgpu::wgpu::BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: egui_wgpu::wgpu::TextureViewDimension::D2,
+                            sample_type: egui_wgpu::wgpu::TextureSampleType::Depth,
+                        },
+                        count: None,
+                    },
+                    egui_wgpu::wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: egui_wgpu::wgpu::ShaderStages::FRAGMENT,
+                        ty: egui_wgpu::wgpu::BindingType::Sampler(
+                            egui_wgpu::wgpu::SamplerBindingType::Comparison,
+                        ),
+                        count: None,
+                    },
+                ],
+            });
+        let uniform_buffer = device.create_buffer(&egui_wgpu::wgpu::BufferDescriptor {
+            label: Some("Shadow Buffer"),
+            size: std::mem::size_of::<ShadowUniform>() as egui_wgpu::wgpu::BufferAddress,
+            usage: egui_wgpu::wgpu::BufferUsages::UNIFORM | egui_wgpu::wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        // Linear filtering compares the four nearest texels, smoothing the
+        // edges on its own
+        let sampler = device.create_sampler(&egui_wgpu::wgpu::SamplerDescriptor {
+            label: Some("Shadow Sampler"),
+            address_mode_u: egui_wgpu::wgpu::AddressMode::ClampToEdge,
+            address_mode_v: egui_wgpu::wgpu::AddressMode::ClampToEdge,
+            address_mode_w: egui_wgpu::wgpu::AddressMode::ClampToEdge,
+            mag_filter: egui_wgpu::wgpu::FilterMode::Linear,
+            min_filter: egui_wgpu::wgpu::FilterMode::Linear,
+            mipmap_filter: egui_wgpu::wgpu::FilterMode::Nearest,
+            compare: Some(egui_wgpu::wgpu::CompareFunction::LessEqual),
+            ..Default::default()
+        });
+        let (texture, view) = create_texture(device, resolution);
+        let bind_group =
+            create_bind_group(device, &bind_group_layout, &uniform_buffer, &view, &sampler);
+        trace!("Shadow map created at {}px", resolution);
+
+        Self {
+            texture,
+            view,
+            sampler,
+            bind_group_layout,
+            uniform_buffer,
+            bind_group,
+        }
+    }
+
+    /// Layout of the bind group the terrain shader samples the map through
+    pub fn bind_group_layout(&self) -> &egui_wgpu::wgpu::BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    pub fn bind_group(&self) -> &egui_wgpu::wgpu::BindGroup {
+        &self.bind_group
+    }
+
+    /// Depth attachment of the shadow pass
+    pub fn view(&self) -> &egui_wgpu::wgpu::TextureView {
+        &self.view
+    }
+
+    pub fn resolution(&self) -> u32 {
+        self.texture.width()
+    }
+
+    /// Recreates the map at `resolution` texels square
+    pub fn set_resolution(&mut self, device: &egui_wgpu::wgpu::Device, resolution: u32) {
+        self.texture.destroy();
+        (self.texture, self.view) = create_texture(device, resolution);
+        self.bind_group = create_bind_group(
+            device,
+            &self.bind_group_layout,
+            &self.uniform_buffer,
+            &self.view,
+            &self.sampler,
+        );
+        trace!("Shadow map resized to {}px", resolution);
+    }
+
+    pub fn prepare(&self, queue: &egui_wgpu::wgpu::Queue, uniform: ShadowUniform) {
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    pub fn destroy(&self) {
+        self.texture.destroy();
+        self.uniform_buffer.destroy();
+    }
+}
+
+fn create_texture(
+    device: &egui_wgpu::wgpu::Device,
+    resolution: u32,
+) -> (egui_wgpu::wgpu::Texture, egui_wgpu::wgpu::TextureView) {
+    let texture = device.create_texture(&egui_wgpu::wgpu::TextureDescriptor {
+        label: Some("Shadow Map"),
+        size: egui_wgpu::wgpu::Extent3d {
+            width: resolution,
+            height: resolution,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: egui_wgpu::wgpu::TextureDimension::D2,
+        format: crate::texture::Texture::DEPTH_FORMAT,
+        usage: egui_wgpu::wgpu::TextureUsages::RENDER_ATTACHMENT
+            | egui_wgpu::wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&egui_wgpu::wgpu::TextureViewDescriptor::default());
+    (texture, view)
+}
+
+fn create_bind_group(
+    device: &egui_wgpu::wgpu::Device,
+    layout: &egui_wgpu::wgpu::BindGroupLayout,
+    uniform_buffer: &egui_wgpu::wgpu::Buffer,
+    view: &egui_wgpu::wgpu::TextureView,
+    sampler: &egui_wgpu::wgpu::Sampler,
+) -> egui_wgpu::wgpu::BindGroup {
+    device.create_bind_group(&egui_wgpu::wgpu::BindGroupDescriptor {
+        label: Some("Shadow Bind Group"),
+        layout,
+        entries: &[
+            egui_wgpu::wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            },
+            egui_wgpu::wgpu::BindGroupEntry {
+                binding: 1,
+                resource: egui_wgpu::wgpu::BindingResource::TextureView(view),
+            },
+            egui_wgpu::wgpu::BindGroupEntry {
+                binding: 2,
+                resource: egui_wgpu::wgpu::BindingResource::Sampler(sampler),
+            },
+        ],
+    })
+}
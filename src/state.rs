@@ -1,7 +1,28 @@
-use crate::{camera, gui, texture};
+use crate::{
+    blit, camera,
+    capabilities::Capabilities,
+    config::{Config, DemSource},
+    download,
+    gtiff::{self, ElevationUnit},
+    gui,
+    keys::{Action, KeyBindings},
+    light, lod, morph, outline, overlay, preset, preview, profile, progressive, ramp, shadow,
+    terrain, texture, track, water,
+};
+use anyhow::Context;
+use cgmath::{EuclideanSpace, InnerSpace, SquareMatrix};
 use egui_wgpu::wgpu::util::DeviceExt;
-use egui_winit::winit::{event::*, keyboard::PhysicalKey, window::Window};
-use tracing::{debug, debug_span, error, trace};
+use egui_winit::winit::{
+    dpi::PhysicalPosition,
+    event::*,
+    keyboard::PhysicalKey,
+    window::{CursorGrabMode, Window},
+};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+use tracing::{debug, debug_span, error, info, trace, warn};
 
 #[rustfmt::skip]
 pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
@@ -16,6 +37,240 @@ pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
 struct Dimensions {
     width: f32,
     height: f32,
+    /// Non-zero when the GeoTIFF texture holds color table colors
+    indexed: u32,
+    /// Non-zero when the surface isn't sRGB and the shader has to encode
+    encode_srgb: u32,
+    /// Where the elevation, mask and normal textures start in the DEM's
+    /// texture coordinates, and their scale, see `Dimensions::of_tile`
+    tile_offset: [f32; 2],
+    tile_scale: [f32; 2],
+}
+
+impl Dimensions {
+    /// The dimensions of a whole DEM, whose textures span it
+    fn new(width: usize, height: usize, indexed: bool, encode_srgb: bool) -> Self {
+        Self {
+            width: width as f32,
+            height: height as f32,
+            indexed: indexed as u32,
+            encode_srgb: encode_srgb as u32,
+            tile_offset: [0.0; 2],
+            tile_scale: [1.0; 2],
+        }
+    }
+
+    /// The dimensions with textures spanning only `tile` of the DEM
+    fn of_tile(self, tile: &gtiff::ElevationTile) -> Self {
+        let ([column, row], [width, height]) = (tile.offset, tile.size);
+        Self {
+            tile_offset: [column as f32 / self.width, row as f32 / self.height],
+            tile_scale: [self.width / width as f32, self.height / height as f32],
+            ..self
+        }
+    }
+}
+
+/// DEM-sized texture `update_normal_map` fills once the option is turned on
+fn create_normal_texture(
+    device: &egui_wgpu::wgpu::Device,
+    size: egui_wgpu::wgpu::Extent3d,
+) -> egui_wgpu::wgpu::Texture {
+    device.create_texture(&egui_wgpu::wgpu::TextureDescriptor {
+        label: Some("Normal Map"),
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: egui_wgpu::wgpu::TextureDimension::D2,
+        format: egui_wgpu::wgpu::TextureFormat::Rgba8Snorm,
+        usage: egui_wgpu::wgpu::TextureUsages::TEXTURE_BINDING
+            | egui_wgpu::wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    })
+}
+
+/// Bind group 0 of the terrain pipelines, laid out like
+/// `texture_bind_group_layout`
+#[allow(clippy::too_many_arguments)]
+fn terrain_bind_group(
+    device: &egui_wgpu::wgpu::Device,
+    layout: &egui_wgpu::wgpu::BindGroupLayout,
+    diffuse_texture: &texture::Texture,
+    elevation_texture: &egui_wgpu::wgpu::Texture,
+    no_data_mask: &egui_wgpu::wgpu::Texture,
+    tint_ramp_texture: &egui_wgpu::wgpu::Texture,
+    normal_texture: &egui_wgpu::wgpu::Texture,
+    dimensions: Dimensions,
+) -> egui_wgpu::wgpu::BindGroup {
+    let elevation_view =
+        elevation_texture.create_view(&egui_wgpu::wgpu::TextureViewDescriptor::default());
+    let elevation_sampler = device.create_sampler(&egui_wgpu::wgpu::SamplerDescriptor {
+        address_mode_u: egui_wgpu::wgpu::AddressMode::ClampToEdge,
+        address_mode_v: egui_wgpu::wgpu::AddressMode::ClampToEdge,
+        address_mode_w: egui_wgpu::wgpu::AddressMode::ClampToEdge,
+        mag_filter: egui_wgpu::wgpu::FilterMode::Nearest,
+        min_filter: egui_wgpu::wgpu::FilterMode::Nearest,
+        mipmap_filter: egui_wgpu::wgpu::FilterMode::Nearest,
+        ..Default::default()
+    });
+    let no_data_mask_view =
+        no_data_mask.create_view(&egui_wgpu::wgpu::TextureViewDescriptor::default());
+    let tint_ramp_view =
+        tint_ramp_texture.create_view(&egui_wgpu::wgpu::TextureViewDescriptor::default());
+    let tint_ramp_sampler = device.create_sampler(&egui_wgpu::wgpu::SamplerDescriptor {
+        address_mode_u: egui_wgpu::wgpu::AddressMode::ClampToEdge,
+        mag_filter: egui_wgpu::wgpu::FilterMode::Linear,
+        min_filter: egui_wgpu::wgpu::FilterMode::Linear,
+        ..Default::default()
+    });
+    let normal_view =
+        normal_texture.create_view(&egui_wgpu::wgpu::TextureViewDescriptor::default());
+    let normal_sampler = device.create_sampler(&egui_wgpu::wgpu::SamplerDescriptor {
+        mag_filter: egui_wgpu::wgpu::FilterMode::Linear,
+        min_filter: egui_wgpu::wgpu::FilterMode::Linear,
+        ..Default::default()
+    });
+    device.create_bind_group(&egui_wgpu::wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[
+            egui_wgpu::wgpu::BindGroupEntry {
+                binding: 0,
+                resource: egui_wgpu::wgpu::BindingResource::TextureView(&diffuse_texture.view),
+            },
+            egui_wgpu::wgpu::BindGroupEntry {
+                binding: 1,
+                resource: egui_wgpu::wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
+            },
+            egui_wgpu::wgpu::BindGroupEntry {
+                binding: 2,
+                resource: egui_wgpu::wgpu::BindingResource::TextureView(&elevation_view),
+            },
+            egui_wgpu::wgpu::BindGroupEntry {
+                binding: 3,
+                resource: egui_wgpu::wgpu::BindingResource::Sampler(&elevation_sampler),
+            },
+            egui_wgpu::wgpu::BindGroupEntry {
+                binding: 4,
+                resource: egui_wgpu::wgpu::BindingResource::Buffer(
+                    egui_wgpu::wgpu::BufferBinding {
+                        buffer: &device.create_buffer_init(
+                            &egui_wgpu::wgpu::util::BufferInitDescriptor {
+                                label: Some("Dimensions Buffer"),
+                                contents: bytemuck::cast_slice(&[dimensions]),
+                                usage: egui_wgpu::wgpu::BufferUsages::UNIFORM
+                                    | egui_wgpu::wgpu::BufferUsages::COPY_DST,
+                            },
+                        ),
+                        offset: 0,
+                        size: None,
+                    },
+                ),
+            },
+            egui_wgpu::wgpu::BindGroupEntry {
+                binding: 5,
+                resource: egui_wgpu::wgpu::BindingResource::TextureView(&no_data_mask_view),
+            },
+            egui_wgpu::wgpu::BindGroupEntry {
+                binding: 6,
+                resource: egui_wgpu::wgpu::BindingResource::TextureView(&tint_ramp_view),
+            },
+            egui_wgpu::wgpu::BindGroupEntry {
+                binding: 7,
+                resource: egui_wgpu::wgpu::BindingResource::Sampler(&tint_ramp_sampler),
+            },
+            egui_wgpu::wgpu::BindGroupEntry {
+                binding: 8,
+                resource: egui_wgpu::wgpu::BindingResource::TextureView(&normal_view),
+            },
+            egui_wgpu::wgpu::BindGroupEntry {
+                binding: 9,
+                resource: egui_wgpu::wgpu::BindingResource::Sampler(&normal_sampler),
+            },
+        ],
+        label: Some("diffuse_bind_group"),
+    })
+}
+
+/// Bind group, buffers and counts of a part of the terrain mesh, see
+/// `State::scene_mesh`
+struct MeshDraw<'a> {
+    bind_group: &'a egui_wgpu::wgpu::BindGroup,
+    vertex_buffer: &'a egui_wgpu::wgpu::Buffer,
+    num_vertices: u32,
+    index_buffer: &'a egui_wgpu::wgpu::Buffer,
+    num_indices: u32,
+}
+
+/// Textures and full resolution mesh of one `gtiff::ElevationTile`, the
+/// tiles of a DEM over the texture limit are drawn one after another
+struct TerrainTile {
+    elevation: gtiff::ElevationTile,
+    /// Tile-sized `State::normal_texture`
+    normal_texture: egui_wgpu::wgpu::Texture,
+    /// `State::diffuse_bind_group` with the tile's textures
+    bind_group: egui_wgpu::wgpu::BindGroup,
+    /// Filled by `State::rebuild_mesh`
+    vertex_buffer: egui_wgpu::wgpu::Buffer,
+    index_buffer: egui_wgpu::wgpu::Buffer,
+    list_index_buffer: egui_wgpu::wgpu::Buffer,
+    num_vertices: u32,
+    num_indices: u32,
+    num_list_indices: u32,
+}
+
+impl TerrainTile {
+    /// `dimensions` are the whole DEM's
+    fn new(
+        device: &egui_wgpu::wgpu::Device,
+        layout: &egui_wgpu::wgpu::BindGroupLayout,
+        diffuse_texture: &texture::Texture,
+        tint_ramp_texture: &egui_wgpu::wgpu::Texture,
+        elevation: gtiff::ElevationTile,
+        dimensions: Dimensions,
+    ) -> Self {
+        let normal_texture = create_normal_texture(device, elevation.texture.size());
+        let bind_group = terrain_bind_group(
+            device,
+            layout,
+            diffuse_texture,
+            &elevation.texture,
+            &elevation.no_data_mask,
+            tint_ramp_texture,
+            &normal_texture,
+            dimensions.of_tile(&elevation),
+        );
+        let buffer = |label: &str, usage: egui_wgpu::wgpu::BufferUsages| {
+            device.create_buffer(&egui_wgpu::wgpu::BufferDescriptor {
+                label: Some(label),
+                size: egui_wgpu::wgpu::COPY_BUFFER_ALIGNMENT,
+                usage: usage | egui_wgpu::wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            })
+        };
+        Self {
+            elevation,
+            normal_texture,
+            bind_group,
+            vertex_buffer: buffer("Tile Vertex Buffer", egui_wgpu::wgpu::BufferUsages::VERTEX),
+            index_buffer: buffer("Tile Index Buffer", egui_wgpu::wgpu::BufferUsages::INDEX),
+            list_index_buffer: buffer(
+                "Tile List Index Buffer",
+                egui_wgpu::wgpu::BufferUsages::INDEX,
+            ),
+            num_vertices: 0,
+            num_indices: 0,
+            num_list_indices: 0,
+        }
+    }
+
+    fn destroy(&self) {
+        self.elevation.texture.destroy();
+        self.elevation.no_data_mask.destroy();
+        self.normal_texture.destroy();
+        self.vertex_buffer.destroy();
+        self.index_buffer.destroy();
+        self.list_index_buffer.destroy();
+    }
 }
 
 #[repr(C)]
@@ -23,6 +278,9 @@ struct Dimensions {
 pub struct Vertex {
     pub position: [f32; 3],
     pub tex_coords: [f32; 2],
+    pub normal: [f32; 3],
+    /// Multiplied with the textured color, white leaves it unchanged
+    pub color: [f32; 4],
 }
 
 impl Vertex {
@@ -42,6 +300,118 @@ impl Vertex {
                     shader_location: 1,
                     format: egui_wgpu::wgpu::VertexFormat::Float32x2,
                 },
+                egui_wgpu::wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 5]>() as egui_wgpu::wgpu::BufferAddress,
+                    shader_location: 2,
+                    format: egui_wgpu::wgpu::VertexFormat::Float32x3,
+                },
+                egui_wgpu::wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 8]>() as egui_wgpu::wgpu::BufferAddress,
+                    shader_location: 3,
+                    format: egui_wgpu::wgpu::VertexFormat::Float32x4,
+                },
+            ],
+        }
+    }
+}
+
+/// World space box `PackedVertex` positions are quantized in
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct PackingBounds {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+}
+
+impl PackingBounds {
+    pub fn of(vertices: &[Vertex]) -> Self {
+        let mut bounds = Self {
+            min: [f32::INFINITY; 3],
+            max: [f32::NEG_INFINITY; 3],
+        };
+        for vertex in vertices {
+            for i in 0..3 {
+                bounds.min[i] = bounds.min[i].min(vertex.position[i]);
+                bounds.max[i] = bounds.max[i].max(vertex.position[i]);
+            }
+        }
+        if vertices.is_empty() {
+            bounds = Self::default();
+        }
+        bounds
+    }
+
+    /// Smallest box holding both
+    pub fn union(self, other: Self) -> Self {
+        Self {
+            min: [0, 1, 2].map(|i| self.min[i].min(other.min[i])),
+            max: [0, 1, 2].map(|i| self.max[i].max(other.max[i])),
+        }
+    }
+
+    /// Size of the box, never zero so the quantization can divide by it
+    fn extent(&self) -> [f32; 3] {
+        [0, 1, 2].map(|i| (self.max[i] - self.min[i]).max(f32::EPSILON))
+    }
+}
+
+/// `Vertex` quantized from 48 to 20 bytes, see `RenderSettings::packed_vertices`.
+/// Positions are 16 bit fractions of a `PackingBounds` the shader gets in the
+/// settings uniform, texture coordinates 16 bit fractions too as they lie in
+/// [0, 1], normals and colors 8 bit.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct PackedVertex {
+    /// w unused
+    pub position: [u16; 4],
+    pub tex_coords: [u16; 2],
+    /// w unused
+    pub normal: [i8; 4],
+    pub color: [u8; 4],
+}
+
+impl PackedVertex {
+    /// Positions outside `bounds` are clamped to them
+    pub fn pack(vertex: &Vertex, bounds: &PackingBounds) -> Self {
+        let unorm16 = |v: f32| (v.clamp(0.0, 1.0) * u16::MAX as f32).round() as u16;
+        let snorm8 = |v: f32| (v.clamp(-1.0, 1.0) * i8::MAX as f32).round() as i8;
+        let unorm8 = |v: f32| (v.clamp(0.0, 1.0) * u8::MAX as f32).round() as u8;
+        let extent = bounds.extent();
+        let [x, y, z] =
+            [0, 1, 2].map(|i| unorm16((vertex.position[i] - bounds.min[i]) / extent[i]));
+        let [nx, ny, nz] = vertex.normal.map(snorm8);
+        Self {
+            position: [x, y, z, 0],
+            tex_coords: vertex.tex_coords.map(unorm16),
+            normal: [nx, ny, nz, 0],
+            color: vertex.color.map(unorm8),
+        }
+    }
+
+    fn desc() -> egui_wgpu::wgpu::VertexBufferLayout<'static> {
+        egui_wgpu::wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<PackedVertex>() as egui_wgpu::wgpu::BufferAddress,
+            step_mode: egui_wgpu::wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                egui_wgpu::wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: egui_wgpu::wgpu::VertexFormat::Unorm16x4,
+                },
+                egui_wgpu::wgpu::VertexAttribute {
+                    offset: 8,
+                    shader_location: 1,
+                    format: egui_wgpu::wgpu::VertexFormat::Unorm16x2,
+                },
+                egui_wgpu::wgpu::VertexAttribute {
+                    offset: 12,
+                    shader_location: 2,
+                    format: egui_wgpu::wgpu::VertexFormat::Snorm8x4,
+                },
+                egui_wgpu::wgpu::VertexAttribute {
+                    offset: 16,
+                    shader_location: 3,
+                    format: egui_wgpu::wgpu::VertexFormat::Unorm8x4,
+                },
             ],
         }
     }
@@ -51,20 +421,561 @@ impl Vertex {
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 struct CameraUniform {
     view_proj: [[f32; 4]; 4],
+    /// 1 / log2(zfar + 1) with logarithmic depth, 0 for the standard depth
+    log_depth: f32,
+    _padding: [f32; 3],
 }
 
 impl CameraUniform {
     fn new() -> Self {
-        use cgmath::SquareMatrix;
-
         Self {
             view_proj: cgmath::Matrix4::identity().into(),
+            log_depth: 0.0,
+            _padding: [0.0; 3],
+        }
+    }
+
+    /// `reversed_z` maps the near plane to depth 1 and the far plane to 0,
+    /// see `RenderSettings::reversed_z`
+    fn update_view_proj(
+        &mut self,
+        camera: &camera::Camera,
+        projection: &camera::Projection,
+        reversed_z: bool,
+    ) {
+        self.set_view_proj(projection.calc_matrix() * camera.calc_matrix(), reversed_z);
+    }
+
+    fn set_view_proj(&mut self, view_proj: cgmath::Matrix4<f32>, reversed_z: bool) {
+        self.view_proj = if reversed_z {
+            // z' = w - z, columns are listed one after another
+            #[rustfmt::skip]
+            let reverse = cgmath::Matrix4::new(
+                1.0, 0.0, 0.0, 0.0,
+                0.0, 1.0, 0.0, 0.0,
+                0.0, 0.0, -1.0, 0.0,
+                0.0, 0.0, 1.0, 1.0,
+            );
+            (reverse * view_proj).into()
+        } else {
+            view_proj.into()
+        };
+    }
+
+    fn set_log_depth(&mut self, enabled: bool, projection: &camera::Projection) {
+        self.log_depth = if enabled {
+            1.0 / (projection.zfar + 1.0).log2()
+        } else {
+            0.0
+        };
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct SettingsUniform {
+    opacity: f32,
+    show_no_data: u32,
+    lighting: u32,
+    /// Contour interval in world units
+    contour_interval: f32,
+    /// Used entries of `lights`
+    light_count: u32,
+    normal_map: u32,
+    _padding: [f32; 2],
+    imagery_offset: [f32; 2],
+    imagery_scale: f32,
+    underside: u32,
+    underside_color: [f32; 4],
+    exposure: f32,
+    gamma: f32,
+    solid: u32,
+    /// Number of filled elevation bands, 0 to blend the tint smoothly
+    isobands: u32,
+    solid_color: [f32; 4],
+    lights: [LightUniform; light::MAX_LIGHTS],
+    /// `PackingBounds` min and extent, w unused
+    packing_min: [f32; 4],
+    packing_extent: [f32; 4],
+    fade: u32,
+    /// `RenderSettings::fade_range` in world heights
+    fade_start: f32,
+    fade_end: f32,
+    clip: u32,
+    /// `RenderSettings::clip_box`
+    clip_box: [f32; 4],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct LightUniform {
+    direction: [f32; 4],
+    /// Color times intensity
+    color: [f32; 4],
+}
+
+impl LightUniform {
+    fn new(direction: cgmath::Vector3<f32>, [r, g, b]: [f32; 3], intensity: f32) -> Self {
+        Self {
+            direction: direction.extend(0.0).into(),
+            color: [r * intensity, g * intensity, b * intensity, 1.0],
+        }
+    }
+}
+
+impl SettingsUniform {
+    /// `lights` beyond `light::MAX_LIGHTS` minus the sun are ignored
+    fn new(
+        settings: &RenderSettings,
+        sun: &light::Sun,
+        lights: &[light::Light],
+        packing_bounds: &PackingBounds,
+        [fade_start, fade_end]: [f32; 2],
+    ) -> Self {
+        let mut light_uniforms = [LightUniform::default(); light::MAX_LIGHTS];
+        light_uniforms[0] = LightUniform::new(sun.direction(), sun.color, sun.intensity);
+        for (uniform, light) in light_uniforms[1..].iter_mut().zip(lights) {
+            *uniform = LightUniform::new(light.direction(), light.color, light.intensity);
+        }
+        Self {
+            opacity: settings.opacity,
+            show_no_data: settings.show_no_data as u32,
+            lighting: settings.lighting as u32,
+            contour_interval: (settings.contour_interval as f64 / terrain::VERTICAL_SCALE
+                * settings.exaggeration as f64) as f32,
+            light_count: 1 + lights.len().min(light::MAX_LIGHTS - 1) as u32,
+            normal_map: settings.normal_map as u32,
+            _padding: [0.0; 2],
+            imagery_offset: settings.imagery_offset,
+            imagery_scale: settings.imagery_scale,
+            underside: settings.underside as u32,
+            underside_color: {
+                let [r, g, b] = settings.underside_color;
+                [r, g, b, 1.0]
+            },
+            exposure: settings.exposure,
+            gamma: settings.gamma,
+            solid: settings.solid as u32,
+            isobands: if settings.isobands {
+                settings.isoband_count
+            } else {
+                0
+            },
+            solid_color: {
+                let [r, g, b] = settings.solid_color;
+                [r, g, b, 1.0]
+            },
+            lights: light_uniforms,
+            packing_min: {
+                let [x, y, z] = packing_bounds.min;
+                [x, y, z, 0.0]
+            },
+            packing_extent: {
+                let [x, y, z] = packing_bounds.extent();
+                [x, y, z, 0.0]
+            },
+            fade: settings.fade as u32,
+            fade_start,
+            fade_end,
+            clip: settings.clip as u32,
+            clip_box: settings.clip_box,
+        }
+    }
+}
+
+/// Per-variant state baked into a terrain render pipeline
+struct PipelineOptions {
+    label: &'static str,
+    topology: terrain::Topology,
+    fragment_entry: &'static str,
+    blend: egui_wgpu::wgpu::BlendState,
+    depth_write_enabled: bool,
+    depth_compare: egui_wgpu::wgpu::CompareFunction,
+    bias: egui_wgpu::wgpu::DepthBiasState,
+    /// Use the `_log_depth` variant of `fragment_entry`, which writes
+    /// logarithmic depth
+    log_depth: bool,
+    /// Read `PackedVertex` instead of `Vertex` buffers
+    packed_vertices: bool,
+    /// Rasterize triangle edges only, needs `Capabilities::polygon_mode_line`
+    wireframe: bool,
+    /// Flip `depth_compare` and `bias` for a reversed depth buffer
+    reversed_z: bool,
+    /// No fragment stage, for rendering depth only
+    depth_only: bool,
+}
+
+impl PipelineOptions {
+    fn opaque() -> Self {
+        Self {
+            label: "Render Pipeline",
+            topology: terrain::Topology::Strip,
+            fragment_entry: "fs_main",
+            blend: egui_wgpu::wgpu::BlendState::REPLACE,
+            depth_write_enabled: true,
+            depth_compare: egui_wgpu::wgpu::CompareFunction::Less,
+            bias: egui_wgpu::wgpu::DepthBiasState::default(),
+            log_depth: false,
+            packed_vertices: false,
+            wireframe: false,
+            reversed_z: false,
+            depth_only: false,
+        }
+    }
+
+    /// Terrain depth seen from the sun, biased away from it against shadow
+    /// acne. Uses the pipeline layout without the shadow map.
+    fn shadow() -> Self {
+        Self {
+            label: "Shadow Pipeline",
+            depth_only: true,
+            bias: egui_wgpu::wgpu::DepthBiasState {
+                constant: 2,
+                slope_scale: 2.0,
+                clamp: 0.0,
+            },
+            ..Self::opaque()
+        }
+    }
+
+    /// Alpha blends and doesn't write depth, since transparent terrain isn't sorted
+    /// and writing depth would hide the surfaces behind it
+    fn transparent() -> Self {
+        Self {
+            label: "Transparent Render Pipeline",
+            blend: egui_wgpu::wgpu::BlendState::ALPHA_BLENDING,
+            depth_write_enabled: false,
+            ..Self::opaque()
+        }
+    }
+
+    /// Ignores and doesn't write depth so overdraw and hidden geometry show,
+    /// blended so lowering the opacity makes overlapping surfaces stand out
+    fn no_depth() -> Self {
+        Self {
+            label: "No Depth Render Pipeline",
+            blend: egui_wgpu::wgpu::BlendState::ALPHA_BLENDING,
+            depth_write_enabled: false,
+            depth_compare: egui_wgpu::wgpu::CompareFunction::Always,
+            ..Self::opaque()
+        }
+    }
+
+    /// Contour lines drawn over the terrain, biased towards the camera to avoid z-fighting
+    fn contour(settings: &RenderSettings) -> Self {
+        Self {
+            label: "Contour Render Pipeline",
+            topology: terrain::Topology::Strip,
+            fragment_entry: "fs_contour",
+            blend: egui_wgpu::wgpu::BlendState::ALPHA_BLENDING,
+            depth_write_enabled: false,
+            depth_compare: egui_wgpu::wgpu::CompareFunction::LessEqual,
+            bias: egui_wgpu::wgpu::DepthBiasState {
+                constant: settings.depth_bias_constant,
+                slope_scale: settings.depth_bias_slope,
+                clamp: 0.0,
+            },
+            log_depth: false,
+            packed_vertices: false,
+            wireframe: false,
+            reversed_z: false,
+            depth_only: false,
+        }
+    }
+}
+
+fn create_terrain_pipeline(
+    device: &egui_wgpu::wgpu::Device,
+    layout: &egui_wgpu::wgpu::PipelineLayout,
+    shader: &egui_wgpu::wgpu::ShaderModule,
+    format: egui_wgpu::wgpu::TextureFormat,
+    options: &PipelineOptions,
+) -> egui_wgpu::wgpu::RenderPipeline {
+    let fragment_entry = if options.log_depth {
+        format!("{}_log_depth", options.fragment_entry)
+    } else {
+        options.fragment_entry.to_string()
+    };
+    let targets = [Some(egui_wgpu::wgpu::ColorTargetState {
+        format,
+        blend: Some(options.blend),
+        write_mask: egui_wgpu::wgpu::ColorWrites::ALL,
+    })];
+    device.create_render_pipeline(&egui_wgpu::wgpu::RenderPipelineDescriptor {
+        label: Some(options.label),
+        layout: Some(layout),
+        vertex: egui_wgpu::wgpu::VertexState {
+            module: shader,
+            entry_point: if options.packed_vertices {
+                "vs_packed"
+            } else {
+                "vs_main"
+            },
+            buffers: &[if options.packed_vertices {
+                PackedVertex::desc()
+            } else {
+                Vertex::desc()
+            }],
+        },
+        fragment: (!options.depth_only).then_some(egui_wgpu::wgpu::FragmentState {
+            module: shader,
+            entry_point: &fragment_entry,
+            targets: &targets,
+        }),
+        primitive: egui_wgpu::wgpu::PrimitiveState {
+            topology: match options.topology {
+                terrain::Topology::Strip => egui_wgpu::wgpu::PrimitiveTopology::TriangleStrip,
+                terrain::Topology::List => egui_wgpu::wgpu::PrimitiveTopology::TriangleList,
+            },
+            // Enables primitive restart for meshes that use `terrain::PRIMITIVE_RESTART`
+            strip_index_format: match options.topology {
+                terrain::Topology::Strip => Some(egui_wgpu::wgpu::IndexFormat::Uint32),
+                terrain::Topology::List => None,
+            },
+            front_face: egui_wgpu::wgpu::FrontFace::Ccw,
+            cull_mode: None,
+            polygon_mode: if options.wireframe {
+                egui_wgpu::wgpu::PolygonMode::Line
+            } else {
+                egui_wgpu::wgpu::PolygonMode::Fill
+            },
+            unclipped_depth: false,
+            conservative: false,
+        },
+        depth_stencil: Some(egui_wgpu::wgpu::DepthStencilState {
+            format: texture::Texture::DEPTH_FORMAT,
+            depth_write_enabled: options.depth_write_enabled,
+            depth_compare: texture::Texture::depth_compare(
+                options.depth_compare,
+                options.reversed_z,
+            ),
+            stencil: egui_wgpu::wgpu::StencilState::default(),
+            // Towards the camera is towards 1 in a reversed depth buffer
+            bias: if options.reversed_z {
+                egui_wgpu::wgpu::DepthBiasState {
+                    constant: -options.bias.constant,
+                    slope_scale: -options.bias.slope_scale,
+                    ..options.bias
+                }
+            } else {
+                options.bias
+            },
+        }),
+        multisample: egui_wgpu::wgpu::MultisampleState {
+            count: 1,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        multiview: None,
+    })
+}
+
+/// Terrain pipeline variants for one primitive topology
+struct TerrainPipelines {
+    opaque: egui_wgpu::wgpu::RenderPipeline,
+    transparent: egui_wgpu::wgpu::RenderPipeline,
+    no_depth: egui_wgpu::wgpu::RenderPipeline,
+    contour: egui_wgpu::wgpu::RenderPipeline,
+    shadow: egui_wgpu::wgpu::RenderPipeline,
+}
+
+impl TerrainPipelines {
+    fn new(
+        device: &egui_wgpu::wgpu::Device,
+        layout: &egui_wgpu::wgpu::PipelineLayout,
+        shadow_layout: &egui_wgpu::wgpu::PipelineLayout,
+        shader: &egui_wgpu::wgpu::ShaderModule,
+        format: egui_wgpu::wgpu::TextureFormat,
+        topology: terrain::Topology,
+        settings: &RenderSettings,
+    ) -> Self {
+        let create = |options: PipelineOptions| {
+            create_terrain_pipeline(
+                device,
+                layout,
+                shader,
+                format,
+                &PipelineOptions {
+                    topology,
+                    log_depth: settings.log_depth,
+                    packed_vertices: settings.packed_vertices,
+                    wireframe: settings.wireframe,
+                    reversed_z: settings.reversed_depth(),
+                    ..options
+                },
+            )
+        };
+        Self {
+            opaque: create(PipelineOptions::opaque()),
+            transparent: create(PipelineOptions::transparent()),
+            no_depth: create(PipelineOptions::no_depth()),
+            contour: create(PipelineOptions::contour(settings)),
+            // Its own depth convention, independent of the camera's
+            shadow: create_terrain_pipeline(
+                device,
+                shadow_layout,
+                shader,
+                format,
+                &PipelineOptions {
+                    topology,
+                    packed_vertices: settings.packed_vertices,
+                    ..PipelineOptions::shadow()
+                },
+            ),
+        }
+    }
+}
+
+/// Plots elevation against distance for a sampled profile
+fn draw_profile_plot(ui: &mut egui::Ui, samples: &[profile::ProfileSample], color: egui::Color32) {
+    let (response, painter) = ui.allocate_painter(egui::vec2(320.0, 120.0), egui::Sense::hover());
+    let rect = response.rect;
+    painter.rect_filled(rect, 2.0, egui::Color32::from_gray(20));
+    if samples.len() < 2 {
+        painter.text(
+            rect.center(),
+            egui::Align2::CENTER_CENTER,
+            "Add at least two points",
+            egui::FontId::default(),
+            egui::Color32::GRAY,
+        );
+        return;
+    }
+
+    let max_distance = samples.last().map(|s| s.distance).unwrap_or(1.0).max(1e-6);
+    let min_elevation = samples
+        .iter()
+        .map(|s| s.elevation)
+        .fold(f64::INFINITY, f64::min);
+    let max_elevation = samples
+        .iter()
+        .map(|s| s.elevation)
+        .fold(f64::NEG_INFINITY, f64::max);
+    let range = (max_elevation - min_elevation).max(1e-6);
+    let points: Vec<egui::Pos2> = samples
+        .iter()
+        .map(|s| {
+            egui::pos2(
+                rect.left() + (s.distance / max_distance) as f32 * rect.width(),
+                rect.bottom() - ((s.elevation - min_elevation) / range) as f32 * rect.height(),
+            )
+        })
+        .collect();
+    painter.add(egui::Shape::line(points, egui::Stroke::new(1.5, color)));
+
+    let font = egui::FontId::monospace(10.0);
+    painter.text(
+        rect.left_top(),
+        egui::Align2::LEFT_TOP,
+        format!("{:.1} m", max_elevation),
+        font.clone(),
+        egui::Color32::LIGHT_GRAY,
+    );
+    painter.text(
+        rect.left_bottom(),
+        egui::Align2::LEFT_BOTTOM,
+        format!("{:.1} m", min_elevation),
+        font.clone(),
+        egui::Color32::LIGHT_GRAY,
+    );
+    painter.text(
+        rect.right_bottom(),
+        egui::Align2::RIGHT_BOTTOM,
+        format!("{:.1} cells", max_distance),
+        font,
+        egui::Color32::LIGHT_GRAY,
+    );
+}
+
+/// `COPY_BUFFER_ALIGNMENT` in bytes
+const COPY_ALIGNMENT: usize = egui_wgpu::wgpu::COPY_BUFFER_ALIGNMENT as usize;
+
+/// Writes `contents` into `buffer` in place, only reallocating when it doesn't fit
+fn write_mesh_buffer(
+    device: &egui_wgpu::wgpu::Device,
+    queue: &egui_wgpu::wgpu::Queue,
+    buffer: &mut egui_wgpu::wgpu::Buffer,
+    contents: &[u8],
+    label: &str,
+    usage: egui_wgpu::wgpu::BufferUsages,
+) {
+    // write_buffer requires the size to be a multiple of COPY_BUFFER_ALIGNMENT
+    let size = egui_wgpu::wgpu::util::align_to(
+        contents.len() as egui_wgpu::wgpu::BufferAddress,
+        egui_wgpu::wgpu::COPY_BUFFER_ALIGNMENT,
+    );
+    if size > buffer.size() {
+        debug!("Growing {} from {} to {} bytes", label, buffer.size(), size);
+        *buffer = device.create_buffer(&egui_wgpu::wgpu::BufferDescriptor {
+            label: Some(label),
+            size,
+            usage: usage | egui_wgpu::wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+    }
+
+    // Only the final partial word is padded, the rest is written in place
+    let aligned = contents.len() - contents.len() % COPY_ALIGNMENT;
+    if aligned > 0 {
+        queue.write_buffer(buffer, 0, &contents[..aligned]);
+    }
+    if aligned < contents.len() {
+        let mut tail = [0; COPY_ALIGNMENT];
+        tail[..contents.len() - aligned].copy_from_slice(&contents[aligned..]);
+        queue.write_buffer(buffer, aligned as egui_wgpu::wgpu::BufferAddress, &tail);
+    }
+}
+
+/// Most vertices of a mesh whose buffers fit in `max_buffer_size` bytes.
+/// Triangle lists take 6 indices per grid vertex, the skirt's walls fit in
+/// a seventh.
+fn vertex_limit(vertex_stride: usize, max_buffer_size: u64) -> u32 {
+    let bytes = vertex_stride.max(7 * std::mem::size_of::<u32>()) as u64;
+    (max_buffer_size / bytes).min(u32::MAX as u64) as u32
+}
+
+/// `options` with a vertex budget when the mesh of a `width`x`height` DEM
+/// would be over `vertex_limit`, see `terrain::MeshOptions::fit`
+fn fit_mesh_options(
+    options: terrain::MeshOptions,
+    [width, height]: [usize; 2],
+    vertex_limit: u32,
+) -> terrain::MeshOptions {
+    match options.fit([width, height], vertex_limit) {
+        Some(fitted) => {
+            info!(
+                "The {}x{} mesh is over the buffer size limit, meshing it with at most {} vertices",
+                width, height, vertex_limit
+            );
+            fitted
         }
+        None => options,
     }
+}
 
-    fn update_view_proj(&mut self, camera: &camera::Camera, projection: &camera::Projection) {
-        self.view_proj = (projection.calc_matrix() * camera.calc_matrix()).into();
+/// Whether `num_vertices` vertices and `num_indices` indices fit in their
+/// buffers. Drawing past the end trips GPU validation, so a mismatch is
+/// logged and the draw skipped instead.
+fn mesh_buffers_fit(
+    vertex_buffer: &egui_wgpu::wgpu::Buffer,
+    num_vertices: u32,
+    vertex_stride: usize,
+    index_buffer: &egui_wgpu::wgpu::Buffer,
+    num_indices: u32,
+) -> bool {
+    let vertex_bytes = num_vertices as u64 * vertex_stride as u64;
+    let index_bytes = num_indices as u64 * std::mem::size_of::<u32>() as u64;
+    if vertex_bytes > vertex_buffer.size() || index_bytes > index_buffer.size() {
+        error!(
+            "Mesh buffers out of sync: {} vertices in {} bytes, {} indices in {} bytes",
+            num_vertices,
+            vertex_buffer.size(),
+            num_indices,
+            index_buffer.size()
+        );
+        return false;
     }
+    true
 }
 
 pub struct Status {
@@ -72,6 +983,12 @@ pub struct Status {
     pub fps_avg: f32,
     pub delta: u128,
     pub cap_frame_rate: bool,
+    /// Terrain mesh drawn in the last frame, see `State::update_render_stats`
+    pub vertices: u32,
+    pub indices: u32,
+    pub triangles: u32,
+    /// Triangles of the full resolution mesh, more than `triangles` with LOD
+    pub total_triangles: u32,
 }
 
 impl Default for Status {
@@ -81,50 +998,564 @@ impl Default for Status {
             fps_avg: 0.0,
             delta: 0,
             cap_frame_rate: true,
+            vertices: 0,
+            indices: 0,
+            triangles: 0,
+            total_triangles: 0,
+        }
+    }
+}
+
+/// Terrain under the cursor, see `State::cursor_coordinates`
+struct CursorCoordinates {
+    /// Fractional (column, row)
+    pixel: [f64; 2],
+    /// Meters, of the picked mesh surface
+    elevation: f64,
+    /// Meters, interpolated from the DEM at `pixel`, `None` over NoData
+    sampled: Option<f64>,
+    /// In the DEM's CRS, or pixel space without a geotransform
+    projected: [f64; 2],
+    /// (longitude, latitude), `None` without a spatial reference and the
+    /// error when the transform failed
+    geographic: Option<Result<[f64; 2], String>>,
+}
+
+/// User-tweakable settings exposed in the GUI
+pub struct RenderSettings {
+    /// Unit of the loaded DEM; elevation is converted to meters for meshing
+    pub elevation_unit: ElevationUnit,
+    /// Flip the sign of the elevation, for bathymetry stored as positive
+    /// depth, so basins show as basins
+    pub invert_elevation: bool,
+    /// Vertical exaggeration applied to the terrain mesh
+    pub exaggeration: f32,
+    /// Mesh vertex grid size, `None` for one vertex per DEM pixel
+    pub mesh_resolution: Option<[u32; 2]>,
+    /// Maximum mesh vertex count, used when `mesh_resolution` is `None`
+    pub vertex_budget: Option<u32>,
+    /// Colors baked into the mesh vertices
+    pub vertex_coloring: terrain::VertexColoring,
+    /// Elevation in meters normalized to [0, 1], `None` for the DEM's min/max
+    pub value_range: Option<[f64; 2]>,
+    /// Without a `value_range`, normalize between the `percentiles` of the
+    /// valid elevation instead of its min/max so outliers don't take up
+    /// the color range
+    pub percentile_clip: bool,
+    /// Lower and upper percentile for `percentile_clip`
+    pub percentiles: [f32; 2],
+    /// Separate row strips with primitive restart, see `terrain::MeshOptions`
+    pub primitive_restart: bool,
+    /// Step the mesh into flat slabs `terrace_interval` meters apart
+    pub terraces: bool,
+    pub terrace_interval: f32,
+    /// Drop the border to a solid base `skirt_depth` meters below the lowest
+    /// point, closed underneath with `skirt_bottom`
+    pub skirt: bool,
+    pub skirt_depth: f64,
+    pub skirt_bottom: bool,
+    /// Upload `PackedVertex` instead of `Vertex`, for less than half the
+    /// vertex memory at 16 bit position precision within the terrain
+    pub packed_vertices: bool,
+    /// Topology the terrain is drawn with
+    pub topology: terrain::Topology,
+    /// Draw quadtree chunks decimated with distance instead of the full mesh
+    pub lod: bool,
+    /// Chunks closer than this many times their size are split, see `lod::QuadTree::select`
+    pub lod_factor: f32,
+    /// Mesh a decimated overview of the DEM as the camera climbs
+    pub overviews: bool,
+    /// Terrain opacity, alpha blended when below 1.0
+    pub opacity: f32,
+    /// Fade the terrain out below `fade_range`, alpha blended
+    pub fade: bool,
+    /// Elevations in meters the terrain is transparent below and opaque above
+    pub fade_range: [f64; 2],
+    /// Only draw the terrain inside `clip_box`
+    pub clip: bool,
+    /// World [min x, min z, max x, max z] of the area of interest
+    pub clip_box: [f32; 4],
+    /// Depth test the terrain, disabled for debugging overdraw
+    pub depth_test: bool,
+    /// Draw the terrain's triangle edges instead of its surface, only
+    /// available with `Capabilities::polygon_mode_line`
+    pub wireframe: bool,
+    /// Draw back faces in `underside_color` to show when the camera is below the surface
+    pub underside: bool,
+    pub underside_color: [f32; 3],
+    /// Hypsometric tint mixed into the imagery
+    pub tint_ramp: ramp::ColorRamp,
+    /// Color the terrain by `isoband_count` flat elevation classes of the
+    /// tint ramp instead of the imagery
+    pub isobands: bool,
+    pub isoband_count: u32,
+    /// Outline depth discontinuities, see `outline::Outline`
+    pub outline: bool,
+    pub outline_color: [f32; 3],
+    /// Relative depth change across a pixel where outlines start
+    pub outline_threshold: f32,
+    /// Draw the terrain in `solid_color` instead of the imagery
+    pub solid: bool,
+    pub solid_color: [f32; 3],
+    /// Fit the near and far planes to the terrain every frame instead of
+    /// using `znear` and `zfar`
+    pub auto_clip: bool,
+    pub znear: f32,
+    pub zfar: f32,
+    /// Logarithmic depth buffer, spreads the depth precision over the whole
+    /// view distance instead of concentrating it near the camera
+    pub log_depth: bool,
+    /// Store depth from 1 at the near plane to 0 at the far plane, which
+    /// evens out the precision of the float depth buffer. Ignored with
+    /// `log_depth`, see `reversed_depth`.
+    pub reversed_z: bool,
+    /// Value the depth buffer is cleared to before the terrain pass, 1 is the
+    /// far plane and stays the far plane with `reversed_z`. Lower values hide
+    /// the terrain beyond that depth.
+    pub clear_depth: f32,
+    /// Background without `sky_gradient`, linear RGB
+    pub clear_color: [f32; 3],
+    /// Exposure of the final image in stops, 0 leaves it unchanged
+    pub exposure: f32,
+    /// Gamma applied on top of the sRGB encoding, 1 leaves it unchanged
+    pub gamma: f32,
+    /// Terrain resolution relative to the window, above 1 supersamples
+    pub render_scale: f32,
+    /// Offset of the imagery over the terrain in texture coordinates
+    pub imagery_offset: [f32; 2],
+    /// Scale of the imagery around the terrain center
+    pub imagery_scale: f32,
+    /// Highlight NoData pixels in magenta
+    pub show_no_data: bool,
+    /// Shade with normals sampled per pixel from `State::normal_texture`
+    /// instead of the mesh's vertex normals, so coarse meshes keep the
+    /// detail of the full DEM
+    pub normal_map: bool,
+    /// Shade the terrain with the sun
+    pub lighting: bool,
+    /// Let the terrain cast shadows from the sun with lighting on, see
+    /// `shadow::ShadowMap`
+    pub shadows: bool,
+    /// Texels along each side of the shadow map, one of `shadow::RESOLUTIONS`
+    pub shadow_resolution: u32,
+    /// Radius of the shadow edge filter in shadow map texels
+    pub shadow_softness: u32,
+    /// Draw contour lines over the terrain
+    pub contours: bool,
+    /// Elevation between contour lines in meters
+    pub contour_interval: f32,
+    /// Depth bias of the overlay passes, negative values pull them towards the camera
+    pub depth_bias_constant: i32,
+    pub depth_bias_slope: f32,
+    /// Draw a north arrow in the top right corner
+    pub north_arrow: bool,
+    /// Draw a scale bar for the terrain in the middle of the window
+    pub scale_bar: bool,
+    /// Draw the loaded track, see `State::load_track`
+    pub track: bool,
+    /// Draw a water plane at `water_level`
+    pub water: bool,
+    /// Elevation of the water plane in meters
+    pub water_level: f64,
+    /// Linear RGB
+    pub water_color: [f32; 3],
+    pub water_opacity: f32,
+    /// Blend the background from `sky_low` near the ground to `sky_high` as
+    /// the camera climbs, see `State::sky_color`
+    pub sky_gradient: bool,
+    /// Linear RGB
+    pub sky_low: [f32; 3],
+    pub sky_high: [f32; 3],
+    /// Height above the terrain, relative to the larger DEM dimension, at
+    /// which the background is `sky_high`
+    pub sky_altitude: f32,
+    /// Draw a reticle in the middle of the window, where picks without a
+    /// cursor position land
+    pub crosshair: bool,
+    /// Outline the box between the terrain's lowest and highest point, see
+    /// `State::terrain_bounds`
+    pub bounding_box: bool,
+    /// Linear RGB
+    pub bounding_box_color: [f32; 3],
+    pub bounding_box_opacity: f32,
+    /// Label DEM pixels with their elevation for checking the loader
+    pub elevation_labels: bool,
+    /// Follow the cursor with a tooltip of the elevation and coordinates below it
+    pub cursor_tooltip: bool,
+    /// Show the imagery and the normalized elevation as images, see
+    /// `preview::TexturePreviews`
+    pub texture_previews: bool,
+    /// DEM pixels between elevation labels
+    pub elevation_label_spacing: u32,
+}
+
+impl RenderSettings {
+    pub fn mesh_options(&self) -> terrain::MeshOptions {
+        terrain::MeshOptions {
+            exaggeration: self.exaggeration as f64,
+            resolution: self.mesh_resolution,
+            vertex_budget: self.vertex_budget,
+            coloring: self.vertex_coloring,
+            value_range: self.value_range,
+            primitive_restart: self.primitive_restart,
+            terrace_interval: self.terraces.then_some(self.terrace_interval as f64),
+            skirt_depth: self.skirt.then_some(self.skirt_depth),
+            skirt_bottom: self.skirt_bottom,
+        }
+    }
+
+    /// Whether the depth buffer is reversed, logarithmic depth writes its own
+    /// depth and takes precedence
+    pub fn reversed_depth(&self) -> bool {
+        self.reversed_z && !self.log_depth
+    }
+
+    /// Depth the buffer is cleared to for `clear_depth`
+    fn depth_clear_value(&self) -> f32 {
+        if self.reversed_depth() {
+            1.0 - self.clear_depth
+        } else {
+            self.clear_depth
+        }
+    }
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            elevation_unit: ElevationUnit::Meters,
+            invert_elevation: false,
+            exaggeration: 1.0,
+            mesh_resolution: None,
+            vertex_budget: None,
+            vertex_coloring: terrain::VertexColoring::default(),
+            value_range: None,
+            percentile_clip: false,
+            percentiles: [2.0, 98.0],
+            primitive_restart: false,
+            terraces: false,
+            terrace_interval: 50.0,
+            skirt: false,
+            skirt_depth: 100.0,
+            skirt_bottom: true,
+            packed_vertices: false,
+            topology: terrain::Topology::default(),
+            lod: false,
+            lod_factor: 2.0,
+            overviews: false,
+            opacity: 1.0,
+            fade: false,
+            fade_range: [0.0, 500.0],
+            clip: false,
+            clip_box: [-100.0, -100.0, 100.0, 100.0],
+            depth_test: true,
+            wireframe: false,
+            underside: false,
+            underside_color: [0.6, 0.15, 0.15],
+            tint_ramp: ramp::ColorRamp::default(),
+            isobands: false,
+            isoband_count: 10,
+            outline: false,
+            outline_color: [0.0; 3],
+            outline_threshold: 0.05,
+            solid: false,
+            solid_color: [0.7, 0.7, 0.7],
+            auto_clip: false,
+            znear: 0.1,
+            zfar: 100.0,
+            log_depth: false,
+            reversed_z: false,
+            clear_depth: 1.0,
+            clear_color: [0.1, 0.2, 0.3],
+            exposure: 0.0,
+            gamma: 1.0,
+            render_scale: 1.0,
+            imagery_offset: [0.0; 2],
+            imagery_scale: 1.0,
+            show_no_data: false,
+            lighting: false,
+            shadows: false,
+            shadow_resolution: 2048,
+            shadow_softness: 1,
+            normal_map: false,
+            contours: false,
+            contour_interval: 50.0,
+            depth_bias_constant: -2,
+            depth_bias_slope: -1.0,
+            north_arrow: false,
+            scale_bar: false,
+            crosshair: false,
+            bounding_box: false,
+            bounding_box_color: [1.0, 1.0, 1.0],
+            bounding_box_opacity: 0.5,
+            track: true,
+            water: false,
+            water_level: 0.0,
+            water_color: [0.05, 0.25, 0.45],
+            water_opacity: 0.6,
+            sky_gradient: false,
+            sky_low: [0.55, 0.6, 0.65],
+            sky_high: [0.005, 0.02, 0.1],
+            sky_altitude: 1.0,
+            elevation_labels: false,
+            cursor_tooltip: false,
+            texture_previews: false,
+            elevation_label_spacing: 50,
         }
     }
 }
 
+/// Number of overviews built for switching the mesh with altitude, see `State::build_overviews`
+const OVERVIEW_LEVELS: usize = 4;
+/// Height above the terrain, relative to the larger DEM dimension, at which
+/// the first overview is used. Each doubling of the height goes a level further.
+const OVERVIEW_ALTITUDE: f32 = 0.25;
+/// Grid size of the flat scene shown while no DEM is loaded
+const PLACEHOLDER_SIZE: usize = 64;
+/// Time a flight to a bookmark takes
+const BOOKMARK_FLIGHT_SECONDS: f32 = 1.5;
+
+/// Colors used to tell profiles apart in the plot and on the terrain
+const PROFILE_COLORS: [egui::Color32; 4] = [
+    egui::Color32::from_rgb(255, 200, 0),
+    egui::Color32::from_rgb(0, 200, 255),
+    egui::Color32::from_rgb(255, 80, 80),
+    egui::Color32::from_rgb(120, 255, 120),
+];
+
+/// The window's surface and the instance it was created from. A device can
+/// be created again for the same surface, which `recover` relies on.
+struct WindowSurface<'a> {
+    instance: egui_wgpu::wgpu::Instance,
+    surface: egui_wgpu::wgpu::Surface<'a>,
+}
+
+/// Elevation `recover` uploads again instead of reading the DEM from disk,
+/// so edits made to it aren't lost
+struct KeptElevation {
+    buffer: Vec<f64>,
+    unit: ElevationUnit,
+    no_data: Option<f64>,
+    sample_type: super::gtiff::SampleType,
+    geo: super::gtiff::GeoReference,
+    range: [f64; 2],
+}
+
 pub struct State<'a> {
     pub size: egui_winit::winit::dpi::PhysicalSize<u32>,
     pub egui: gui::EguiRenderer,
     pub window: &'a Window,
     pub status: Status,
+    pub settings: RenderSettings,
+    pub sun: light::Sun,
+    /// Fill lights next to the sun, at most `light::MAX_LIGHTS - 1`
+    pub lights: Vec<light::Light>,
+    /// Freezes time based updates such as the sun while the camera stays movable
+    pub paused: bool,
     pub mouse_pressed: bool,
+    /// Cursor grabbed and hidden so mouse motion looks around without a
+    /// button held, toggled with M, see `set_mouse_grab`
+    mouse_grabbed: bool,
+    /// Copy the camera to the clipboard in the next GUI frame
+    copy_camera_requested: bool,
+    /// Orbit the camera around the terrain center until there is user input
+    pub auto_rotate: bool,
+    /// Finer stages of the DEM still to come, `None` once it is fully loaded
+    progressive: Option<progressive::Progressive>,
+    /// DEM being downloaded, opened by `poll_download` once it is done
+    download: Option<download::Download>,
+    /// Temp file of the downloaded DEM on screen, removed once it is replaced
+    downloaded_dem: Option<std::path::PathBuf>,
+    /// Orbit speed in degrees per second
+    pub auto_rotate_speed: f32,
+    /// Physical pixels per logical pixel of the window's monitor
+    scale_factor: f64,
+    /// GUI pixels per point instead of `scale_factor`
+    ui_scale: Option<f32>,
+    cursor_position: Option<PhysicalPosition<f64>>,
+    /// Elevation cross-sections, the last one receives picked points
+    pub profiles: Vec<profile::Profile>,
+    /// Whether right clicks add points to the active profile
+    pub profile_editing: bool,
+    /// Whether right clicks pick the two points of the distance and bearing tool
+    pub measuring: bool,
+    /// Picked measurement points as (column, elevation in meters, row), a
+    /// third pick starts over
+    measure_points: Vec<cgmath::Vector3<f64>>,
+    /// Whether right clicks pick the corners of the region to flatten
+    pub flatten_editing: bool,
+    /// Whether right clicks pick the corners of `RenderSettings::clip_box`
+    pub clip_editing: bool,
+    /// First corner of the clip box being picked, world (x, z)
+    clip_corner: Option<[f32; 2]>,
+    /// First corner of the region being picked, (column, row)
+    flatten_corner: Option<[f64; 2]>,
+    /// Inclusive (left, top, right, bottom) DEM pixels to flatten
+    flatten_region: Option<[usize; 4]>,
+    /// Elevation in meters the region is flattened to
+    pub flatten_elevation: f64,
+    /// Second DEM the elevation is blended towards, see `morph::Morph`
+    morph: Option<morph::Morph>,
+    /// Set while `animate_morph` only updates the vertices, the rest follows
+    /// in `apply_morph` once the animation stops
+    morph_partial: bool,
+    /// GeoTIFF loaded as the morph target
+    morph_path: String,
+    morph_status: Option<String>,
+    /// Why the DEM from the command line couldn't be loaded, the scene is a
+    /// flat placeholder until a DEM is opened from the prompt
+    dem_error: Option<String>,
+    /// Path typed into the Open DEM prompt
+    open_dem_path: String,
+    open_dem_status: Option<String>,
+    /// DEM `open_dem` should load before the next frame
+    open_dem_requested: Option<String>,
     clear_color: egui_wgpu::wgpu::Color,
-    surface: egui_wgpu::wgpu::Surface<'a>,
+    /// Only `None` while `recover` hands it to the new state
+    surface: Option<WindowSurface<'a>>,
     device: egui_wgpu::wgpu::Device,
+    device_lost: Arc<AtomicBool>,
+    adapter_info: egui_wgpu::wgpu::AdapterInfo,
+    capabilities: Capabilities,
+    limits: egui_wgpu::wgpu::Limits,
     queue: egui_wgpu::wgpu::Queue,
     config: egui_wgpu::wgpu::SurfaceConfiguration,
-    render_pipeline: egui_wgpu::wgpu::RenderPipeline,
+    strip_pipelines: TerrainPipelines,
+    list_pipelines: TerrainPipelines,
+    render_pipeline_layout: egui_wgpu::wgpu::PipelineLayout,
+    /// `render_pipeline_layout` without the shadow map, which the shadow pass
+    /// renders into
+    shadow_pipeline_layout: egui_wgpu::wgpu::PipelineLayout,
+    shadow_map: shadow::ShadowMap,
+    /// `CameraUniform` of the sun's view for the shadow pass
+    shadow_camera_buffer: egui_wgpu::wgpu::Buffer,
+    shadow_camera_bind_group: egui_wgpu::wgpu::BindGroup,
+    shader: egui_wgpu::wgpu::ShaderModule,
+    #[cfg(feature = "hot-reload")]
+    shader_watcher: Option<crate::hot_reload::ShaderWatcher>,
+    /// Preallocated for the full resolution mesh and updated in place with `write_buffer`
     vertex_buffer: egui_wgpu::wgpu::Buffer,
     index_buffer: egui_wgpu::wgpu::Buffer,
+    list_index_buffer: egui_wgpu::wgpu::Buffer,
+    num_vertices: u32,
     num_indices: u32,
+    num_list_indices: u32,
+    /// Tiles of a DEM over the texture limit, drawn in place of the buffers
+    /// above at full resolution. Empty for other DEMs.
+    terrain_tiles: Vec<TerrainTile>,
+    quadtree: lod::QuadTree,
+    /// Chunks in the LOD buffers, empty when they need to be rebuilt
+    lod_nodes: Vec<lod::Node>,
+    /// Elevation in meters and its range that `update_lod` meshes from,
+    /// `None` until it's next needed once the elevation changed
+    lod_elevation: Option<(Vec<f64>, [f64; 2])>,
+    lod_vertex_buffer: egui_wgpu::wgpu::Buffer,
+    lod_index_buffer: egui_wgpu::wgpu::Buffer,
+    num_lod_vertices: u32,
+    num_lod_indices: u32,
+    /// Box of the full mesh `PackedVertex` positions are relative to
+    packing_bounds: PackingBounds,
+    /// Decimated copies of `elevation`, coarsest last
+    overviews: Vec<gtiff::Overview>,
+    /// GeoTIFF `elevation` was read from at full resolution, for reading its
+    /// overviews. `None` for other sources and once `elevation` is edited.
+    elevation_path: Option<String>,
+    /// Overview the mesh was built from, 0 for the full resolution
+    overview_level: usize,
+    /// Dataset position of the world origin, see `terrain::Mesh::origin`
+    terrain_origin: cgmath::Vector3<f64>,
+    elevation_texture: egui_wgpu::wgpu::Texture,
+    no_data_mask_texture: egui_wgpu::wgpu::Texture,
+    /// Rgba8Snorm normals of the full resolution DEM, written by
+    /// `update_normal_map` while `settings.normal_map` is on
+    normal_texture: egui_wgpu::wgpu::Texture,
+    tint_ramp_texture: egui_wgpu::wgpu::Texture,
+    /// Not normalized elevation in `settings.elevation_unit`
+    elevation: Vec<f64>,
+    elevation_min: f64,
+    elevation_max: f64,
+    /// Range the texture is normalized over without `settings.value_range`, see `gtiff::Elevation::range`
+    normalization_range: [f64; 2],
+    no_data_count: usize,
+    is_no_data: Vec<bool>,
+    no_data: Option<f64>,
+    sample_type: super::gtiff::SampleType,
+    geo: super::gtiff::GeoReference,
+    /// Startup options such as where the elevation was loaded from, reused
+    /// when recovering the device
+    startup: Config,
+    /// Ground size of a DEM pixel, `None` when the DEM has no spatial reference
+    meters_per_pixel: Option<f64>,
+    /// Whether the elevation texture holds color table colors, see `gtiff::Elevation`
+    indexed: bool,
+    /// WGS84 extent of the DEM, see `gtiff::GeoReference::geographic_bounds`
+    geographic_bounds: Option<[f64; 4]>,
+    /// DEM CRS to WGS84 for the cursor readout, `None` without a spatial reference
+    geographic_transform: Option<gdal::spatial_ref::CoordTransform>,
+    /// Path the processed elevation is exported to
+    pub export_path: String,
+    export_status: Option<String>,
+    /// PNG the top down map is rendered to, with a `.pgw` world file and a
+    /// `.prj` next to it, see `export_map`
+    pub map_export_path: String,
+    /// Width in pixels of the map, the height follows the DEM's aspect
+    pub map_export_width: u32,
+    /// Deferred to the start of the next frame, the export replaces the
+    /// depth texture the current frame draws with
+    map_export_requested: bool,
+    map_export_status: Option<String>,
+    /// Elevation in meters `terrain::volume_above` measures from
+    pub volume_level: f64,
+    /// Result of the last Measure Surface click
+    measure_status: Option<String>,
+    /// Path user preferences are saved to, see `preset::Preset`
+    pub preset_path: String,
+    /// Saved views, see `preset::Preset::bookmarks`
+    pub bookmarks: Vec<preset::CameraPreset>,
+    /// Keys of the camera movement and toggles, see `preset::Preset::keys`
+    pub key_bindings: KeyBindings,
+    /// Bookmark `G` flies to next
+    next_bookmark: usize,
+    /// Move to a bookmark in progress, any input cancels it
+    flight: Option<camera::Flight>,
+    preset_status: Option<String>,
+    /// Layout of `diffuse_bind_group`, kept to bind another DEM
+    texture_bind_group_layout: egui_wgpu::wgpu::BindGroupLayout,
     diffuse_bind_group: egui_wgpu::wgpu::BindGroup,
     _diffuse_texture: texture::Texture,
+    /// Registered with egui while `RenderSettings::texture_previews` is on
+    texture_previews: Option<preview::TexturePreviews>,
     camera: camera::Camera,
     projection: camera::Projection,
     pub camera_controller: camera::CameraController,
     camera_uniform: CameraUniform,
     camera_buffer: egui_wgpu::wgpu::Buffer,
     camera_bind_group: egui_wgpu::wgpu::BindGroup,
+    settings_buffer: egui_wgpu::wgpu::Buffer,
+    settings_bind_group: egui_wgpu::wgpu::BindGroup,
+    /// Sized for `settings.render_scale` like the render target
     depth_texture: texture::Texture,
+    /// Terrain is drawn here and blitted to the surface unless the scale is 1
+    render_target: Option<(texture::Texture, egui_wgpu::wgpu::BindGroup)>,
+    blit: blit::Blit,
+    outline: outline::Outline,
+    /// Vector overlay draped over the terrain, see `track::Track`
+    track: Option<track::Track>,
+    track_pipeline: track::TrackPipeline,
+    bounding_box_pipeline: track::TrackPipeline,
+    /// Bounds and color `bounding_box_pipeline` holds the lines of
+    bounding_box_lines: Option<(cgmath::Point3<f32>, cgmath::Point3<f32>, [f32; 4])>,
+    water: water::Water,
+    /// Seconds the water animation has run, stops while paused
+    water_time: f32,
+    /// Vector file loaded as the track
+    track_path: String,
+    track_status: Option<String>,
     pub gui_consumed: bool,
 }
 
 impl<'a> State<'a> {
-    pub async fn new(window: &'a Window) -> State<'a> {
-        let span = debug_span!("State::new");
-        let _enter = span.enter();
-
-        let size = window.inner_size();
-        if size.width == 0 || size.height == 0 {
-            error!("Window has a width or height of 0");
-            panic!();
-        } else {
-            trace!("Window size: {:?}", size);
-        }
-
+    pub async fn new(window: &'a Window, startup: &Config) -> State<'a> {
         // The instance is a handle to our GPU
         // Backends::all => Vulkan + Metal + DX12 + Browser WebGPU
         let instance = egui_wgpu::wgpu::Instance::new(egui_wgpu::wgpu::InstanceDescriptor {
@@ -142,10 +1573,51 @@ impl<'a> State<'a> {
         };
         trace!("Surface created");
 
+        Self::with_surface(window, startup, WindowSurface { instance, surface }, None).await
+    }
+
+    /// Creates the state on a surface made for `window` already. `kept` is
+    /// uploaded in place of loading `startup.dem`.
+    async fn with_surface(
+        window: &'a Window,
+        startup: &Config,
+        window_surface: WindowSurface<'a>,
+        kept: Option<KeptElevation>,
+    ) -> State<'a> {
+        let span = debug_span!("State::new");
+        let _enter = span.enter();
+
+        let dem = &startup.dem;
+        // Start with a flat placeholder and ask for a DEM instead of failing.
+        // GDAL's /vsi paths aren't on the file system.
+        // Shown as a flat placeholder until `poll_download` opens it
+        let downloading = matches!(dem, DemSource::File(path) if download::is_url(path));
+        let kept_elevation = kept.is_some();
+        let mut dem_error = match dem {
+            _ if kept_elevation || downloading => None,
+            DemSource::File(path)
+                if !path.starts_with("/vsi") && !std::path::Path::new(path).exists() =>
+            {
+                warn!("DEM {} not found, starting with an empty scene", path);
+                Some(format!("No DEM found at {}", path))
+            }
+            _ => None,
+        };
+        let linear_surface = startup.linear_surface;
+
+        let size = window.inner_size();
+        if size.width == 0 || size.height == 0 {
+            error!("Window has a width or height of 0");
+            panic!();
+        } else {
+            trace!("Window size: {:?}", size);
+        }
+
+        let WindowSurface { instance, surface } = &window_surface;
         let adapter = match instance
             .request_adapter(&egui_wgpu::wgpu::RequestAdapterOptions {
                 power_preference: egui_wgpu::wgpu::PowerPreference::default(),
-                compatible_surface: Some(&surface),
+                compatible_surface: Some(surface),
                 force_fallback_adapter: false,
             })
             .await
@@ -161,7 +1633,7 @@ impl<'a> State<'a> {
         let (device, queue) = match adapter
             .request_device(
                 &egui_wgpu::wgpu::DeviceDescriptor {
-                    required_features: egui_wgpu::wgpu::Features::empty(),
+                    required_features: Capabilities::required_features(&adapter),
                     required_limits: egui_wgpu::wgpu::Limits::default(),
                     label: None,
                     // memory_hints: Default::default(),
@@ -178,14 +1650,45 @@ impl<'a> State<'a> {
         };
         trace!("Device and queue created");
 
-        let surface_caps = surface.get_capabilities(&adapter);
-        // sRGB is a color space that is standard for the web and most displays
-        let surface_format = surface_caps
-            .formats
+        // Device loss (driver reset, sleep) is only reported through callbacks,
+        // so flag it and let the event loop rebuild the state
+        let device_lost = Arc::new(AtomicBool::new(false));
+        {
+            let device_lost = device_lost.clone();
+            device.set_device_lost_callback(move |reason, message| {
+                if matches!(reason, egui_wgpu::wgpu::DeviceLostReason::Unknown) {
+                    error!("Device lost: {}", message);
+                    device_lost.store(true, Ordering::SeqCst);
+                }
+            });
+        }
+        device.on_uncaptured_error(Box::new(|e| {
+            error!("Uncaptured GPU error: {}", e);
+        }));
+        let adapter_info = adapter.get_info();
+        let capabilities = Capabilities::detect(&adapter);
+        let limits = device.limits();
+
+        let surface_caps = surface.get_capabilities(&adapter);
+        // sRGB is a color space that is standard for the web and most displays,
+        // a linear surface is for compositing into other pipelines
+        let surface_format = match surface_caps
+            .formats
             .iter()
-            .find(|f| f.is_srgb())
-            .copied()
-            .unwrap_or(surface_caps.formats[0]);
+            .find(|f| f.is_srgb() != linear_surface)
+        {
+            Some(format) => *format,
+            None => {
+                warn!(
+                    "No {} surface format in {:?}, using {:?}",
+                    if linear_surface { "linear" } else { "sRGB" },
+                    surface_caps.formats,
+                    surface_caps.formats[0]
+                );
+                surface_caps.formats[0]
+            }
+        };
+        debug!("Surface format: {:?}", surface_format);
         let config = egui_wgpu::wgpu::SurfaceConfiguration {
             usage: egui_wgpu::wgpu::TextureUsages::RENDER_ATTACHMENT,
             format: surface_format,
@@ -199,28 +1702,111 @@ impl<'a> State<'a> {
         trace!("Surface configuration created: {:?}", config);
 
         surface.configure(&device, &config);
-        let diffuse_bytes = include_bytes!("../satelite.png");
-        let diffuse_texture =
-            texture::Texture::from_bytes(&device, &queue, diffuse_bytes, "satelite.png").unwrap();
+        let diffuse_texture = match &startup.imagery {
+            Some(path)
+                if std::path::Path::new(path).extension().is_some_and(|ext| {
+                    ext.eq_ignore_ascii_case("tif") || ext.eq_ignore_ascii_case("tiff")
+                }) =>
+            {
+                super::gtiff::load_geotiff_rgb_as_texture(
+                    &device,
+                    &queue,
+                    path,
+                    startup.max_texture_size,
+                )
+            }
+            Some(path) => {
+                let bytes = match std::fs::read(path) {
+                    Ok(bytes) => bytes,
+                    Err(e) => {
+                        error!("Failed to read {}: {}", path, e);
+                        panic!("Failed to read {}: {}", path, e);
+                    }
+                };
+                texture::Texture::from_bytes(
+                    &device,
+                    &queue,
+                    &bytes,
+                    path,
+                    startup.max_texture_size,
+                )
+            }
+            None => texture::Texture::from_bytes(
+                &device,
+                &queue,
+                include_bytes!("../satelite.png"),
+                "satelite.png",
+                startup.max_texture_size,
+            ),
+        };
+        let diffuse_texture = match diffuse_texture {
+            Ok(texture) => texture,
+            Err(e) => {
+                error!("Failed to load the imagery: {:?}", e);
+                panic!("Failed to load the imagery: {:?}", e);
+            }
+        };
         trace!("Diffuse texture created");
 
-        let (gtiff_texture, gtiff_buffer) =
-            super::gtiff::load_geotiff_as_texture(&device, &queue, "output.tif");
-        let gtiff_texture_view =
-            gtiff_texture.create_view(&egui_wgpu::wgpu::TextureViewDescriptor::default());
-        let gtiff_texture_sampler = device.create_sampler(&egui_wgpu::wgpu::SamplerDescriptor {
-            address_mode_u: egui_wgpu::wgpu::AddressMode::ClampToEdge,
-            address_mode_v: egui_wgpu::wgpu::AddressMode::ClampToEdge,
-            address_mode_w: egui_wgpu::wgpu::AddressMode::ClampToEdge,
-            mag_filter: egui_wgpu::wgpu::FilterMode::Nearest,
-            min_filter: egui_wgpu::wgpu::FilterMode::Nearest,
-            mipmap_filter: egui_wgpu::wgpu::FilterMode::Nearest,
-            ..Default::default()
+        let super::gtiff::Elevation {
+            texture: gtiff_texture,
+            buffer: gtiff_buffer,
+            unit: elevation_unit,
+            no_data_mask,
+            no_data_count,
+            is_no_data,
+            no_data,
+            sample_type,
+            geo,
+            indexed,
+            range: normalization_range,
+            tiles: elevation_tiles,
+        } = match (kept, dem) {
+            (Some(kept), _) => Ok(super::gtiff::elevation_from_parts(
+                &device,
+                &queue,
+                kept.buffer,
+                kept.unit,
+                kept.no_data,
+                kept.sample_type,
+                kept.geo,
+                kept.range,
+            )),
+            (None, DemSource::File(path)) if dem_error.is_none() && !downloading => {
+                super::gtiff::load_elevation(&device, &queue, path, startup.preview_level).map_err(
+                    |e| {
+                        error!("Failed to load DEM {}: {}", path, e);
+                        dem_error = Some(format!("Failed to load {}: {}", path, e));
+                    },
+                )
+            }
+            (None, DemSource::File(_)) => Err(()),
+            (None, DemSource::Synthetic(kind)) => {
+                let size = DemSource::SYNTHETIC_SIZE;
+                Ok(super::gtiff::elevation_from_buffer(
+                    &device,
+                    &queue,
+                    terrain::generate_synthetic(size, size, *kind),
+                    size,
+                    size,
+                ))
+            }
+        }
+        .unwrap_or_else(|()| {
+            super::gtiff::elevation_from_buffer(
+                &device,
+                &queue,
+                vec![0.0; PLACEHOLDER_SIZE * PLACEHOLDER_SIZE],
+                PLACEHOLDER_SIZE,
+                PLACEHOLDER_SIZE,
+            )
         });
-        let dimensions = Dimensions {
-            width: gtiff_texture.size().width as f32,
-            height: gtiff_texture.size().height as f32,
-        };
+        let elevation_min = gtiff_buffer.iter().cloned().fold(f64::INFINITY, f64::min);
+        let elevation_max = gtiff_buffer
+            .iter()
+            .cloned()
+            .fold(f64::NEG_INFINITY, f64::max);
+        let dimensions = Dimensions::new(geo.width, geo.height, indexed, !surface_format.is_srgb());
 
         let texture_bind_group_layout =
             device.create_bind_group_layout(&egui_wgpu::wgpu::BindGroupLayoutDescriptor {
@@ -278,48 +1864,89 @@ impl<'a> State<'a> {
                         },
                         count: None,
                     },
+                    // NoData mask, read with textureLoad so it needs no sampler
+                    egui_wgpu::wgpu::BindGroupLayoutEntry {
+                        binding: 5,
+                        visibility: egui_wgpu::wgpu::ShaderStages::FRAGMENT,
+                        ty: egui_wgpu::wgpu::BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: egui_wgpu::wgpu::TextureViewDimension::D2,
+                            sample_type: egui_wgpu::wgpu::TextureSampleType::Float {
+                                filterable: false,
+                            },
+                        },
+                        count: None,
+                    },
+                    // Tint ramp lookup, see `ramp::ColorRamp`
+                    egui_wgpu::wgpu::BindGroupLayoutEntry {
+                        binding: 6,
+                        visibility: egui_wgpu::wgpu::ShaderStages::FRAGMENT,
+                        ty: egui_wgpu::wgpu::BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: egui_wgpu::wgpu::TextureViewDimension::D1,
+                            sample_type: egui_wgpu::wgpu::TextureSampleType::Float {
+                                filterable: true,
+                            },
+                        },
+                        count: None,
+                    },
+                    egui_wgpu::wgpu::BindGroupLayoutEntry {
+                        binding: 7,
+                        visibility: egui_wgpu::wgpu::ShaderStages::FRAGMENT,
+                        ty: egui_wgpu::wgpu::BindingType::Sampler(
+                            egui_wgpu::wgpu::SamplerBindingType::Filtering,
+                        ),
+                        count: None,
+                    },
+                    // Normal map, see `RenderSettings::normal_map`
+                    egui_wgpu::wgpu::BindGroupLayoutEntry {
+                        binding: 8,
+                        visibility: egui_wgpu::wgpu::ShaderStages::FRAGMENT,
+                        ty: egui_wgpu::wgpu::BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: egui_wgpu::wgpu::TextureViewDimension::D2,
+                            sample_type: egui_wgpu::wgpu::TextureSampleType::Float {
+                                filterable: true,
+                            },
+                        },
+                        count: None,
+                    },
+                    egui_wgpu::wgpu::BindGroupLayoutEntry {
+                        binding: 9,
+                        visibility: egui_wgpu::wgpu::ShaderStages::FRAGMENT,
+                        ty: egui_wgpu::wgpu::BindingType::Sampler(
+                            egui_wgpu::wgpu::SamplerBindingType::Filtering,
+                        ),
+                        count: None,
+                    },
                 ],
                 label: Some("texture_bind_group_layout"),
             });
-        let diffuse_bind_group = device.create_bind_group(&egui_wgpu::wgpu::BindGroupDescriptor {
-            layout: &texture_bind_group_layout,
-            entries: &[
-                egui_wgpu::wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: egui_wgpu::wgpu::BindingResource::TextureView(&diffuse_texture.view),
-                },
-                egui_wgpu::wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: egui_wgpu::wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
-                },
-                egui_wgpu::wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: egui_wgpu::wgpu::BindingResource::TextureView(&gtiff_texture_view), // Use GeoTIFF texture view
-                },
-                egui_wgpu::wgpu::BindGroupEntry {
-                    binding: 3,
-                    resource: egui_wgpu::wgpu::BindingResource::Sampler(&gtiff_texture_sampler), // Use GeoTIFF texture sampler
-                },
-                egui_wgpu::wgpu::BindGroupEntry {
-                    binding: 4,
-                    resource: egui_wgpu::wgpu::BindingResource::Buffer(
-                        egui_wgpu::wgpu::BufferBinding {
-                            buffer: &device.create_buffer_init(
-                                &egui_wgpu::wgpu::util::BufferInitDescriptor {
-                                    label: Some("Dimensions Buffer"),
-                                    contents: bytemuck::cast_slice(&[dimensions]),
-                                    usage: egui_wgpu::wgpu::BufferUsages::UNIFORM
-                                        | egui_wgpu::wgpu::BufferUsages::COPY_DST,
-                                },
-                            ),
-                            offset: 0,
-                            size: None,
-                        },
-                    ),
-                },
-            ],
-            label: Some("diffuse_bind_group"),
-        });
+        let tint_ramp_texture = ramp::ColorRamp::default().create_texture(&device, &queue);
+        let normal_texture = create_normal_texture(&device, gtiff_texture.size());
+        let diffuse_bind_group = terrain_bind_group(
+            &device,
+            &texture_bind_group_layout,
+            &diffuse_texture,
+            &gtiff_texture,
+            &no_data_mask,
+            &tint_ramp_texture,
+            &normal_texture,
+            dimensions,
+        );
+        let terrain_tiles: Vec<TerrainTile> = elevation_tiles
+            .into_iter()
+            .map(|tile| {
+                TerrainTile::new(
+                    &device,
+                    &texture_bind_group_layout,
+                    &diffuse_texture,
+                    &tint_ramp_texture,
+                    tile,
+                    dimensions,
+                )
+            })
+            .collect();
         debug!("Diffuse bind group created");
 
         let camera = camera::Camera::new((0.0, 5.0, 20.0), cgmath::Deg(-90.0), cgmath::Deg(-20.0));
@@ -328,7 +1955,7 @@ impl<'a> State<'a> {
         let camera_controller = camera::CameraController::new(10.0, 1.0);
 
         let mut camera_uniform = CameraUniform::new();
-        camera_uniform.update_view_proj(&camera, &projection);
+        camera_uniform.update_view_proj(&camera, &projection, false);
         let camera_buffer =
             device.create_buffer_init(&egui_wgpu::wgpu::util::BufferInitDescriptor {
                 label: Some("Camera Buffer"),
@@ -340,7 +1967,9 @@ impl<'a> State<'a> {
             device.create_bind_group_layout(&egui_wgpu::wgpu::BindGroupLayoutDescriptor {
                 entries: &[egui_wgpu::wgpu::BindGroupLayoutEntry {
                     binding: 0,
-                    visibility: egui_wgpu::wgpu::ShaderStages::VERTEX,
+                    // The fragment stage writes logarithmic depth
+                    visibility: egui_wgpu::wgpu::ShaderStages::VERTEX
+                        | egui_wgpu::wgpu::ShaderStages::FRAGMENT,
                     ty: egui_wgpu::wgpu::BindingType::Buffer {
                         ty: egui_wgpu::wgpu::BufferBindingType::Uniform,
                         has_dynamic_offset: false,
@@ -360,62 +1989,129 @@ impl<'a> State<'a> {
         });
         trace!("Camera created");
 
+        let sun = light::Sun::default();
+        let settings_uniform = SettingsUniform::new(
+            &RenderSettings::default(),
+            &sun,
+            &[],
+            &PackingBounds::default(),
+            [0.0; 2],
+        );
+        let settings_buffer =
+            device.create_buffer_init(&egui_wgpu::wgpu::util::BufferInitDescriptor {
+                label: Some("Settings Buffer"),
+                contents: bytemuck::cast_slice(&[settings_uniform]),
+                usage: egui_wgpu::wgpu::BufferUsages::UNIFORM
+                    | egui_wgpu::wgpu::BufferUsages::COPY_DST,
+            });
+        let settings_bind_group_layout =
+            device.create_bind_group_layout(&egui_wgpu::wgpu::BindGroupLayoutDescriptor {
+                entries: &[egui_wgpu::wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: egui_wgpu::wgpu::ShaderStages::VERTEX
+                        | egui_wgpu::wgpu::ShaderStages::FRAGMENT,
+                    ty: egui_wgpu::wgpu::BindingType::Buffer {
+                        ty: egui_wgpu::wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+                label: Some("settings_bind_group_layout"),
+            });
+        let settings_bind_group = device.create_bind_group(&egui_wgpu::wgpu::BindGroupDescriptor {
+            layout: &settings_bind_group_layout,
+            entries: &[egui_wgpu::wgpu::BindGroupEntry {
+                binding: 0,
+                resource: settings_buffer.as_entire_binding(),
+            }],
+            label: Some("settings_bind_group"),
+        });
+        trace!("Settings uniform created");
+
         let depth_texture =
             texture::Texture::create_depth_texture(&device, &config, "depth_texture");
 
         trace!("Creating render pipeline");
         let shader = device.create_shader_module(egui_wgpu::wgpu::include_wgsl!("shader.wgsl"));
         debug!("Shader created");
+        let shadow_map =
+            shadow::ShadowMap::new(&device, RenderSettings::default().shadow_resolution);
+        let shadow_camera_buffer =
+            device.create_buffer_init(&egui_wgpu::wgpu::util::BufferInitDescriptor {
+                label: Some("Shadow Camera Buffer"),
+                contents: bytemuck::cast_slice(&[CameraUniform::new()]),
+                usage: egui_wgpu::wgpu::BufferUsages::UNIFORM
+                    | egui_wgpu::wgpu::BufferUsages::COPY_DST,
+            });
+        let shadow_camera_bind_group =
+            device.create_bind_group(&egui_wgpu::wgpu::BindGroupDescriptor {
+                layout: &camera_bind_group_layout,
+                entries: &[egui_wgpu::wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: shadow_camera_buffer.as_entire_binding(),
+                }],
+                label: Some("shadow_camera_bind_group"),
+            });
         let render_pipeline_layout =
             device.create_pipeline_layout(&egui_wgpu::wgpu::PipelineLayoutDescriptor {
                 label: Some("Render Pipeline Layout"),
-                bind_group_layouts: &[&texture_bind_group_layout, &camera_bind_group_layout],
+                bind_group_layouts: &[
+                    &texture_bind_group_layout,
+                    &camera_bind_group_layout,
+                    &settings_bind_group_layout,
+                    shadow_map.bind_group_layout(),
+                ],
                 push_constant_ranges: &[],
             });
-        let render_pipeline =
-            device.create_render_pipeline(&egui_wgpu::wgpu::RenderPipelineDescriptor {
-                label: Some("Render Pipeline"),
-                layout: Some(&render_pipeline_layout),
-                vertex: egui_wgpu::wgpu::VertexState {
-                    module: &shader,
-                    entry_point: "vs_main",
-                    buffers: &[Vertex::desc()],
-                },
-                fragment: Some(egui_wgpu::wgpu::FragmentState {
-                    module: &shader,
-                    entry_point: "fs_main",
-                    targets: &[Some(egui_wgpu::wgpu::ColorTargetState {
-                        format: config.format,
-                        blend: Some(egui_wgpu::wgpu::BlendState::REPLACE),
-                        write_mask: egui_wgpu::wgpu::ColorWrites::ALL,
-                    })],
-                }),
-                primitive: egui_wgpu::wgpu::PrimitiveState {
-                    topology: egui_wgpu::wgpu::PrimitiveTopology::TriangleStrip,
-                    strip_index_format: None,
-                    front_face: egui_wgpu::wgpu::FrontFace::Ccw,
-                    cull_mode: None,
-                    polygon_mode: egui_wgpu::wgpu::PolygonMode::Fill,
-                    unclipped_depth: false,
-                    conservative: false,
-                },
-                depth_stencil: Some(egui_wgpu::wgpu::DepthStencilState {
-                    format: texture::Texture::DEPTH_FORMAT,
-                    depth_write_enabled: true,
-                    depth_compare: egui_wgpu::wgpu::CompareFunction::Less,
-                    stencil: egui_wgpu::wgpu::StencilState::default(),
-                    bias: egui_wgpu::wgpu::DepthBiasState::default(),
-                }),
-                multisample: egui_wgpu::wgpu::MultisampleState {
-                    count: 1,
-                    mask: !0,
-                    alpha_to_coverage_enabled: false,
-                },
-                multiview: None,
+        let shadow_pipeline_layout =
+            device.create_pipeline_layout(&egui_wgpu::wgpu::PipelineLayoutDescriptor {
+                label: Some("Shadow Pipeline Layout"),
+                bind_group_layouts: &[
+                    &texture_bind_group_layout,
+                    &camera_bind_group_layout,
+                    &settings_bind_group_layout,
+                ],
+                push_constant_ranges: &[],
             });
+        let strip_pipelines = TerrainPipelines::new(
+            &device,
+            &render_pipeline_layout,
+            &shadow_pipeline_layout,
+            &shader,
+            config.format,
+            terrain::Topology::Strip,
+            &RenderSettings::default(),
+        );
+        let list_pipelines = TerrainPipelines::new(
+            &device,
+            &render_pipeline_layout,
+            &shadow_pipeline_layout,
+            &shader,
+            config.format,
+            terrain::Topology::List,
+            &RenderSettings::default(),
+        );
         trace!("Render pipeline created");
 
-        let (verticies, indices) = super::terrain::texture_to_vertices(gtiff_texture, gtiff_buffer);
+        let elevation_meters: Vec<f64> = gtiff_buffer
+            .iter()
+            .map(|&v| elevation_unit.to_meters(v))
+            .collect();
+        let super::terrain::Mesh {
+            vertices: verticies,
+            indices,
+            list_indices,
+            origin,
+        } = super::terrain::texture_to_vertices(
+            &elevation_meters,
+            [geo.width, geo.height],
+            &fit_mesh_options(
+                RenderSettings::default().mesh_options(),
+                [geo.width, geo.height],
+                vertex_limit(std::mem::size_of::<Vertex>(), limits.max_buffer_size),
+            ),
+        );
         debug!(
             "Generated {} verticies, {} indices",
             verticies.len(),
@@ -426,172 +2122,2833 @@ impl<'a> State<'a> {
             device.create_buffer_init(&egui_wgpu::wgpu::util::BufferInitDescriptor {
                 label: Some("Vertex Buffer"),
                 contents: bytemuck::cast_slice(&verticies),
-                usage: egui_wgpu::wgpu::BufferUsages::VERTEX,
+                usage: egui_wgpu::wgpu::BufferUsages::VERTEX
+                    | egui_wgpu::wgpu::BufferUsages::COPY_DST,
             });
         trace!("Vertex buffer created");
         let index_buffer =
             device.create_buffer_init(&egui_wgpu::wgpu::util::BufferInitDescriptor {
                 label: Some("Index Buffer"),
                 contents: bytemuck::cast_slice(&indices),
-                usage: egui_wgpu::wgpu::BufferUsages::INDEX,
+                usage: egui_wgpu::wgpu::BufferUsages::INDEX
+                    | egui_wgpu::wgpu::BufferUsages::COPY_DST,
+            });
+        let list_index_buffer =
+            device.create_buffer_init(&egui_wgpu::wgpu::util::BufferInitDescriptor {
+                label: Some("List Index Buffer"),
+                contents: bytemuck::cast_slice(&list_indices),
+                usage: egui_wgpu::wgpu::BufferUsages::INDEX
+                    | egui_wgpu::wgpu::BufferUsages::COPY_DST,
             });
         // let num_indices = INDICES.len() as u32;
-        trace!("Index buffer created");
+        trace!("Index buffers created");
+        // Filled by `update_lod` once LOD is enabled
+        let lod_vertex_buffer = device.create_buffer(&egui_wgpu::wgpu::BufferDescriptor {
+            label: Some("LOD Vertex Buffer"),
+            size: egui_wgpu::wgpu::COPY_BUFFER_ALIGNMENT,
+            usage: egui_wgpu::wgpu::BufferUsages::VERTEX | egui_wgpu::wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let lod_index_buffer = device.create_buffer(&egui_wgpu::wgpu::BufferDescriptor {
+            label: Some("LOD Index Buffer"),
+            size: egui_wgpu::wgpu::COPY_BUFFER_ALIGNMENT,
+            usage: egui_wgpu::wgpu::BufferUsages::INDEX | egui_wgpu::wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
 
+        let blit = blit::Blit::new(&device, config.format);
+        let outline = outline::Outline::new(&device, config.format, &depth_texture.view);
+        let track_pipeline =
+            track::TrackPipeline::new(&device, config.format, &camera_bind_group_layout);
+        let bounding_box_pipeline =
+            track::TrackPipeline::new(&device, config.format, &camera_bind_group_layout);
+        let water = water::Water::new(&device, config.format, &camera_bind_group_layout);
         let egui = gui::EguiRenderer::new(&device, window);
         trace!("Egui renderer created");
 
-        debug!("State created successfully");
-        Self {
+        let mut state = Self {
             size,
-            clear_color: egui_wgpu::wgpu::Color {
-                r: 0.1,
-                g: 0.2,
-                b: 0.3,
-                a: 1.0,
-            },
-            surface,
+            // Set from the settings in `update`, before the first frame
+            clear_color: egui_wgpu::wgpu::Color::BLACK,
+            surface: Some(window_surface),
             device,
+            device_lost,
+            adapter_info,
+            capabilities,
+            limits,
             queue,
             config,
             window,
-            render_pipeline,
+            strip_pipelines,
+            list_pipelines,
+            render_pipeline_layout,
+            shadow_pipeline_layout,
+            shadow_map,
+            shadow_camera_buffer,
+            shadow_camera_bind_group,
+            shader,
+            #[cfg(feature = "hot-reload")]
+            shader_watcher: match crate::hot_reload::ShaderWatcher::new() {
+                Ok(watcher) => Some(watcher),
+                Err(e) => {
+                    warn!("Failed to watch shader.wgsl: {}", e);
+                    None
+                }
+            },
             vertex_buffer,
             index_buffer,
+            list_index_buffer,
+            num_vertices: verticies.len() as u32,
             num_indices: indicies_size as u32,
+            num_list_indices: list_indices.len() as u32,
+            terrain_tiles,
+            quadtree: lod::QuadTree::new(geo.width as u32, geo.height as u32),
+            lod_nodes: Vec::new(),
+            lod_elevation: None,
+            lod_vertex_buffer,
+            lod_index_buffer,
+            num_lod_vertices: 0,
+            num_lod_indices: 0,
+            packing_bounds: PackingBounds::of(&verticies),
+            // Built by `build_overviews` once they're turned on
+            overviews: Vec::new(),
+            // The overviews are of the full resolution, not of a preview
+            elevation_path: match dem {
+                DemSource::File(path)
+                    if !kept_elevation
+                        && dem_error.is_none()
+                        && startup.preview_level.is_none()
+                        && !downloading =>
+                {
+                    Some(path.clone())
+                }
+                // `recover` carries the kept elevation's over
+                _ => None,
+            },
+            overview_level: 0,
+            terrain_origin: origin,
+            elevation_texture: gtiff_texture,
+            no_data_mask_texture: no_data_mask,
+            normal_texture,
+            tint_ramp_texture,
+            elevation: gtiff_buffer,
+            elevation_min,
+            elevation_max,
+            normalization_range,
+            no_data_count,
+            is_no_data,
+            no_data,
+            sample_type,
+            meters_per_pixel: geo.meters_per_pixel(),
+            indexed,
+            geographic_bounds: match geo.geographic_bounds() {
+                Ok(bounds) => bounds,
+                Err(e) => {
+                    warn!("Failed to compute geographic bounds: {}", e);
+                    None
+                }
+            },
+            geographic_transform: match geo.geographic_transform() {
+                Ok(transform) => transform,
+                Err(e) => {
+                    warn!("Failed to create the WGS84 transform: {}", e);
+                    None
+                }
+            },
+            startup: startup.clone(),
+            geo,
+            export_path: "export.tif".to_string(),
+            export_status: None,
+            map_export_path: "map.png".to_string(),
+            map_export_width: 2048,
+            map_export_requested: false,
+            map_export_status: None,
+            volume_level: 0.0,
+            measure_status: None,
+            preset_path: "preset.toml".to_string(),
+            bookmarks: Vec::new(),
+            key_bindings: KeyBindings::default(),
+            next_bookmark: 0,
+            flight: None,
+            preset_status: None,
+            texture_bind_group_layout,
             diffuse_bind_group,
             _diffuse_texture: diffuse_texture,
+            texture_previews: None,
             camera,
             projection,
             camera_controller,
             camera_uniform,
             camera_buffer,
             camera_bind_group,
+            settings_buffer,
+            settings_bind_group,
             depth_texture,
+            render_target: None,
+            blit,
+            outline,
+            track: None,
+            track_pipeline,
+            bounding_box_pipeline,
+            bounding_box_lines: None,
+            water,
+            water_time: 0.0,
+            track_path: String::new(),
+            track_status: None,
             egui,
             status: Status::default(),
+            settings: RenderSettings {
+                elevation_unit,
+                ..Default::default()
+            },
+            sun,
+            lights: Vec::new(),
+            paused: false,
             mouse_pressed: false,
+            mouse_grabbed: false,
+            copy_camera_requested: false,
+            auto_rotate: false,
+            progressive: None,
+            download: None,
+            downloaded_dem: None,
+            auto_rotate_speed: 10.0,
+            scale_factor: window.scale_factor(),
+            ui_scale: None,
+            cursor_position: None,
+            profiles: Vec::new(),
+            profile_editing: false,
+            flatten_editing: false,
+            flatten_corner: None,
+            clip_editing: false,
+            clip_corner: None,
+            measuring: false,
+            measure_points: Vec::new(),
+            flatten_region: None,
+            flatten_elevation: 0.0,
+            morph: None,
+            morph_partial: false,
+            morph_path: String::new(),
+            morph_status: None,
+            open_dem_path: match dem {
+                DemSource::File(path) if dem_error.is_some() => path.clone(),
+                _ => String::new(),
+            },
+            dem_error,
+            open_dem_status: None,
+            open_dem_requested: None,
             gui_consumed: false,
+        };
+        // The tiles are meshed at full resolution in place of the fitted mesh
+        if !state.terrain_tiles.is_empty() {
+            state.rebuild_mesh();
         }
+        debug!("State created successfully");
+        state
     }
 
-    pub fn window(&self) -> &Window {
-        &self.window
+    /// Brings everything derived from the elevation buffer up to date after
+    /// it was edited: its range, the normalized texture, the LOD chunks and
+    /// the mesh with its normals and bounds. Edit tools call this instead of
+    /// rebuilding those themselves, the overviews are theirs to update with
+    /// `average_overviews`.
+    pub fn rebuild_terrain(&mut self) {
+        let span = debug_span!("rebuild_terrain");
+        let _enter = span.enter();
+
+        [self.elevation_min, self.elevation_max] = self.elevation_range();
+        self.renormalize();
+        self.lod_nodes.clear();
+        self.lod_elevation = None;
+        self.rebuild_mesh();
     }
 
-    pub fn resize(&mut self, new_size: egui_winit::winit::dpi::PhysicalSize<u32>) {
-        if new_size.width > 0 && new_size.height > 0 {
-            self.size = new_size;
-            self.config.width = new_size.width;
-            self.config.height = new_size.height;
-            self.surface.configure(&self.device, &self.config);
-            self.depth_texture =
-                texture::Texture::create_depth_texture(&self.device, &self.config, "depth_texture");
-            self.projection.resize(new_size.width, new_size.height);
-        }
+    /// Lowest and highest value of the elevation buffer
+    fn elevation_range(&self) -> [f64; 2] {
+        self.elevation
+            .iter()
+            .fold([f64::INFINITY, f64::NEG_INFINITY], |[min, max], &v| {
+                [min.min(v), max.max(v)]
+            })
     }
 
-    pub fn input(&mut self, event: &WindowEvent) -> bool {
-        if self.gui_consumed {
-            return true;
-        }
+    /// Re-runs meshing from the current elevation buffer and writes it into the vertex/index buffers
+    fn rebuild_mesh(&mut self) {
+        let span = debug_span!("rebuild_mesh");
+        let _enter = span.enter();
 
-        match event {
-            WindowEvent::KeyboardInput {
-                event:
-                    KeyEvent {
-                        physical_key: PhysicalKey::Code(key),
-                        state,
-                        ..
+        if self.overview_level == 0 && !self.terrain_tiles.is_empty() {
+            self.rebuild_tiles();
+            return;
+        }
+        let unit = self.settings.elevation_unit;
+        let super::terrain::Mesh {
+            vertices: verticies,
+            indices,
+            list_indices,
+            origin,
+        } = match self.overview_level.checked_sub(1) {
+            Some(level) => {
+                // The overview is the decimation, custom resolutions and
+                // budgets apply to the full resolution mesh
+                let overview = &self.overviews[level];
+                let options = fit_mesh_options(
+                    terrain::MeshOptions {
+                        resolution: None,
+                        vertex_budget: None,
+                        ..self.settings.mesh_options()
                     },
-                ..
-            } => self.camera_controller.process_keyboard(*key, *state),
-            WindowEvent::MouseInput {
-                button: MouseButton::Left,
-                state,
-                ..
-            } => {
-                self.mouse_pressed = *state == ElementState::Pressed;
-                false
+                    [overview.width, overview.height],
+                    self.vertex_limit(),
+                );
+                let size = self.dem_size();
+                let buffer: Vec<f64> = overview.buffer.iter().map(|&v| unit.to_meters(v)).collect();
+                let field =
+                    terrain::HeightField::new(&buffer, overview.width, overview.height, &options)
+                        .overview_of(
+                            size.width as usize,
+                            size.height as usize,
+                            unit.to_meters(self.elevation_min),
+                            unit.to_meters(self.elevation_max),
+                        );
+                terrain::field_to_vertices(&field, &options)
             }
-            _ => false,
-        }
+            None => {
+                let elevation_meters: Vec<f64> =
+                    self.elevation.iter().map(|&v| unit.to_meters(v)).collect();
+                let size = self.dem_size();
+                super::terrain::texture_to_vertices(
+                    &elevation_meters,
+                    [size.width as usize, size.height as usize],
+                    &self.mesh_options(),
+                )
+            }
+        };
+        self.terrain_origin = origin;
+        self.packing_bounds = PackingBounds::of(&verticies);
+        let vertex_bytes = self.vertex_bytes(&verticies);
+        write_mesh_buffer(
+            &self.device,
+            &self.queue,
+            &mut self.vertex_buffer,
+            &vertex_bytes,
+            "Vertex Buffer",
+            egui_wgpu::wgpu::BufferUsages::VERTEX,
+        );
+        write_mesh_buffer(
+            &self.device,
+            &self.queue,
+            &mut self.index_buffer,
+            bytemuck::cast_slice(&indices),
+            "Index Buffer",
+            egui_wgpu::wgpu::BufferUsages::INDEX,
+        );
+        write_mesh_buffer(
+            &self.device,
+            &self.queue,
+            &mut self.list_index_buffer,
+            bytemuck::cast_slice(&list_indices),
+            "List Index Buffer",
+            egui_wgpu::wgpu::BufferUsages::INDEX,
+        );
+        self.num_vertices = verticies.len() as u32;
+        self.num_indices = indices.len() as u32;
+        self.num_list_indices = list_indices.len() as u32;
+        self.lod_nodes.clear();
+        self.update_track();
+        self.update_normal_map();
+        debug!(
+            "Rebuilt mesh: {} verticies, {} indices",
+            verticies.len(),
+            indices.len()
+        );
     }
 
-    pub fn update(&mut self, dt: std::time::Duration) {
-        self.camera_controller.update_camera(&mut self.camera, dt);
-        self.camera_uniform
-            .update_view_proj(&self.camera, &self.projection);
-        self.queue.write_buffer(
-            &self.camera_buffer,
-            0,
-            bytemuck::cast_slice(&[self.camera_uniform]),
+    /// Meshes every tile of a DEM over the texture limit into its own
+    /// buffers, with the stride that keeps each under the buffer size limit
+    fn rebuild_tiles(&mut self) {
+        let unit = self.settings.elevation_unit;
+        let elevation_meters: Vec<f64> =
+            self.elevation.iter().map(|&v| unit.to_meters(v)).collect();
+        let size = self.dem_size();
+        let size = [size.width as usize, size.height as usize];
+        let options = self.settings.mesh_options();
+        let windows: Vec<[usize; 4]> = self
+            .terrain_tiles
+            .iter()
+            .map(|tile| tile.elevation.window())
+            .collect();
+        let stride = terrain::tile_stride(&windows, size, self.vertex_limit(), &options);
+        info!(
+            "Meshing {} tiles of the {}x{} DEM every {} pixels",
+            windows.len(),
+            size[0],
+            size[1],
+            stride
         );
-    }
+        let field = terrain::HeightField::new(&elevation_meters, size[0], size[1], &options);
+        let meshes = terrain::tiles_to_vertices(&field, &windows, stride, &options);
 
-    pub fn render(&mut self) -> Result<(), egui_wgpu::wgpu::SurfaceError> {
-        let output = self.surface.get_current_texture()?;
+        self.terrain_origin = field.origin();
+        self.packing_bounds = meshes
+            .iter()
+            .map(|mesh| PackingBounds::of(&mesh.vertices))
+            .reduce(PackingBounds::union)
+            .unwrap_or_default();
+        let vertex_bytes: Vec<Vec<u8>> = meshes
+            .iter()
+            .map(|mesh| self.vertex_bytes(&mesh.vertices))
+            .collect();
+        for ((tile, mesh), vertex_bytes) in
+            self.terrain_tiles.iter_mut().zip(&meshes).zip(vertex_bytes)
+        {
+            write_mesh_buffer(
+                &self.device,
+                &self.queue,
+                &mut tile.vertex_buffer,
+                &vertex_bytes,
+                "Tile Vertex Buffer",
+                egui_wgpu::wgpu::BufferUsages::VERTEX,
+            );
+            write_mesh_buffer(
+                &self.device,
+                &self.queue,
+                &mut tile.index_buffer,
+                bytemuck::cast_slice(&mesh.indices),
+                "Tile Index Buffer",
+                egui_wgpu::wgpu::BufferUsages::INDEX,
+            );
+            write_mesh_buffer(
+                &self.device,
+                &self.queue,
+                &mut tile.list_index_buffer,
+                bytemuck::cast_slice(&mesh.list_indices),
+                "Tile List Index Buffer",
+                egui_wgpu::wgpu::BufferUsages::INDEX,
+            );
+            tile.num_vertices = mesh.vertices.len() as u32;
+            tile.num_indices = mesh.indices.len() as u32;
+            tile.num_list_indices = mesh.list_indices.len() as u32;
+        }
+        self.lod_nodes.clear();
+        self.update_track();
+        self.update_normal_map();
+        debug!(
+            "Rebuilt {} tiles: {} verticies",
+            meshes.len(),
+            meshes.iter().map(|mesh| mesh.vertices.len()).sum::<usize>()
+        );
+    }
 
-        let view = output
-            .texture
-            .create_view(&egui_wgpu::wgpu::TextureViewDescriptor::default());
+    /// `settings.mesh_options` for the full resolution mesh, with a vertex
+    /// budget when it wouldn't fit the buffer size limit otherwise
+    fn mesh_options(&self) -> terrain::MeshOptions {
+        let size = self.dem_size();
+        fit_mesh_options(
+            self.settings.mesh_options(),
+            [size.width as usize, size.height as usize],
+            self.vertex_limit(),
+        )
+    }
 
-        let mut encoder =
-            self.device
-                .create_command_encoder(&egui_wgpu::wgpu::CommandEncoderDescriptor {
-                    label: Some("Render Encoder"),
-                });
+    /// Most vertices a mesh in the current vertex format fits the buffers with
+    fn vertex_limit(&self) -> u32 {
+        vertex_limit(self.vertex_stride(), self.limits.max_buffer_size)
+    }
 
-        {
-            let mut render_pass =
-                encoder.begin_render_pass(&egui_wgpu::wgpu::RenderPassDescriptor {
-                    label: Some("Render Pass"),
-                    color_attachments: &[Some(egui_wgpu::wgpu::RenderPassColorAttachment {
-                        view: &view,
-                        resolve_target: None,
-                        ops: egui_wgpu::wgpu::Operations {
-                            load: egui_wgpu::wgpu::LoadOp::Clear(self.clear_color),
-                            store: egui_wgpu::wgpu::StoreOp::Store,
-                        },
-                    })],
-                    depth_stencil_attachment: Some(
-                        egui_wgpu::wgpu::RenderPassDepthStencilAttachment {
-                            view: &self.depth_texture.view,
-                            depth_ops: Some(egui_wgpu::wgpu::Operations {
-                                load: egui_wgpu::wgpu::LoadOp::Clear(1.0),
-                                store: egui_wgpu::wgpu::StoreOp::Store,
-                            }),
-                            stencil_ops: None,
-                        },
-                    ),
-                    occlusion_query_set: None,
-                    timestamp_writes: None,
+    /// Selects the quadtree chunks for the current camera and remeshes them
+    /// when the selection changed
+    fn update_lod(&mut self) {
+        let camera = [
+            self.camera.position.x as f64 + self.terrain_origin.x,
+            self.camera.position.y as f64,
+            self.camera.position.z as f64 + self.terrain_origin.z,
+        ];
+        let unit = self.settings.elevation_unit;
+        let size = self.dem_size();
+        let elevation = &self.elevation;
+        let (elevation_meters, [minimum, maximum]) = self.lod_elevation.get_or_insert_with(|| {
+            let meters: Vec<f64> = elevation.iter().map(|&v| unit.to_meters(v)).collect();
+            let range = meters
+                .iter()
+                .fold([f64::INFINITY, f64::NEG_INFINITY], |[min, max], &v| {
+                    [min.min(v), max.max(v)]
                 });
-
-            render_pass.set_pipeline(&self.render_pipeline);
-            render_pass.set_bind_group(0, &self.diffuse_bind_group, &[]);
-            render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
-            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            render_pass.set_index_buffer(
-                self.index_buffer.slice(..),
-                egui_wgpu::wgpu::IndexFormat::Uint16,
-            );
-            render_pass.draw_indexed(0..self.num_indices, 0, 0..1);
+            (meters, range)
+        });
+        let options = self.settings.mesh_options();
+        // Cheap without the range scans, the mesh is only built on a change
+        let field = terrain::HeightField::with_range(
+            elevation_meters,
+            size.width as usize,
+            size.height as usize,
+            [*minimum, *maximum],
+            &options,
+        );
+        let nodes = self
+            .quadtree
+            .select(camera, field.top(), self.settings.lod_factor as f64);
+        if nodes == self.lod_nodes {
+            return;
         }
 
-        let screen_descriptor = egui_wgpu::ScreenDescriptor {
-            size_in_pixels: [self.size.width, self.size.height],
-            pixels_per_point: 1.0,
-        };
-
-        self.egui.render(
+        let (vertices, indices) = self.quadtree.build(&field, &nodes);
+        let vertex_bytes = self.vertex_bytes(&vertices);
+        write_mesh_buffer(
             &self.device,
             &self.queue,
-            &mut encoder,
+            &mut self.lod_vertex_buffer,
+            &vertex_bytes,
+            "LOD Vertex Buffer",
+            egui_wgpu::wgpu::BufferUsages::VERTEX,
+        );
+        write_mesh_buffer(
+            &self.device,
+            &self.queue,
+            &mut self.lod_index_buffer,
+            bytemuck::cast_slice(&indices),
+            "LOD Index Buffer",
+            egui_wgpu::wgpu::BufferUsages::INDEX,
+        );
+        self.num_lod_vertices = vertices.len() as u32;
+        self.num_lod_indices = indices.len() as u32;
+        self.lod_nodes = nodes;
+    }
+
+    /// Sets the elevation of `region`, see `flatten_region`, to `elevation`
+    /// meters. NoData pixels are left alone. Only the affected vertices are
+    /// rewritten when the mesh has one vertex per DEM pixel and the elevation
+    /// range didn't change, otherwise the whole mesh is rebuilt.
+    /// Reads the overviews of `elevation_path` into `overviews` when they
+    /// aren't built yet. Without a file, or once the elevation was edited,
+    /// the elevation is averaged down by 2, 4, 8... instead.
+    fn build_overviews(&mut self) {
+        if self.indexed || !self.overviews.is_empty() {
+            return;
+        }
+        let span = debug_span!("build_overviews");
+        let _enter = span.enter();
+
+        if let Some(path) = &self.elevation_path {
+            match gtiff::read_overviews(path, OVERVIEW_LEVELS) {
+                Ok(overviews) => {
+                    debug!("Read {} overviews", overviews.len());
+                    self.overviews = overviews;
+                    return;
+                }
+                Err(e) => warn!("Failed to read overviews, averaging instead: {}", e),
+            }
+        }
+
+        let size = self.dem_size();
+        let (width, height) = (size.width as usize, size.height as usize);
+        for level in 1..=OVERVIEW_LEVELS {
+            let factor = 1 << level;
+            let size = (width.div_ceil(factor), height.div_ceil(factor));
+            if size.0 < 2 || size.1 < 2 {
+                break;
+            }
+            self.overviews.push(gtiff::Overview {
+                factor,
+                width: size.0,
+                height: size.1,
+                buffer: vec![f64::NAN; size.0 * size.1],
+            });
+        }
+        self.average_overviews([0, 0, width - 1, height - 1]);
+        debug!("Built {} overviews", self.overviews.len());
+    }
+
+    /// Averages the overview pixels covering `region` of `elevation` again
+    fn average_overviews(&mut self, [left, top, right, bottom]: [usize; 4]) {
+        let size = self.dem_size();
+        let width = size.width as usize;
+        let height = size.height as usize;
+        for overview in &mut self.overviews {
+            let factor = overview.factor;
+            for row in top / factor..=bottom / factor {
+                for column in left / factor..=right / factor {
+                    let rows = row * factor..((row + 1) * factor).min(height);
+                    let columns = column * factor..((column + 1) * factor).min(width);
+                    let (sum, count) = rows
+                        .flat_map(|r| columns.clone().map(move |c| r * width + c))
+                        .map(|index| self.elevation[index])
+                        .filter(|v| !v.is_nan())
+                        .fold((0.0, 0), |(sum, count), v| (sum + v, count + 1));
+                    // NoData stays NaN, like in `elevation`
+                    overview.buffer[row * overview.width + column] = if count > 0 {
+                        sum / count as f64
+                    } else {
+                        f64::NAN
+                    };
+                }
+            }
+        }
+    }
+
+    /// Loads the GeoTIFF at `path` as the DEM to morph towards. It has to be
+    /// the size of the loaded DEM.
+    fn load_morph(&mut self, path: &str) -> anyhow::Result<()> {
+        let span = debug_span!("load_morph", path = path);
+        let _enter = span.enter();
+
+        let target = super::gtiff::read_elevation(path)?;
+        let [low, high] = target
+            .2
+            .iter()
+            .flatten()
+            .fold(self.normalization_range, |[low, high], &v| {
+                [low.min(v), high.max(v)]
+            });
+        let from = match &self.morph {
+            Some(morph) => morph.from().to_vec(),
+            None => self.elevation.clone(),
+        };
+        let size = self.dem_size();
+        let morph = morph::Morph::new(from, (size.width as usize, size.height as usize), target)?;
+        // Normalize over both DEMs so the colors don't shift during the morph
+        self.normalization_range = [low, high];
+        self.morph = Some(morph);
+        self.apply_morph();
+        Ok(())
+    }
+
+    /// Replaces the elevation with the morph blend and rebuilds everything
+    /// derived from it
+    fn apply_morph(&mut self) {
+        let span = debug_span!("apply_morph");
+        let _enter = span.enter();
+
+        let Some(morph) = &self.morph else {
+            return;
+        };
+        self.elevation = morph.blend(&self.is_no_data);
+        self.elevation_path = None;
+        let size = self.dem_size();
+        self.average_overviews([0, 0, size.width as usize - 1, size.height as usize - 1]);
+        self.morph_partial = false;
+        self.rebuild_terrain();
+    }
+
+    /// Follows an animated morph every frame by rewriting only the vertex
+    /// buffer and the elevation texture. The overviews, normal map and the
+    /// rest derived from the elevation are left for `apply_morph`.
+    fn animate_morph(&mut self) {
+        let span = debug_span!("animate_morph");
+        let _enter = span.enter();
+
+        let Some(morph) = &self.morph else {
+            return;
+        };
+        // Overview, LOD and tile meshes aren't built from the vertex buffer
+        if self.overview_level != 0 || self.settings.lod || !self.terrain_tiles.is_empty() {
+            self.apply_morph();
+            return;
+        }
+        self.elevation = morph.blend(&self.is_no_data);
+        self.elevation_path = None;
+        [self.elevation_min, self.elevation_max] = self.elevation_range();
+        self.renormalize();
+
+        let unit = self.settings.elevation_unit;
+        let elevation_meters: Vec<f64> =
+            self.elevation.iter().map(|&v| unit.to_meters(v)).collect();
+        let size = self.dem_size();
+        let mesh = terrain::texture_to_vertices(
+            &elevation_meters,
+            [size.width as usize, size.height as usize],
+            &self.mesh_options(),
+        );
+        // The indices only depend on the size and the NoData, which stay the same
+        self.terrain_origin = mesh.origin;
+        self.packing_bounds = PackingBounds::of(&mesh.vertices);
+        let vertex_bytes = self.vertex_bytes(&mesh.vertices);
+        write_mesh_buffer(
+            &self.device,
+            &self.queue,
+            &mut self.vertex_buffer,
+            &vertex_bytes,
+            "Vertex Buffer",
+            egui_wgpu::wgpu::BufferUsages::VERTEX,
+        );
+        self.morph_partial = true;
+    }
+
+    /// Flips the sign of the loaded elevation and everything derived from it,
+    /// see `RenderSettings::invert_elevation`
+    fn negate_elevation(&mut self) {
+        let span = debug_span!("negate_elevation");
+        let _enter = span.enter();
+
+        self.elevation_path = None;
+        for value in self.elevation.iter_mut().chain(
+            self.overviews
+                .iter_mut()
+                .flat_map(|overview| overview.buffer.iter_mut()),
+        ) {
+            *value = -*value;
+        }
+        if let Some(morph) = &mut self.morph {
+            morph.negate();
+        }
+        let [low, high] = self.normalization_range;
+        self.normalization_range = [-high, -low];
+        self.rebuild_terrain();
+    }
+
+    /// Loads the vector file at `path` as the track
+    fn load_track(&mut self, path: &str) -> gdal::errors::Result<()> {
+        self.track = Some(track::Track::load(path, &self.geo)?);
+        self.update_track();
+        Ok(())
+    }
+
+    /// Computes the normals of the current elevation into `normal_texture`,
+    /// skipped while `settings.normal_map` is off
+    fn update_normal_map(&mut self) {
+        if !self.settings.normal_map {
+            return;
+        }
+        let dem_size = self.dem_size();
+        let unit = self.settings.elevation_unit;
+        let elevation_meters: Vec<f64> =
+            self.elevation.iter().map(|&v| unit.to_meters(v)).collect();
+        let options = self.settings.mesh_options();
+        let field = terrain::HeightField::new(
+            &elevation_meters,
+            dem_size.width as usize,
+            dem_size.height as usize,
+            &options,
+        );
+        // The tiles have their own at full resolution
+        let whole = [0, 0, dem_size.width as usize, dem_size.height as usize];
+        let textures = std::iter::once((&self.normal_texture, whole)).chain(
+            self.terrain_tiles
+                .iter()
+                .map(|tile| (&tile.normal_texture, tile.elevation.window())),
+        );
+        for (texture, window) in textures {
+            let size = texture.size();
+            let normals = field.normal_map(window, [size.width as usize, size.height as usize]);
+            self.queue.write_texture(
+                texture.as_image_copy(),
+                bytemuck::cast_slice(&normals),
+                egui_wgpu::wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(4 * size.width),
+                    rows_per_image: Some(size.height),
+                },
+                size,
+            );
+        }
+        debug!("Updated normal map");
+    }
+
+    /// World space box around the terrain between its lowest and highest point
+    fn terrain_bounds(&self) -> (cgmath::Point3<f32>, cgmath::Point3<f32>) {
+        let size = self.dem_size();
+        let corner = self.dataset_to_world(0.0, self.elevation_min, 0.0);
+        let opposite = self.dataset_to_world(
+            (size.width - 1) as f64,
+            self.elevation_max,
+            (size.height - 1) as f64,
+        );
+        let min = cgmath::Point3::new(
+            corner.x.min(opposite.x),
+            corner.y.min(opposite.y),
+            corner.z.min(opposite.z),
+        );
+        let max = cgmath::Point3::new(
+            corner.x.max(opposite.x),
+            corner.y.max(opposite.y),
+            corner.z.max(opposite.z),
+        );
+        (min, max)
+    }
+
+    /// Drapes the track over the current terrain again
+    fn update_track(&mut self) {
+        let Some(track) = &self.track else {
+            return;
+        };
+        let vertices = track.vertices(
+            |column, row| {
+                self.sample_elevation(column, row)
+                    .map(|elevation| self.dataset_to_world(column, elevation, row))
+            },
+            !self.config.format.is_srgb(),
+        );
+        trace!("Track has {} vertices", vertices.len());
+        self.track_pipeline.set_vertices(&self.device, &vertices);
+    }
+
+    fn flatten(&mut self, region: [usize; 4], elevation: f64) {
+        let span = debug_span!("flatten", ?region);
+        let _enter = span.enter();
+
+        let size = self.dem_size();
+        let width = size.width as usize;
+        let height = size.height as usize;
+        let unit = self.settings.elevation_unit;
+        let value = unit.from_meters(elevation);
+        let [left, top, right, bottom] = region;
+        for row in top..=bottom {
+            for column in left..=right {
+                let index = row * width + column;
+                if !self.is_no_data[index] {
+                    self.elevation[index] = value;
+                }
+            }
+        }
+        self.elevation_path = None;
+
+        self.average_overviews(region);
+
+        let range = self.elevation_range();
+        let range_changed = range != [self.elevation_min, self.elevation_max];
+        let [low, high] = self.normalization_range;
+        self.normalization_range = [low.min(value), high.max(value)];
+
+        // Tiles and meshes fitted to the buffer size limit don't have a
+        // vertex per pixel in row-major order
+        let tile_size = self.limits.max_texture_dimension_2d as usize;
+        let full_grid = self.overview_level == 0
+            && width <= tile_size
+            && height <= tile_size
+            && self.mesh_options().vertex_budget.is_none()
+            && self.settings.mesh_resolution.is_none();
+        // The skirt copies the border vertices
+        let on_border = left == 0 || top == 0 || right + 1 >= width || bottom + 1 >= height;
+        if range_changed || !full_grid || (self.settings.skirt && on_border) {
+            self.rebuild_terrain();
+            return;
+        }
+        // Only the vertices of the region change, they are rewritten in place
+        self.renormalize();
+        self.lod_nodes.clear();
+        self.lod_elevation = None;
+        self.update_track();
+        self.update_normal_map();
+
+        // The neighbouring vertices are rewritten too, their normals change
+        let elevation_meters: Vec<f64> =
+            self.elevation.iter().map(|&v| unit.to_meters(v)).collect();
+        let options = self.settings.mesh_options();
+        let field = terrain::HeightField::new(&elevation_meters, width, height, &options);
+        let columns = left.saturating_sub(1)..=(right + 1).min(width - 1);
+        let rows = top.saturating_sub(1)..=(bottom + 1).min(height - 1);
+        for row in rows.clone() {
+            let vertices: Vec<Vertex> = columns
+                .clone()
+                .map(|column| field.vertex(column as f64, row as f64, [1.0, 1.0]))
+                .collect();
+            let offset = (row * width + columns.start()) * self.vertex_stride();
+            self.queue.write_buffer(
+                &self.vertex_buffer,
+                offset as egui_wgpu::wgpu::BufferAddress,
+                &self.vertex_bytes(&vertices),
+            );
+        }
+        debug!("Rewrote {}x{} vertices", columns.count(), rows.count());
+    }
+
+    /// Distance and bearing between the two measurement points, `None`
+    /// before both are picked
+    fn measurement(&self) -> Option<String> {
+        let [a, b] = self.measure_points[..] else {
+            return None;
+        };
+        let cells = (b.x - a.x).hypot(b.z - a.z);
+        let rise = b.y - a.y;
+        let distance = match self.meters_per_pixel {
+            Some(meters_per_pixel) => {
+                let horizontal = cells * meters_per_pixel;
+                format!(
+                    "Distance: {:.1} m, {:.1} m along the slope",
+                    horizontal,
+                    horizontal.hypot(rise)
+                )
+            }
+            None => format!("Distance: {:.1} cells", cells),
+        };
+        let bearing = match self.geo.bearing([a.x, a.z], [b.x, b.z]) {
+            Some(bearing) => format!("Bearing: {:.1}°", bearing),
+            None => "Bearing: undefined, the points coincide".to_string(),
+        };
+        Some(format!("{}\n{}\nRise: {:.1} m", distance, bearing, rise))
+    }
+
+    /// Surface area and the volume above `volume_level` of the loaded DEM
+    fn measure_surface(&self) -> String {
+        let span = debug_span!("measure_surface");
+        let _enter = span.enter();
+
+        let unit = self.settings.elevation_unit;
+        let size = self.dem_size();
+        let (width, height) = (size.width as usize, size.height as usize);
+        let elevation: Vec<f64> = self.elevation.iter().map(|&v| unit.to_meters(v)).collect();
+        let cell = self.meters_per_pixel.unwrap_or(1.0);
+        let area = terrain::surface_area(&elevation, width, height, cell, &self.is_no_data);
+        let volume = terrain::volume_above(
+            &elevation,
+            width,
+            height,
+            cell,
+            &self.is_no_data,
+            self.volume_level,
+        );
+        debug!("Surface area {} m², volume {} m³", area, volume);
+        let status = format!(
+            "Area: {:.4} km², Volume: {:.4} km³",
+            area / 1e6,
+            volume / 1e9
+        );
+        if self.meters_per_pixel.is_none() {
+            format!("{} (no spatial reference, 1 m pixels)", status)
+        } else {
+            status
+        }
+    }
+
+    /// Background for the camera `above` world units over the terrain
+    fn sky_color(&self, above: f32) -> egui_wgpu::wgpu::Color {
+        if !self.settings.sky_gradient {
+            let [r, g, b] = self.settings.clear_color.map(|c| c as f64);
+            return egui_wgpu::wgpu::Color { r, g, b, a: 1.0 };
+        }
+        let size = self.dem_size();
+        let altitude = size.width.max(size.height) as f32 * self.settings.sky_altitude;
+        let t = (above / altitude.max(f32::EPSILON)).clamp(0.0, 1.0);
+        let t = t * t * (3.0 - 2.0 * t);
+        let [r, g, b] = [0, 1, 2].map(|i| {
+            let (low, high) = (self.settings.sky_low[i], self.settings.sky_high[i]);
+            (low + (high - low) * t) as f64
+        });
+        egui_wgpu::wgpu::Color { r, g, b, a: 1.0 }
+    }
+
+    /// Converts a world position to (column, elevation in meters, row) of the loaded DEM
+    pub fn world_to_dataset(&self, position: cgmath::Point3<f32>) -> cgmath::Vector3<f64> {
+        cgmath::Vector3::new(
+            position.x as f64 + self.terrain_origin.x,
+            position.y as f64 * super::terrain::VERTICAL_SCALE / self.settings.exaggeration as f64
+                + self.terrain_origin.y,
+            position.z as f64 + self.terrain_origin.z,
+        )
+    }
+
+    fn surface(&self) -> &egui_wgpu::wgpu::Surface<'a> {
+        match &self.surface {
+            Some(window_surface) => &window_surface.surface,
+            None => {
+                error!("The surface was taken and not put back");
+                panic!();
+            }
+        }
+    }
+
+    pub fn is_device_lost(&self) -> bool {
+        self.device_lost.load(Ordering::SeqCst)
+    }
+
+    /// Recreates the device and every GPU resource after a device loss on the
+    /// same surface, keeping the camera, user settings and edited elevation
+    pub async fn recover(&mut self) {
+        let span = debug_span!("State::recover");
+        let _enter = span.enter();
+        warn!("Recreating state after device loss");
+
+        self.shutdown();
+        let Some(window_surface) = self.surface.take() else {
+            error!("The surface was taken and not put back");
+            panic!();
+        };
+        // Indexed DEMs are colors rather than heights and can't be edited, so
+        // those are read again
+        let kept = (!self.indexed).then(|| KeptElevation {
+            buffer: std::mem::take(&mut self.elevation),
+            unit: self.settings.elevation_unit,
+            no_data: self.no_data,
+            sample_type: self.sample_type,
+            geo: self.geo.clone(),
+            range: self.normalization_range,
+        });
+        let mut state = State::with_surface(self.window, &self.startup, window_surface, kept).await;
+        std::mem::swap(&mut state.camera, &mut self.camera);
+        std::mem::swap(&mut state.camera_controller, &mut self.camera_controller);
+        std::mem::swap(&mut state.settings, &mut self.settings);
+        std::mem::swap(&mut state.sun, &mut self.sun);
+        std::mem::swap(&mut state.lights, &mut self.lights);
+        std::mem::swap(&mut state.key_bindings, &mut self.key_bindings);
+        std::mem::swap(&mut state.profiles, &mut self.profiles);
+        std::mem::swap(&mut state.status, &mut self.status);
+        state.profile_editing = self.profile_editing;
+        state.paused = self.paused;
+        state.auto_rotate = self.auto_rotate;
+        // The window keeps the grab across the new state
+        state.mouse_grabbed = self.mouse_grabbed;
+        state.auto_rotate_speed = self.auto_rotate_speed;
+        state.ui_scale = self.ui_scale;
+        std::mem::swap(&mut state.preset_path, &mut self.preset_path);
+        std::mem::swap(&mut state.bookmarks, &mut self.bookmarks);
+        state.next_bookmark = self.next_bookmark;
+        std::mem::swap(&mut state.morph, &mut self.morph);
+        std::mem::swap(&mut state.morph_path, &mut self.morph_path);
+        std::mem::swap(&mut state.track, &mut self.track);
+        std::mem::swap(&mut state.track_path, &mut self.track_path);
+        if !self.indexed {
+            // Inverted, morphed and flattened like the kept elevation
+            std::mem::swap(&mut state.overviews, &mut self.overviews);
+            std::mem::swap(&mut state.elevation_path, &mut self.elevation_path);
+            std::mem::swap(&mut state.dem_error, &mut self.dem_error);
+        }
+        state.resize(self.size);
+        *self = state;
+
+        // Settings baked into the mesh and pipelines need to be applied again
+        if self.indexed {
+            // The morph was inverted with the old elevation already
+            let morph = self.morph.take();
+            if self.settings.invert_elevation {
+                self.negate_elevation();
+            }
+            self.morph = morph;
+            self.apply_morph();
+        }
+        self.rebuild_terrain();
+        self.recreate_pipelines();
+        self.settings
+            .tint_ramp
+            .write_texture(&self.queue, &self.tint_ramp_texture);
+        debug!("State recovered");
+    }
+
+    /// Downloads the DEM at `url` in the background, the window shows the
+    /// progress until `poll_download` opens it
+    pub fn start_download(&mut self, url: &str) {
+        info!("Downloading DEM from {}", url);
+        self.download = Some(download::Download::start(url));
+    }
+
+    /// Opens the downloaded DEM once the download is done. A failed download
+    /// is shown in the Open DEM prompt.
+    pub fn poll_download(&mut self) {
+        let Some(result) = self.download.as_mut().and_then(|download| download.poll()) else {
+            return;
+        };
+        let Some(download) = self.download.take() else {
+            return;
+        };
+        match result {
+            Ok(path) => {
+                self.open_dem(path.to_string_lossy().into_owned());
+                if matches!(&self.startup.dem, DemSource::File(dem) if std::path::Path::new(dem) == path)
+                {
+                    self.downloaded_dem = Some(path);
+                } else {
+                    download::remove(&path);
+                }
+            }
+            Err(e) => {
+                error!("Failed to download DEM: {}", e);
+                self.dem_error = Some(format!("Failed to download {}: {}", download.url, e));
+            }
+        }
+    }
+
+    /// DEM picked in the Open DEM prompt, for `open_dem`
+    pub fn take_open_dem_request(&mut self) -> Option<String> {
+        self.open_dem_requested.take()
+    }
+
+    /// Shows the loaded DEM as the first stage of `loader`, flattened by its
+    /// decimation to look like the full resolution
+    pub fn start_progressive(&mut self, loader: progressive::Progressive) {
+        self.settings.exaggeration /= loader.factor as f32;
+        self.progressive = Some(loader);
+        self.rebuild_terrain();
+    }
+
+    /// Next stage of a progressive load when one is ready, for `load_stage`
+    pub fn take_progressive_stage(&mut self) -> Option<progressive::Stage> {
+        self.progressive.as_ref()?.poll()
+    }
+
+    /// Swaps in a finer stage of a progressive load, keeping the view and
+    /// settings. The world grows with the resolution, so the camera and the
+    /// exaggeration are scaled along to keep the terrain looking the same.
+    pub fn load_stage(&mut self, stage: progressive::Stage) {
+        let span = debug_span!("State::load_stage", factor = stage.factor);
+        let _enter = span.enter();
+
+        let Some(mut loader) = self.progressive.take() else {
+            return;
+        };
+        // The stages are decimated already
+        let elevation =
+            match super::gtiff::load_elevation(&self.device, &self.queue, &stage.path, None) {
+                Ok(elevation) => elevation,
+                Err(e) => {
+                    error!("Failed to load the 1/{} stage: {}", stage.factor, e);
+                    return;
+                }
+            };
+        let ratio = loader.factor as f32 / stage.factor as f32;
+        self.settings.exaggeration *= ratio;
+        self.camera.position = cgmath::Point3::from_vec(self.camera.position.to_vec() * ratio);
+        // The stages are in meters, only the DEM itself has its own unit
+        self.replace_elevation(elevation);
+        self.elevation_path = Some(stage.path);
+        loader.factor = stage.factor;
+        self.progressive = (stage.factor > 1).then_some(loader);
+        info!("Swapped in the 1/{} stage", stage.factor);
+    }
+
+    /// Swaps in the textures and metadata of another DEM on the current
+    /// device and rebuilds the terrain from it
+    fn replace_elevation(&mut self, elevation: super::gtiff::Elevation) {
+        let span = debug_span!("replace_elevation");
+        let _enter = span.enter();
+
+        let super::gtiff::Elevation {
+            texture,
+            buffer,
+            unit,
+            no_data_mask,
+            no_data_count,
+            is_no_data,
+            no_data,
+            sample_type,
+            geo,
+            indexed,
+            range,
+            tiles,
+        } = elevation;
+        // The textures are still bound by frames in flight
+        self.device.poll(egui_wgpu::wgpu::Maintain::Wait);
+        self.elevation_texture.destroy();
+        self.no_data_mask_texture.destroy();
+        self.normal_texture.destroy();
+        for tile in &self.terrain_tiles {
+            tile.destroy();
+        }
+        self.normal_texture = create_normal_texture(&self.device, texture.size());
+        let dimensions = Dimensions::new(
+            geo.width,
+            geo.height,
+            indexed,
+            !self.config.format.is_srgb(),
+        );
+        self.diffuse_bind_group = terrain_bind_group(
+            &self.device,
+            &self.texture_bind_group_layout,
+            &self._diffuse_texture,
+            &texture,
+            &no_data_mask,
+            &self.tint_ramp_texture,
+            &self.normal_texture,
+            dimensions,
+        );
+        self.terrain_tiles = tiles
+            .into_iter()
+            .map(|tile| {
+                TerrainTile::new(
+                    &self.device,
+                    &self.texture_bind_group_layout,
+                    &self._diffuse_texture,
+                    &self.tint_ramp_texture,
+                    tile,
+                    dimensions,
+                )
+            })
+            .collect();
+        self.quadtree = lod::QuadTree::new(geo.width as u32, geo.height as u32);
+        self.elevation_texture = texture;
+        self.no_data_mask_texture = no_data_mask;
+        self.elevation = buffer;
+        self.normalization_range = range;
+        self.no_data_count = no_data_count;
+        self.is_no_data = is_no_data;
+        self.no_data = no_data;
+        self.sample_type = sample_type;
+        self.indexed = indexed;
+        self.overviews = Vec::new();
+        self.elevation_path = None;
+        self.overview_level = 0;
+        self.meters_per_pixel = geo.meters_per_pixel();
+        self.geographic_bounds = match geo.geographic_bounds() {
+            Ok(bounds) => bounds,
+            Err(e) => {
+                warn!("Failed to compute geographic bounds: {}", e);
+                None
+            }
+        };
+        self.geographic_transform = match geo.geographic_transform() {
+            Ok(transform) => transform,
+            Err(e) => {
+                warn!("Failed to create the WGS84 transform: {}", e);
+                None
+            }
+        };
+        self.geo = geo;
+        self.settings.elevation_unit = unit;
+
+        // Everything placed on the previous DEM's pixels
+        self.profiles.clear();
+        self.profile_editing = false;
+        self.flatten_editing = false;
+        self.flatten_corner = None;
+        self.flatten_region = None;
+        self.clip_editing = false;
+        self.clip_corner = None;
+        self.measuring = false;
+        self.measure_points.clear();
+        self.measure_status = None;
+        self.morph = None;
+        self.morph_partial = false;
+        self.morph_status = None;
+        // Tracks are georeferenced, so they drape over the new DEM once read again
+        if self.track.take().is_some() {
+            let path = self.track_path.clone();
+            if let Err(e) = self.load_track(&path) {
+                warn!("Failed to read track {} again: {}", path, e);
+                self.track_status = Some(format!("Failed to load: {}", e));
+                self.track_pipeline.set_vertices(&self.device, &[]);
+            }
+        }
+        if let Some(previews) = self.texture_previews.take() {
+            previews.destroy(&mut self.egui);
+            self.update_texture_previews();
+        }
+
+        if self.settings.invert_elevation {
+            self.negate_elevation();
+        } else {
+            self.rebuild_terrain();
+        }
+    }
+
+    /// Swaps in the DEM at `path`, keeping the device and the preferences.
+    /// Everything tied to the previous DEM starts over. A DEM that fails to
+    /// load leaves the current one and shows the error in the Open DEM prompt.
+    pub fn open_dem(&mut self, path: String) {
+        let span = debug_span!("State::open_dem", path = path);
+        let _enter = span.enter();
+
+        let preview_level = self.startup.preview_level;
+        let elevation =
+            match super::gtiff::load_elevation(&self.device, &self.queue, &path, preview_level) {
+                Ok(elevation) => elevation,
+                Err(e) => {
+                    error!("Failed to load DEM {}: {}", path, e);
+                    let message = format!("Failed to load {}: {}", path, e);
+                    // The prompt only shows while there's an error to explain it
+                    if self.dem_error.is_none() {
+                        self.dem_error = Some(message);
+                    } else {
+                        self.open_dem_status = Some(message);
+                    }
+                    return;
+                }
+            };
+        self.settings.value_range = None;
+        self.replace_elevation(elevation);
+        if let Some(previous) = self.downloaded_dem.take() {
+            download::remove(&previous);
+        }
+        // The overviews are of the full resolution, not of a preview
+        if preview_level.is_none() {
+            self.elevation_path = Some(path.clone());
+        }
+        self.startup.dem = DemSource::File(path);
+        self.progressive = None;
+        self.dem_error = None;
+        self.open_dem_status = None;
+        self.camera = camera::Camera::new((0.0, 5.0, 20.0), cgmath::Deg(-90.0), cgmath::Deg(-20.0));
+        self.flight = None;
+        debug!("Opened DEM");
+    }
+
+    /// Uploads the elevation texture again normalized to `settings.value_range`
+    fn renormalize(&mut self) {
+        if self.indexed {
+            return;
+        }
+        let unit = self.settings.elevation_unit;
+        let elevation_meters: Vec<f64> =
+            self.elevation.iter().map(|&v| unit.to_meters(v)).collect();
+        let range = self.settings.value_range.unwrap_or_else(|| {
+            self.settings
+                .percentile_clip
+                .then(|| {
+                    super::gtiff::percentile_range(
+                        &elevation_meters,
+                        &self.is_no_data,
+                        self.settings.percentiles,
+                    )
+                })
+                .flatten()
+                .unwrap_or(self.normalization_range.map(|v| unit.to_meters(v)))
+        });
+        let normalized = super::gtiff::normalize(&elevation_meters, Some(range));
+        let size = self.dem_size();
+        super::gtiff::write_normalized(
+            &self.queue,
+            &self.elevation_texture,
+            &normalized,
+            [size.width as usize, size.height as usize],
+        );
+        super::gtiff::write_tiles(
+            &self.queue,
+            self.terrain_tiles.iter().map(|tile| &tile.elevation),
+            &normalized,
+            size.width as usize,
+        );
+        if let Some(previews) = &self.texture_previews {
+            previews.write_elevation(
+                &self.queue,
+                &normalized,
+                [size.width as usize, size.height as usize],
+            );
+        }
+        debug!("Renormalized elevation to {:?}", range);
+    }
+
+    /// Creates or frees the texture previews to follow `RenderSettings::texture_previews`
+    fn update_texture_previews(&mut self) {
+        match (self.settings.texture_previews, &self.texture_previews) {
+            (true, None) => {
+                let size = self.dem_size();
+                let size = [size.width as usize, size.height as usize];
+                self.texture_previews = Some(preview::TexturePreviews::new(
+                    &self.device,
+                    &self.queue,
+                    &mut self.egui,
+                    &self._diffuse_texture.view,
+                    &vec![0.0; size[0] * size[1]],
+                    size,
+                ));
+                // Fills the elevation preview
+                self.renormalize();
+            }
+            (false, Some(_)) => {
+                if let Some(previews) = self.texture_previews.take() {
+                    previews.destroy(&mut self.egui);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Swaps in `shader.wgsl` from disk when it was saved. A shader that fails
+    /// to compile is logged and the previous one stays in use.
+    #[cfg(feature = "hot-reload")]
+    fn reload_shader(&mut self) {
+        let Some(source) = self
+            .shader_watcher
+            .as_ref()
+            .and_then(|watcher| watcher.poll())
+        else {
+            return;
+        };
+        let span = debug_span!("reload_shader");
+        let _enter = span.enter();
+
+        self.device
+            .push_error_scope(egui_wgpu::wgpu::ErrorFilter::Validation);
+        let shader = self
+            .device
+            .create_shader_module(egui_wgpu::wgpu::ShaderModuleDescriptor {
+                label: Some("shader.wgsl"),
+                source: egui_wgpu::wgpu::ShaderSource::Wgsl(source.into()),
+            });
+        let previous = std::mem::replace(&mut self.shader, shader);
+        self.recreate_pipelines();
+        match pollster::block_on(self.device.pop_error_scope()) {
+            None => info!("Reloaded shader.wgsl"),
+            Some(e) => {
+                error!("Failed to reload shader.wgsl: {}", e);
+                self.shader = previous;
+                self.recreate_pipelines();
+            }
+        }
+    }
+
+    /// Bytes per vertex in the vertex buffers
+    fn vertex_stride(&self) -> usize {
+        if self.settings.packed_vertices {
+            std::mem::size_of::<PackedVertex>()
+        } else {
+            std::mem::size_of::<Vertex>()
+        }
+    }
+
+    /// `vertices` in the format of `settings.packed_vertices`
+    fn vertex_bytes(&self, vertices: &[Vertex]) -> Vec<u8> {
+        if self.settings.packed_vertices {
+            let packed: Vec<PackedVertex> = vertices
+                .iter()
+                .map(|vertex| PackedVertex::pack(vertex, &self.packing_bounds))
+                .collect();
+            bytemuck::cast_slice(&packed).to_vec()
+        } else {
+            bytemuck::cast_slice(vertices).to_vec()
+        }
+    }
+
+    /// Switches the vertex buffers between `Vertex` and `PackedVertex`
+    pub fn set_packed_vertices(&mut self, packed: bool) {
+        debug!("Packed vertices: {}", packed);
+        self.settings.packed_vertices = packed;
+        self.recreate_pipelines();
+        self.rebuild_mesh();
+    }
+
+    /// Recreates the pipelines of both topologies from the current settings
+    fn recreate_pipelines(&mut self) {
+        // Settings carried over from another device may ask for more
+        self.settings.wireframe &= self.capabilities.polygon_mode_line;
+        for (pipelines, topology) in [
+            (&mut self.strip_pipelines, terrain::Topology::Strip),
+            (&mut self.list_pipelines, terrain::Topology::List),
+        ] {
+            *pipelines = TerrainPipelines::new(
+                &self.device,
+                &self.render_pipeline_layout,
+                &self.shadow_pipeline_layout,
+                &self.shader,
+                self.config.format,
+                topology,
+                &self.settings,
+            );
+        }
+        let reversed_z = self.settings.reversed_depth();
+        self.water.set_reversed_z(&self.device, reversed_z);
+        self.track_pipeline.set_reversed_z(&self.device, reversed_z);
+        self.bounding_box_pipeline
+            .set_reversed_z(&self.device, reversed_z);
+    }
+
+    /// Waits for the queue to go idle, then frees the GPU resources before the
+    /// device is dropped. Dropping resources the GPU still uses at exit makes
+    /// some Vulkan drivers raise validation errors or hang.
+    pub fn shutdown(&mut self) {
+        let span = debug_span!("State::shutdown");
+        let _enter = span.enter();
+
+        self.device.poll(egui_wgpu::wgpu::Maintain::Wait);
+        trace!("Queue idle");
+
+        self.vertex_buffer.destroy();
+        self.index_buffer.destroy();
+        self.list_index_buffer.destroy();
+        self.lod_vertex_buffer.destroy();
+        self.lod_index_buffer.destroy();
+        for tile in &self.terrain_tiles {
+            tile.destroy();
+        }
+        self.outline.destroy();
+        self.track_pipeline.destroy();
+        self.bounding_box_pipeline.destroy();
+        self.water.destroy();
+        self.camera_buffer.destroy();
+        self.shadow_map.destroy();
+        self.shadow_camera_buffer.destroy();
+        self.settings_buffer.destroy();
+        self.depth_texture.texture.destroy();
+        if let Some((target, _)) = &self.render_target {
+            target.texture.destroy();
+        }
+        self.elevation_texture.destroy();
+        self.no_data_mask_texture.destroy();
+        self.normal_texture.destroy();
+        self.tint_ramp_texture.destroy();
+        self._diffuse_texture.texture.destroy();
+        if let Some(previews) = self.texture_previews.take() {
+            previews.destroy(&mut self.egui);
+        }
+        self.device.poll(egui_wgpu::wgpu::Maintain::Wait);
+        debug!("GPU resources released");
+    }
+
+    pub fn apply_preset(&mut self, preset: &preset::Preset) {
+        self.camera_controller.sensitivity = preset.input.mouse_sensitivity;
+        self.camera_controller.invert_y = preset.input.invert_y;
+        self.settings.imagery_offset = preset.imagery.offset;
+        self.settings.imagery_scale = preset.imagery.scale;
+        self.settings.tint_ramp = preset.tint.clone();
+        self.settings.tint_ramp.sort();
+        self.settings.clear_color = preset.render.clear_color;
+        self.settings.clear_depth = preset.render.clear_depth;
+        self.settings.reversed_z = preset.render.reversed_z;
+        if let Some(camera) = &preset.camera {
+            self.apply_camera(camera);
+        }
+        self.bookmarks = preset.bookmarks.clone();
+        self.next_bookmark = 0;
+        self.key_bindings = preset.keys.clone();
+        for (key, actions) in self.key_bindings.conflicts() {
+            warn!("{:?} is bound to several actions: {:?}", key, actions);
+        }
+        self.settings
+            .tint_ramp
+            .write_texture(&self.queue, &self.tint_ramp_texture);
+        self.recreate_pipelines();
+    }
+
+    pub fn preset(&self) -> preset::Preset {
+        preset::Preset {
+            input: preset::InputPreset {
+                mouse_sensitivity: self.camera_controller.sensitivity,
+                invert_y: self.camera_controller.invert_y,
+            },
+            imagery: preset::ImageryPreset {
+                offset: self.settings.imagery_offset,
+                scale: self.settings.imagery_scale,
+            },
+            tint: self.settings.tint_ramp.clone(),
+            // Only set by pasting a copied camera, the view isn't a preference
+            camera: None,
+            bookmarks: self.bookmarks.clone(),
+            keys: self.key_bindings.clone(),
+            render: preset::RenderPreset {
+                clear_color: self.settings.clear_color,
+                clear_depth: self.settings.clear_depth,
+                reversed_z: self.settings.reversed_z,
+            },
+        }
+    }
+
+    fn apply_camera(&mut self, camera: &preset::CameraPreset) {
+        self.camera.position = camera.position.into();
+        self.camera.yaw = cgmath::Deg(camera.yaw_degrees).into();
+        self.camera.pitch = cgmath::Deg(camera.pitch_degrees).into();
+        self.projection.fovy = cgmath::Deg(camera.fovy_degrees).into();
+    }
+
+    /// Flies to the bookmark after the last one flown to
+    fn next_bookmark(&mut self) {
+        let Some(bookmark) = self
+            .bookmarks
+            .get(self.next_bookmark % self.bookmarks.len().max(1))
+        else {
+            debug!("No bookmarks to fly to");
+            return;
+        };
+        let target = camera::View {
+            position: bookmark.position.into(),
+            yaw: cgmath::Deg(bookmark.yaw_degrees).into(),
+            pitch: cgmath::Deg(bookmark.pitch_degrees).into(),
+            fovy: cgmath::Deg(bookmark.fovy_degrees).into(),
+        };
+        let from = camera::View {
+            position: self.camera.position,
+            yaw: self.camera.yaw,
+            pitch: self.camera.pitch,
+            fovy: self.projection.fovy,
+        };
+        debug!("Flying to bookmark {}", self.next_bookmark);
+        self.flight = Some(camera::Flight::new(from, target, BOOKMARK_FLIGHT_SECONDS));
+        self.next_bookmark = (self.next_bookmark + 1) % self.bookmarks.len();
+    }
+
+    /// Current view as a preset `[camera]` table, see `preset::CameraPreset`
+    fn camera_preset(&self) -> preset::CameraPreset {
+        preset::CameraPreset {
+            position: self.camera.position.into(),
+            yaw_degrees: cgmath::Deg::from(self.camera.yaw).0,
+            pitch_degrees: cgmath::Deg::from(self.camera.pitch).0,
+            fovy_degrees: cgmath::Deg::from(self.projection.fovy).0,
+        }
+    }
+
+    /// Writes the processed elevation in meters to a GeoTIFF with the original
+    /// georeferencing, NoData pixels keep the original NoData value
+    pub fn export_elevation(&self, path: &str) -> gdal::errors::Result<()> {
+        let unit = self.settings.elevation_unit;
+        let buffer: Vec<f64> = self
+            .elevation
+            .iter()
+            .zip(&self.is_no_data)
+            .map(|(&v, &no_data)| if no_data { f64::NAN } else { unit.to_meters(v) })
+            .collect();
+        super::gtiff::save_geotiff(path, &buffer, &self.geo, self.no_data)
+    }
+
+    /// Converts (column, elevation in meters, row) of the loaded DEM to a world position
+    /// Size of the DEM the elevation buffer holds, the elevation texture is
+    /// smaller for DEMs over the texture limit
+    fn dem_size(&self) -> egui_wgpu::wgpu::Extent3d {
+        egui_wgpu::wgpu::Extent3d {
+            width: self.geo.width as u32,
+            height: self.geo.height as u32,
+            depth_or_array_layers: 1,
+        }
+    }
+
+    pub fn dataset_to_world(&self, column: f64, elevation: f64, row: f64) -> cgmath::Point3<f32> {
+        cgmath::Point3::new(
+            (column - self.terrain_origin.x) as f32,
+            ((elevation - self.terrain_origin.y) / super::terrain::VERTICAL_SCALE
+                * self.settings.exaggeration as f64) as f32,
+            (row - self.terrain_origin.z) as f32,
+        )
+    }
+
+    /// Interpolated elevation in meters at a fractional (column, row) of the loaded DEM
+    pub fn sample_elevation(&self, column: f64, row: f64) -> Option<f64> {
+        let size = self.dem_size();
+        super::terrain::sample_elevation(
+            &self.elevation,
+            size.width as usize,
+            size.height as usize,
+            column,
+            row,
+        )
+        .map(|v| self.settings.elevation_unit.to_meters(v))
+    }
+
+    /// World height of the terrain surface below a world (x, z) position
+    fn terrain_height(&self, x: f32, z: f32) -> Option<f32> {
+        let column = x as f64 + self.terrain_origin.x;
+        let row = z as f64 + self.terrain_origin.z;
+        let options = self.settings.mesh_options();
+        self.sample_elevation(column, row).map(|elevation| {
+            let elevation = options.terrace(elevation, self.terrain_origin.y);
+            self.dataset_to_world(column, elevation, row).y
+        })
+    }
+
+    fn view_proj(&self) -> cgmath::Matrix4<f32> {
+        self.projection.calc_matrix() * self.camera.calc_matrix()
+    }
+
+    /// World space ray through a window position
+    fn cursor_ray(
+        &self,
+        cursor: PhysicalPosition<f64>,
+    ) -> Option<(cgmath::Point3<f32>, cgmath::Vector3<f32>)> {
+        let inverse = self.view_proj().invert()?;
+        let x = (2.0 * cursor.x / self.size.width as f64 - 1.0) as f32;
+        let y = (1.0 - 2.0 * cursor.y / self.size.height as f64) as f32;
+        let near = inverse * cgmath::Vector4::new(x, y, 0.0, 1.0);
+        let far = inverse * cgmath::Vector4::new(x, y, 1.0, 1.0);
+        let near = cgmath::Point3::from_homogeneous(near);
+        let far = cgmath::Point3::from_homogeneous(far);
+        Some((near, (far - near).normalize()))
+    }
+
+    /// Marches a ray from the cursor until it hits the terrain surface
+    pub fn pick(&self, cursor: PhysicalPosition<f64>) -> Option<cgmath::Point3<f32>> {
+        const STEP: f32 = 0.5;
+        let (origin, direction) = self.cursor_ray(cursor)?;
+
+        let is_below = |t: f32| {
+            let p = origin + direction * t;
+            self.terrain_height(p.x, p.z).map(|h| p.y <= h)
+        };
+        let mut t = 0.0;
+        while t < self.projection.zfar {
+            if is_below(t + STEP) == Some(true) {
+                // Refine the hit between the last two steps
+                let (mut low, mut high) = (t, t + STEP);
+                for _ in 0..16 {
+                    let mid = (low + high) / 2.0;
+                    if is_below(mid) == Some(true) {
+                        high = mid;
+                    } else {
+                        low = mid;
+                    }
+                }
+                return Some(origin + direction * high);
+            }
+            t += STEP;
+        }
+        None
+    }
+
+    /// Terrain under the cursor in dataset, projected and WGS84 coordinates
+    fn cursor_coordinates(&self) -> Option<CursorCoordinates> {
+        let hit = self.pick(self.pick_position())?;
+        let dataset = self.world_to_dataset(hit);
+        let projected = self.geo.pixel_to_projected(dataset.x, dataset.z);
+        let geographic = self.geographic_transform.as_ref().map(|transform| {
+            let (mut x, mut y) = ([projected[0]], [projected[1]]);
+            transform
+                .transform_coords(&mut x, &mut y, &mut [])
+                .map(|()| [x[0], y[0]])
+                .map_err(|e| e.to_string())
+        });
+        Some(CursorCoordinates {
+            pixel: [dataset.x, dataset.z],
+            elevation: dataset.y,
+            sampled: self.sample_elevation(dataset.x, dataset.z),
+            projected,
+            geographic,
+        })
+    }
+
+    /// Screen direction of north at the current camera heading
+    fn north_on_screen(&self) -> egui::Vec2 {
+        let [column, row] = self.geo.north();
+        let (yaw_sin, yaw_cos) = self.camera.yaw.0.sin_cos();
+        // Screen up is the camera's forward direction on the ground
+        let forward = column * yaw_cos as f64 + row * yaw_sin as f64;
+        let right = -column * yaw_sin as f64 + row * yaw_cos as f64;
+        egui::vec2(right as f32, -forward as f32).normalized()
+    }
+
+    fn screen_center(&self) -> PhysicalPosition<f64> {
+        PhysicalPosition::new(self.size.width as f64 / 2.0, self.size.height as f64 / 2.0)
+    }
+
+    /// Where picks aim, the cursor or the crosshair in the middle of the
+    /// window before the cursor has moved over it or while it is grabbed
+    fn pick_position(&self) -> PhysicalPosition<f64> {
+        match self.cursor_position {
+            Some(position) if !self.mouse_grabbed => position,
+            _ => self.screen_center(),
+        }
+    }
+
+    /// Screen pixels per meter along the ground in the middle of the window
+    fn screen_pixels_per_meter(&self, view_proj: &cgmath::Matrix4<f32>) -> Option<f32> {
+        let meters_per_pixel = self.meters_per_pixel?;
+        let center = self.screen_center();
+        let hit = self.pick(center)?;
+        let (_, direction) = self.cursor_ray(center)?;
+        // Measure along the screen's horizontal axis so the bar matches it
+        let right = direction.cross(cgmath::Vector3::unit_y()).normalize();
+        let a = self.world_to_screen(view_proj, hit)?;
+        let b = self.world_to_screen(view_proj, hit + right)?;
+        Some(((b - a).length() as f64 / meters_per_pixel) as f32)
+    }
+
+    /// Elevation in meters of every `spacing`th DEM pixel that projects into the window
+    fn elevation_labels(
+        &self,
+        view_proj: &cgmath::Matrix4<f32>,
+        spacing: usize,
+    ) -> Vec<(egui::Pos2, f64)> {
+        let size = self.dem_size();
+        let (width, height) = (size.width as usize, size.height as usize);
+        let screen = egui::Rect::from_min_size(
+            egui::Pos2::ZERO,
+            egui::vec2(self.size.width as f32, self.size.height as f32),
+        );
+        let mut labels = Vec::new();
+        for row in (0..height).step_by(spacing.max(1)) {
+            for column in (0..width).step_by(spacing.max(1)) {
+                let index = row * width + column;
+                if self.is_no_data[index] {
+                    continue;
+                }
+                let meters = self
+                    .settings
+                    .elevation_unit
+                    .to_meters(self.elevation[index]);
+                let world = self.dataset_to_world(column as f64, meters, row as f64);
+                if let Some(position) = self.world_to_screen(view_proj, world) {
+                    if screen.contains(position) {
+                        labels.push((position, meters));
+                    }
+                }
+            }
+        }
+        labels
+    }
+
+    /// Projects a world position to window coordinates, `None` when behind the camera
+    fn world_to_screen(
+        &self,
+        view_proj: &cgmath::Matrix4<f32>,
+        position: cgmath::Point3<f32>,
+    ) -> Option<egui::Pos2> {
+        let clip = view_proj * position.to_homogeneous();
+        if clip.w <= 0.0 {
+            return None;
+        }
+        Some(egui::pos2(
+            (clip.x / clip.w + 1.0) / 2.0 * self.size.width as f32,
+            (1.0 - clip.y / clip.w) / 2.0 * self.size.height as f32,
+        ))
+    }
+
+    /// Follows the window to a monitor with another DPI
+    pub fn set_scale_factor(&mut self, scale_factor: f64) {
+        debug!("Scale factor changed to {}", scale_factor);
+        self.scale_factor = scale_factor;
+    }
+
+    pub fn mouse_grabbed(&self) -> bool {
+        self.mouse_grabbed
+    }
+
+    /// Grabs and hides the cursor for look mode, or releases it
+    pub fn set_mouse_grab(&mut self, grab: bool) {
+        if grab {
+            // Locked isn't supported on Windows and X11, Confined not on macOS
+            let result = self
+                .window
+                .set_cursor_grab(CursorGrabMode::Locked)
+                .or_else(|_| self.window.set_cursor_grab(CursorGrabMode::Confined));
+            if let Err(e) = result {
+                warn!("Failed to grab the cursor: {}", e);
+                return;
+            }
+        } else if let Err(e) = self.window.set_cursor_grab(CursorGrabMode::None) {
+            warn!("Failed to release the cursor: {}", e);
+        }
+        self.window.set_cursor_visible(!grab);
+        self.mouse_grabbed = grab;
+        debug!("Mouse grabbed: {}", grab);
+    }
+
+    pub fn window(&self) -> &Window {
+        &self.window
+    }
+
+    pub fn resize(&mut self, new_size: egui_winit::winit::dpi::PhysicalSize<u32>) {
+        if new_size.width > 0 && new_size.height > 0 {
+            self.size = new_size;
+            self.config.width = new_size.width;
+            self.config.height = new_size.height;
+            self.surface().configure(&self.device, &self.config);
+            self.recreate_render_target();
+            self.projection.resize(new_size.width, new_size.height);
+        }
+    }
+
+    /// Recreates the depth texture and render target at `settings.render_scale`
+    fn recreate_render_target(&mut self) {
+        let scale = self.settings.render_scale;
+        let max = self.limits.max_texture_dimension_2d;
+        let scaled = |size: u32| ((size as f32 * scale).round() as u32).clamp(1, max);
+        let config = egui_wgpu::wgpu::SurfaceConfiguration {
+            width: scaled(self.config.width),
+            height: scaled(self.config.height),
+            ..self.config.clone()
+        };
+        debug!("Render resolution: {}x{}", config.width, config.height);
+
+        self.depth_texture.texture.destroy();
+        self.depth_texture =
+            texture::Texture::create_depth_texture(&self.device, &config, "depth_texture");
+        self.outline
+            .set_depth(&self.device, &self.depth_texture.view);
+        if let Some((target, _)) = self.render_target.take() {
+            target.texture.destroy();
+        }
+        if scale != 1.0 {
+            let target =
+                texture::Texture::create_render_target(&self.device, &config, "Render Target");
+            let bind_group = self
+                .blit
+                .bind_group(&self.device, &target.view, &target.sampler);
+            self.render_target = Some((target, bind_group));
+        }
+    }
+
+    /// Runs a key press bound to a toggle, returns whether `action` is one
+    fn trigger(&mut self, action: Action) -> bool {
+        match action {
+            Action::Pause => {
+                self.paused = !self.paused;
+                debug!("Paused: {}", self.paused);
+            }
+            Action::CopyCamera => self.copy_camera_requested = true,
+            Action::AddBookmark => {
+                self.bookmarks.push(self.camera_preset());
+                debug!("Added bookmark {}", self.bookmarks.len());
+            }
+            Action::NextBookmark => self.next_bookmark(),
+            Action::Precision => {
+                let precision = !self.camera_controller.precision();
+                self.camera_controller.set_precision(precision);
+                debug!("Precision mode: {}", precision);
+            }
+            Action::GrabMouse => self.set_mouse_grab(!self.mouse_grabbed),
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn input(&mut self, event: &WindowEvent) -> bool {
+        // Ahead of the GUI so the cursor can't get stuck grabbed behind a window
+        if self.mouse_grabbed {
+            match event {
+                WindowEvent::KeyboardInput {
+                    event:
+                        KeyEvent {
+                            physical_key: PhysicalKey::Code(key),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } if self.key_bindings.actions(*key).contains(&Action::Quit) => {
+                    self.set_mouse_grab(false);
+                    return true;
+                }
+                // Most platforms drop the grab with the focus without telling
+                WindowEvent::Focused(false) => {
+                    self.set_mouse_grab(false);
+                }
+                _ => {}
+            }
+        }
+        if self.gui_consumed {
+            return true;
+        }
+
+        // Any key or click hands the camera back to the user
+        if self.auto_rotate
+            && matches!(
+                event,
+                WindowEvent::KeyboardInput {
+                    event: KeyEvent {
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                    ..
+                } | WindowEvent::MouseInput {
+                    state: ElementState::Pressed,
+                    ..
+                }
+            )
+        {
+            debug!("User input, stopping auto rotate");
+            self.auto_rotate = false;
+        }
+        if self.flight.is_some()
+            && matches!(
+                event,
+                WindowEvent::KeyboardInput {
+                    event: KeyEvent {
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                    ..
+                } | WindowEvent::MouseInput {
+                    state: ElementState::Pressed,
+                    ..
+                }
+            )
+        {
+            debug!("User input, stopping the bookmark flight");
+            self.flight = None;
+        }
+
+        match event {
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(key),
+                        state,
+                        repeat,
+                        ..
+                    },
+                ..
+            } => {
+                let actions = self.key_bindings.actions(*key);
+                if *state == ElementState::Pressed && !repeat {
+                    for &action in &actions {
+                        if self.trigger(action) {
+                            return true;
+                        }
+                    }
+                }
+                self.camera_controller.process_keyboard(&actions, *state)
+            }
+            WindowEvent::MouseInput {
+                button: MouseButton::Left,
+                state,
+                ..
+            } => {
+                self.mouse_pressed = *state == ElementState::Pressed;
+                false
+            }
+            WindowEvent::MouseInput {
+                button: MouseButton::Right,
+                state: ElementState::Pressed,
+                ..
+            } if self.profile_editing => {
+                let hit = self.pick(self.pick_position());
+                match hit {
+                    Some(hit) => {
+                        let position = self.world_to_dataset(hit);
+                        debug!("Added profile point at {:?}", position);
+                        if self.profiles.is_empty() {
+                            self.profiles.push(profile::Profile::default());
+                        }
+                        if let Some(profile) = self.profiles.last_mut() {
+                            profile.points.push([position.x, position.z]);
+                        }
+                    }
+                    None => debug!("Profile pick missed the terrain"),
+                }
+                true
+            }
+            WindowEvent::MouseInput {
+                button: MouseButton::Right,
+                state: ElementState::Pressed,
+                ..
+            } if self.measuring => {
+                match self.pick(self.pick_position()) {
+                    Some(hit) => {
+                        if self.measure_points.len() == 2 {
+                            self.measure_points.clear();
+                        }
+                        let position = self.world_to_dataset(hit);
+                        debug!("Added measurement point at {:?}", position);
+                        self.measure_points.push(position);
+                    }
+                    None => debug!("Measurement pick missed the terrain"),
+                }
+                true
+            }
+            WindowEvent::MouseInput {
+                button: MouseButton::Right,
+                state: ElementState::Pressed,
+                ..
+            } if self.flatten_editing => {
+                let hit = self.pick(self.pick_position());
+                match hit {
+                    Some(hit) => {
+                        let position = self.world_to_dataset(hit);
+                        let corner = [position.x, position.z];
+                        match self.flatten_corner.take() {
+                            None => {
+                                // Start at the picked elevation, it's usually the one wanted
+                                self.flatten_corner = Some(corner);
+                                self.flatten_region = None;
+                                self.flatten_elevation = position.y;
+                            }
+                            Some(first) => {
+                                let size = self.dem_size();
+                                let clamp = |value: f64, max: u32| {
+                                    value.round().clamp(0.0, (max - 1) as f64) as usize
+                                };
+                                let region = [
+                                    clamp(first[0].min(corner[0]), size.width),
+                                    clamp(first[1].min(corner[1]), size.height),
+                                    clamp(first[0].max(corner[0]), size.width),
+                                    clamp(first[1].max(corner[1]), size.height),
+                                ];
+                                debug!("Selected flatten region {:?}", region);
+                                self.flatten_region = Some(region);
+                            }
+                        }
+                    }
+                    None => debug!("Flatten pick missed the terrain"),
+                }
+                true
+            }
+            WindowEvent::MouseInput {
+                button: MouseButton::Right,
+                state: ElementState::Pressed,
+                ..
+            } if self.clip_editing => {
+                match self.pick(self.pick_position()) {
+                    Some(hit) => {
+                        let corner = [hit.x, hit.z];
+                        match self.clip_corner.take() {
+                            None => self.clip_corner = Some(corner),
+                            Some(first) => {
+                                self.settings.clip_box = [
+                                    first[0].min(corner[0]),
+                                    first[1].min(corner[1]),
+                                    first[0].max(corner[0]),
+                                    first[1].max(corner[1]),
+                                ];
+                                self.settings.clip = true;
+                                debug!("Clip box {:?}", self.settings.clip_box);
+                            }
+                        }
+                    }
+                    None => debug!("Clip box pick missed the terrain"),
+                }
+                true
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor_position = Some(*position);
+                false
+            }
+            _ => false,
+        }
+    }
+
+    pub fn update(&mut self, dt: std::time::Duration) {
+        #[cfg(feature = "hot-reload")]
+        self.reload_shader();
+        if !self.paused {
+            self.sun.advance(dt);
+            self.water_time += dt.as_secs_f32();
+        }
+        if !self.paused && self.morph.as_mut().is_some_and(|morph| morph.advance(dt)) {
+            self.animate_morph();
+        } else if self.morph_partial {
+            self.apply_morph();
+        }
+        // Outside the DEM fall back to the lowest point, which sits at y = 0
+        let ground = self
+            .terrain_height(self.camera.position.x, self.camera.position.z)
+            .unwrap_or(0.0);
+        self.camera_controller
+            .set_height_above_terrain(self.camera.position.y - ground);
+        self.clear_color = self.sky_color(self.camera.position.y - ground);
+        if self.settings.overviews && !self.settings.lod {
+            self.build_overviews();
+        }
+        let overview_level = if self.settings.overviews && !self.settings.lod {
+            let size = self.dem_size();
+            let switch = size.width.max(size.height) as f32 * OVERVIEW_ALTITUDE;
+            let above = (self.camera.position.y - ground).max(0.0);
+            ((above / switch).log2().floor() + 1.0).clamp(0.0, self.overviews.len() as f32) as usize
+        } else {
+            0
+        };
+        if overview_level != self.overview_level {
+            debug!("Switching to overview level {}", overview_level);
+            self.overview_level = overview_level;
+            self.rebuild_mesh();
+        }
+        // Back on the full resolution mesh, they're built again when needed
+        if !self.settings.overviews && !self.overviews.is_empty() {
+            self.overviews = Vec::new();
+        }
+        let (min, max) = self.terrain_bounds();
+        self.camera_controller.set_bounds(
+            cgmath::Point2::new(min.x, min.z),
+            cgmath::Point2::new(max.x, max.z),
+        );
+        if self.settings.bounding_box {
+            let [r, g, b] = self.settings.bounding_box_color;
+            let lines = (min, max, [r, g, b, self.settings.bounding_box_opacity]);
+            // The bounds move with the exaggeration and every edit
+            if self.bounding_box_lines != Some(lines) {
+                let vertices =
+                    track::box_vertices(min, max, lines.2, !self.config.format.is_srgb());
+                self.bounding_box_pipeline
+                    .set_vertices(&self.device, &vertices);
+                self.bounding_box_lines = Some(lines);
+            }
+        }
+        self.camera_controller.update_camera(&mut self.camera, dt);
+        if let Some(flight) = &mut self.flight {
+            let (view, arrived) = flight.advance(dt);
+            self.camera.position = view.position;
+            self.camera.yaw = view.yaw;
+            self.camera.pitch = view.pitch;
+            self.projection.fovy = view.fovy;
+            if arrived {
+                self.flight = None;
+            }
+        }
+        if self.settings.lod {
+            self.update_lod();
+        }
+        if self.auto_rotate && !self.paused {
+            let center =
+                cgmath::Point3::new(0.0, self.terrain_height(0.0, 0.0).unwrap_or(0.0), 0.0);
+            self.camera.orbit(
+                center,
+                cgmath::Deg(self.auto_rotate_speed * dt.as_secs_f32()),
+            );
+        }
+        if self.settings.auto_clip {
+            let (min, max) = self.terrain_bounds();
+            let clearance = (self.camera.position.y - ground).abs();
+            self.projection
+                .fit_to_bounds(self.camera.position, min, max, clearance);
+        } else {
+            self.projection.znear = self.settings.znear;
+            self.projection.zfar = self.settings.zfar;
+        }
+        self.camera_uniform.update_view_proj(
+            &self.camera,
+            &self.projection,
+            self.settings.reversed_depth(),
+        );
+        self.camera_uniform
+            .set_log_depth(self.settings.log_depth, &self.projection);
+        self.queue.write_buffer(
+            &self.camera_buffer,
+            0,
+            bytemuck::cast_slice(&[self.camera_uniform]),
+        );
+        self.queue.write_buffer(
+            &self.settings_buffer,
+            0,
+            bytemuck::cast_slice(&[SettingsUniform::new(
+                &self.settings,
+                &self.sun,
+                &self.lights,
+                &self.packing_bounds,
+                self.settings
+                    .fade_range
+                    .map(|meters| self.dataset_to_world(0.0, meters, 0.0).y),
+            )]),
+        );
+        let (min, max) = self.terrain_bounds();
+        let light_view_proj = shadow::light_view_proj(self.sun.direction(), min, max);
+        self.queue.write_buffer(
+            &self.shadow_camera_buffer,
+            0,
+            bytemuck::cast_slice(&[CameraUniform {
+                view_proj: light_view_proj.into(),
+                ..CameraUniform::new()
+            }]),
+        );
+        self.shadow_map.prepare(
+            &self.queue,
+            shadow::ShadowUniform {
+                light_view_proj: light_view_proj.into(),
+                enabled: self.shadows_enabled() as u32,
+                softness: self.settings.shadow_softness,
+                bias: shadow::BIAS,
+                _padding: 0.0,
+            },
+        );
+    }
+
+    /// Whether the shadow pass runs and the terrain shader samples its map
+    fn shadows_enabled(&self) -> bool {
+        self.settings.shadows && self.settings.lighting
+    }
+
+    /// Counts the terrain mesh `draw_scene` draws into `status`
+    fn update_render_stats(&mut self) {
+        let (vertices, indices, triangles) = if self.settings.lod {
+            (
+                self.num_lod_vertices,
+                self.num_lod_indices,
+                self.num_lod_indices / 3,
+            )
+        } else {
+            // Both topologies hold the same triangles
+            let [vertices, strip_indices, list_indices] = self.mesh_counts();
+            let indices = match self.settings.topology {
+                terrain::Topology::Strip => strip_indices,
+                terrain::Topology::List => list_indices,
+            };
+            (vertices, indices, list_indices / 3)
+        };
+        self.status.vertices = vertices;
+        self.status.indices = indices;
+        self.status.triangles = triangles;
+        self.status.total_triangles = self.mesh_counts()[2] / 3;
+    }
+
+    /// Vertex, strip index and list index count of the full mesh, summed
+    /// over the tiles when they are in use
+    fn mesh_counts(&self) -> [u32; 3] {
+        if self.tiles_in_use() {
+            self.terrain_tiles.iter().fold([0; 3], |[v, i, l], tile| {
+                [
+                    v + tile.num_vertices,
+                    i + tile.num_indices,
+                    l + tile.num_list_indices,
+                ]
+            })
+        } else {
+            [self.num_vertices, self.num_indices, self.num_list_indices]
+        }
+    }
+
+    /// Whether `draw_scene` draws the tiles of a DEM over the texture limit
+    /// in place of the vertex buffers
+    fn tiles_in_use(&self) -> bool {
+        !self.settings.lod && self.overview_level == 0 && !self.terrain_tiles.is_empty()
+    }
+
+    /// Pipelines of the terrain mesh in use with the draws of its parts, only
+    /// tiles have more than one
+    fn scene_mesh(&self) -> (&TerrainPipelines, Vec<MeshDraw<'_>>) {
+        let topology = if self.settings.lod {
+            terrain::Topology::List
+        } else {
+            self.settings.topology
+        };
+        let pipelines = match topology {
+            terrain::Topology::Strip => &self.strip_pipelines,
+            terrain::Topology::List => &self.list_pipelines,
+        };
+        let mut draws = if self.settings.lod {
+            vec![MeshDraw {
+                bind_group: &self.diffuse_bind_group,
+                vertex_buffer: &self.lod_vertex_buffer,
+                num_vertices: self.num_lod_vertices,
+                index_buffer: &self.lod_index_buffer,
+                num_indices: self.num_lod_indices,
+            }]
+        } else if self.tiles_in_use() {
+            self.terrain_tiles
+                .iter()
+                .map(|tile| {
+                    let (index_buffer, num_indices) = match topology {
+                        terrain::Topology::Strip => (&tile.index_buffer, tile.num_indices),
+                        terrain::Topology::List => (&tile.list_index_buffer, tile.num_list_indices),
+                    };
+                    MeshDraw {
+                        bind_group: &tile.bind_group,
+                        vertex_buffer: &tile.vertex_buffer,
+                        num_vertices: tile.num_vertices,
+                        index_buffer,
+                        num_indices,
+                    }
+                })
+                .collect()
+        } else {
+            let (index_buffer, num_indices) = match topology {
+                terrain::Topology::Strip => (&self.index_buffer, self.num_indices),
+                terrain::Topology::List => (&self.list_index_buffer, self.num_list_indices),
+            };
+            vec![MeshDraw {
+                bind_group: &self.diffuse_bind_group,
+                vertex_buffer: &self.vertex_buffer,
+                num_vertices: self.num_vertices,
+                index_buffer,
+                num_indices,
+            }]
+        };
+        for draw in &mut draws {
+            if !mesh_buffers_fit(
+                draw.vertex_buffer,
+                draw.num_vertices,
+                self.vertex_stride(),
+                draw.index_buffer,
+                draw.num_indices,
+            ) {
+                draw.num_indices = 0;
+            }
+        }
+        (pipelines, draws)
+    }
+
+    /// Draws the terrain and its passes into `view`, without the GUI
+    fn draw_scene(
+        &self,
+        encoder: &mut egui_wgpu::wgpu::CommandEncoder,
+        view: &egui_wgpu::wgpu::TextureView,
+    ) {
+        let terrain_view = match &self.render_target {
+            Some((target, _)) => &target.view,
+            None => view,
+        };
+        if self.settings.water {
+            let (min, max) = self.terrain_bounds();
+            let [r, g, b] = self.settings.water_color;
+            self.water.prepare(
+                &self.queue,
+                water::WaterUniform {
+                    bounds: [min.x, min.z, max.x, max.z],
+                    color: [r, g, b, self.settings.water_opacity],
+                    level: self.dataset_to_world(0.0, self.settings.water_level, 0.0).y,
+                    time: self.water_time,
+                    encode_srgb: !self.config.format.is_srgb() as u32,
+                    _padding: 0.0,
+                },
+            );
+        }
+        if self.shadows_enabled() {
+            let mut shadow_pass =
+                encoder.begin_render_pass(&egui_wgpu::wgpu::RenderPassDescriptor {
+                    label: Some("Shadow Pass"),
+                    color_attachments: &[],
+                    depth_stencil_attachment: Some(
+                        egui_wgpu::wgpu::RenderPassDepthStencilAttachment {
+                            view: self.shadow_map.view(),
+                            depth_ops: Some(egui_wgpu::wgpu::Operations {
+                                load: egui_wgpu::wgpu::LoadOp::Clear(1.0),
+                                store: egui_wgpu::wgpu::StoreOp::Store,
+                            }),
+                            stencil_ops: None,
+                        },
+                    ),
+                    occlusion_query_set: None,
+                    timestamp_writes: None,
+                });
+            let (pipelines, draws) = self.scene_mesh();
+            shadow_pass.set_pipeline(&pipelines.shadow);
+            shadow_pass.set_bind_group(1, &self.shadow_camera_bind_group, &[]);
+            shadow_pass.set_bind_group(2, &self.settings_bind_group, &[]);
+            for draw in &draws {
+                shadow_pass.set_bind_group(0, draw.bind_group, &[]);
+                shadow_pass.set_vertex_buffer(0, draw.vertex_buffer.slice(..));
+                shadow_pass.set_index_buffer(
+                    draw.index_buffer.slice(..),
+                    egui_wgpu::wgpu::IndexFormat::Uint32,
+                );
+                shadow_pass.draw_indexed(0..draw.num_indices, 0, 0..1);
+            }
+        }
+        {
+            let mut render_pass =
+                encoder.begin_render_pass(&egui_wgpu::wgpu::RenderPassDescriptor {
+                    label: Some("Render Pass"),
+                    color_attachments: &[Some(egui_wgpu::wgpu::RenderPassColorAttachment {
+                        view: terrain_view,
+                        resolve_target: None,
+                        ops: egui_wgpu::wgpu::Operations {
+                            load: egui_wgpu::wgpu::LoadOp::Clear(self.clear_color),
+                            store: egui_wgpu::wgpu::StoreOp::Store,
+                        },
+                    })],
+                    depth_stencil_attachment: Some(
+                        egui_wgpu::wgpu::RenderPassDepthStencilAttachment {
+                            view: &self.depth_texture.view,
+                            depth_ops: Some(egui_wgpu::wgpu::Operations {
+                                load: egui_wgpu::wgpu::LoadOp::Clear(
+                                    self.settings.depth_clear_value(),
+                                ),
+                                store: egui_wgpu::wgpu::StoreOp::Store,
+                            }),
+                            stencil_ops: None,
+                        },
+                    ),
+                    occlusion_query_set: None,
+                    timestamp_writes: None,
+                });
+
+            let (pipelines, draws) = self.scene_mesh();
+            if !self.settings.depth_test {
+                render_pass.set_pipeline(&pipelines.no_depth);
+            } else if self.settings.opacity < 1.0 || self.settings.fade {
+                render_pass.set_pipeline(&pipelines.transparent);
+            } else {
+                render_pass.set_pipeline(&pipelines.opaque);
+            }
+            render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
+            render_pass.set_bind_group(2, &self.settings_bind_group, &[]);
+            render_pass.set_bind_group(3, self.shadow_map.bind_group(), &[]);
+            for draw in &draws {
+                render_pass.set_bind_group(0, draw.bind_group, &[]);
+                render_pass.set_vertex_buffer(0, draw.vertex_buffer.slice(..));
+                render_pass.set_index_buffer(
+                    draw.index_buffer.slice(..),
+                    egui_wgpu::wgpu::IndexFormat::Uint32,
+                );
+                render_pass.draw_indexed(0..draw.num_indices, 0, 0..1);
+            }
+
+            if self.settings.contours {
+                render_pass.set_pipeline(&pipelines.contour);
+                for draw in &draws {
+                    render_pass.set_bind_group(0, draw.bind_group, &[]);
+                    render_pass.set_vertex_buffer(0, draw.vertex_buffer.slice(..));
+                    render_pass.set_index_buffer(
+                        draw.index_buffer.slice(..),
+                        egui_wgpu::wgpu::IndexFormat::Uint32,
+                    );
+                    render_pass.draw_indexed(0..draw.num_indices, 0, 0..1);
+                }
+            }
+            if self.settings.track {
+                self.track_pipeline
+                    .draw(&mut render_pass, &self.camera_bind_group);
+            }
+            if self.settings.bounding_box {
+                self.bounding_box_pipeline
+                    .draw(&mut render_pass, &self.camera_bind_group);
+            }
+            // Blended, so after everything opaque
+            if self.settings.water {
+                self.water.draw(&mut render_pass, &self.camera_bind_group);
+            }
+        }
+
+        if self.settings.outline {
+            let [r, g, b] = self.settings.outline_color;
+            self.outline.draw(
+                encoder,
+                &self.queue,
+                terrain_view,
+                outline::OutlineUniform {
+                    color: [r, g, b, 1.0],
+                    znear: self.projection.znear,
+                    zfar: self.projection.zfar,
+                    threshold: self.settings.outline_threshold,
+                    encode_srgb: !self.config.format.is_srgb() as u32,
+                    log_depth: self.camera_uniform.log_depth,
+                    reversed_z: self.settings.reversed_depth() as u32,
+                    _padding: [0.0; 2],
+                },
+            );
+        }
+
+        if let Some((_, bind_group)) = &self.render_target {
+            self.blit.draw(encoder, view, bind_group);
+        }
+    }
+
+    /// Renders every view of the batch file at `path` to `<name>.png` next
+    /// to it, see `preset::Batch`
+    pub fn render_batch(&mut self, path: &str) -> anyhow::Result<()> {
+        let span = debug_span!("render_batch", path = path);
+        let _enter = span.enter();
+
+        let batch = preset::Batch::load(path)?;
+        for view in &batch.views {
+            let name: String = view
+                .name
+                .chars()
+                .map(|c| {
+                    if c.is_alphanumeric() || c == '-' || c == '_' {
+                        c
+                    } else {
+                        '_'
+                    }
+                })
+                .collect();
+            let output = std::path::Path::new(path).with_file_name(format!("{}.png", name));
+            self.apply_camera(&view.camera);
+            self.update(std::time::Duration::ZERO);
+            self.capture(&output)?;
+            info!("Rendered {} to {}", view.name, output.display());
+        }
+        Ok(())
+    }
+
+    /// Renders the scene offscreen at the window size and saves it as a PNG
+    fn capture(&self, path: &std::path::Path) -> anyhow::Result<()> {
+        let format = self.config.format;
+        let bgra = match format.remove_srgb_suffix() {
+            egui_wgpu::wgpu::TextureFormat::Rgba8Unorm => false,
+            egui_wgpu::wgpu::TextureFormat::Bgra8Unorm => true,
+            _ => anyhow::bail!("Can't capture a {:?} surface", format),
+        };
+        let (width, height) = (self.config.width, self.config.height);
+        let texture = self
+            .device
+            .create_texture(&egui_wgpu::wgpu::TextureDescriptor {
+                label: Some("Capture Texture"),
+                size: egui_wgpu::wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: egui_wgpu::wgpu::TextureDimension::D2,
+                format,
+                usage: egui_wgpu::wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | egui_wgpu::wgpu::TextureUsages::COPY_SRC,
+                view_formats: &[],
+            });
+        let view = texture.create_view(&egui_wgpu::wgpu::TextureViewDescriptor::default());
+        // Rows of a texture to buffer copy are padded to 256 bytes
+        let row_bytes = 4 * width;
+        let padded_row_bytes = row_bytes.div_ceil(egui_wgpu::wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+            * egui_wgpu::wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let buffer = self
+            .device
+            .create_buffer(&egui_wgpu::wgpu::BufferDescriptor {
+                label: Some("Capture Buffer"),
+                size: (padded_row_bytes * height) as egui_wgpu::wgpu::BufferAddress,
+                usage: egui_wgpu::wgpu::BufferUsages::COPY_DST
+                    | egui_wgpu::wgpu::BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            });
+
+        let mut encoder =
+            self.device
+                .create_command_encoder(&egui_wgpu::wgpu::CommandEncoderDescriptor {
+                    label: Some("Capture Encoder"),
+                });
+        self.draw_scene(&mut encoder, &view);
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            egui_wgpu::wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: egui_wgpu::wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_row_bytes),
+                    rows_per_image: None,
+                },
+            },
+            texture.size(),
+        );
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(egui_wgpu::wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.device.poll(egui_wgpu::wgpu::Maintain::Wait);
+        receiver.recv()??;
+        let mut pixels = Vec::with_capacity((row_bytes * height) as usize);
+        for row in slice.get_mapped_range().chunks(padded_row_bytes as usize) {
+            pixels.extend_from_slice(&row[..row_bytes as usize]);
+        }
+        buffer.unmap();
+        buffer.destroy();
+        texture.destroy();
+        if bgra {
+            for pixel in pixels.chunks_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+        image::save_buffer(path, &pixels, width, height, image::ColorType::Rgba8)?;
+        Ok(())
+    }
+
+    /// Renders the terrain top down with an orthographic camera over the
+    /// whole raster and saves it to `map_export_path`, with a world file and
+    /// the CRS as `.pgw` and `.prj` so GIS software places it
+    fn export_map(&mut self) -> anyhow::Result<()> {
+        let span = debug_span!("export_map", path = self.map_export_path);
+        let _enter = span.enter();
+
+        let size = self.dem_size();
+        let max_size = self.limits.max_texture_dimension_2d;
+        let width = self.map_export_width.clamp(1, max_size);
+        let height = ((width as f64 * size.height as f64 / size.width as f64).round() as u32)
+            .clamp(1, max_size);
+
+        // The raster's pixel edges are half a pixel outside the vertex grid
+        let corner = self.dataset_to_world(-0.5, 0.0, -0.5);
+        let opposite =
+            self.dataset_to_world(size.width as f64 - 0.5, 0.0, size.height as f64 - 0.5);
+        let (low, high) = self.terrain_bounds();
+        let skirt = if self.settings.skirt {
+            self.settings.skirt_depth / terrain::VERTICAL_SCALE
+        } else {
+            0.0
+        };
+        let bottom = low.y - skirt as f32;
+        let eye = cgmath::Point3::new(
+            (corner.x + opposite.x) / 2.0,
+            high.y + 1.0,
+            (corner.z + opposite.z) / 2.0,
+        );
+        // Looking down with row 0 at the top of the image
+        let view = cgmath::Matrix4::look_at_rh(
+            eye,
+            eye - cgmath::Vector3::unit_y(),
+            -cgmath::Vector3::unit_z(),
+        );
+        let [half_width, half_height] =
+            [(opposite.x - corner.x) / 2.0, (opposite.z - corner.z) / 2.0];
+        let projection = cgmath::ortho(
+            -half_width,
+            half_width,
+            -half_height,
+            half_height,
+            0.0,
+            eye.y - bottom + 1.0,
+        );
+
+        // Render at the map size, edge detection and logarithmic depth
+        // assume the perspective projection
+        let config = self.config.clone();
+        let (render_scale, outline, log_depth) = (
+            self.settings.render_scale,
+            self.settings.outline,
+            self.settings.log_depth,
+        );
+        self.config.width = width;
+        self.config.height = height;
+        self.settings.render_scale = 1.0;
+        self.settings.outline = false;
+        if log_depth {
+            self.settings.log_depth = false;
+            self.recreate_pipelines();
+        }
+        self.recreate_render_target();
+        let mut camera_uniform = self.camera_uniform;
+        camera_uniform.set_view_proj(
+            camera::OPENGL_TO_WGPU_MATRIX * projection * view,
+            self.settings.reversed_depth(),
+        );
+        camera_uniform.log_depth = 0.0;
+        self.queue.write_buffer(
+            &self.camera_buffer,
+            0,
+            bytemuck::cast_slice(&[camera_uniform]),
+        );
+
+        let path = std::path::PathBuf::from(&self.map_export_path);
+        let result = self.capture(&path);
+
+        self.config = config;
+        self.settings.render_scale = render_scale;
+        self.settings.outline = outline;
+        if log_depth {
+            self.settings.log_depth = true;
+            self.recreate_pipelines();
+        }
+        self.recreate_render_target();
+        self.queue.write_buffer(
+            &self.camera_buffer,
+            0,
+            bytemuck::cast_slice(&[self.camera_uniform]),
+        );
+        result?;
+
+        let world_file = path.with_extension("pgw");
+        std::fs::write(&world_file, self.geo.world_file(width, height))
+            .with_context(|| format!("Failed to write {}", world_file.display()))?;
+        match self.geo.esri_wkt()? {
+            Some(wkt) => {
+                let prj = path.with_extension("prj");
+                std::fs::write(&prj, wkt)
+                    .with_context(|| format!("Failed to write {}", prj.display()))?;
+            }
+            None => warn!("No spatial reference, the map has no .prj"),
+        }
+        info!("Exported a {}x{} map to {}", width, height, path.display());
+        Ok(())
+    }
+
+    pub fn render(&mut self) -> Result<(), egui_wgpu::wgpu::SurfaceError> {
+        if std::mem::take(&mut self.map_export_requested) {
+            self.map_export_status = Some(match self.export_map() {
+                Ok(()) => format!("Exported to {}", self.map_export_path),
+                Err(e) => {
+                    error!("Failed to export map: {:?}", e);
+                    format!("Export failed: {}", e)
+                }
+            });
+        }
+        let output = self.surface().get_current_texture()?;
+
+        let view = output
+            .texture
+            .create_view(&egui_wgpu::wgpu::TextureViewDescriptor::default());
+
+        let mut encoder =
+            self.device
+                .create_command_encoder(&egui_wgpu::wgpu::CommandEncoderDescriptor {
+                    label: Some("Render Encoder"),
+                });
+
+        self.update_render_stats();
+        self.draw_scene(&mut encoder, &view);
+        self.update_texture_previews();
+
+        let pixels_per_point = self.ui_scale.unwrap_or(self.scale_factor as f32);
+        self.egui
+            .set_pixels_per_point(pixels_per_point, self.scale_factor as f32);
+        let screen_descriptor = egui_wgpu::ScreenDescriptor {
+            size_in_pixels: [self.size.width, self.size.height],
+            pixels_per_point,
+        };
+
+        let dataset_position = self.world_to_dataset(self.camera.position);
+        let cursor_coordinates = self.cursor_coordinates();
+        let view_proj = self.view_proj();
+        let terrain_bounds = self.terrain_bounds();
+        let profile_samples: Vec<Vec<profile::ProfileSample>> = self
+            .profiles
+            .iter()
+            .map(|profile| profile.sample(1.0, |column, row| self.sample_elevation(column, row)))
+            .collect();
+        // Section lines follow the samples so they drape over the surface
+        let profile_lines: Vec<Vec<Option<egui::Pos2>>> = profile_samples
+            .iter()
+            .map(|samples| {
+                samples
+                    .iter()
+                    .map(|s| {
+                        let world = self.dataset_to_world(s.column, s.elevation, s.row);
+                        self.world_to_screen(&view_proj, world)
+                    })
+                    .collect()
+            })
+            .collect();
+        let flatten_outline: Vec<Option<egui::Pos2>> = self
+            .flatten_region
+            .map(|[left, top, right, bottom]| {
+                [
+                    (left, top),
+                    (right, top),
+                    (right, bottom),
+                    (left, bottom),
+                    (left, top),
+                ]
+                .iter()
+                .map(|&(column, row)| {
+                    let world =
+                        self.dataset_to_world(column as f64, self.flatten_elevation, row as f64);
+                    self.world_to_screen(&view_proj, world)
+                })
+                .collect()
+            })
+            .unwrap_or_default();
+        let measurement = self.measurement();
+        let measure_line: Vec<Option<egui::Pos2>> = self
+            .measure_points
+            .iter()
+            .map(|p| self.world_to_screen(&view_proj, self.dataset_to_world(p.x, p.y, p.z)))
+            .collect();
+        let mut flatten_requested = false;
+        let mut morph_load_requested = false;
+        let mut track_load_requested = false;
+        let previous_morph_factor = self.morph.as_ref().map(|morph| morph.factor);
+        let north = self.north_on_screen();
+        let elevation_labels = if self.settings.elevation_labels {
+            self.elevation_labels(&view_proj, self.settings.elevation_label_spacing as usize)
+        } else {
+            Vec::new()
+        };
+        let pixels_per_meter = self
+            .settings
+            .scale_bar
+            .then(|| self.screen_pixels_per_meter(&view_proj))
+            .flatten();
+        let previous_unit = self.settings.elevation_unit;
+        let previous_invert = self.settings.invert_elevation;
+        let previous_exaggeration = self.settings.exaggeration;
+        let previous_resolution = self.settings.mesh_resolution;
+        let previous_budget = self.settings.vertex_budget;
+        let previous_coloring = self.settings.vertex_coloring;
+        let previous_value_range = self.settings.value_range;
+        let previous_percentiles = (self.settings.percentile_clip, self.settings.percentiles);
+        let previous_restart = self.settings.primitive_restart;
+        let previous_terraces = (self.settings.terraces, self.settings.terrace_interval);
+        let previous_skirt = (
+            self.settings.skirt,
+            self.settings.skirt_depth,
+            self.settings.skirt_bottom,
+        );
+        let dem_size = self.dem_size();
+        let mesh_counts = self.mesh_counts();
+        let mut export_requested = false;
+        let mut measure_requested = false;
+        let mut save_preset_requested = false;
+        let previous_render_scale = self.settings.render_scale;
+        let previous_tint_ramp = self.settings.tint_ramp.clone();
+        let previous_bias = (
+            self.settings.depth_bias_constant,
+            self.settings.depth_bias_slope,
+        );
+        let previous_depth_mode = (self.settings.log_depth, self.settings.reversed_z);
+        let previous_wireframe = self.settings.wireframe;
+        let previous_normal_map = self.settings.normal_map;
+        let mut copied_camera = None;
+        let current_camera = self.camera_preset();
+        let mut bookmark_go = None;
+        let mut packed_vertices = self.settings.packed_vertices;
+        if std::mem::take(&mut self.copy_camera_requested) {
+            match self.camera_preset().to_toml() {
+                Ok(text) => {
+                    debug!("Copied camera:\n{}", text);
+                    copied_camera = Some(text);
+                }
+                Err(e) => error!("Failed to serialize camera: {:?}", e),
+            }
+        }
+        self.egui.render(
+            &self.device,
+            &self.queue,
+            &mut encoder,
             self.window,
             &view,
             &screen_descriptor,
             |ui| {
+                if let Some(text) = copied_camera.take() {
+                    ui.output_mut(|output| output.copied_text = text);
+                }
                 egui::Window::new("Debug").show(&ui, |ui| {
                     ui.label(format!("FPS: {:.2}", self.status.fps));
                     ui.label(format!("Avg FPS: {:.2}", self.status.fps_avg));
@@ -604,20 +4961,1292 @@ impl<'a> State<'a> {
                     ui.label("Window");
                     ui.label(format!("Width: {}", self.size.width));
                     ui.label(format!("Height: {}", self.size.height));
+                    ui.label(format!("Paused: {}", self.paused));
+                    if let Some(loader) = &self.progressive {
+                        ui.label(format!("Loading: showing 1/{} resolution", loader.factor));
+                    }
                     ui.separator();
                     ui.label("Camera");
                     ui.label(format!("Camera Position: {:?}", self.camera.position));
                     ui.label(format!("Camera Yaw: {:?}", self.camera.yaw));
                     ui.label(format!("Camera Pitch: {:?}", self.camera.pitch));
+                    ui.add(
+                        egui::Slider::new(&mut self.camera_controller.look_speed, 0.1..=4.0)
+                            .text("Key Look Speed"),
+                    );
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut self.auto_rotate, "Auto Rotate");
+                        ui.add(
+                            egui::Slider::new(&mut self.auto_rotate_speed, -90.0..=90.0)
+                                .text("°/s"),
+                        );
+                    });
+                    ui.checkbox(
+                        &mut self.camera_controller.auto_speed,
+                        "Scale Speed With Height",
+                    );
+                    ui.add_enabled(
+                        self.camera_controller.auto_speed,
+                        egui::Slider::new(&mut self.camera_controller.auto_speed_factor, 0.0..=0.5)
+                            .text("Speed Per Unit Height"),
+                    );
+                    ui.label(format!(
+                        "Speed: {:.1}",
+                        self.camera_controller.current_speed()
+                    ));
+                    ui.label(format!(
+                        "Dataset Position: col {:.1}, row {:.1}, {:.1} m",
+                        dataset_position.x, dataset_position.z, dataset_position.y
+                    ));
+                    match &cursor_coordinates {
+                        Some(cursor) => {
+                            ui.label(format!(
+                                "Cursor: col {:.1}, row {:.1}, {:.1} m",
+                                cursor.pixel[0], cursor.pixel[1], cursor.elevation
+                            ));
+                            ui.label(format!(
+                                "Cursor Projected: {:.2}, {:.2}",
+                                cursor.projected[0], cursor.projected[1]
+                            ));
+                            match &cursor.geographic {
+                                Some(Ok([lon, lat])) => {
+                                    ui.label(format!("Cursor WGS84: {:.6}°, {:.6}°", lon, lat));
+                                }
+                                Some(Err(e)) => {
+                                    ui.colored_label(
+                                        egui::Color32::YELLOW,
+                                        format!("Cursor WGS84: {}", e),
+                                    );
+                                }
+                                None => {
+                                    ui.label("Cursor WGS84: no spatial reference");
+                                }
+                            }
+                        }
+                        None => {
+                            ui.label("Cursor: off the terrain");
+                        }
+                    }
+                    if self.geo.is_rotated() {
+                        ui.colored_label(
+                            egui::Color32::YELLOW,
+                            "Rotated geotransform, north isn't image up",
+                        );
+                    }
+                    match self.geographic_bounds {
+                        Some([min_lon, min_lat, max_lon, max_lat]) => {
+                            ui.label(format!("Longitude: {:.5}° to {:.5}°", min_lon, max_lon));
+                            ui.label(format!("Latitude: {:.5}° to {:.5}°", min_lat, max_lat));
+                        }
+                        None => {
+                            let [min_x, min_y, max_x, max_y] = self.geo.projected_bounds();
+                            ui.label(format!("Projected X: {:.2} to {:.2}", min_x, max_x));
+                            ui.label(format!("Projected Y: {:.2} to {:.2}", min_y, max_y));
+                        }
+                    }
                     ui.separator();
                     ui.label("Projection");
                     ui.label(format!("Aspect: {}", self.projection.aspect));
                     ui.label(format!("Fovy: {:?}", self.projection.fovy));
-                    ui.label(format!("Znear: {}", self.projection.znear));
-                    ui.label(format!("Zfar: {}", self.projection.zfar));
+                    if ui.button("Copy Camera (C)").clicked() {
+                        self.copy_camera_requested = true;
+                    }
+                    ui.checkbox(&mut self.settings.auto_clip, "Fit Near/Far to Terrain");
+                    ui.checkbox(&mut self.settings.log_depth, "Logarithmic Depth");
+                    ui.add_enabled(
+                        !self.settings.log_depth,
+                        egui::Checkbox::new(&mut self.settings.reversed_z, "Reversed Depth"),
+                    );
+                    ui.add(
+                        egui::Slider::new(&mut self.settings.clear_depth, 0.0..=1.0)
+                            .text("Clear Depth"),
+                    )
+                    .on_hover_text("1 is the far plane");
+                    if self.settings.auto_clip {
+                        ui.label(format!("Znear: {}", self.projection.znear));
+                        ui.label(format!("Zfar: {}", self.projection.zfar));
+                    } else {
+                        ui.horizontal(|ui| {
+                            ui.label("Znear:");
+                            ui.add(
+                                egui::DragValue::new(&mut self.settings.znear)
+                                    .speed(0.01)
+                                    .clamp_range(0.001..=self.settings.zfar),
+                            );
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Zfar:");
+                            ui.add(
+                                egui::DragValue::new(&mut self.settings.zfar)
+                                    .clamp_range(self.settings.znear..=f32::MAX),
+                            );
+                        });
+                    }
+                    ui.separator();
+                    ui.label("Terrain");
+                    ui.label(format!(
+                        "Vertices: {}, Indices: {}",
+                        self.status.vertices, self.status.indices
+                    ));
+                    if self.status.triangles < self.status.total_triangles {
+                        ui.label(format!(
+                            "Triangles: {} drawn of {}",
+                            self.status.triangles, self.status.total_triangles
+                        ));
+                    } else {
+                        ui.label(format!("Triangles: {}", self.status.triangles));
+                    }
+                    if self.elevation_min == self.elevation_max {
+                        ui.colored_label(
+                            egui::Color32::YELLOW,
+                            "Constant elevation, the terrain is flat",
+                        );
+                    }
+                    ui.add(
+                        egui::Slider::new(&mut self.settings.exaggeration, 0.1..=10.0)
+                            .text("Exaggeration"),
+                    );
+                    let mut custom_resolution = self.settings.mesh_resolution.is_some();
+                    ui.checkbox(&mut custom_resolution, "Custom Mesh Resolution");
+                    if !custom_resolution {
+                        self.settings.mesh_resolution = None;
+                    } else {
+                        let resolution = self
+                            .settings
+                            .mesh_resolution
+                            .get_or_insert([dem_size.width, dem_size.height]);
+                        ui.horizontal(|ui| {
+                            ui.add(egui::DragValue::new(&mut resolution[0]).clamp_range(2..=8192));
+                            ui.label("x");
+                            ui.add(egui::DragValue::new(&mut resolution[1]).clamp_range(2..=8192));
+                        });
+                    }
+                    ui.add_enabled_ui(!custom_resolution, |ui| {
+                        let mut limited = self.settings.vertex_budget.is_some();
+                        ui.checkbox(&mut limited, "Vertex Budget");
+                        if !limited {
+                            self.settings.vertex_budget = None;
+                        } else {
+                            let budget = self.settings.vertex_budget.get_or_insert(2_000_000);
+                            ui.add(
+                                egui::DragValue::new(budget)
+                                    .clamp_range(4..=64_000_000)
+                                    .speed(10_000),
+                            );
+                        }
+                    });
+                    ui.checkbox(&mut self.settings.lod, "Quadtree LOD");
+                    if self.settings.lod {
+                        ui.add(
+                            egui::Slider::new(&mut self.settings.lod_factor, 0.5..=8.0)
+                                .text("LOD Distance"),
+                        );
+                        ui.label(format!(
+                            "Chunks: {}, Triangles: {}",
+                            self.lod_nodes.len(),
+                            self.num_lod_indices / 3
+                        ));
+                    }
+                    ui.add_enabled_ui(!self.indexed && !self.settings.lod, |ui| {
+                        ui.checkbox(&mut self.settings.overviews, "Overviews")
+                            .on_hover_text(
+                                "Fewer triangles from high up, the overviews take another third \
+                                 of the DEM's memory while on",
+                            );
+                    });
+                    if self.settings.overviews {
+                        ui.label(match self.overview_level.checked_sub(1) {
+                            Some(level) => {
+                                let overview = &self.overviews[level];
+                                format!(
+                                    "Overview {}: 1/{} ({}x{})",
+                                    self.overview_level,
+                                    overview.factor,
+                                    overview.width,
+                                    overview.height
+                                )
+                            }
+                            None => "Full resolution".to_string(),
+                        });
+                    }
+                    let topology = &mut self.settings.topology;
+                    egui::ComboBox::from_label("Topology")
+                        .selected_text(topology.name())
+                        .show_ui(ui, |ui| {
+                            for option in terrain::Topology::ALL {
+                                ui.selectable_value(topology, option, option.name());
+                            }
+                        });
+                    let [_, strip_indices, list_indices] = mesh_counts;
+                    let active_indices = match self.settings.topology {
+                        terrain::Topology::Strip => strip_indices,
+                        terrain::Topology::List => list_indices,
+                    };
+                    ui.label(format!(
+                        "Triangles: {}, Indices: {}",
+                        list_indices / 3,
+                        active_indices
+                    ));
+                    ui.add_enabled(
+                        self.settings.topology == terrain::Topology::Strip,
+                        egui::Checkbox::new(
+                            &mut self.settings.primitive_restart,
+                            "Primitive Restart",
+                        ),
+                    );
+                    let mut packed = self.settings.packed_vertices;
+                    ui.checkbox(&mut packed, "Packed Vertices")
+                        .on_hover_text(format!(
+                            "{} instead of {} bytes per vertex",
+                            std::mem::size_of::<PackedVertex>(),
+                            std::mem::size_of::<Vertex>()
+                        ));
+                    packed_vertices = packed;
+                    ui.checkbox(&mut self.settings.terraces, "Terraces")
+                        .on_hover_text("Stacked slabs like a cut relief model");
+                    ui.add_enabled(
+                        self.settings.terraces,
+                        egui::Slider::new(&mut self.settings.terrace_interval, 1.0..=500.0)
+                            .text("Terrace Interval (m)"),
+                    );
+                    ui.checkbox(&mut self.settings.skirt, "Skirt")
+                        .on_hover_text("Walls down from the edges to a solid base");
+                    ui.add_enabled_ui(self.settings.skirt, |ui| {
+                        ui.add(
+                            egui::Slider::new(&mut self.settings.skirt_depth, 0.0..=5000.0)
+                                .logarithmic(true)
+                                .text("Base Depth (m)"),
+                        )
+                        .on_hover_text("Below the lowest point, not exaggerated");
+                        ui.checkbox(&mut self.settings.skirt_bottom, "Closed Bottom");
+                    });
+                    let coloring = &mut self.settings.vertex_coloring;
+                    egui::ComboBox::from_label("Vertex Colors")
+                        .selected_text(coloring.name())
+                        .show_ui(ui, |ui| {
+                            for option in terrain::VertexColoring::ALL {
+                                ui.selectable_value(coloring, option, option.name());
+                            }
+                        });
+                    let mut auto_range = self.settings.value_range.is_none();
+                    ui.checkbox(&mut auto_range, "Auto Normalization Range");
+                    if auto_range {
+                        self.settings.value_range = None;
+                        ui.checkbox(&mut self.settings.percentile_clip, "Clip Tails");
+                        if self.settings.percentile_clip {
+                            let [low, high] = &mut self.settings.percentiles;
+                            ui.add(egui::Slider::new(low, 0.0..=50.0).text("Low Percentile"));
+                            ui.add(egui::Slider::new(high, 50.0..=100.0).text("High Percentile"));
+                        }
+                    } else {
+                        let unit = self.settings.elevation_unit;
+                        let range = self.settings.value_range.get_or_insert([
+                            unit.to_meters(self.elevation_min),
+                            unit.to_meters(self.elevation_max),
+                        ]);
+                        ui.horizontal(|ui| {
+                            ui.add(egui::DragValue::new(&mut range[0]).suffix(" m"));
+                            ui.label("to");
+                            ui.add(egui::DragValue::new(&mut range[1]).suffix(" m"));
+                        });
+                    }
+                    ui.add(
+                        egui::Slider::new(&mut self.settings.opacity, 0.0..=1.0).text("Opacity"),
+                    );
+                    ui.checkbox(&mut self.settings.fade, "Fade Low Terrain");
+                    if self.settings.fade {
+                        let unit = self.settings.elevation_unit;
+                        let range = unit.to_meters(self.elevation_min.min(self.elevation_max))
+                            ..=unit.to_meters(self.elevation_max.max(self.elevation_min));
+                        let [start, end] = &mut self.settings.fade_range;
+                        ui.add(
+                            egui::Slider::new(start, range.clone())
+                                .clamp_to_range(false)
+                                .suffix(" m")
+                                .text("Transparent Below"),
+                        );
+                        ui.add(
+                            egui::Slider::new(end, range)
+                                .clamp_to_range(false)
+                                .suffix(" m")
+                                .text("Opaque Above"),
+                        );
+                    }
+                    ui.checkbox(&mut self.settings.depth_test, "Depth Test");
+                    ui.add_enabled(
+                        self.capabilities.polygon_mode_line,
+                        egui::Checkbox::new(&mut self.settings.wireframe, "Wireframe"),
+                    )
+                    .on_disabled_hover_text("The GPU doesn't support line polygon mode");
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut self.settings.outline, "Outlines");
+                        ui.color_edit_button_rgb(&mut self.settings.outline_color);
+                    });
+                    if self.settings.outline {
+                        ui.add(
+                            egui::Slider::new(&mut self.settings.outline_threshold, 0.005..=0.5)
+                                .logarithmic(true)
+                                .text("Outline Threshold"),
+                        );
+                    }
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut self.settings.solid, "Solid Color");
+                        ui.color_edit_button_rgb(&mut self.settings.solid_color);
+                    });
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut self.settings.underside, "Highlight Underside");
+                        ui.color_edit_button_rgb(&mut self.settings.underside_color);
+                    });
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut self.settings.water, "Water");
+                        ui.color_edit_button_rgb(&mut self.settings.water_color);
+                    });
+                    if self.settings.water {
+                        let unit = self.settings.elevation_unit;
+                        let range = unit.to_meters(self.elevation_min.min(self.elevation_max))
+                            ..=unit.to_meters(self.elevation_max.max(self.elevation_min));
+                        ui.add(
+                            egui::Slider::new(&mut self.settings.water_level, range)
+                                .clamp_to_range(false)
+                                .suffix(" m")
+                                .text("Water Level"),
+                        );
+                        ui.add(
+                            egui::Slider::new(&mut self.settings.water_opacity, 0.0..=1.0)
+                                .text("Water Opacity"),
+                        );
+                    }
+                    ui.horizontal(|ui| {
+                        ui.label("Background:");
+                        ui.color_edit_button_rgb(&mut self.settings.clear_color);
+                    });
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut self.settings.sky_gradient, "Sky Gradient");
+                        ui.color_edit_button_rgb(&mut self.settings.sky_low)
+                            .on_hover_text("Near the ground");
+                        ui.color_edit_button_rgb(&mut self.settings.sky_high)
+                            .on_hover_text("High above the terrain");
+                    });
+                    ui.add_enabled(
+                        self.settings.sky_gradient,
+                        egui::Slider::new(&mut self.settings.sky_altitude, 0.05..=4.0)
+                            .logarithmic(true)
+                            .text("Sky Altitude (× DEM size)"),
+                    );
+                    ui.checkbox(&mut self.settings.contours, "Contours");
+                    ui.add(
+                        egui::Slider::new(&mut self.settings.contour_interval, 1.0..=500.0)
+                            .text("Contour Interval (m)"),
+                    );
+                    ui.add(
+                        egui::Slider::new(&mut self.settings.depth_bias_constant, -64..=0)
+                            .text("Depth Bias"),
+                    );
+                    ui.add(
+                        egui::Slider::new(&mut self.settings.depth_bias_slope, -8.0..=0.0)
+                            .text("Depth Bias Slope"),
+                    );
+                    ui.checkbox(&mut self.settings.north_arrow, "North Arrow");
+                    ui.add_enabled(
+                        self.meters_per_pixel.is_some(),
+                        egui::Checkbox::new(&mut self.settings.scale_bar, "Scale Bar"),
+                    );
+                    ui.checkbox(&mut self.settings.crosshair, "Crosshair");
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut self.settings.bounding_box, "Bounding Box");
+                        ui.color_edit_button_rgb(&mut self.settings.bounding_box_color);
+                    });
+                    ui.add_enabled(
+                        self.settings.bounding_box,
+                        egui::Slider::new(&mut self.settings.bounding_box_opacity, 0.0..=1.0)
+                            .text("Bounding Box Opacity"),
+                    );
+                    ui.checkbox(&mut self.settings.elevation_labels, "Elevation Labels");
+                    ui.checkbox(&mut self.settings.cursor_tooltip, "Cursor Tooltip")
+                        .on_hover_text("Elevation and coordinates under the cursor");
+                    ui.add_enabled(
+                        self.settings.elevation_labels,
+                        egui::Slider::new(&mut self.settings.elevation_label_spacing, 5..=500)
+                            .text("Label Spacing (px)"),
+                    );
+                    ui.label(format!("Sample Type: {}", self.sample_type.name()));
+                    ui.label(format!("NoData Pixels: {}", self.no_data_count));
+                    if let (Some(level), DemSource::File(path)) =
+                        (self.startup.preview_level, self.startup.dem.clone())
+                    {
+                        ui.horizontal(|ui| {
+                            ui.label(format!("Preview of overview {}", level));
+                            if ui.button("Load Full Resolution").clicked() {
+                                self.startup.preview_level = None;
+                                self.open_dem_requested = Some(path);
+                            }
+                        });
+                    }
+                    ui.checkbox(&mut self.settings.show_no_data, "Highlight NoData");
+                    ui.checkbox(&mut self.settings.texture_previews, "Texture Previews");
+                    ui.horizontal(|ui| {
+                        ui.text_edit_singleline(&mut self.export_path);
+                        export_requested = ui.button("Export GeoTIFF").clicked();
+                    });
+                    if let Some(status) = &self.export_status {
+                        ui.label(status);
+                    }
+                    ui.horizontal(|ui| {
+                        ui.text_edit_singleline(&mut self.map_export_path);
+                        ui.add(
+                            egui::DragValue::new(&mut self.map_export_width)
+                                .clamp_range(1..=self.limits.max_texture_dimension_2d)
+                                .suffix(" px"),
+                        );
+                        if ui
+                            .button("Export Map")
+                            .on_hover_text("Top down PNG with a world file, as shaded now")
+                            .clicked()
+                        {
+                            self.map_export_requested = true;
+                        }
+                    });
+                    if let Some(status) = &self.map_export_status {
+                        ui.label(status);
+                    }
+                    ui.horizontal(|ui| {
+                        ui.label("Volume Above:");
+                        ui.add(egui::DragValue::new(&mut self.volume_level).suffix(" m"));
+                        measure_requested = ui.button("Measure Surface").clicked();
+                    });
+                    if let Some(status) = &self.measure_status {
+                        ui.label(status);
+                    }
+                    let unit = &mut self.settings.elevation_unit;
+                    egui::ComboBox::from_label("Elevation Unit")
+                        .selected_text(unit.name())
+                        .show_ui(ui, |ui| {
+                            for option in ElevationUnit::ALL {
+                                ui.selectable_value(unit, option, option.name());
+                            }
+                        });
+                    ui.checkbox(
+                        &mut self.settings.invert_elevation,
+                        "Invert Elevation (Bathymetry)",
+                    );
+                    for (label, value) in [
+                        ("Min Elevation", self.elevation_min),
+                        ("Max Elevation", self.elevation_max),
+                    ] {
+                        let meters = unit.to_meters(value);
+                        if *unit == ElevationUnit::Meters {
+                            ui.label(format!("{}: {:.2} m", label, meters));
+                        } else {
+                            ui.label(format!(
+                                "{}: {:.2} m ({:.2} {})",
+                                label,
+                                meters,
+                                value,
+                                unit.symbol()
+                            ));
+                        }
+                    }
+                });
+
+                let painter = ui.layer_painter(egui::LayerId::background());
+                for (index, line) in profile_lines.iter().enumerate() {
+                    let stroke =
+                        egui::Stroke::new(2.0, PROFILE_COLORS[index % PROFILE_COLORS.len()]);
+                    for segment in line.windows(2) {
+                        if let [Some(a), Some(b)] = segment {
+                            painter.line_segment([*a, *b], stroke);
+                        }
+                    }
+                }
+
+                for segment in flatten_outline.windows(2) {
+                    if let [Some(a), Some(b)] = segment {
+                        painter.line_segment(
+                            [*a, *b],
+                            egui::Stroke::new(2.0, egui::Color32::from_rgb(255, 0, 255)),
+                        );
+                    }
+                }
+
+                let measure_color = egui::Color32::from_rgb(0, 255, 200);
+                for point in measure_line.iter().flatten() {
+                    painter.circle_filled(*point, 4.0, measure_color);
+                }
+                if let [Some(a), Some(b)] = measure_line[..] {
+                    painter.line_segment([a, b], egui::Stroke::new(2.0, measure_color));
+                    if let Some(measurement) = &measurement {
+                        painter.text(
+                            a + (b - a) / 2.0 + egui::vec2(6.0, 0.0),
+                            egui::Align2::LEFT_CENTER,
+                            measurement,
+                            egui::FontId::monospace(12.0),
+                            measure_color,
+                        );
+                    }
+                }
+
+                for (position, meters) in &elevation_labels {
+                    painter.circle_filled(*position, 2.0, egui::Color32::WHITE);
+                    painter.text(
+                        *position + egui::vec2(4.0, 0.0),
+                        egui::Align2::LEFT_CENTER,
+                        format!("{:.1}", meters),
+                        egui::FontId::monospace(11.0),
+                        egui::Color32::WHITE,
+                    );
+                }
+
+                let screen = painter.clip_rect();
+                if self.paused {
+                    painter.text(
+                        screen.center_top() + egui::vec2(0.0, 16.0),
+                        egui::Align2::CENTER_TOP,
+                        "Paused",
+                        egui::FontId::proportional(20.0),
+                        egui::Color32::WHITE,
+                    );
+                }
+                if self.settings.north_arrow {
+                    overlay::draw_north_arrow(
+                        &painter,
+                        screen.right_top() + egui::vec2(-60.0, 60.0),
+                        north,
+                    );
+                }
+                if self.settings.crosshair || self.mouse_grabbed {
+                    overlay::draw_crosshair(&painter, screen.center());
+                }
+                let over_gui = ui.is_pointer_over_area() || ui.is_using_pointer();
+                if let (true, false, false, Some(cursor)) = (
+                    self.settings.cursor_tooltip,
+                    self.mouse_grabbed,
+                    over_gui,
+                    &cursor_coordinates,
+                ) {
+                    egui::show_tooltip_at_pointer(ui, egui::Id::new("cursor_tooltip"), |ui| {
+                        ui.label(match cursor.sampled {
+                            Some(elevation) => format!("{:.1} m", elevation),
+                            None => "NoData".to_string(),
+                        });
+                        ui.label(format!(
+                            "col {:.1}, row {:.1}",
+                            cursor.pixel[0], cursor.pixel[1]
+                        ));
+                        match &cursor.geographic {
+                            Some(Ok([lon, lat])) => {
+                                ui.label(format!("{:.6}°, {:.6}°", lon, lat));
+                            }
+                            _ => {
+                                ui.label(format!(
+                                    "{:.2}, {:.2}",
+                                    cursor.projected[0], cursor.projected[1]
+                                ));
+                            }
+                        }
+                    });
+                }
+                if let Some(pixels_per_meter) = pixels_per_meter {
+                    overlay::draw_scale_bar(
+                        &painter,
+                        screen.left_bottom() + egui::vec2(20.0, -20.0),
+                        pixels_per_meter,
+                    );
+                }
+
+                if let Some(previews) = &self.texture_previews {
+                    egui::Window::new("Textures")
+                        .open(&mut self.settings.texture_previews)
+                        .show(ui, |ui| {
+                            let size = egui::vec2(256.0, 256.0 / previews.aspect);
+                            ui.label("Imagery");
+                            ui.image((previews.diffuse, size));
+                            ui.label(if self.indexed {
+                                "Elevation (indexed, flat)"
+                            } else {
+                                "Normalized Elevation"
+                            });
+                            ui.image((previews.elevation, size));
+                        });
+                }
+
+                egui::Window::new("Profiles")
+                    .default_open(false)
+                    .show(ui, |ui| {
+                        ui.checkbox(&mut self.profile_editing, "Right click to add points");
+                        if ui.button("New Profile").clicked() {
+                            self.profiles.push(profile::Profile::default());
+                        }
+                        let mut removed = None;
+                        for (index, (profile, samples)) in
+                            self.profiles.iter().zip(&profile_samples).enumerate()
+                        {
+                            let color = PROFILE_COLORS[index % PROFILE_COLORS.len()];
+                            ui.separator();
+                            ui.horizontal(|ui| {
+                                ui.colored_label(
+                                    color,
+                                    format!(
+                                        "Profile {}: {} points, {:.1} cells",
+                                        index + 1,
+                                        profile.points.len(),
+                                        profile.length()
+                                    ),
+                                );
+                                if ui.button("Remove").clicked() {
+                                    removed = Some(index);
+                                }
+                            });
+                            draw_profile_plot(ui, samples, color);
+                        }
+                        if let Some(index) = removed {
+                            self.profiles.remove(index);
+                        }
+                    });
+
+                egui::Window::new("Tint Ramp")
+                    .default_open(false)
+                    .show(ui, |ui| {
+                        ui.checkbox(&mut self.settings.isobands, "Filled Elevation Bands");
+                        ui.add_enabled(
+                            self.settings.isobands,
+                            egui::Slider::new(&mut self.settings.isoband_count, 2..=32)
+                                .text("Bands"),
+                        );
+                        let ramp = &mut self.settings.tint_ramp;
+                        let (rect, _) = ui.allocate_exact_size(
+                            egui::vec2(ui.available_width(), 20.0),
+                            egui::Sense::hover(),
+                        );
+                        const SEGMENTS: usize = 64;
+                        let width = rect.width() / SEGMENTS as f32;
+                        for i in 0..SEGMENTS {
+                            let [r, g, b] = ramp.sample((i as f32 + 0.5) / SEGMENTS as f32);
+                            let left = rect.left() + i as f32 * width;
+                            ui.painter().rect_filled(
+                                egui::Rect::from_min_max(
+                                    egui::pos2(left, rect.top()),
+                                    egui::pos2(left + width + 0.5, rect.bottom()),
+                                ),
+                                0.0,
+                                egui::Rgba::from_rgb(r, g, b),
+                            );
+                        }
+
+                        let removable = ramp.stops.len() > 2;
+                        let mut removed = None;
+                        for (index, stop) in ramp.stops.iter_mut().enumerate() {
+                            ui.horizontal(|ui| {
+                                ui.add(
+                                    egui::DragValue::new(&mut stop.position)
+                                        .clamp_range(0.0..=1.0)
+                                        .speed(0.005),
+                                );
+                                ui.color_edit_button_rgb(&mut stop.color);
+                                if ui
+                                    .add_enabled(removable, egui::Button::new("Remove"))
+                                    .clicked()
+                                {
+                                    removed = Some(index);
+                                }
+                            });
+                        }
+                        if let Some(index) = removed {
+                            ramp.stops.remove(index);
+                        }
+                        ui.horizontal(|ui| {
+                            if ui.button("Add Stop").clicked() {
+                                // Split the widest gap so the ramp doesn't change
+                                let (position, _) = ramp
+                                    .stops
+                                    .windows(2)
+                                    .map(|pair| {
+                                        let middle = (pair[0].position + pair[1].position) / 2.0;
+                                        (middle, pair[1].position - pair[0].position)
+                                    })
+                                    .fold((0.5, f32::NEG_INFINITY), |best, gap| {
+                                        if gap.1 > best.1 {
+                                            gap
+                                        } else {
+                                            best
+                                        }
+                                    });
+                                let color = ramp.sample(position);
+                                ramp.stops.push(ramp::ColorStop { position, color });
+                            }
+                            if ui.button("Reset").clicked() {
+                                *ramp = ramp::ColorRamp::default();
+                            }
+                        });
+                    });
+
+                egui::Window::new("Measure")
+                    .default_open(false)
+                    .show(ui, |ui| {
+                        ui.checkbox(&mut self.measuring, "Right click two points");
+                        match &measurement {
+                            Some(measurement) => {
+                                ui.label(measurement);
+                            }
+                            None => {
+                                ui.label(format!("{} of 2 points", self.measure_points.len()));
+                            }
+                        }
+                        if ui.button("Clear").clicked() {
+                            self.measure_points.clear();
+                        }
+                    });
+
+                egui::Window::new("Clip Box")
+                    .default_open(false)
+                    .show(ui, |ui| {
+                        ui.checkbox(&mut self.settings.clip, "Clip to Box");
+                        ui.checkbox(&mut self.clip_editing, "Right click two corners");
+                        if self.clip_editing && self.clip_corner.is_some() {
+                            ui.label("1 of 2 corners");
+                        }
+                        let clip_box = &mut self.settings.clip_box;
+                        egui::Grid::new("clip_box").show(ui, |ui| {
+                            ui.label("");
+                            ui.label("Min");
+                            ui.label("Max");
+                            ui.end_row();
+                            for (axis, [min, max]) in [("X", [0, 2]), ("Z", [1, 3])] {
+                                ui.label(axis);
+                                let upper = clip_box[max];
+                                ui.add(
+                                    egui::DragValue::new(&mut clip_box[min])
+                                        .clamp_range(f32::MIN..=upper),
+                                );
+                                let lower = clip_box[min];
+                                ui.add(
+                                    egui::DragValue::new(&mut clip_box[max])
+                                        .clamp_range(lower..=f32::MAX),
+                                );
+                                ui.end_row();
+                            }
+                        });
+                        if ui.button("Fit Terrain").clicked() {
+                            let (min, max) = terrain_bounds;
+                            *clip_box = [min.x, min.z, max.x, max.z];
+                        }
+                    });
+
+                egui::Window::new("Edit")
+                    .default_open(false)
+                    .show(ui, |ui| {
+                        ui.checkbox(
+                            &mut self.flatten_editing,
+                            "Right click two corners to select a region",
+                        );
+                        ui.label(match (self.flatten_corner, self.flatten_region) {
+                            (Some([column, row]), _) => {
+                                format!("First corner at {:.0}, {:.0}", column, row)
+                            }
+                            (None, Some([left, top, right, bottom])) => {
+                                format!("Columns {}-{}, rows {}-{}", left, right, top, bottom)
+                            }
+                            (None, None) => "No region selected".to_string(),
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Elevation");
+                            ui.add(
+                                egui::DragValue::new(&mut self.flatten_elevation)
+                                    .suffix(" m")
+                                    .speed(1.0),
+                            );
+                        });
+                        if ui
+                            .add_enabled(
+                                self.flatten_region.is_some(),
+                                egui::Button::new("Flatten"),
+                            )
+                            .clicked()
+                        {
+                            flatten_requested = true;
+                        }
+                    });
+
+                if let Some(download) = &self.download {
+                    egui::Window::new("Downloading DEM")
+                        .collapsible(false)
+                        .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+                        .show(ui, |ui| {
+                            ui.label(&download.url);
+                            let (downloaded, total) = download.progress;
+                            match total {
+                                Some(total) => ui.add(
+                                    egui::ProgressBar::new(downloaded as f32 / total as f32)
+                                        .show_percentage(),
+                                ),
+                                None => {
+                                    ui.label(format!("{:.1} MiB", downloaded as f64 / 1048576.0))
+                                }
+                            };
+                        });
+                }
+
+                if let Some(dem_error) = &self.dem_error {
+                    egui::Window::new("Open DEM")
+                        .collapsible(false)
+                        .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+                        .show(ui, |ui| {
+                            ui.label(dem_error);
+                            ui.label("Path of a GeoTIFF to render:");
+                            ui.horizontal(|ui| {
+                                ui.text_edit_singleline(&mut self.open_dem_path);
+                                if ui.button("Open").clicked() {
+                                    if std::path::Path::new(&self.open_dem_path).exists() {
+                                        self.open_dem_requested = Some(self.open_dem_path.clone());
+                                    } else {
+                                        self.open_dem_status =
+                                            Some(format!("{} not found", self.open_dem_path));
+                                    }
+                                }
+                            });
+                            if let Some(status) = &self.open_dem_status {
+                                ui.colored_label(egui::Color32::RED, status);
+                            }
+                        });
+                }
+
+                egui::Window::new("Morph")
+                    .default_open(false)
+                    .show(ui, |ui| {
+                        ui.label("Blend towards a second DEM of the same size");
+                        ui.horizontal(|ui| {
+                            ui.text_edit_singleline(&mut self.morph_path);
+                            morph_load_requested = ui.button("Load").clicked();
+                        });
+                        if let Some(status) = &self.morph_status {
+                            ui.label(status);
+                        }
+                        if let Some(morph) = &mut self.morph {
+                            ui.add_enabled(
+                                !morph.animate,
+                                egui::Slider::new(&mut morph.factor, 0.0..=1.0).text("Blend"),
+                            );
+                            ui.checkbox(&mut morph.animate, "Animate");
+                            ui.add_enabled(
+                                morph.animate,
+                                egui::Slider::new(&mut morph.period, 0.5..=60.0).text("Period (s)"),
+                            );
+                        }
+                    });
+
+                egui::Window::new("Track")
+                    .default_open(false)
+                    .show(ui, |ui| {
+                        ui.label("GeoJSON, GPX or other vector file to drape over the terrain");
+                        ui.horizontal(|ui| {
+                            ui.text_edit_singleline(&mut self.track_path);
+                            track_load_requested = ui.button("Load").clicked();
+                        });
+                        if let Some(status) = &self.track_status {
+                            ui.label(status);
+                        }
+                        if let Some(track) = &self.track {
+                            ui.checkbox(&mut self.settings.track, "Show Track");
+                            ui.label(format!(
+                                "Lines: {}, Points: {}",
+                                track.lines.len(),
+                                track.points.len()
+                            ));
+                        }
+                    });
+
+                egui::Window::new("Bookmarks")
+                    .default_open(false)
+                    .show(ui, |ui| {
+                        let mut go = None;
+                        let mut removed = None;
+                        for index in 0..self.bookmarks.len() {
+                            ui.horizontal(|ui| {
+                                ui.label(format!("Bookmark {}", index + 1));
+                                if ui.button("Go").clicked() {
+                                    go = Some(index);
+                                }
+                                if ui.button("Remove").clicked() {
+                                    removed = Some(index);
+                                }
+                            });
+                        }
+                        bookmark_go = go;
+                        if let Some(index) = removed {
+                            self.bookmarks.remove(index);
+                            self.next_bookmark = 0;
+                        }
+                        if ui.button("Add (B)").clicked() {
+                            self.bookmarks.push(current_camera);
+                        }
+                        ui.label("G flies to the next bookmark, save the preset to keep them");
+                    });
+
+                egui::Window::new("Sun").default_open(false).show(ui, |ui| {
+                    ui.checkbox(&mut self.settings.lighting, "Lighting");
+                    ui.add_enabled_ui(self.settings.lighting, |ui| {
+                        ui.checkbox(&mut self.settings.shadows, "Cast Shadows");
+                        egui::ComboBox::from_label("Shadow Resolution")
+                            .selected_text(format!("{}px", self.settings.shadow_resolution))
+                            .show_ui(ui, |ui| {
+                                for resolution in shadow::RESOLUTIONS {
+                                    ui.selectable_value(
+                                        &mut self.settings.shadow_resolution,
+                                        resolution,
+                                        format!("{}px", resolution),
+                                    );
+                                }
+                            });
+                        ui.add(
+                            egui::Slider::new(&mut self.settings.shadow_softness, 0..=4)
+                                .text("Shadow Softness (texels)"),
+                        );
+                    });
+                    ui.checkbox(&mut self.settings.normal_map, "Per Pixel Normals")
+                        .on_hover_text("Shade with normals of the full DEM instead of the mesh");
+                    let (azimuth, altitude) = self.sun.azimuth_altitude();
+                    ui.label(format!("Time: {}", self.sun.clock()));
+                    ui.checkbox(&mut self.paused, "Paused (P)");
+                    ui.label(format!("Azimuth: {:.1}°", azimuth.0));
+                    ui.label(format!("Altitude: {:.1}°", altitude.0));
+                    let label = if self.sun.playing { "Pause" } else { "Play" };
+                    if ui.button(label).clicked() {
+                        self.sun.playing = !self.sun.playing;
+                    }
+                    ui.add(egui::Slider::new(&mut self.sun.time, 0.0..=24.0).text("Time of Day"));
+                    ui.add(
+                        egui::Slider::new(&mut self.sun.day_length, 1.0..=600.0)
+                            .text("Day Length (s)"),
+                    );
+                    ui.add(
+                        egui::Slider::new(&mut self.sun.max_altitude.0, 0.0..=90.0)
+                            .text("Noon Altitude"),
+                    );
+                    ui.horizontal(|ui| {
+                        ui.color_edit_button_rgb(&mut self.sun.color);
+                        ui.add(
+                            egui::Slider::new(&mut self.sun.intensity, 0.0..=2.0).text("Intensity"),
+                        );
+                    });
+                });
+
+                egui::Window::new("Lights")
+                    .default_open(false)
+                    .show(ui, |ui| {
+                        ui.label("Fill lights added to the sun");
+                        let mut removed = None;
+                        for (index, light) in self.lights.iter_mut().enumerate() {
+                            ui.separator();
+                            ui.horizontal(|ui| {
+                                ui.label(format!("Light {}", index + 1));
+                                if ui.button("Remove").clicked() {
+                                    removed = Some(index);
+                                }
+                            });
+                            ui.add(
+                                egui::Slider::new(&mut light.azimuth.0, 0.0..=360.0)
+                                    .text("Azimuth"),
+                            );
+                            ui.add(
+                                egui::Slider::new(&mut light.altitude.0, 0.0..=90.0)
+                                    .text("Altitude"),
+                            );
+                            ui.horizontal(|ui| {
+                                ui.color_edit_button_rgb(&mut light.color);
+                                ui.add(
+                                    egui::Slider::new(&mut light.intensity, 0.0..=2.0)
+                                        .text("Intensity"),
+                                );
+                            });
+                        }
+                        if let Some(index) = removed {
+                            self.lights.remove(index);
+                        }
+                        ui.separator();
+                        let can_add = self.lights.len() < light::MAX_LIGHTS - 1;
+                        if ui
+                            .add_enabled(can_add, egui::Button::new("Add Light"))
+                            .clicked()
+                        {
+                            self.lights.push(light::Light::default());
+                        }
+                    });
+
+                egui::Window::new("Settings")
+                    .default_open(false)
+                    .show(ui, |ui| {
+                        ui.label("Mouse");
+                        ui.add(
+                            egui::Slider::new(&mut self.camera_controller.sensitivity, 0.1..=5.0)
+                                .text("Sensitivity"),
+                        );
+                        ui.checkbox(&mut self.camera_controller.invert_y, "Invert Y");
+                        ui.checkbox(&mut self.camera_controller.look_smoothing, "Smooth Look");
+                        ui.separator();
+                        ui.label("Movement");
+                        ui.checkbox(&mut self.camera_controller.smoothing, "Smooth Movement");
+                        ui.checkbox(
+                            &mut self.camera_controller.clamp_to_bounds,
+                            "Stay Over Terrain",
+                        );
+                        ui.add_enabled(
+                            self.camera_controller.clamp_to_bounds,
+                            egui::Slider::new(&mut self.camera_controller.bounds_margin, 0.0..=1.0)
+                                .text("Margin"),
+                        );
+                        let mut precision = self.camera_controller.precision();
+                        if ui.checkbox(&mut precision, "Precision Mode (N)").changed() {
+                            self.camera_controller.set_precision(precision);
+                        }
+                        ui.add_enabled(
+                            precision,
+                            egui::Slider::new(&mut self.camera_controller.nudge_step, 0.01..=10.0)
+                                .logarithmic(true)
+                                .text("Nudge Step"),
+                        );
+                        ui.add_enabled(
+                            self.camera_controller.smoothing
+                                || self.camera_controller.look_smoothing,
+                            egui::Slider::new(&mut self.camera_controller.damping, 1.0..=20.0)
+                                .text("Damping"),
+                        );
+                        ui.separator();
+                        ui.label("Interface");
+                        let mut override_scale = self.ui_scale.is_some();
+                        ui.checkbox(
+                            &mut override_scale,
+                            format!("Override UI Scale (display {:.2})", self.scale_factor),
+                        );
+                        if override_scale {
+                            let scale = self.ui_scale.get_or_insert(self.scale_factor as f32);
+                            ui.add(egui::Slider::new(scale, 0.5..=4.0).text("UI Scale"));
+                        } else {
+                            self.ui_scale = None;
+                        }
+                        ui.separator();
+                        ui.label("Image");
+                        ui.add(
+                            egui::Slider::new(&mut self.settings.exposure, -4.0..=4.0)
+                                .text("Exposure (stops)"),
+                        );
+                        ui.add(
+                            egui::Slider::new(&mut self.settings.gamma, 0.2..=3.0).text("Gamma"),
+                        );
+                        if ui.button("Reset").clicked() {
+                            let defaults = RenderSettings::default();
+                            self.settings.exposure = defaults.exposure;
+                            self.settings.gamma = defaults.gamma;
+                        }
+                        ui.add(
+                            egui::Slider::new(&mut self.settings.render_scale, 0.25..=2.0)
+                                .step_by(0.25)
+                                .text("Render Scale"),
+                        );
+                        ui.separator();
+                        ui.label("Imagery Registration");
+                        const NUDGE: f32 = 0.0005;
+                        let offset = &mut self.settings.imagery_offset;
+                        ui.horizontal(|ui| {
+                            if ui.button("⬅").clicked() {
+                                offset[0] -= NUDGE;
+                            }
+                            if ui.button("➡").clicked() {
+                                offset[0] += NUDGE;
+                            }
+                            if ui.button("⬆").clicked() {
+                                offset[1] -= NUDGE;
+                            }
+                            if ui.button("⬇").clicked() {
+                                offset[1] += NUDGE;
+                            }
+                            if ui.button("Reset").clicked() {
+                                *offset = [0.0; 2];
+                                self.settings.imagery_scale = 1.0;
+                            }
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Offset");
+                            ui.add(egui::DragValue::new(&mut offset[0]).speed(NUDGE));
+                            ui.add(egui::DragValue::new(&mut offset[1]).speed(NUDGE));
+                        });
+                        ui.add(
+                            egui::Slider::new(&mut self.settings.imagery_scale, 0.5..=2.0)
+                                .text("Scale"),
+                        );
+                        ui.separator();
+                        ui.horizontal(|ui| {
+                            ui.label(format!("Preset: {}", self.preset_path));
+                            if ui.button("Save").clicked() {
+                                save_preset_requested = true;
+                            }
+                        });
+                        if let Some(status) = &self.preset_status {
+                            ui.label(status);
+                        }
+                    });
+
+                egui::Window::new("GPU").default_open(false).show(ui, |ui| {
+                    let info = &self.adapter_info;
+                    ui.label(format!("Name: {}", info.name));
+                    ui.label(format!("Backend: {:?}", info.backend));
+                    ui.label(format!("Device Type: {:?}", info.device_type));
+                    ui.label(format!("Driver: {} {}", info.driver, info.driver_info));
+                    ui.label(format!(
+                        "Vendor: {:#06x}, Device: {:#06x}",
+                        info.vendor, info.device
+                    ));
+                    ui.separator();
+                    ui.label("Limits");
+                    let limits = &self.limits;
+                    ui.label(format!(
+                        "Max Texture Size: {}",
+                        limits.max_texture_dimension_2d
+                    ));
+                    ui.label(format!("Max Buffer Size: {}", limits.max_buffer_size));
+                    ui.label(format!(
+                        "Max Uniform Buffer Binding: {}",
+                        limits.max_uniform_buffer_binding_size
+                    ));
+                    ui.label(format!("Max Bind Groups: {}", limits.max_bind_groups));
+                    ui.label(format!(
+                        "Max Vertex Attributes: {}",
+                        limits.max_vertex_attributes
+                    ));
+                    ui.separator();
+                    ui.label("Optional Features");
+                    for (name, available) in self.capabilities.list() {
+                        ui.label(format!(
+                            "{}: {}",
+                            name,
+                            if available { "yes" } else { "no" }
+                        ));
+                    }
                 });
             },
         );
+        if measure_requested {
+            self.measure_status = Some(self.measure_surface());
+        }
+        if export_requested {
+            self.export_status = Some(match self.export_elevation(&self.export_path) {
+                Ok(()) => format!("Exported to {}", self.export_path),
+                Err(e) => {
+                    error!("Failed to export elevation: {}", e);
+                    format!("Export failed: {}", e)
+                }
+            });
+        }
+        if track_load_requested {
+            let path = self.track_path.clone();
+            self.track_status = Some(match self.load_track(&path) {
+                Ok(()) => format!("Loaded {}", path),
+                Err(e) => {
+                    error!("Failed to load track: {}", e);
+                    format!("Load failed: {}", e)
+                }
+            });
+        }
+        if morph_load_requested {
+            let path = self.morph_path.clone();
+            self.morph_status = Some(match self.load_morph(&path) {
+                Ok(()) => format!("Loaded {}", path),
+                Err(e) => {
+                    error!("Failed to load morph target: {}", e);
+                    format!("Load failed: {}", e)
+                }
+            });
+        } else if self.morph.as_ref().map(|morph| morph.factor) != previous_morph_factor {
+            self.apply_morph();
+        }
+        if flatten_requested {
+            if let Some(region) = self.flatten_region {
+                self.flatten(region, self.flatten_elevation);
+            }
+        }
+        if packed_vertices != self.settings.packed_vertices {
+            self.set_packed_vertices(packed_vertices);
+        }
+        if let Some(index) = bookmark_go {
+            self.next_bookmark = index;
+            self.next_bookmark();
+        }
+        if save_preset_requested {
+            self.preset_status = Some(match self.preset().save(&self.preset_path) {
+                Ok(()) => format!("Saved to {}", self.preset_path),
+                Err(e) => {
+                    error!("Failed to save preset: {:?}", e);
+                    format!("Save failed: {}", e)
+                }
+            });
+        }
+        if self.settings.elevation_unit != previous_unit
+            || self.settings.exaggeration != previous_exaggeration
+            || self.settings.mesh_resolution != previous_resolution
+            || self.settings.vertex_budget != previous_budget
+            || self.settings.vertex_coloring != previous_coloring
+            || self.settings.value_range != previous_value_range
+            || self.settings.primitive_restart != previous_restart
+            || (self.settings.terraces, self.settings.terrace_interval) != previous_terraces
+            || (
+                self.settings.skirt,
+                self.settings.skirt_depth,
+                self.settings.skirt_bottom,
+            ) != previous_skirt
+        {
+            debug!(
+                "Mesh parameters changed: unit {:?}, exaggeration {}, resolution {:?}",
+                self.settings.elevation_unit,
+                self.settings.exaggeration,
+                self.settings.mesh_resolution
+            );
+            self.rebuild_terrain();
+        }
+        let percentiles_changed =
+            (self.settings.percentile_clip, self.settings.percentiles) != previous_percentiles;
+        if self.settings.value_range != previous_value_range
+            || (self.settings.value_range.is_none() && percentiles_changed)
+            || ((self.settings.value_range.is_some() || self.settings.percentile_clip)
+                && self.settings.elevation_unit != previous_unit)
+        {
+            self.renormalize();
+        }
+        if self.settings.normal_map && !previous_normal_map {
+            self.update_normal_map();
+        }
+        if self.settings.invert_elevation != previous_invert {
+            debug!("Inverting elevation: {}", self.settings.invert_elevation);
+            self.settings.value_range = self.settings.value_range.map(|[low, high]| [-high, -low]);
+            self.negate_elevation();
+        }
+        if (
+            self.settings.depth_bias_constant,
+            self.settings.depth_bias_slope,
+        ) != previous_bias
+        {
+            debug!("Depth bias changed, recreating pipelines");
+            self.recreate_pipelines();
+        }
+        if (self.settings.log_depth, self.settings.reversed_z) != previous_depth_mode {
+            debug!("Depth mode changed, recreating pipelines");
+            self.recreate_pipelines();
+        }
+        if self.settings.wireframe != previous_wireframe {
+            debug!("Wireframe changed, recreating pipelines");
+            self.recreate_pipelines();
+        }
+
+        if self.settings.tint_ramp != previous_tint_ramp {
+            self.settings.tint_ramp.sort();
+            self.settings
+                .tint_ramp
+                .write_texture(&self.queue, &self.tint_ramp_texture);
+        }
+        if self.settings.render_scale != previous_render_scale {
+            self.recreate_render_target();
+        }
+        if self.settings.shadow_resolution != self.shadow_map.resolution() {
+            self.shadow_map
+                .set_resolution(&self.device, self.settings.shadow_resolution);
+        }
 
         self.queue.submit(std::iter::once(encoder.finish()));
         output.present();
@@ -625,3 +6254,114 @@ impl<'a> State<'a> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Decodes a `PackedVertex` the way `vs_packed` does, before normalizing
+    fn unpack(packed: &PackedVertex, bounds: &PackingBounds) -> Vertex {
+        let unorm16 = |v: u16| v as f32 / u16::MAX as f32;
+        let snorm8 = |v: i8| (v as f32 / i8::MAX as f32).max(-1.0);
+        let unorm8 = |v: u8| v as f32 / u8::MAX as f32;
+        let extent = bounds.extent();
+        Vertex {
+            position: [0, 1, 2].map(|i| bounds.min[i] + unorm16(packed.position[i]) * extent[i]),
+            tex_coords: packed.tex_coords.map(unorm16),
+            normal: [0, 1, 2].map(|i| snorm8(packed.normal[i])),
+            color: packed.color.map(unorm8),
+        }
+    }
+
+    fn assert_close(actual: &[f32], expected: &[f32], tolerance: f32) {
+        for (a, e) in actual.iter().zip(expected) {
+            assert!(
+                (a - e).abs() <= tolerance,
+                "{:?} is not within {} of {:?}",
+                actual,
+                tolerance,
+                expected
+            );
+        }
+    }
+
+    /// Within half a 16 bit step of the bounds along each axis
+    fn assert_position_close(actual: &Vertex, expected: &Vertex, bounds: &PackingBounds) {
+        for ((a, e), extent) in actual
+            .position
+            .iter()
+            .zip(expected.position)
+            .zip(bounds.extent())
+        {
+            assert_close(&[*a], &[e], extent / u16::MAX as f32 * 0.5 + 1e-3);
+        }
+    }
+
+    fn bounds() -> PackingBounds {
+        PackingBounds {
+            min: [-2048.0, 0.0, -1500.5],
+            max: [2048.0, 87.25, 1500.5],
+        }
+    }
+
+    #[test]
+    fn packed_vertex_round_trip_at_bounds() {
+        let bounds = bounds();
+        for (position, tex_coords, normal, color) in [
+            (bounds.min, [0.0, 0.0], [-1.0, -1.0, -1.0], [0.0; 4]),
+            (bounds.max, [1.0, 1.0], [1.0, 1.0, 1.0], [1.0; 4]),
+        ] {
+            let vertex = Vertex {
+                position,
+                tex_coords,
+                normal,
+                color,
+            };
+            let unpacked = unpack(&PackedVertex::pack(&vertex, &bounds), &bounds);
+            assert_position_close(&unpacked, &vertex, &bounds);
+            assert_close(&unpacked.tex_coords, &vertex.tex_coords, 1e-6);
+            assert_close(&unpacked.normal, &vertex.normal, 1e-6);
+            assert_close(&unpacked.color, &vertex.color, 1e-6);
+        }
+    }
+
+    #[test]
+    fn packed_vertex_round_trip_at_midpoints() {
+        let bounds = bounds();
+        let vertex = Vertex {
+            position: [0, 1, 2].map(|i| (bounds.min[i] + bounds.max[i]) / 2.0),
+            tex_coords: [0.5, 0.25],
+            normal: cgmath::InnerSpace::normalize(cgmath::Vector3::new(0.3f32, 0.9, -0.2)).into(),
+            color: [0.5, 0.3, 0.75, 1.0],
+        };
+        let unpacked = unpack(&PackedVertex::pack(&vertex, &bounds), &bounds);
+        assert_position_close(&unpacked, &vertex, &bounds);
+        assert_close(
+            &unpacked.tex_coords,
+            &vertex.tex_coords,
+            0.5 / u16::MAX as f32 + 1e-7,
+        );
+        assert_close(
+            &unpacked.normal,
+            &vertex.normal,
+            0.5 / i8::MAX as f32 + 1e-6,
+        );
+        assert_close(&unpacked.color, &vertex.color, 0.5 / u8::MAX as f32 + 1e-6);
+    }
+
+    #[test]
+    fn packed_vertex_clamps_outside_bounds() {
+        let bounds = bounds();
+        let vertex = Vertex {
+            position: [5000.0, -10.0, 0.0],
+            tex_coords: [1.5, -0.5],
+            normal: [0.0, 1.0, 0.0],
+            color: [2.0, -1.0, 0.0, 1.0],
+        };
+        let packed = PackedVertex::pack(&vertex, &bounds);
+        assert_eq!(packed.position[0], u16::MAX);
+        assert_eq!(packed.position[1], 0);
+        assert_eq!(packed.tex_coords, [u16::MAX, 0]);
+        assert_eq!(packed.color, [u8::MAX, 0, 0, u8::MAX]);
+    }
+}
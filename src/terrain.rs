@@ -1,46 +1,1144 @@
 use super::state::Vertex;
-use egui_wgpu::wgpu::Texture;
+use cgmath::Vector3;
 use tracing::{debug, trace, trace_span};
 
-pub fn texture_to_vertices(texture: Texture, buffer: Vec<f64>) -> (Vec<Vertex>, Vec<u16>) {
-    let span = trace_span!("texture_to_vertices");
+/// Meters of elevation per world unit before exaggeration
+pub const VERTICAL_SCALE: f64 = 30.0;
+
+/// Index that ends a triangle strip when `MeshOptions::primitive_restart` is set
+pub const PRIMITIVE_RESTART: u32 = u32::MAX;
+
+pub struct Mesh {
+    pub vertices: Vec<Vertex>,
+    /// Triangle strip indices
+    pub indices: Vec<u32>,
+    /// Triangle list indices of the same surface, two triangles per grid cell
+    pub list_indices: Vec<u32>,
+    /// Dataset position of the world origin as (column, elevation in meters, row).
+    /// Vertices are stored relative to it so they stay near zero and keep f32 precision.
+    pub origin: Vector3<f64>,
+}
+
+/// Primitive topology the terrain is drawn with
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Topology {
+    #[default]
+    Strip,
+    List,
+}
+
+impl Topology {
+    pub const ALL: [Topology; 2] = [Topology::Strip, Topology::List];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Strip => "Triangle Strip",
+            Self::List => "Triangle List",
+        }
+    }
+}
+
+/// Source of the per-vertex colors baked into the mesh
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum VertexColoring {
+    /// White, leaves the texture unchanged
+    #[default]
+    None,
+    /// Ramp from green lowlands over brown slopes to white peaks
+    Elevation,
+}
+
+impl VertexColoring {
+    pub const ALL: [VertexColoring; 2] = [VertexColoring::None, VertexColoring::Elevation];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::None => "None",
+            Self::Elevation => "Elevation",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MeshOptions {
+    pub exaggeration: f64,
+    /// Vertex grid size, `None` for one vertex per DEM pixel. The grid is
+    /// bilinearly sampled from the DEM, so it can be denser or sparser than it
+    pub resolution: Option<[u32; 2]>,
+    /// Maximum vertex count when `resolution` is `None`, the DEM is then
+    /// meshed with the smallest pixel stride that stays under it
+    pub vertex_budget: Option<u32>,
+    pub coloring: VertexColoring,
+    /// Elevation in meters mapped to the ends of the color ramp, `None` for the
+    /// DEM's own min/max. Fixing it keeps colors consistent across tiles.
+    pub value_range: Option<[f64; 2]>,
+    /// Emit one strip per row separated by `PRIMITIVE_RESTART` instead of a
+    /// single serpentine strip, which leaves no stitching triangles between rows
+    pub primitive_restart: bool,
+    /// Meters between the slabs of a stepped relief model, `None` for the
+    /// smooth surface. See `MeshOptions::terrace`.
+    pub terrace_interval: Option<f64>,
+    /// Meters below the lowest point the border is dropped to as walls, for
+    /// the look of a solid relief model. `None` leaves the edges open.
+    /// Not exaggerated, the base stays as deep at any exaggeration.
+    pub skirt_depth: Option<f64>,
+    /// Close the skirt with a flat bottom at its base
+    pub skirt_bottom: bool,
+}
+
+impl Default for MeshOptions {
+    fn default() -> Self {
+        Self {
+            exaggeration: 1.0,
+            resolution: None,
+            vertex_budget: None,
+            coloring: VertexColoring::None,
+            value_range: None,
+            primitive_restart: false,
+            terrace_interval: None,
+            skirt_depth: None,
+            skirt_bottom: false,
+        }
+    }
+}
+
+impl MeshOptions {
+    /// Rounds `elevation` down to the slab it lies in, slabs are counted from
+    /// `minimum` so their edges line up with the contour lines
+    pub fn terrace(&self, elevation: f64, minimum: f64) -> f64 {
+        match self.terrace_interval {
+            Some(interval) if interval > 0.0 => {
+                minimum + ((elevation - minimum) / interval).floor() * interval
+            }
+            _ => elevation,
+        }
+    }
+
+    /// These options with a vertex budget of `vertex_limit` when the mesh
+    /// of a `width`x`height` DEM would have more vertices, `None` when it
+    /// has no more
+    pub fn fit(&self, [width, height]: [usize; 2], vertex_limit: u32) -> Option<Self> {
+        let vertices = match (self.resolution, self.vertex_budget) {
+            (Some([grid_width, grid_height]), _) => grid_width as u64 * grid_height as u64,
+            (None, Some(budget)) => (width as u64 * height as u64).min(budget as u64),
+            (None, None) => width as u64 * height as u64,
+        };
+        (vertices > vertex_limit as u64).then_some(Self {
+            resolution: None,
+            vertex_budget: Some(vertex_limit),
+            ..*self
+        })
+    }
+}
+
+/// Meshes a `width`x`height` DEM, see `field_to_vertices`
+pub fn texture_to_vertices(
+    buffer: &[f64],
+    [width, height]: [usize; 2],
+    options: &MeshOptions,
+) -> Mesh {
+    let field = HeightField::new(buffer, width, height, options);
+    field_to_vertices(&field, options)
+}
+
+/// Meshes every `[column, row, width, height]` window of a height field
+/// with `window_to_vertices`, one mesh per window
+pub fn tiles_to_vertices(
+    field: &HeightField,
+    windows: &[[usize; 4]],
+    stride: usize,
+    options: &MeshOptions,
+) -> Vec<Mesh> {
+    let span = trace_span!("tiles_to_vertices", tiles = windows.len());
+    let _enter = span.enter();
+
+    let meshes: Vec<Mesh> = windows
+        .iter()
+        .map(|&window| window_to_vertices(field, window, stride, options))
+        .collect();
+    debug!(
+        "Meshed {} tiles with stride {}: {} vertices",
+        windows.len(),
+        stride,
+        meshes.iter().map(|mesh| mesh.vertices.len()).sum::<usize>()
+    );
+    meshes
+}
+
+/// Meshes the `[column, row, width, height]` window of a height field with
+/// every `stride`th pixel and its last one, so windows sharing their edge
+/// pixels meet without cracks. Positions and texture coordinates are the
+/// whole field's. The skirt doesn't apply per window and is left out.
+pub fn window_to_vertices(
+    field: &HeightField,
+    [column, row, width, height]: [usize; 4],
+    stride: usize,
+    options: &MeshOptions,
+) -> Mesh {
+    let samples = |start: usize, size: usize| -> Vec<usize> {
+        let last = start + size - 1;
+        (start..last)
+            .step_by(stride.max(1))
+            .chain(std::iter::once(last))
+            .collect()
+    };
+    let (columns, rows) = (samples(column, width), samples(row, height));
+    let step = [stride.max(1) as f64; 2];
+    let vertices = rows
+        .iter()
+        .flat_map(|&y| {
+            columns
+                .iter()
+                .map(move |&x| field.vertex(x as f64, y as f64, step))
+        })
+        .collect();
+    let (indices, list_indices) = grid_indices([columns.len() as u32, rows.len() as u32], options);
+
+    Mesh {
+        vertices,
+        indices,
+        list_indices,
+        origin: field.origin(),
+    }
+}
+
+/// Smallest pixel stride `window_to_vertices` meshes each of `windows` of a
+/// `width`x`height` DEM with at most `vertex_limit` vertices, and the DEM
+/// with no more than the resolution or budget of `options`
+pub fn tile_stride(
+    windows: &[[usize; 4]],
+    [width, height]: [usize; 2],
+    vertex_limit: u32,
+    options: &MeshOptions,
+) -> usize {
+    let samples = |size: usize, stride: usize| (size.max(1) - 1).div_ceil(stride) + 1;
+    let mut stride = match (options.resolution, options.vertex_budget) {
+        (Some([grid_width, grid_height]), _) => {
+            let spacing = |size: usize, grid: u32| (size - 1) / (grid.max(2) as usize - 1);
+            spacing(width, grid_width)
+                .min(spacing(height, grid_height))
+                .max(1)
+        }
+        (None, Some(budget)) => budget_resolution(width as u32, height as u32, budget).0 as usize,
+        (None, None) => 1,
+    };
+    for &[_, _, tile_width, tile_height] in windows {
+        while samples(tile_width, stride) * samples(tile_height, stride) > vertex_limit as usize
+            && stride < tile_width.max(tile_height)
+        {
+            stride += 1;
+        }
+    }
+    stride
+}
+
+/// Meshes a height field as a grid, see `texture_to_vertices`
+pub fn field_to_vertices(field: &HeightField, options: &MeshOptions) -> Mesh {
+    let span = trace_span!("field_to_vertices");
     let _enter = span.enter();
 
     let mut vertices = Vec::new();
-    let mut indices = Vec::new();
 
-    let size = texture.size();
-    let width = size.width as f32;
-    let height = size.height as f32;
+    let width = field.width;
+    let height = field.height;
+    let [grid_width, grid_height] = match (options.resolution, options.vertex_budget) {
+        (Some(resolution), _) => resolution,
+        (None, Some(budget)) => {
+            let (stride, resolution) = budget_resolution(width as u32, height as u32, budget);
+            debug!(
+                "Vertex budget {}: stride {}, {}x{}",
+                budget, stride, resolution[0], resolution[1]
+            );
+            resolution
+        }
+        (None, None) => [width as u32, height as u32],
+    }
+    .map(|v| v.max(2));
+    debug!("Mesh grid: {}x{}", grid_width, grid_height);
 
-    let minimum_value = buffer.iter().cloned().fold(f64::INFINITY, f64::min);
-    debug!("Minimum value: {}", minimum_value);
+    let origin = field.origin();
 
-    for y in 0..height as u32 {
-        for x in 0..width as u32 {
-            let pixel = (buffer[(y * width as u32 + x) as usize] - minimum_value) / 30.0;
-            trace!("Pixel value: {}", pixel);
-            vertices.push(Vertex {
-                position: [x as f32, pixel as f32, y as f32],
-                tex_coords: [x as f32 / width as f32, y as f32 / height as f32],
-            });
+    // Grid vertices span the whole DEM, one world unit per DEM pixel
+    let column_step = (width - 1) as f64 / (grid_width - 1) as f64;
+    let row_step = (height - 1) as f64 / (grid_height - 1) as f64;
+    for y in 0..grid_height {
+        for x in 0..grid_width {
+            let column = x as f64 * column_step;
+            let row = y as f64 * row_step;
+            vertices.push(field.vertex(column, row, [column_step, row_step]));
         }
     }
 
-    for y in 0..height as i32 - 1 {
-        if y % 2 == 0 {
-            for x in 0..width as i32 {
-                indices.push((y as f32 * width + x as f32) as u16);
-                indices.push(((y + 1) as f32 * width + x as f32) as u16);
+    let (mut indices, mut list_indices) = grid_indices([grid_width, grid_height], options);
+
+    if let Some(depth) = options.skirt_depth {
+        add_skirt(
+            &mut vertices,
+            &mut indices,
+            &mut list_indices,
+            [grid_width, grid_height],
+            (-depth / VERTICAL_SCALE) as f32,
+            options,
+        );
+    }
+
+    Mesh {
+        vertices,
+        indices,
+        list_indices,
+        origin,
+    }
+}
+
+/// Triangle strip and triangle list indices of a `grid_width`x`grid_height`
+/// vertex grid in row-major order
+fn grid_indices(
+    [grid_width, grid_height]: [u32; 2],
+    options: &MeshOptions,
+) -> (Vec<u32>, Vec<u32>) {
+    let mut indices = Vec::new();
+    let index = |x: u32, y: u32| y * grid_width + x;
+    for y in 0..grid_height - 1 {
+        if options.primitive_restart {
+            if y > 0 {
+                indices.push(PRIMITIVE_RESTART);
+            }
+            for x in 0..grid_width {
+                indices.push(index(x, y));
+                indices.push(index(x, y + 1));
+            }
+        } else if y % 2 == 0 {
+            for x in 0..grid_width {
+                indices.push(index(x, y));
+                indices.push(index(x, y + 1));
             }
         } else {
-            // Reverse the direction of the row
-            for x in (0..width as i32).rev() {
-                indices.push((y as f32 * width + x as f32) as u16);
-                indices.push(((y + 1) as f32 * width + x as f32) as u16);
+            // Reverse the direction of the row, starting on the lower row keeps
+            // the triangles counter-clockwise so the top stays the front face
+            for x in (0..grid_width).rev() {
+                indices.push(index(x, y + 1));
+                indices.push(index(x, y));
+            }
+        }
+    }
+
+    let mut list_indices = Vec::new();
+    for y in 0..grid_height - 1 {
+        for x in 0..grid_width - 1 {
+            list_indices.extend_from_slice(&[
+                index(x, y),
+                index(x, y + 1),
+                index(x + 1, y),
+                index(x + 1, y),
+                index(x, y + 1),
+                index(x + 1, y + 1),
+            ]);
+        }
+    }
+    (indices, list_indices)
+}
+
+/// Appends walls from the border of a `grid_width`x`grid_height` vertex grid
+/// down to world height `base`, and the bottom with `MeshOptions::skirt_bottom`.
+/// The grid vertices keep their indices, the skirt's own vertices follow them.
+fn add_skirt(
+    vertices: &mut Vec<Vertex>,
+    indices: &mut Vec<u32>,
+    list_indices: &mut Vec<u32>,
+    [grid_width, grid_height]: [u32; 2],
+    base: f32,
+    options: &MeshOptions,
+) {
+    // Around the border with the outside on the left, which keeps the walls
+    // counter-clockwise seen from outside
+    let (last_x, last_y) = (grid_width - 1, grid_height - 1);
+    let border: Vec<(u32, u32)> = (1..=last_x)
+        .rev()
+        .map(|x| (x, 0))
+        .chain((0..last_y).map(|y| (0, y)))
+        .chain((0..last_x).map(|x| (x, last_y)))
+        .chain((1..=last_y).rev().map(|y| (last_x, y)))
+        .collect();
+
+    // A top and a bottom vertex per border vertex, facing outwards
+    let first = vertices.len() as u32;
+    for &(x, y) in &border {
+        let top = vertices[(y * grid_width + x) as usize];
+        let outwards = |i: u32, last: u32| (i == last) as i32 as f32 - (i == 0) as i32 as f32;
+        let normal = cgmath::InnerSpace::normalize(Vector3::new(
+            outwards(x, last_x),
+            0.0,
+            outwards(y, last_y),
+        ));
+        let top = Vertex {
+            normal: normal.into(),
+            ..top
+        };
+        let mut bottom = top;
+        bottom.position[1] = base;
+        vertices.extend([top, bottom]);
+    }
+    let count = border.len() as u32;
+    let top = |i: u32| first + 2 * (i % count);
+    let wall: Vec<u32> = (0..=count).flat_map(|i| [top(i), top(i) + 1]).collect();
+    append_strip(indices, &wall, options.primitive_restart);
+    for i in 0..count {
+        let (a, b) = (top(i), top(i + 1));
+        list_indices.extend_from_slice(&[a, a + 1, b, b, a + 1, b + 1]);
+    }
+
+    if options.skirt_bottom {
+        let corner = first + 2 * count;
+        for (x, y) in [(0, 0), (last_x, 0), (0, last_y), (last_x, last_y)] {
+            let mut vertex = vertices[(y * grid_width + x) as usize];
+            vertex.position[1] = base;
+            vertex.normal = [0.0, -1.0, 0.0];
+            vertices.push(vertex);
+        }
+        // Clockwise from above, so it faces down
+        let [c00, c10, c01, c11] = [corner, corner + 1, corner + 2, corner + 3];
+        append_strip(indices, &[c00, c10, c01, c11], options.primitive_restart);
+        list_indices.extend_from_slice(&[c00, c10, c01, c10, c11, c01]);
+    }
+    trace!("Skirt: {} vertices", vertices.len() as u32 - first);
+}
+
+/// Continues a triangle strip with `strip`, after a restart index or joined
+/// by degenerate triangles
+fn append_strip(indices: &mut Vec<u32>, strip: &[u32], primitive_restart: bool) {
+    if primitive_restart {
+        indices.push(PRIMITIVE_RESTART);
+    } else if let Some(&last) = indices.last() {
+        // Starting on an even index keeps the strip's winding
+        if indices.len() % 2 == 1 {
+            indices.push(last);
+        }
+        indices.extend_from_slice(&[last, strip[0]]);
+    }
+    indices.extend_from_slice(strip);
+}
+
+/// Elevation buffer in meters with the mapping of its pixels to mesh vertices
+pub struct HeightField<'a> {
+    buffer: &'a [f64],
+    width: usize,
+    height: usize,
+    minimum: f64,
+    maximum: f64,
+    /// DEM pixels per buffer pixel, above 1 for overviews
+    scale: [f64; 2],
+    options: &'a MeshOptions,
+}
+
+impl<'a> HeightField<'a> {
+    pub fn new(buffer: &'a [f64], width: usize, height: usize, options: &'a MeshOptions) -> Self {
+        let minimum = buffer.iter().cloned().fold(f64::INFINITY, f64::min);
+        let maximum = buffer.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        debug!("Minimum value: {}, maximum value: {}", minimum, maximum);
+        Self::with_range(buffer, width, height, [minimum, maximum], options)
+    }
+
+    /// Uses `[minimum, maximum]` as the elevation range instead of scanning
+    /// the buffer for it, so fields cut from one DEM share a vertical frame
+    /// and a known range isn't computed again
+    pub fn with_range(
+        buffer: &'a [f64],
+        width: usize,
+        height: usize,
+        [minimum, maximum]: [f64; 2],
+        options: &'a MeshOptions,
+    ) -> Self {
+        Self {
+            buffer,
+            width,
+            height,
+            minimum,
+            maximum,
+            scale: [1.0; 2],
+            options,
+        }
+    }
+
+    /// Treats the buffer as a decimated copy of a `width`x`height` DEM with
+    /// elevation in `[minimum, maximum]`, so its mesh lines up with the full
+    /// resolution one
+    pub fn overview_of(mut self, width: usize, height: usize, minimum: f64, maximum: f64) -> Self {
+        self.scale = [
+            width as f64 / self.width as f64,
+            height as f64 / self.height as f64,
+        ];
+        self.minimum = minimum;
+        self.maximum = maximum;
+        self
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Dataset position of the world origin, see `Mesh::origin`. The terrain is
+    /// centered on it and the lowest point sits at y = 0.
+    pub fn origin(&self) -> Vector3<f64> {
+        Vector3::new(
+            self.width as f64 * self.scale[0] / 2.0,
+            self.minimum,
+            self.height as f64 * self.scale[1] / 2.0,
+        )
+    }
+
+    /// World height of the highest point
+    pub fn top(&self) -> f64 {
+        (self.maximum - self.minimum) / VERTICAL_SCALE * self.options.exaggeration
+    }
+
+    /// World height at a fractional (column, row), clamped to the DEM
+    pub fn world_height(&self, column: f64, row: f64) -> f64 {
+        let max_column = (self.width - 1) as f64;
+        let max_row = (self.height - 1) as f64;
+        let elevation = sample_elevation(
+            self.buffer,
+            self.width,
+            self.height,
+            column.clamp(0.0, max_column),
+            row.clamp(0.0, max_row),
+        )
+        .unwrap_or(self.minimum);
+        let elevation = self.options.terrace(elevation, self.minimum);
+        (elevation - self.minimum) / VERTICAL_SCALE * self.options.exaggeration
+    }
+
+    /// World space normal at a fractional (column, row), from central
+    /// differences of the heights `step` pixels away
+    pub fn normal(&self, column: f64, row: f64, [column_step, row_step]: [f64; 2]) -> Vector3<f64> {
+        let dx = (self.world_height(column + column_step, row)
+            - self.world_height(column - column_step, row))
+            / (2.0 * column_step * self.scale[0]);
+        let dz = (self.world_height(column, row + row_step)
+            - self.world_height(column, row - row_step))
+            / (2.0 * row_step * self.scale[1]);
+        cgmath::InnerSpace::normalize(Vector3::new(-dx, 1.0, -dz))
+    }
+
+    /// Normals as `width`x`height` Rgba8Snorm texels spanning the
+    /// `[column, row, width, height]` window of the field, for shading
+    /// independent of the mesh resolution. At the window's own size there is
+    /// one per pixel, smaller maps estimate them over their texel.
+    pub fn normal_map(&self, window: [usize; 4], [width, height]: [usize; 2]) -> Vec<[i8; 4]> {
+        let snorm = |v: f64| (v.clamp(-1.0, 1.0) * 127.0).round() as i8;
+        let [first_column, first_row, window_width, window_height] = window.map(|v| v as f64);
+        let step = [window_width / width as f64, window_height / height as f64];
+        (0..height)
+            .flat_map(|row| {
+                (0..width).map(move |column| {
+                    let n = self.normal(
+                        first_column + (column as f64 + 0.5) * step[0] - 0.5,
+                        first_row + (row as f64 + 0.5) * step[1] - 0.5,
+                        step,
+                    );
+                    [snorm(n.x), snorm(n.y), snorm(n.z), 0]
+                })
+            })
+            .collect()
+    }
+
+    /// Mesh vertex at a fractional (column, row), `step` is the grid spacing
+    /// in pixels the normal is estimated over
+    pub fn vertex(&self, column: f64, row: f64, step: [f64; 2]) -> Vertex {
+        let origin = self.origin();
+        let pixel = self.world_height(column, row);
+        trace!("Pixel value: {}", pixel);
+        let normal = self.normal(column, row, step);
+        let color = match self.options.coloring {
+            VertexColoring::None => [1.0; 4],
+            VertexColoring::Elevation => {
+                let [low, high] = self
+                    .options
+                    .value_range
+                    .unwrap_or([self.minimum, self.maximum]);
+                let meters = self.minimum + pixel / self.options.exaggeration * VERTICAL_SCALE;
+                elevation_color(if high > low {
+                    (meters - low) / (high - low)
+                } else {
+                    0.0
+                })
+            }
+        };
+
+        // Pixel centers of the buffer in DEM pixels, the identity without an overview
+        let dataset_column = (column + 0.5) * self.scale[0] - 0.5;
+        let dataset_row = (row + 0.5) * self.scale[1] - 0.5;
+        Vertex {
+            position: [
+                (dataset_column - origin.x) as f32,
+                pixel as f32,
+                (dataset_row - origin.z) as f32,
+            ],
+            tex_coords: [
+                (dataset_column / (self.width as f64 * self.scale[0])) as f32,
+                (dataset_row / (self.height as f64 * self.scale[1])) as f32,
+            ],
+            normal: [normal.x as f32, normal.y as f32, normal.z as f32],
+            color,
+        }
+    }
+}
+
+/// Smallest pixel stride whose grid over a `width`x`height` DEM has at most
+/// `budget` vertices, with the resulting grid size
+pub fn budget_resolution(width: u32, height: u32, budget: u32) -> (u32, [u32; 2]) {
+    let grid = |stride: u32| [(width - 1) / stride + 1, (height - 1) / stride + 1];
+    // Start from the estimate and step up for the rounding of the grid size
+    let estimate = ((width as f64 * height as f64) / budget.max(1) as f64).sqrt();
+    let mut stride = (estimate.floor() as u32).max(1);
+    while grid(stride)[0] as u64 * grid(stride)[1] as u64 > budget as u64
+        && stride < width.max(height)
+    {
+        stride += 1;
+    }
+    (stride, grid(stride))
+}
+
+/// Color ramp for a normalized elevation in [0, 1]
+fn elevation_color(t: f64) -> [f32; 4] {
+    const STOPS: [(f64, [f64; 3]); 3] = [
+        (0.0, [0.3, 0.55, 0.25]),
+        (0.5, [0.6, 0.45, 0.3]),
+        (1.0, [1.0, 1.0, 1.0]),
+    ];
+
+    let t = t.clamp(0.0, 1.0);
+    let (low, high) = if t < STOPS[1].0 {
+        (STOPS[0], STOPS[1])
+    } else {
+        (STOPS[1], STOPS[2])
+    };
+    let f = (t - low.0) / (high.0 - low.0);
+    let [r, g, b] = [0, 1, 2].map(|i| (low.1[i] + (high.1[i] - low.1[i]) * f) as f32);
+    [r, g, b, 1.0]
+}
+
+/// Bilinearly samples `buffer` at a fractional (column, row) over the corners
+/// that aren't NaN (NoData), `None` outside the grid or when all are NaN
+pub fn sample_elevation(
+    buffer: &[f64],
+    width: usize,
+    height: usize,
+    column: f64,
+    row: f64,
+) -> Option<f64> {
+    if width == 0
+        || height == 0
+        || column < 0.0
+        || row < 0.0
+        || column > (width - 1) as f64
+        || row > (height - 1) as f64
+    {
+        return None;
+    }
+
+    let x0 = column.floor() as usize;
+    let y0 = row.floor() as usize;
+    let x1 = (x0 + 1).min(width - 1);
+    let y1 = (y0 + 1).min(height - 1);
+    let fx = column - x0 as f64;
+    let fy = row - y0 as f64;
+
+    let (sum, weight) = [
+        (x0, y0, (1.0 - fx) * (1.0 - fy)),
+        (x1, y0, fx * (1.0 - fy)),
+        (x0, y1, (1.0 - fx) * fy),
+        (x1, y1, fx * fy),
+    ]
+    .into_iter()
+    .map(|(x, y, weight)| (buffer[y * width + x], weight))
+    .filter(|&(v, weight)| !v.is_nan() && weight > 0.0)
+    .fold((0.0, 0.0), |(sum, total), (v, weight)| {
+        (sum + v * weight, total + weight)
+    });
+    (weight > 0.0).then(|| sum / weight)
+}
+
+/// Corner heights of the two triangles of every grid cell whose corners
+/// all have data, in the same split as `Mesh::list_indices`. Corners are
+/// (x, y, elevation) in cell units and elevation units.
+fn cell_triangles<'a>(
+    buffer: &'a [f64],
+    width: usize,
+    height: usize,
+    is_no_data: &'a [bool],
+) -> impl Iterator<Item = [[f64; 3]; 3]> + 'a {
+    let at = move |x: usize, y: usize| [x as f64, y as f64, buffer[y * width + x]];
+    let valid = move |x: usize, y: usize| !is_no_data.get(y * width + x).copied().unwrap_or(false);
+    (0..height.saturating_sub(1))
+        .flat_map(move |y| (0..width.saturating_sub(1)).map(move |x| (x, y)))
+        .filter(move |&(x, y)| {
+            valid(x, y) && valid(x + 1, y) && valid(x, y + 1) && valid(x + 1, y + 1)
+        })
+        .flat_map(move |(x, y)| {
+            [
+                [at(x, y), at(x, y + 1), at(x + 1, y)],
+                [at(x + 1, y), at(x, y + 1), at(x + 1, y + 1)],
+            ]
+        })
+}
+
+/// True 3D area in square meters of the surface through the pixel centers of
+/// a grid of elevations in meters, `cell` meters apart. Cells touching a
+/// `is_no_data` pixel are left out.
+pub fn surface_area(
+    buffer: &[f64],
+    width: usize,
+    height: usize,
+    cell: f64,
+    is_no_data: &[bool],
+) -> f64 {
+    cell_triangles(buffer, width, height, is_no_data)
+        .map(|[a, b, c]| {
+            let point = |[x, y, z]: [f64; 3]| Vector3::new(x * cell, y * cell, z);
+            let (a, b, c) = (point(a), point(b), point(c));
+            cgmath::InnerSpace::magnitude((b - a).cross(c - a)) / 2.0
+        })
+        .sum()
+}
+
+/// Volume in cubic meters between the surface and the plane at `level`
+/// meters where the surface is above it, see `surface_area`. Triangles that
+/// cross the plane count with their corners clamped to it, which slightly
+/// overestimates the volume along the shoreline.
+pub fn volume_above(
+    buffer: &[f64],
+    width: usize,
+    height: usize,
+    cell: f64,
+    is_no_data: &[bool],
+    level: f64,
+) -> f64 {
+    let triangle_area = cell * cell / 2.0;
+    cell_triangles(buffer, width, height, is_no_data)
+        .map(|corners| {
+            let depth: f64 = corners.iter().map(|[_, _, z]| (z - level).max(0.0)).sum();
+            triangle_area * depth / 3.0
+        })
+        .sum()
+}
+
+/// Shape of a generated terrain, see `generate_synthetic`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyntheticKind {
+    /// Fractal value noise hills
+    Noise,
+    /// Single cone in the middle
+    Cone,
+    /// Concentric sine ripples
+    Ripple,
+}
+
+impl std::str::FromStr for SyntheticKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "noise" => Ok(Self::Noise),
+            "cone" => Ok(Self::Cone),
+            "ripple" => Ok(Self::Ripple),
+            _ => Err(format!("Unknown synthetic terrain {:?}", s)),
+        }
+    }
+}
+
+/// Generates a height buffer in meters like the one the GeoTIFF loader returns
+pub fn generate_synthetic(width: usize, height: usize, kind: SyntheticKind) -> Vec<f64> {
+    const RELIEF: f64 = 500.0;
+
+    let span = trace_span!("generate_synthetic");
+    let _enter = span.enter();
+    debug!("Generating {:?} terrain: {}x{}", kind, width, height);
+
+    let mut buffer = Vec::with_capacity(width * height);
+    for row in 0..height {
+        for column in 0..width {
+            // Coordinates in [-1, 1] with the center of the grid at 0
+            let x = column as f64 / (width.max(2) - 1) as f64 * 2.0 - 1.0;
+            let y = row as f64 / (height.max(2) - 1) as f64 * 2.0 - 1.0;
+            let r = x.hypot(y);
+            let value = match kind {
+                SyntheticKind::Noise => fractal_noise(x * 4.0, y * 4.0, 5),
+                SyntheticKind::Cone => (1.0 - r).max(0.0),
+                SyntheticKind::Ripple => ((r * 6.0 * std::f64::consts::TAU).cos() + 1.0) / 2.0,
+            };
+            buffer.push(value * RELIEF);
+        }
+    }
+    buffer
+}
+
+/// Sum of `octaves` layers of value noise, in [0, 1]
+fn fractal_noise(x: f64, y: f64, octaves: u32) -> f64 {
+    let mut sum = 0.0;
+    let mut amplitude = 1.0;
+    let mut total = 0.0;
+    for octave in 0..octaves {
+        let frequency = (1 << octave) as f64;
+        sum += value_noise(x * frequency, y * frequency, octave) * amplitude;
+        total += amplitude;
+        amplitude /= 2.0;
+    }
+    sum / total
+}
+
+/// Smoothly interpolated random values at integer lattice points, in [0, 1]
+fn value_noise(x: f64, y: f64, seed: u32) -> f64 {
+    let lattice = |x: i64, y: i64| -> f64 {
+        let mut h = (x as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+            ^ (y as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F)
+            ^ (seed as u64).wrapping_mul(0x1656_67B1_9E37_79F9);
+        h ^= h >> 33;
+        h = h.wrapping_mul(0xFF51_AFD7_ED55_8CCD);
+        h ^= h >> 33;
+        (h >> 11) as f64 / (1u64 << 53) as f64
+    };
+    let smooth = |t: f64| t * t * (3.0 - 2.0 * t);
+
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (smooth(x - x0), smooth(y - y0));
+    let (x0, y0) = (x0 as i64, y0 as i64);
+    let top = lattice(x0, y0) * (1.0 - fx) + lattice(x0 + 1, y0) * fx;
+    let bottom = lattice(x0, y0 + 1) * (1.0 - fx) + lattice(x0 + 1, y0 + 1) * fx;
+    top * (1.0 - fy) + bottom * fy
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ramp(width: usize, height: usize) -> Vec<f64> {
+        (0..width * height)
+            .map(|i| ((i % width) as f64 * 0.7).sin() * 40.0 + (i / width) as f64 * 3.0)
+            .collect()
+    }
+
+    /// Triangles of a mesh as the bits of their corner positions, sorted
+    fn triangles(mesh: &Mesh) -> Vec<[[u32; 3]; 3]> {
+        let mut triangles: Vec<_> = mesh
+            .list_indices
+            .chunks(3)
+            .map(|triangle| {
+                let corner = |i: u32| mesh.vertices[i as usize].position.map(f32::to_bits);
+                [
+                    corner(triangle[0]),
+                    corner(triangle[1]),
+                    corner(triangle[2]),
+                ]
+            })
+            .collect();
+        triangles.sort();
+        triangles
+    }
+
+    #[test]
+    fn primitive_restart_only_between_rows() {
+        let (width, height) = (7, 5);
+        let buffer = ramp(width, height);
+        let options = MeshOptions {
+            primitive_restart: true,
+            ..Default::default()
+        };
+        let field = HeightField::new(&buffer, width, height, &options);
+        let mesh = field_to_vertices(&field, &options);
+
+        let rows: Vec<&[u32]> = mesh.indices.split(|&i| i == PRIMITIVE_RESTART).collect();
+        assert_eq!(rows.len(), height - 1);
+        for (y, row) in rows.iter().enumerate() {
+            let expected: Vec<u32> = (0..width as u32)
+                .flat_map(|x| {
+                    [
+                        y as u32 * width as u32 + x,
+                        (y as u32 + 1) * width as u32 + x,
+                    ]
+                })
+                .collect();
+            assert_eq!(*row, expected.as_slice());
+        }
+    }
+
+    #[test]
+    fn no_primitive_restart_without_the_option() {
+        let buffer = ramp(7, 5);
+        let options = MeshOptions::default();
+        let mesh = field_to_vertices(&HeightField::new(&buffer, 7, 5, &options), &options);
+        assert!(!mesh.indices.contains(&PRIMITIVE_RESTART));
+    }
+
+    #[test]
+    fn budget_resolution_stays_under_budget_with_minimal_stride() {
+        let grid = |width: u32, height: u32, stride: u32| {
+            ((width - 1) / stride + 1) as u64 * ((height - 1) / stride + 1) as u64
+        };
+        for (width, height) in [
+            (2, 2),
+            (100, 100),
+            (1000, 750),
+            (4097, 1201),
+            (10000, 10000),
+        ] {
+            for budget in [4, 1000, 65_536, 2_000_000] {
+                let (stride, [grid_width, grid_height]) = budget_resolution(width, height, budget);
+                let count = grid_width as u64 * grid_height as u64;
+                assert_eq!(count, grid(width, height, stride));
+                assert!(
+                    count <= budget as u64,
+                    "{}x{} at {}: {} vertices",
+                    width,
+                    height,
+                    budget,
+                    count
+                );
+                if stride > 1 {
+                    assert!(grid(width, height, stride - 1) > budget as u64);
+                }
             }
         }
     }
 
-    (vertices, indices)
+    #[test]
+    fn budget_resolution_keeps_small_dem() {
+        assert_eq!(budget_resolution(100, 50, 10_000), (1, [100, 50]));
+    }
+
+    #[test]
+    fn skirt_vertex_and_index_counts() {
+        let (width, height) = (7, 5);
+        let buffer = ramp(width, height);
+        let border = 2 * (width - 1) + 2 * (height - 1);
+        for skirt_bottom in [false, true] {
+            let open = MeshOptions {
+                primitive_restart: true,
+                ..Default::default()
+            };
+            let skirted = MeshOptions {
+                skirt_depth: Some(60.0),
+                skirt_bottom,
+                ..open
+            };
+            let plain = field_to_vertices(&HeightField::new(&buffer, width, height, &open), &open);
+            let mesh = field_to_vertices(
+                &HeightField::new(&buffer, width, height, &skirted),
+                &skirted,
+            );
+
+            let bottom = skirt_bottom as usize;
+            // A top and a bottom vertex per border vertex, four bottom corners
+            assert_eq!(
+                mesh.vertices.len() - plain.vertices.len(),
+                2 * border + 4 * bottom
+            );
+            // Two triangles per wall segment, two for the bottom
+            assert_eq!(
+                mesh.list_indices.len() - plain.list_indices.len(),
+                6 * border + 6 * bottom
+            );
+            // The closed wall strip and the bottom strip, each after a restart
+            assert_eq!(
+                mesh.indices.len() - plain.indices.len(),
+                1 + 2 * (border + 1) + 5 * bottom
+            );
+            // The grid keeps its indices, the skirt follows it
+            assert_eq!(
+                mesh.list_indices[..plain.list_indices.len()],
+                plain.list_indices[..]
+            );
+            let base = (-60.0 / VERTICAL_SCALE) as f32;
+            let skirt = &mesh.vertices[plain.vertices.len()..];
+            assert!(skirt
+                .chunks(2)
+                .take(border)
+                .all(|pair| pair[1].position[1] == base));
+        }
+    }
+
+    fn assert_near(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() <= 1e-9 * expected.abs().max(1.0),
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn flat_plane_area_and_volume() {
+        let (width, height, cell) = (6, 4, 2.5);
+        let buffer = vec![10.0; width * height];
+        let no_data = vec![false; width * height];
+        let area = (width - 1) as f64 * (height - 1) as f64 * cell * cell;
+        assert_near(surface_area(&buffer, width, height, cell, &no_data), area);
+        assert_near(
+            volume_above(&buffer, width, height, cell, &no_data, 4.0),
+            area * 6.0,
+        );
+        assert_near(
+            volume_above(&buffer, width, height, cell, &no_data, 12.0),
+            0.0,
+        );
+    }
+
+    #[test]
+    fn flat_plane_leaves_out_no_data_cells() {
+        let (width, height) = (3, 3);
+        let buffer = vec![1.0; width * height];
+        let mut no_data = vec![false; width * height];
+        // The corner pixel only touches one of the four cells
+        no_data[0] = true;
+        assert_near(surface_area(&buffer, width, height, 1.0, &no_data), 3.0);
+    }
+
+    #[test]
+    fn pyramid_area_and_volume() {
+        // Four planar faces meeting along the middle row and column, so the
+        // grid triangles lie in them exactly
+        let (size, cell, peak, slope) = (9, 3.0, 50.0, 4.0);
+        let center = (size / 2) as f64;
+        let buffer: Vec<f64> = (0..size * size)
+            .map(|i| {
+                let (x, y) = ((i % size) as f64, (i / size) as f64);
+                peak - slope * ((x - center).abs() + (y - center).abs())
+            })
+            .collect();
+        let no_data = vec![false; size * size];
+
+        let side = (size - 1) as f64 * cell;
+        // Each face rises `slope` meters per cell along both axes
+        let gradient = slope / cell;
+        assert_near(
+            surface_area(&buffer, size, size, cell, &no_data),
+            side * side * (1.0 + 2.0 * gradient * gradient).sqrt(),
+        );
+        // Mean of |x - center| + |y - center| over the square is half its side
+        assert_near(
+            volume_above(&buffer, size, size, cell, &no_data, 0.0),
+            side * side * (peak - slope * center),
+        );
+    }
+
+    #[test]
+    fn tiled_mesh_matches_untiled() {
+        let (width, height) = (40, 30);
+        let buffer = ramp(width, height);
+        let options = MeshOptions::default();
+        let untiled = texture_to_vertices(&buffer, [width, height], &options);
+        let field = HeightField::new(&buffer, width, height, &options);
+        let windows = crate::gtiff::tile_windows(width, height, 16);
+        let tiles = tiles_to_vertices(&field, &windows, 1, &options);
+
+        assert_eq!(tiles.len(), windows.len());
+        let mut tiled: Vec<_> = tiles.iter().flat_map(triangles).collect();
+        tiled.sort();
+        assert_eq!(tiled, triangles(&untiled));
+        for tile in &tiles {
+            assert_eq!(tile.origin, untiled.origin);
+            assert!(tile
+                .indices
+                .iter()
+                .all(|&i| (i as usize) < tile.vertices.len()));
+        }
+    }
+
+    #[test]
+    fn tiled_mesh_keeps_vertices_across_tiles() {
+        let (width, height) = (40, 30);
+        let buffer = ramp(width, height);
+        let options = MeshOptions::default();
+        let untiled = texture_to_vertices(&buffer, [width, height], &options);
+        let field = HeightField::new(&buffer, width, height, &options);
+        let windows = crate::gtiff::tile_windows(width, height, 16);
+        for tile in tiles_to_vertices(&field, &windows, 1, &options) {
+            for vertex in &tile.vertices {
+                let column = (vertex.position[0] as f64 + untiled.origin.x).round() as usize;
+                let row = (vertex.position[2] as f64 + untiled.origin.z).round() as usize;
+                let expected = untiled.vertices[row * width + column];
+                assert_eq!(vertex.position, expected.position);
+                assert_eq!(vertex.tex_coords, expected.tex_coords);
+                assert_eq!(vertex.normal, expected.normal);
+            }
+        }
+    }
+
+    #[test]
+    fn strided_tiles_share_edge_vertices() {
+        let (width, height) = (40, 30);
+        let buffer = ramp(width, height);
+        let options = MeshOptions::default();
+        let field = HeightField::new(&buffer, width, height, &options);
+        let windows = crate::gtiff::tile_windows(width, height, 16);
+        let tiles = tiles_to_vertices(&field, &windows, 4, &options);
+        // The right edge of the first tile is the left edge of the second
+        let edge = |mesh: &Mesh, x: f32| -> Vec<[u32; 3]> {
+            mesh.vertices
+                .iter()
+                .filter(|vertex| vertex.position[0] == x)
+                .map(|vertex| vertex.position.map(f32::to_bits))
+                .collect()
+        };
+        let x = (windows[1][0] as f64 - tiles[0].origin.x) as f32;
+        assert!(!edge(&tiles[0], x).is_empty());
+        assert_eq!(edge(&tiles[0], x), edge(&tiles[1], x));
+    }
+
+    #[test]
+    fn tile_stride_fits_vertex_limit() {
+        let (width, height) = (10000, 10000);
+        let windows = crate::gtiff::tile_windows(width, height, 8192);
+        let options = MeshOptions::default();
+        let limit = 5_000_000;
+        let stride = tile_stride(&windows, [width, height], limit, &options);
+        let samples = |size: usize| (size - 1).div_ceil(stride) + 1;
+        for &[_, _, tile_width, tile_height] in &windows {
+            assert!(samples(tile_width) * samples(tile_height) <= limit as usize);
+        }
+        assert!(tile_stride(&windows, [width, height], limit * 4, &options) < stride);
+        assert_eq!(
+            tile_stride(&windows, [width, height], u32::MAX, &options),
+            1
+        );
+
+        let budget = MeshOptions {
+            vertex_budget: Some(1_000_000),
+            ..options
+        };
+        assert_eq!(
+            tile_stride(&windows, [width, height], u32::MAX, &budget),
+            10
+        );
+    }
+
+    #[test]
+    fn fit_budgets_only_oversized_meshes() {
+        let options = MeshOptions::default();
+        assert!(options.fit([1000, 1000], 1_000_000).is_none());
+        let fitted = options.fit([10000, 10000], 1_000_000).unwrap();
+        assert_eq!(fitted.vertex_budget, Some(1_000_000));
+        assert_eq!(fitted.resolution, None);
+
+        let resolution = MeshOptions {
+            resolution: Some([2000, 2000]),
+            ..options
+        };
+        assert!(resolution.fit([100, 100], 1_000_000).is_some());
+    }
+
+    #[test]
+    fn normal_map_of_window_matches_whole_field() {
+        let (width, height) = (40, 30);
+        let buffer = ramp(width, height);
+        let options = MeshOptions::default();
+        let field = HeightField::new(&buffer, width, height, &options);
+        let whole = field.normal_map([0, 0, width, height], [width, height]);
+        let window = [15, 10, 16, 12];
+        let part = field.normal_map(window, [16, 12]);
+        assert_eq!(part, crate::gtiff::crop(&whole, width, window));
+    }
 }
@@ -1,5 +1,6 @@
 use anyhow::*;
 use image::GenericImageView;
+use tracing::warn;
 
 pub struct Texture {
     #[allow(unused)]
@@ -14,9 +15,20 @@ impl Texture {
         queue: &egui_wgpu::wgpu::Queue,
         bytes: &[u8],
         label: &str,
+        max_dimension: Option<u32>,
     ) -> Result<Self> {
-        let img = image::load_from_memory(bytes)?;
-        Self::from_image(device, queue, &img, Some(label))
+        let format = image::guess_format(bytes)
+            .with_context(|| format!("{} is not in a recognized image format", label))?;
+        let img = image::load_from_memory_with_format(bytes, format).with_context(|| {
+            format!(
+                "Failed to decode {} as {:?}, supported are PNG, JPEG, WebP and TIFF",
+                label, format
+            )
+        })?;
+        if img.width() == 0 || img.height() == 0 {
+            bail!("{} is empty", label);
+        }
+        Self::from_image(device, queue, &img, Some(label), max_dimension)
     }
 
     pub fn from_image(
@@ -24,7 +36,31 @@ impl Texture {
         queue: &egui_wgpu::wgpu::Queue,
         img: &image::DynamicImage,
         label: Option<&str>,
+        max_dimension: Option<u32>,
     ) -> Result<Self> {
+        // Images over the device limit would fail to upload
+        let limit = device
+            .limits()
+            .max_texture_dimension_2d
+            .min(max_dimension.unwrap_or(u32::MAX))
+            .max(1);
+        let (width, height) = img.dimensions();
+        let resized;
+        let img = if width > limit || height > limit {
+            resized = img.resize(limit, limit, image::imageops::FilterType::Triangle);
+            warn!(
+                "{} is {}x{}, downsampled to {}x{} to fit {}",
+                label.unwrap_or("Texture"),
+                width,
+                height,
+                resized.width(),
+                resized.height(),
+                limit
+            );
+            &resized
+        } else {
+            img
+        };
         let rgba = img.to_rgba8();
         let dimensions = img.dimensions();
 
@@ -79,6 +115,62 @@ impl Texture {
         })
     }
 
+    /// Color texture sized and formatted like `config` to render into and
+    /// sample afterwards
+    pub fn create_render_target(
+        device: &egui_wgpu::wgpu::Device,
+        config: &egui_wgpu::wgpu::SurfaceConfiguration,
+        label: &str,
+    ) -> Self {
+        let texture = device.create_texture(&egui_wgpu::wgpu::TextureDescriptor {
+            label: Some(label),
+            size: egui_wgpu::wgpu::Extent3d {
+                width: config.width.max(1),
+                height: config.height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: egui_wgpu::wgpu::TextureDimension::D2,
+            format: config.format,
+            usage: egui_wgpu::wgpu::TextureUsages::RENDER_ATTACHMENT
+                | egui_wgpu::wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+
+        let view = texture.create_view(&egui_wgpu::wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&egui_wgpu::wgpu::SamplerDescriptor {
+            address_mode_u: egui_wgpu::wgpu::AddressMode::ClampToEdge,
+            address_mode_v: egui_wgpu::wgpu::AddressMode::ClampToEdge,
+            address_mode_w: egui_wgpu::wgpu::AddressMode::ClampToEdge,
+            mag_filter: egui_wgpu::wgpu::FilterMode::Linear,
+            min_filter: egui_wgpu::wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Self {
+            texture,
+            view,
+            sampler,
+        }
+    }
+
+    /// `compare` for a depth buffer with near at 1 and far at 0 when
+    /// `reversed_z`, see `RenderSettings::reversed_z`
+    pub fn depth_compare(
+        compare: egui_wgpu::wgpu::CompareFunction,
+        reversed_z: bool,
+    ) -> egui_wgpu::wgpu::CompareFunction {
+        use egui_wgpu::wgpu::CompareFunction::*;
+        match (compare, reversed_z) {
+            (Less, true) => Greater,
+            (LessEqual, true) => GreaterEqual,
+            (Greater, true) => Less,
+            (GreaterEqual, true) => LessEqual,
+            (compare, _) => compare,
+        }
+    }
+
     pub const DEPTH_FORMAT: egui_wgpu::wgpu::TextureFormat =
         egui_wgpu::wgpu::TextureFormat::Depth32Float;
 
@@ -0,0 +1,329 @@
+use crate::gtiff::GeoReference;
+use egui_wgpu::wgpu::util::DeviceExt;
+use gdal::{
+    spatial_ref::{AxisMappingStrategy, CoordTransform, SpatialRef},
+    vector::{Geometry, LayerAccess},
+    Dataset,
+};
+use tracing::{debug, debug_span, trace, warn};
+
+/// World units the overlay floats above the terrain so it isn't hidden in it
+const LIFT: f32 = 0.1;
+/// World height of the pins drawn for points
+const PIN_HEIGHT: f32 = 4.0;
+/// Lines are resampled every this many DEM pixels to follow the terrain
+/// between their vertices
+const STEP: f64 = 1.0;
+
+const LINE_COLOR: [f32; 3] = [1.0, 0.85, 0.1];
+const POINT_COLOR: [f32; 3] = [0.9, 0.1, 0.1];
+
+/// Lines and points of a vector file in dataset coordinates (column, row)
+#[derive(Debug, Default, Clone)]
+pub struct Track {
+    pub lines: Vec<Vec<[f64; 2]>>,
+    pub points: Vec<[f64; 2]>,
+}
+
+impl Track {
+    /// Reads every line and point of a GeoJSON, GPX or other OGR vector file,
+    /// reprojected into the DEM's CRS. Polygons contribute their rings as lines.
+    pub fn load(path: &str, geo: &GeoReference) -> gdal::errors::Result<Self> {
+        let span = debug_span!("Track::load", path = path);
+        let _enter = span.enter();
+
+        let dataset = Dataset::open(path)?;
+        let target = if geo.crs_wkt.is_empty() {
+            None
+        } else {
+            let mut target = SpatialRef::from_wkt(&geo.crs_wkt)?;
+            target.set_axis_mapping_strategy(AxisMappingStrategy::TraditionalGisOrder);
+            Some(target)
+        };
+
+        let mut track = Self::default();
+        for mut layer in dataset.layers() {
+            // GPX repeats every track and route vertex as a point layer
+            let name = layer.name();
+            if name == "track_points" || name == "route_points" {
+                continue;
+            }
+            let transform = match (layer.spatial_ref(), &target) {
+                (Some(mut source), Some(target)) => {
+                    source.set_axis_mapping_strategy(AxisMappingStrategy::TraditionalGisOrder);
+                    Some(CoordTransform::new(&source, target)?)
+                }
+                _ => {
+                    warn!("Layer {} or the DEM has no CRS, not reprojecting", name);
+                    None
+                }
+            };
+            for feature in layer.features() {
+                if let Some(geometry) = feature.geometry() {
+                    track.add(geometry, transform.as_ref(), geo)?;
+                }
+            }
+            trace!("Read layer {}", name);
+        }
+        debug!(
+            "Loaded {} lines and {} points",
+            track.lines.len(),
+            track.points.len()
+        );
+        Ok(track)
+    }
+
+    fn add(
+        &mut self,
+        geometry: &Geometry,
+        transform: Option<&CoordTransform>,
+        geo: &GeoReference,
+    ) -> gdal::errors::Result<()> {
+        // Collections, multi geometries and polygons hold their parts
+        if geometry.geometry_count() > 0 {
+            for index in 0..geometry.geometry_count() {
+                self.add(&geometry.get_geometry(index), transform, geo)?;
+            }
+            return Ok(());
+        }
+
+        let points = geometry.get_point_vec();
+        let mut x: Vec<f64> = points.iter().map(|p| p.0).collect();
+        let mut y: Vec<f64> = points.iter().map(|p| p.1).collect();
+        if let Some(transform) = transform {
+            transform.transform_coords(&mut x, &mut y, &mut [])?;
+        }
+        // Nothing can be placed on a DEM with a singular geotransform
+        let pixels: Vec<[f64; 2]> = x
+            .into_iter()
+            .zip(y)
+            .filter_map(|(x, y)| geo.projected_to_pixel(x, y))
+            .collect();
+        match geometry.geometry_name().as_str() {
+            "POINT" => self.points.extend(pixels),
+            _ if pixels.len() >= 2 => self.lines.push(pixels),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Line list vertices draped over the terrain. `world_at` gives the
+    /// terrain surface for a (column, row), `None` outside the DEM, where
+    /// points are skipped and lines are cut. Colors are sRGB encoded for
+    /// non-sRGB targets when `encode_srgb` is set.
+    pub fn vertices(
+        &self,
+        world_at: impl Fn(f64, f64) -> Option<cgmath::Point3<f32>>,
+        encode_srgb: bool,
+    ) -> Vec<TrackVertex> {
+        let color = |[r, g, b]: [f32; 3]| {
+            let encode = |c: f32| if encode_srgb { linear_to_srgb(c) } else { c };
+            [encode(r), encode(g), encode(b), 1.0]
+        };
+        let line_color = color(LINE_COLOR);
+        let point_color = color(POINT_COLOR);
+        let lifted = |column, row| {
+            world_at(column, row).map(|p| TrackVertex {
+                position: [p.x, p.y + LIFT, p.z],
+                color: line_color,
+            })
+        };
+
+        let mut vertices = Vec::new();
+        for line in &self.lines {
+            let mut previous = None;
+            for segment in line.windows(2) {
+                let [[x0, y0], [x1, y1]] = [segment[0], segment[1]];
+                let steps = ((x1 - x0).hypot(y1 - y0) / STEP).ceil().max(1.0) as usize;
+                for step in 0..=steps {
+                    let t = step as f64 / steps as f64;
+                    let vertex = lifted(x0 + (x1 - x0) * t, y0 + (y1 - y0) * t);
+                    if let (Some(a), Some(b)) = (previous, vertex) {
+                        vertices.extend([a, b]);
+                    }
+                    previous = vertex;
+                }
+            }
+        }
+        for &[column, row] in &self.points {
+            if let Some(base) = world_at(column, row) {
+                let top = [base.x, base.y + PIN_HEIGHT, base.z];
+                vertices.extend([base.into(), top].map(|position| TrackVertex {
+                    position,
+                    color: point_color,
+                }));
+            }
+        }
+        vertices
+    }
+}
+
+/// Line list vertices of the 12 edges of the box between `min` and `max`,
+/// `color` is linear RGBA and sRGB encoded like in `Track::vertices`
+pub fn box_vertices(
+    min: cgmath::Point3<f32>,
+    max: cgmath::Point3<f32>,
+    [r, g, b, a]: [f32; 4],
+    encode_srgb: bool,
+) -> Vec<TrackVertex> {
+    let encode = |c: f32| if encode_srgb { linear_to_srgb(c) } else { c };
+    let color = [encode(r), encode(g), encode(b), a];
+    // Corner i takes max on the axes whose bit is set
+    let corner = |i: usize| {
+        [
+            if i & 1 != 0 { max.x } else { min.x },
+            if i & 2 != 0 { max.y } else { min.y },
+            if i & 4 != 0 { max.z } else { min.z },
+        ]
+    };
+    (0..8)
+        .flat_map(|i| [1, 2, 4].map(move |bit| (i, i | bit)))
+        .filter(|(i, j)| i != j)
+        .flat_map(|(i, j)| [corner(i), corner(j)].map(|position| TrackVertex { position, color }))
+        .collect()
+}
+
+fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct TrackVertex {
+    pub position: [f32; 3],
+    pub color: [f32; 4],
+}
+
+/// Line pipeline the overlay is drawn with inside the terrain pass, depth
+/// tested against the terrain and alpha blended
+pub struct TrackPipeline {
+    pipeline: egui_wgpu::wgpu::RenderPipeline,
+    shader: egui_wgpu::wgpu::ShaderModule,
+    layout: egui_wgpu::wgpu::PipelineLayout,
+    format: egui_wgpu::wgpu::TextureFormat,
+    vertex_buffer: Option<egui_wgpu::wgpu::Buffer>,
+    num_vertices: u32,
+}
+
+impl TrackPipeline {
+    pub fn new(
+        device: &egui_wgpu::wgpu::Device,
+        format: egui_wgpu::wgpu::TextureFormat,
+        camera_bind_group_layout: &egui_wgpu::wgpu::BindGroupLayout,
+    ) -> Self {
+        let shader = device.create_shader_module(egui_wgpu::wgpu::include_wgsl!("track.wgsl"));
+        let layout = device.create_pipeline_layout(&egui_wgpu::wgpu::PipelineLayoutDescriptor {
+            label: Some("Track Pipeline Layout"),
+            bind_group_layouts: &[camera_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = create_pipeline(device, &shader, &layout, format, false);
+        trace!("Track pipeline created");
+
+        Self {
+            pipeline,
+            shader,
+            layout,
+            format,
+            vertex_buffer: None,
+            num_vertices: 0,
+        }
+    }
+
+    /// Recreates the pipeline for the depth convention, see
+    /// `RenderSettings::reversed_z`
+    pub fn set_reversed_z(&mut self, device: &egui_wgpu::wgpu::Device, reversed_z: bool) {
+        self.pipeline =
+            create_pipeline(device, &self.shader, &self.layout, self.format, reversed_z);
+    }
+
+    /// Replaces the drawn vertices, an empty slice draws nothing
+    pub fn set_vertices(&mut self, device: &egui_wgpu::wgpu::Device, vertices: &[TrackVertex]) {
+        self.destroy();
+        self.num_vertices = vertices.len() as u32;
+        self.vertex_buffer = (!vertices.is_empty()).then(|| {
+            device.create_buffer_init(&egui_wgpu::wgpu::util::BufferInitDescriptor {
+                label: Some("Track Vertex Buffer"),
+                contents: bytemuck::cast_slice(vertices),
+                usage: egui_wgpu::wgpu::BufferUsages::VERTEX,
+            })
+        });
+    }
+
+    /// Draws into a pass with the terrain's depth attachment, replacing its
+    /// bind group 0
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut egui_wgpu::wgpu::RenderPass<'a>,
+        camera_bind_group: &'a egui_wgpu::wgpu::BindGroup,
+    ) {
+        let Some(vertex_buffer) = &self.vertex_buffer else {
+            return;
+        };
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+        render_pass.draw(0..self.num_vertices, 0..1);
+    }
+
+    pub fn destroy(&self) {
+        if let Some(vertex_buffer) = &self.vertex_buffer {
+            vertex_buffer.destroy();
+        }
+    }
+}
+
+fn create_pipeline(
+    device: &egui_wgpu::wgpu::Device,
+    shader: &egui_wgpu::wgpu::ShaderModule,
+    layout: &egui_wgpu::wgpu::PipelineLayout,
+    format: egui_wgpu::wgpu::TextureFormat,
+    reversed_z: bool,
+) -> egui_wgpu::wgpu::RenderPipeline {
+    device.create_render_pipeline(&egui_wgpu::wgpu::RenderPipelineDescriptor {
+        label: Some("Track Pipeline"),
+        layout: Some(layout),
+        vertex: egui_wgpu::wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_main",
+            buffers: &[egui_wgpu::wgpu::VertexBufferLayout {
+                array_stride: std::mem::size_of::<TrackVertex>() as egui_wgpu::wgpu::BufferAddress,
+                step_mode: egui_wgpu::wgpu::VertexStepMode::Vertex,
+                attributes: &egui_wgpu::wgpu::vertex_attr_array![
+                    0 => Float32x3,
+                    1 => Float32x4,
+                ],
+            }],
+        },
+        fragment: Some(egui_wgpu::wgpu::FragmentState {
+            module: shader,
+            entry_point: "fs_main",
+            targets: &[Some(egui_wgpu::wgpu::ColorTargetState {
+                format,
+                blend: Some(egui_wgpu::wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: egui_wgpu::wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: egui_wgpu::wgpu::PrimitiveState {
+            topology: egui_wgpu::wgpu::PrimitiveTopology::LineList,
+            ..Default::default()
+        },
+        // Line topologies can't have a depth bias, see `LIFT` instead
+        depth_stencil: Some(egui_wgpu::wgpu::DepthStencilState {
+            format: crate::texture::Texture::DEPTH_FORMAT,
+            depth_write_enabled: false,
+            depth_compare: crate::texture::Texture::depth_compare(
+                egui_wgpu::wgpu::CompareFunction::LessEqual,
+                reversed_z,
+            ),
+            stencil: egui_wgpu::wgpu::StencilState::default(),
+            bias: egui_wgpu::wgpu::DepthBiasState::default(),
+        }),
+        multisample: egui_wgpu::wgpu::MultisampleState::default(),
+        multiview: None,
+    })
+}
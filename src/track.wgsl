@@ -0,0 +1,40 @@
+// Draws a GPS track or other vector overlay draped over the terrain
+
+struct Camera {
+    view_proj: mat4x4<f32>,
+    log_depth: f32,
+    _padding0: f32,
+    _padding1: f32,
+    _padding2: f32,
+}
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) color: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+}
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(in.position, 1.0);
+    // Per vertex only, the resampled lines are short enough for it
+    if camera.log_depth > 0.0 {
+        let w = out.clip_position.w;
+        out.clip_position.z = log2(max(1e-6, 1.0 + w)) * camera.log_depth * w;
+    }
+    out.color = in.color;
+    return out;
+}
+
+// Colors are encoded on the CPU for non-sRGB targets, see `track::Track::vertices`
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return in.color;
+}
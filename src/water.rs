@@ -0,0 +1,154 @@
+use tracing::trace;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct WaterUniform {
+    /// World space XZ extent, min x, min z, max x, max z
+    pub bounds: [f32; 4],
+    /// Linear RGB and opacity
+    pub color: [f32; 4],
+    /// World height of the plane
+    pub level: f32,
+    /// Seconds driving the wave shimmer
+    pub time: f32,
+    /// Non-zero when the target isn't sRGB and the shader has to encode
+    pub encode_srgb: u32,
+    pub _padding: f32,
+}
+
+/// Flat semi-transparent water plane, drawn last in the terrain pass so the
+/// terrain above the water level stays visible through depth testing
+pub struct Water {
+    pipeline: egui_wgpu::wgpu::RenderPipeline,
+    shader: egui_wgpu::wgpu::ShaderModule,
+    layout: egui_wgpu::wgpu::PipelineLayout,
+    format: egui_wgpu::wgpu::TextureFormat,
+    uniform_buffer: egui_wgpu::wgpu::Buffer,
+    bind_group: egui_wgpu::wgpu::BindGroup,
+}
+
+impl Water {
+    pub fn new(
+        device: &egui_wgpu::wgpu::Device,
+        format: egui_wgpu::wgpu::TextureFormat,
+        camera_bind_group_layout: &egui_wgpu::wgpu::BindGroupLayout,
+    ) -> Self {
+        let shader = device.create_shader_module(egui_wgpu::wgpu::include_wgsl!("water.wgsl"));
+        let bind_group_layout =
+            device.create_bind_group_layout(&egui_wgpu::wgpu::BindGroupLayoutDescriptor {
+                label: Some("Water Bind Group Layout"),
+                entries: &[egui_wgpu::wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: egui_wgpu::wgpu::ShaderStages::VERTEX
+                        | egui_wgpu::wgpu::ShaderStages::FRAGMENT,
+                    ty: egui_wgpu::wgpu::BindingType::Buffer {
+                        ty: egui_wgpu::wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            });
+        let uniform_buffer = device.create_buffer(&egui_wgpu::wgpu::BufferDescriptor {
+            label: Some("Water Buffer"),
+            size: std::mem::size_of::<WaterUniform>() as egui_wgpu::wgpu::BufferAddress,
+            usage: egui_wgpu::wgpu::BufferUsages::UNIFORM | egui_wgpu::wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&egui_wgpu::wgpu::BindGroupDescriptor {
+            label: Some("Water Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[egui_wgpu::wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+        });
+        let layout = device.create_pipeline_layout(&egui_wgpu::wgpu::PipelineLayoutDescriptor {
+            label: Some("Water Pipeline Layout"),
+            bind_group_layouts: &[camera_bind_group_layout, &bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = create_pipeline(device, &shader, &layout, format, false);
+        trace!("Water pipeline created");
+
+        Self {
+            pipeline,
+            shader,
+            layout,
+            format,
+            uniform_buffer,
+            bind_group,
+        }
+    }
+
+    /// Recreates the pipeline for the depth convention, see
+    /// `RenderSettings::reversed_z`
+    pub fn set_reversed_z(&mut self, device: &egui_wgpu::wgpu::Device, reversed_z: bool) {
+        self.pipeline =
+            create_pipeline(device, &self.shader, &self.layout, self.format, reversed_z);
+    }
+
+    /// Uploads the plane's parameters, before the pass `draw` records into
+    pub fn prepare(&self, queue: &egui_wgpu::wgpu::Queue, uniform: WaterUniform) {
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    /// Draws into a pass with the terrain's depth attachment, replacing its
+    /// bind groups 0 and 1
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut egui_wgpu::wgpu::RenderPass<'a>,
+        camera_bind_group: &'a egui_wgpu::wgpu::BindGroup,
+    ) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.bind_group, &[]);
+        render_pass.draw(0..6, 0..1);
+    }
+
+    pub fn destroy(&self) {
+        self.uniform_buffer.destroy();
+    }
+}
+
+fn create_pipeline(
+    device: &egui_wgpu::wgpu::Device,
+    shader: &egui_wgpu::wgpu::ShaderModule,
+    layout: &egui_wgpu::wgpu::PipelineLayout,
+    format: egui_wgpu::wgpu::TextureFormat,
+    reversed_z: bool,
+) -> egui_wgpu::wgpu::RenderPipeline {
+    device.create_render_pipeline(&egui_wgpu::wgpu::RenderPipelineDescriptor {
+        label: Some("Water Pipeline"),
+        layout: Some(layout),
+        vertex: egui_wgpu::wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_main",
+            buffers: &[],
+        },
+        fragment: Some(egui_wgpu::wgpu::FragmentState {
+            module: shader,
+            entry_point: "fs_main",
+            targets: &[Some(egui_wgpu::wgpu::ColorTargetState {
+                format,
+                blend: Some(egui_wgpu::wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: egui_wgpu::wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: egui_wgpu::wgpu::PrimitiveState::default(),
+        // Tested but not written, the terrain below stays in the depth
+        // buffer for the outlines
+        depth_stencil: Some(egui_wgpu::wgpu::DepthStencilState {
+            format: crate::texture::Texture::DEPTH_FORMAT,
+            depth_write_enabled: false,
+            depth_compare: crate::texture::Texture::depth_compare(
+                egui_wgpu::wgpu::CompareFunction::LessEqual,
+                reversed_z,
+            ),
+            stencil: egui_wgpu::wgpu::StencilState::default(),
+            bias: egui_wgpu::wgpu::DepthBiasState::default(),
+        }),
+        multisample: egui_wgpu::wgpu::MultisampleState::default(),
+        multiview: None,
+    })
+}
@@ -0,0 +1,80 @@
+// Flat alpha blended water plane over the terrain's extent
+
+struct Camera {
+    view_proj: mat4x4<f32>,
+    log_depth: f32,
+    _padding0: f32,
+    _padding1: f32,
+    _padding2: f32,
+}
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+
+struct Water {
+    // min x, min z, max x, max z in world space
+    bounds: vec4<f32>,
+    // Linear RGB and opacity
+    color: vec4<f32>,
+    level: f32,
+    time: f32,
+    encode_srgb: u32,
+    _padding0: f32,
+}
+@group(1) @binding(0)
+var<uniform> water: Water;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world: vec2<f32>,
+    @location(1) clip_w: f32,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    // Two triangles covering the bounds
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(1.0, 0.0),
+        vec2<f32>(0.0, 1.0),
+        vec2<f32>(0.0, 1.0),
+        vec2<f32>(1.0, 0.0),
+        vec2<f32>(1.0, 1.0),
+    );
+    let corner = corners[index];
+    let world = mix(water.bounds.xy, water.bounds.zw, corner);
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(world.x, water.level, world.y, 1.0);
+    out.world = world;
+    out.clip_w = out.clip_position.w;
+    return out;
+}
+
+struct FragmentOutput {
+    @location(0) color: vec4<f32>,
+    @builtin(frag_depth) depth: f32,
+}
+
+fn linearToSrgb(c: vec3<f32>) -> vec3<f32> {
+    let low = c * 12.92;
+    let high = 1.055 * pow(c, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(high, low, c <= vec3<f32>(0.0031308));
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+    // Two crossing wave trains for a slight shimmer
+    let waves = sin(in.world.x * 0.7 + water.time * 1.3) * sin(in.world.y * 0.5 - water.time * 0.9);
+    var color = water.color.rgb * (1.0 + 0.08 * waves);
+    if water.encode_srgb != 0u {
+        color = linearToSrgb(max(color, vec3<f32>(0.0)));
+    }
+    var out: FragmentOutput;
+    out.color = vec4<f32>(color, water.color.a);
+    // The quad is large, so the logarithmic depth is written per fragment
+    if camera.log_depth > 0.0 {
+        out.depth = log2(max(1e-6, 1.0 + in.clip_w)) * camera.log_depth;
+    } else {
+        out.depth = in.clip_position.z;
+    }
+    return out;
+}